@@ -0,0 +1,102 @@
+//! ## Admin Routes
+//!
+//! Allows admins to inspect deliveries which ended up in the dead-letter state and to requeue
+//! them once the underlying issue is resolved.
+//!
+//! Admin routes require the `Authorization: Bearer <token>` header to match the configured admin
+//! token. If no admin token is configured, the routes are disabled.
+
+use crate::{
+	auth::secrets_match,
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use rocket::{
+	get,
+	http::Status,
+	post,
+	request::{FromRequest, Outcome, Request},
+	response::status,
+	serde::json::Json,
+	State,
+};
+use storage::{outbox::OutboxJob, DbConn};
+use types::api::ErrorResponse;
+
+pub struct AdminConfig {
+	/// The token admins authenticate with.
+	pub token: Option<String>,
+}
+
+/// Request guard which succeeds if the request is made by an admin.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+	type Error = Error;
+
+	async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+		let expected = request.rocket().state::<AdminConfig>().and_then(|c| c.token.as_ref());
+		let provided = request
+			.headers()
+			.get_one("Authorization")
+			.and_then(|header| header.strip_prefix("Bearer "));
+
+		match (expected, provided) {
+			(Some(expected), Some(provided)) if secrets_match(expected, provided) =>
+				Outcome::Success(Admin),
+			_ => Outcome::Error((Status::Unauthorized, Error::Unauthorized)),
+		}
+	}
+}
+
+#[get("/admin/dead_letters")]
+pub async fn dead_letters(
+	conn: &State<DbConn>,
+	admin: Result<Admin, Error>,
+) -> Result<status::Custom<String>, status::Custom<Json<ErrorResponse>>> {
+	admin.map_err(|err| custom_error(Status::Unauthorized, err))?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let jobs = OutboxJob::query_dead_letters(&conn).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query dead letters: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	let serialized = serde_json::to_string(&jobs).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to serialize: {:?}", err);
+		custom_error(Status::InternalServerError, Error::FailedToSerialize)
+	})?;
+
+	Ok(status::Custom(Status::Ok, serialized))
+}
+
+#[post("/admin/dead_letters/<job_id>/requeue")]
+pub async fn requeue_dead_letter(
+	conn: &State<DbConn>,
+	admin: Result<Admin, Error>,
+	job_id: i64,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	admin.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	log::info!(target: LOG_TARGET, "Requeuing dead letter: {}", job_id);
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let requeued = OutboxJob::requeue(&conn, job_id, notification::timestamp()).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to requeue job: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	if requeued == 0 {
+		return Err(custom_error(Status::NotFound, Error::JobNotFound));
+	}
+
+	Ok(status::Custom(Status::Ok, ()))
+}
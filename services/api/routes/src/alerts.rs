@@ -0,0 +1,73 @@
+//! ## Alert Routes
+//!
+//! Critical alerts are escalated until they are acknowledged. Every delivery of an alert links to
+//! the acknowledge route, so that anyone who received the alert can stop the escalation, e.g. the
+//! team member it was escalated to. The token in the link is the only credential required.
+//!
+//! Opening the link with `GET` only returns a page on which the alert is acknowledged, see
+//! [`crate::pages`]. Otherwise a link preview could stop the escalation before anyone saw the
+//! alert. The page, like webhook receivers, posts to the link.
+
+use crate::{
+	errors::{custom_error, Error},
+	pages, LOG_TARGET,
+};
+use rocket::{
+	get,
+	http::Status,
+	post,
+	response::{content::RawHtml, status},
+	serde::json::Json,
+	State,
+};
+use storage::{escalations::Alert, tokens::hash_token, DbConn};
+use types::api::ErrorResponse;
+
+/// Returns the page on which the alert is acknowledged.
+#[get("/alert/<token>/acknowledge")]
+pub async fn acknowledgement_page(
+	conn: &State<DbConn>,
+	token: &str,
+) -> Result<RawHtml<String>, status::Custom<Json<ErrorResponse>>> {
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let alert = Alert::query_by_token(&conn, &hash_token(token)).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query alert: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	let alert = alert.ok_or(custom_error(Status::NotFound, Error::AlertNotFound))?;
+
+	let question = match alert.acknowledged_at {
+		Some(_) => "The alert was acknowledged already.",
+		None => "Do you want to acknowledge the alert? It won't be escalated any further.",
+	};
+	Ok(pages::confirmation("Acknowledge alert", question, "Acknowledge"))
+}
+
+#[post("/alert/<token>/acknowledge")]
+pub async fn acknowledge_alert(
+	conn: &State<DbConn>,
+	token: &str,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let now = notification::timestamp();
+	let alert = Alert::acknowledge(&conn, &hash_token(token), now).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to acknowledge alert: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	match alert {
+		Some(alert) => {
+			log::info!(target: LOG_TARGET, "Alert {} was acknowledged", alert.id);
+			Ok(status::Custom(Status::Ok, ()))
+		},
+		None => Err(custom_error(Status::NotFound, Error::AlertNotFound)),
+	}
+}
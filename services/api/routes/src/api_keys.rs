@@ -0,0 +1,133 @@
+//! ## API Key Routes
+//!
+//! Scripts and bots act on behalf of a user through API keys, which are sent in place of a
+//! session token as `Authorization: Bearer <key>`. Keys are managed within a session:
+//! - `POST /user/api_keys` creates a key. The key is only returned in this response.
+//! - `GET /user/api_keys` lists the keys of the user.
+//! - `DELETE /user/api_keys/<id>` revokes a key.
+//!
+//! The scope of a key limits what it can be used for, see [`api::ApiKeyScope`]. Keys may expire,
+//! and can't be used to manage keys themselves.
+
+use crate::{
+	auth::{session_user, Authenticated, API_KEY_PREFIX, SESSION_ONLY},
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use rocket::{delete, get, http::Status, post, response::status, serde::json::Json, State};
+use storage::{
+	auth::ApiKey,
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	DbConn,
+};
+use types::api::{self, ErrorResponse};
+
+/// The maximum length of the name of a key.
+pub const MAX_NAME_LENGTH: usize = 64;
+
+#[post("/user/api_keys", data = "<request>")]
+pub async fn create_api_key(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	request: Json<api::ApiKeyRequest>,
+) -> Result<Json<api::NewApiKey>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SESSION_ONLY)?;
+
+	let name = request.name.trim();
+	ensure!(
+		!name.is_empty() && name.chars().count() <= MAX_NAME_LENGTH,
+		custom_error(Status::BadRequest, Error::InvalidApiKeyName)
+	);
+	let now = notification::timestamp();
+	ensure!(
+		request.expires_at.map_or(true, |expires_at| expires_at > now),
+		custom_error(Status::BadRequest, Error::InvalidApiKeyExpiry)
+	);
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = session_user(&conn, &auth)?;
+
+	let key = format!("{}{}", API_KEY_PREFIX, random_token(TOKEN_BYTES));
+	let id = random_token(8);
+	let api_key = ApiKey {
+		id,
+		user_id: user.id,
+		name: name.to_string(),
+		key_hash: hash_token(&key),
+		scope: request.scope,
+		created_at: now,
+		expires_at: request.expires_at,
+		last_used_at: None,
+	};
+	ApiKey::create(&conn, &api_key).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to create API key: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	log::info!(target: LOG_TARGET, "Created API key {} of user {}", api_key.id, user.id);
+	Ok(Json(api::NewApiKey { key, api_key: to_response(api_key) }))
+}
+
+#[get("/user/api_keys")]
+pub async fn api_keys(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<Json<Vec<api::ApiKey>>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SESSION_ONLY)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = session_user(&conn, &auth)?;
+	let keys = ApiKey::query_by_user(&conn, user.id).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query API keys: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(Json(keys.into_iter().map(to_response).collect()))
+}
+
+#[delete("/user/api_keys/<id>")]
+pub async fn revoke_api_key(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	id: &str,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SESSION_ONLY)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = session_user(&conn, &auth)?;
+	let revoked = ApiKey::revoke(&conn, user.id, id).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to revoke API key: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	ensure!(revoked, custom_error(Status::NotFound, Error::ApiKeyNotFound));
+
+	log::info!(target: LOG_TARGET, "Revoked API key {} of user {}", id, user.id);
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+pub(crate) fn to_response(key: ApiKey) -> api::ApiKey {
+	api::ApiKey {
+		id: key.id,
+		name: key.name,
+		scope: key.scope,
+		created_at: key.created_at,
+		expires_at: key.expires_at,
+		last_used_at: key.last_used_at,
+	}
+}
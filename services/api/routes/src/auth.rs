@@ -0,0 +1,420 @@
+//! ## Authentication Routes
+//!
+//! Users sign in with a Substrate account, following Sign-In with Substrate:
+//! 1. `POST /auth/challenge` with the SS58 `address` of the account returns a message which
+//!    contains a fresh nonce.
+//! 2. The message is signed with the account, e.g. through `signRaw` of a wallet extension. Both
+//!    sr25519 and ed25519 accounts are supported.
+//! 3. `POST /auth/login` with the `nonce` and the hex encoded `signature` returns a session token.
+//!
+//! The address of a key differs between networks, so accounts are identified by their address on
+//! the generic Substrate network, see [`ACCOUNT_PREFIX`]. A key signs in as the same account with
+//! the address of any network.
+//!
+//! Users who come in through Telegram sign in with the Telegram Login Widget instead, see
+//! [`crate::telegram`], and users without an account through their email address, see
+//! [`crate::login_links`].
+//!
+//! Routes which act on a user require the `Authorization: Bearer <token>` header. A user is bound
+//! to the account which registered it, and the routes act on the user bound to the account of the
+//! session.
+//!
+//! Instead of a session token, scripts send an API key of the user, see [`crate::api_keys`]. The
+//! scope of the key limits which routes it can be used for.
+
+use crate::{
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use blake2::{Blake2b512, Digest};
+use chrono::{DateTime, SecondsFormat};
+use common_macros::ensure;
+use rocket::{
+	http::{uri::Absolute, Status},
+	post,
+	request::{FromRequest, Outcome, Request},
+	response::status,
+	serde::json::Json,
+	State,
+};
+use rusqlite::Connection;
+use std::env;
+use storage::{
+	auth::{ApiKey, Challenge, Session},
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	users::User,
+	DbConn,
+};
+use subtle::ConstantTimeEq;
+use types::{
+	api::{self, ApiKeyScope, ErrorResponse},
+	Timestamp,
+};
+
+/// How long a challenge can be signed, in seconds.
+pub const CHALLENGE_LIFETIME: u64 = 10 * 60;
+/// How long a session lasts, in seconds.
+pub const SESSION_LIFETIME: u64 = 7 * 24 * 60 * 60;
+/// The network prefix of the addresses which identify Substrate accounts.
+pub const ACCOUNT_PREFIX: u8 = 42;
+
+/// What the messages to sign are issued for.
+pub struct AuthConfig {
+	/// The domain which requests the signature, e.g. `api.example.com`.
+	pub domain: String,
+	/// The url of the api.
+	pub uri: String,
+}
+
+impl AuthConfig {
+	/// Issues the messages for the url of the api, e.g. `https://api.example.com`.
+	pub fn for_url(url: &str) -> Self {
+		let uri = url.trim_end_matches('/').to_string();
+		let domain = Absolute::parse(&uri)
+			.ok()
+			.and_then(|uri| uri.authority().map(|authority| authority.to_string()))
+			.unwrap_or(uri.clone());
+
+		Self { domain, uri }
+	}
+
+	/// Reads the url of the api from `PUBLIC_API_URL`.
+	pub fn from_env() -> Self {
+		Self::for_url(&env::var("PUBLIC_API_URL").unwrap_or("http://localhost:8000".into()))
+	}
+
+	/// Returns the message the account signs to sign in.
+	fn message(
+		&self,
+		address: &str,
+		nonce: &str,
+		issued_at: Timestamp,
+		expires_at: Timestamp,
+	) -> String {
+		format!(
+			"{domain} wants you to sign in with your Substrate account:\n\
+				{address}\n\n\
+				Sign in to manage your coretime notifications.\n\n\
+				URI: {uri}\n\
+				Version: 1\n\
+				Nonce: {nonce}\n\
+				Issued At: {issued_at}\n\
+				Expiration Time: {expires_at}",
+			domain = self.domain,
+			uri = self.uri,
+			issued_at = format_time(issued_at),
+			expires_at = format_time(expires_at),
+		)
+	}
+}
+
+/// The prefix of API keys, which tells them apart from session tokens.
+pub const API_KEY_PREFIX: &str = "key_";
+
+/// The scopes of API keys which may read the user.
+pub(crate) const READ: &[ApiKeyScope] =
+	&[ApiKeyScope::Full, ApiKeyScope::ReadOnly, ApiKeyScope::Subscriptions];
+/// The scopes of API keys which may manage subscriptions.
+pub(crate) const SUBSCRIPTIONS: &[ApiKeyScope] = &[ApiKeyScope::Full, ApiKeyScope::Subscriptions];
+/// The scopes of API keys which may change the user.
+pub(crate) const WRITE: &[ApiKeyScope] = &[ApiKeyScope::Full];
+/// Requests which only sessions may make, e.g. managing API keys.
+pub(crate) const SESSION_ONLY: &[ApiKeyScope] = &[];
+
+/// Request guard which succeeds if the request is made within a session or with an API key.
+pub struct Authenticated {
+	/// The account which signed in, either an SS58 address with the [`ACCOUNT_PREFIX`], a Telegram
+	/// account or an email address. For API keys, the account of their user.
+	pub account: String,
+	credential: Credential,
+}
+
+/// How a request was authenticated.
+enum Credential {
+	/// The hash of the session token.
+	Session(String),
+	/// The scope of the API key.
+	ApiKey(ApiKeyScope),
+}
+
+impl Authenticated {
+	/// Ensures that the request is made within a session, or with an API key of the given scopes.
+	pub(crate) fn authorize(
+		&self,
+		scopes: &[ApiKeyScope],
+	) -> Result<(), status::Custom<Json<ErrorResponse>>> {
+		match &self.credential {
+			Credential::Session(_) => Ok(()),
+			Credential::ApiKey(scope) if scopes.contains(scope) => Ok(()),
+			Credential::ApiKey(_) => Err(custom_error(Status::Forbidden, Error::InsufficientScope)),
+		}
+	}
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authenticated {
+	type Error = Error;
+
+	async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+		let Some(token) = request
+			.headers()
+			.get_one("Authorization")
+			.and_then(|header| header.strip_prefix("Bearer "))
+		else {
+			return Outcome::Error((Status::Unauthorized, Error::Unauthorized));
+		};
+		let Some(conn) = request.rocket().state::<DbConn>() else {
+			return Outcome::Error((Status::InternalServerError, Error::DbConnectionFailed));
+		};
+		let Ok(conn) = conn.lock() else {
+			return Outcome::Error((Status::InternalServerError, Error::DbConnectionFailed));
+		};
+
+		let token_hash = hash_token(token);
+		let authenticated = if token.starts_with(API_KEY_PREFIX) {
+			authenticate_key(&conn, &token_hash)
+		} else {
+			Session::query(&conn, &token_hash, notification::timestamp()).map(|session| {
+				session.map(|session| Authenticated {
+					account: session.account,
+					credential: Credential::Session(token_hash),
+				})
+			})
+		};
+
+		match authenticated {
+			Ok(Some(authenticated)) => Outcome::Success(authenticated),
+			Ok(None) => Outcome::Error((Status::Unauthorized, Error::Unauthorized)),
+			Err(err) => {
+				log::error!(target: LOG_TARGET, "Failed to authenticate: {:?}", err);
+				Outcome::Error((Status::InternalServerError, Error::DbError))
+			},
+		}
+	}
+}
+
+/// Authenticates the request as the account of the user of the API key.
+fn authenticate_key(conn: &Connection, key_hash: &str) -> rusqlite::Result<Option<Authenticated>> {
+	let Some(key) = ApiKey::authenticate(conn, key_hash, notification::timestamp())? else {
+		return Ok(None);
+	};
+
+	Ok(User::query_account(conn, key.user_id)?
+		.map(|account| Authenticated { account, credential: Credential::ApiKey(key.scope) }))
+}
+
+/// Returns whether the provided secret matches the expected one.
+///
+/// The comparison takes the same time wherever the secrets differ, so that the expected secret
+/// can't be guessed from the response times.
+pub(crate) fn secrets_match(expected: &str, provided: &str) -> bool {
+	expected.as_bytes().ct_eq(provided.as_bytes()).into()
+}
+
+/// Returns the user bound to the account which signed in.
+pub(crate) fn session_user(
+	conn: &Connection,
+	auth: &Authenticated,
+) -> Result<User, status::Custom<Json<ErrorResponse>>> {
+	let user = User::query_by_account(conn, &auth.account).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to search user by account: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	user.ok_or_else(|| custom_error(Status::NotFound, Error::UserNotFound))
+}
+
+#[post("/auth/challenge", data = "<request>")]
+pub async fn challenge(
+	conn: &State<DbConn>,
+	config: &State<AuthConfig>,
+	request: Json<api::ChallengeRequest>,
+) -> Result<Json<api::Challenge>, status::Custom<Json<ErrorResponse>>> {
+	ensure!(
+		decode_address(&request.address).is_some(),
+		custom_error(Status::BadRequest, Error::InvalidAccount)
+	);
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let now = notification::timestamp();
+	let nonce = random_token(16);
+	let challenge = Challenge {
+		message: config.message(&request.address, &nonce, now, now + CHALLENGE_LIFETIME),
+		nonce,
+		account: request.address.clone(),
+		expires_at: now + CHALLENGE_LIFETIME,
+	};
+	Challenge::create(&conn, &challenge).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to create challenge: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(Json(api::Challenge {
+		nonce: challenge.nonce,
+		message: challenge.message,
+		expires_at: challenge.expires_at,
+	}))
+}
+
+#[post("/auth/login", data = "<request>")]
+pub async fn login(
+	conn: &State<DbConn>,
+	request: Json<api::LoginRequest>,
+) -> Result<Json<api::SessionToken>, status::Custom<Json<ErrorResponse>>> {
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let now = notification::timestamp();
+	// The challenge is used up even if the signature is invalid.
+	let challenge = Challenge::take(&conn, &request.nonce, now).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query challenge: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	let Some(challenge) = challenge else {
+		return Err(custom_error(Status::Unauthorized, Error::Unauthorized));
+	};
+
+	let invalid = || custom_error(Status::Unauthorized, Error::InvalidSignature);
+	let public_key = decode_address(&challenge.account).ok_or_else(invalid)?;
+	let signature =
+		hex::decode(request.signature.trim_start_matches("0x")).map_err(|_| invalid())?;
+	ensure!(verify_signature(&public_key, &challenge.message, &signature), invalid());
+
+	// Users were bound to the address they signed in with before accounts were identified by the
+	// address of their key on the generic network.
+	let account = encode_address(ACCOUNT_PREFIX, &public_key);
+	if account != challenge.account {
+		User::rebind_account(&conn, &challenge.account, &account).map_err(|err| {
+			log::error!(target: LOG_TARGET, "Failed to rebind account: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		})?;
+	}
+
+	start_session(&conn, account, now).map(Json)
+}
+
+#[post("/auth/logout")]
+pub async fn logout(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	// API keys can't end the session they weren't issued for.
+	let Credential::Session(token_hash) = &auth.credential else {
+		return Err(custom_error(Status::Forbidden, Error::InsufficientScope));
+	};
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	Session::delete(&conn, token_hash, notification::timestamp()).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to delete session: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+/// Starts a session of the account, which lasts [`SESSION_LIFETIME`].
+pub(crate) fn start_session(
+	conn: &Connection,
+	account: String,
+	now: Timestamp,
+) -> Result<api::SessionToken, status::Custom<Json<ErrorResponse>>> {
+	let token = random_token(TOKEN_BYTES);
+	let session =
+		Session { token_hash: hash_token(&token), account, expires_at: now + SESSION_LIFETIME };
+	Session::create(conn, &session).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to create session: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	log::info!(target: LOG_TARGET, "Account {} signed in", session.account);
+	Ok(api::SessionToken { token, expires_at: session.expires_at })
+}
+
+/// Decodes the public key of an SS58 address.
+pub fn decode_address(address: &str) -> Option<[u8; 32]> {
+	let data = bs58::decode(address).into_vec().ok()?;
+	// Prefixes of networks up to 63 take one byte, the others two.
+	let prefix_len = match data.first()? {
+		0..=63 => 1,
+		64..=127 => 2,
+		_ => return None,
+	};
+	if data.len() != prefix_len + 32 + 2 {
+		return None;
+	}
+
+	let (payload, checksum) = data.split_at(data.len() - 2);
+	if ss58_hash(payload)[..2] != *checksum {
+		return None;
+	}
+	payload[prefix_len..].try_into().ok()
+}
+
+/// Encodes the public key as the SS58 address of a network with a prefix up to 63.
+pub fn encode_address(prefix: u8, public_key: &[u8; 32]) -> String {
+	let mut data = vec![prefix];
+	data.extend(public_key);
+	let checksum = ss58_hash(&data);
+	data.extend(&checksum[..2]);
+	bs58::encode(data).into_string()
+}
+
+/// Whether the message was signed with the key, either through sr25519 or ed25519.
+///
+/// Wallets wrap the messages they sign in `<Bytes>` tags, so the wrapped message is accepted as
+/// well. Signatures may be prefixed with the byte which denotes their scheme.
+pub fn verify_signature(public_key: &[u8; 32], message: &str, signature: &[u8]) -> bool {
+	let signature: [u8; 64] = match signature.len() {
+		64 => signature.try_into().expect("length is checked"),
+		65 => signature[1..].try_into().expect("length is checked"),
+		_ => return false,
+	};
+	let wrapped = format!("<Bytes>{}</Bytes>", message);
+
+	[message.as_bytes(), wrapped.as_bytes()].iter().any(|message| {
+		verify_sr25519(public_key, message, &signature) ||
+			verify_ed25519(public_key, message, &signature)
+	})
+}
+
+fn verify_sr25519(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+	let (Ok(public_key), Ok(signature)) = (
+		schnorrkel::PublicKey::from_bytes(public_key),
+		schnorrkel::Signature::from_bytes(signature),
+	) else {
+		return false;
+	};
+	public_key.verify_simple(b"substrate", message, &signature).is_ok()
+}
+
+fn verify_ed25519(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+	let Ok(public_key) = ed25519_zebra::VerificationKey::try_from(*public_key) else {
+		return false;
+	};
+	public_key.verify(&ed25519_zebra::Signature::from(*signature), message).is_ok()
+}
+
+fn ss58_hash(data: &[u8]) -> Vec<u8> {
+	Blake2b512::new()
+		.chain_update(b"SS58PRE")
+		.chain_update(data)
+		.finalize()
+		.to_vec()
+}
+
+fn format_time(timestamp: Timestamp) -> String {
+	DateTime::from_timestamp(timestamp as i64, 0)
+		.map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+		.unwrap_or_default()
+}
@@ -0,0 +1,181 @@
+//! ## Escalation Contact Routes
+//!
+//! Escalation steps can deliver alerts to contacts, e.g. team members, which aren't addresses of
+//! the user. Nothing is delivered to a contact before it confirmed that it wants to receive the
+//! alerts of the user, so that nobody can flood someone else with alerts. Whenever a subscription
+//! escalates to a contact which didn't confirm yet, a confirmation link is sent to the contact
+//! through the channel of the step. Opening the link returns a page on which the contact confirms,
+//! see [`crate::pages`], so that a link preview can't confirm on behalf of the contact.
+//!
+//! Telegram contacts are messaged through the chat they started with the bot, they can't be sent
+//! a link before.
+//!
+//! The token in the link is the only credential required, only its hash is stored.
+
+use crate::{
+	errors::{custom_error, Error},
+	pages, LOG_TARGET,
+};
+use notification::{
+	channels::{self, Channels},
+	message::{Action, Message},
+};
+use rocket::{
+	get,
+	http::Status,
+	post,
+	response::{content::RawHtml, status},
+	serde::json::Json,
+	State,
+};
+use rusqlite::{Connection, Result};
+use std::env;
+use storage::{
+	escalations::Contact,
+	telegram::TelegramChat,
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	DbConn,
+};
+use types::{api::ErrorResponse, EscalationStep, Notifier};
+
+pub struct ContactConfirmation {
+	/// The public url of the api, under which the links are opened.
+	pub api_url: Option<String>,
+	/// The channels through which the links are sent.
+	pub channels: Channels,
+}
+
+/// A confirmation link which has to be sent to a contact.
+pub(crate) struct ConfirmationRequest {
+	channel: Notifier,
+	/// The address the link is sent to, which is the chat of Telegram contacts.
+	address: String,
+	token: String,
+}
+
+impl ContactConfirmation {
+	/// Reads the url of the api from `PUBLIC_API_URL`. The links are sent through the channels
+	/// which are configured for the delivery of notifications.
+	pub fn from_env() -> Self {
+		Self { api_url: env::var("PUBLIC_API_URL").ok(), channels: channels::from_env() }
+	}
+
+	/// Requests a confirmation from the contacts of the steps which didn't confirm yet.
+	///
+	/// Returns the links to send once the connection is released, see [`Self::send_links`].
+	pub(crate) fn request(
+		conn: &Connection,
+		user_id: u32,
+		steps: &[EscalationStep],
+	) -> Result<Vec<ConfirmationRequest>> {
+		let now = notification::timestamp();
+
+		let mut requests = vec![];
+		for step in steps {
+			let Some(contact) = &step.contact else { continue };
+			let address = match step.channel {
+				Notifier::Telegram => match TelegramChat::query_by_username(conn, contact)? {
+					Some(chat) => chat.chat_id.to_string(),
+					None => {
+						log::warn!(target: LOG_TARGET, "Contact {} didn't start a chat", contact);
+						continue;
+					},
+				},
+				_ => contact.clone(),
+			};
+			let token = random_token(TOKEN_BYTES);
+			if Contact::request(conn, user_id, &step.channel, contact, &hash_token(&token), now)? {
+				requests.push(ConfirmationRequest {
+					channel: step.channel.clone(),
+					address,
+					token,
+				});
+			}
+		}
+		Ok(requests)
+	}
+
+	/// Sends the confirmation links to the contacts.
+	///
+	/// Failures are only logged, since the user can request another link by setting the steps
+	/// again.
+	pub(crate) async fn send_links(&self, requests: Vec<ConfirmationRequest>) {
+		let Some(api_url) = &self.api_url else {
+			if !requests.is_empty() {
+				log::warn!(target: LOG_TARGET, "Can't send confirmation links, PUBLIC_API_URL isn't set");
+			}
+			return;
+		};
+
+		for request in requests {
+			let Some(channel) = self.channels.get(&request.channel) else {
+				log::warn!(target: LOG_TARGET, "{:?} channel is not configured", request.channel);
+				continue;
+			};
+			let url = format!(
+				"{}/escalation_contacts/{}/confirm",
+				api_url.trim_end_matches('/'),
+				request.token
+			);
+			let message = Message::plain(
+				"Confirm coretime alerts",
+				"A user of the coretime notifier wants to escalate critical alerts to you. Confirm \
+					that you want to receive them, otherwise ignore this message.",
+			)
+			.with_action(Action { label: "Confirm alerts".into(), url });
+
+			if let Err(err) = channel.send(&request.address, &message).await {
+				log::error!(target: LOG_TARGET, "Failed to send confirmation link: {}", err);
+			}
+		}
+	}
+}
+
+/// Returns the page on which the contact confirms.
+#[get("/escalation_contacts/<token>/confirm")]
+pub async fn contact_confirmation_page(
+	conn: &State<DbConn>,
+	token: &str,
+) -> Result<RawHtml<String>, status::Custom<Json<ErrorResponse>>> {
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let contact = Contact::query_by_token(&conn, &hash_token(token)).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query contact: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	let contact = contact.ok_or(custom_error(Status::NotFound, Error::ContactNotFound))?;
+
+	let question = match contact.confirmed_at {
+		Some(_) => "You confirmed receiving the alerts already.",
+		None => "Do you want to receive the critical alerts of a coretime notifier user?",
+	};
+	Ok(pages::confirmation("Confirm alerts", question, "Confirm alerts"))
+}
+
+#[post("/escalation_contacts/<token>/confirm")]
+pub async fn confirm_contact(
+	conn: &State<DbConn>,
+	token: &str,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let now = notification::timestamp();
+	let contact = Contact::confirm(&conn, &hash_token(token), now).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to confirm contact: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	match contact {
+		Some(contact) => {
+			log::info!(target: LOG_TARGET, "Contact of user {} was confirmed", contact.user_id);
+			Ok(status::Custom(Status::Ok, ()))
+		},
+		None => Err(custom_error(Status::NotFound, Error::ContactNotFound)),
+	}
+}
@@ -0,0 +1,55 @@
+//! ## Account Deletion Route
+//!
+//! `DELETE /user` removes the user of the session along with everything stored about it, see
+//! [`User::delete`]. Notifications which weren't delivered yet are dropped.
+//!
+//! Deleting requires a session, API keys can't delete their user. The last owner of an
+//! organization with other members has to hand the organization over first.
+
+use crate::{
+	auth::{session_user, Authenticated, SESSION_ONLY},
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use rocket::{delete, http::Status, response::status, serde::json::Json, State};
+use storage::{
+	organizations::{Member, Organization},
+	users::User,
+	DbConn,
+};
+use types::api::{ErrorResponse, OrganizationRole};
+
+#[delete("/user")]
+pub async fn delete_user(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SESSION_ONLY)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to delete user: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let user = session_user(&conn, &auth)?;
+
+	for (organization, role) in Organization::query_by_member(&conn, user.id).map_err(db_error)? {
+		if role != OrganizationRole::Owner {
+			continue;
+		}
+		let owners = Member::count_owners(&conn, organization.id).map_err(db_error)?;
+		let members = Member::query_by_organization(&conn, organization.id).map_err(db_error)?;
+		ensure!(owners > 1 || members.len() == 1, custom_error(Status::Conflict, Error::LastOwner));
+	}
+
+	User::delete(&conn, user.id).map_err(db_error)?;
+	log::info!(target: LOG_TARGET, "Deleted user {}", user.id);
+
+	Ok(status::Custom(Status::Ok, ()))
+}
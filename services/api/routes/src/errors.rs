@@ -11,7 +11,7 @@ use types::api::ErrorResponse;
 pub enum Error {
 	/// Failed to get the db connection.
 	DbConnectionFailed,
-	/// The configured notifier cannot be empty.
+	/// A notification isn't delivered through any configured channel.
 	NotifierEmpty,
 	/// Attempted accessing the db but failed.
 	DbError,
@@ -23,6 +23,92 @@ pub enum Error {
 	UserNotFound,
 	/// Failed to serialize some data,
 	FailedToSerialize,
+	/// The request lacks valid credentials.
+	Unauthorized,
+	/// The outbox job doesn't exist or isn't in the expected state.
+	JobNotFound,
+	/// The language isn't a valid language tag.
+	InvalidLanguage,
+	/// The time zone isn't a known IANA time zone.
+	InvalidTimezone,
+	/// The quiet hours aren't hours of the day, or start and end at the same hour.
+	InvalidQuietHours,
+	/// The handle isn't a Telegram username.
+	InvalidTelegramHandle,
+	/// The url isn't the url of a Discord webhook.
+	InvalidDiscordWebhook,
+	/// The url isn't the url of a Slack incoming webhook.
+	InvalidSlackWebhook,
+	/// The room id isn't a Matrix room id.
+	InvalidMatrixRoom,
+	/// The webhook url isn't an https url.
+	InvalidWebhookUrl,
+	/// The user didn't configure a webhook.
+	WebhookNotConfigured,
+	/// The escalation steps aren't ordered by time, or escalate to an unreachable channel.
+	InvalidEscalation,
+	/// There is no alert with the token.
+	AlertNotFound,
+	/// No contact was sent the confirmation token.
+	ContactNotFound,
+	/// The token isn't a valid confirmation token for the email address of the user.
+	InvalidVerificationToken,
+	/// The confirmation link expired.
+	VerificationTokenExpired,
+	/// The address isn't a valid SS58 address.
+	InvalidAccount,
+	/// The challenge wasn't signed by its account.
+	InvalidSignature,
+	/// The user isn't bound to the account which signed in.
+	Forbidden,
+	/// The account which signed in already registered a user.
+	AccountInUse,
+	/// The data of the Telegram Login Widget isn't signed by Telegram.
+	InvalidTelegramLogin,
+	/// The data of the Telegram Login Widget is too old.
+	TelegramLoginExpired,
+	/// The email address is malformed.
+	InvalidEmail,
+	/// The sign-in link doesn't exist, expired or was used before.
+	InvalidLoginLink,
+	/// Too many sign-in links were requested for the address.
+	TooManyRequests,
+	/// The scope of the API key doesn't allow the request.
+	InsufficientScope,
+	/// The name of the API key is empty or too long.
+	InvalidApiKeyName,
+	/// The expiry of the API key isn't in the future.
+	InvalidApiKeyExpiry,
+	/// The user doesn't have an API key with the id.
+	ApiKeyNotFound,
+	/// The organization doesn't exist, or the user isn't a member of it.
+	OrganizationNotFound,
+	/// The name of the organization is empty or too long.
+	InvalidOrganizationName,
+	/// The role of the user in the organization doesn't allow the request.
+	InsufficientRole,
+	/// Organizations can only subscribe to the state of parachains.
+	InvalidOrganizationSubscription,
+	/// The user doesn't have an address for the channel.
+	ChannelNotConfigured,
+	/// The invite doesn't exist, expired or was accepted before.
+	InvalidInvite,
+	/// The user is already a member of the organization.
+	AlreadyMember,
+	/// The user isn't a member of the organization.
+	MemberNotFound,
+	/// The last owner can't leave the organization.
+	LastOwner,
+	/// The parachain id is reserved and never assigned to a parachain.
+	InvalidParaId,
+	/// The notification is due too far ahead of the phase.
+	InvalidPhaseOffset,
+	/// The user is already subscribed to the notification.
+	AlreadySubscribed,
+	/// The user isn't subscribed to the notification.
+	SubscriptionNotFound,
+	/// The unsubscribe link wasn't signed by the service.
+	InvalidUnsubscribeToken,
 }
 
 impl fmt::Display for Error {
@@ -41,6 +127,49 @@ impl From<String> for Error {
 			"NotifierNotUnique" => Error::NotifierNotUnique,
 			"UserNotFound" => Error::UserNotFound,
 			"FailedToSerialize" => Error::FailedToSerialize,
+			"Unauthorized" => Error::Unauthorized,
+			"JobNotFound" => Error::JobNotFound,
+			"InvalidLanguage" => Error::InvalidLanguage,
+			"InvalidTimezone" => Error::InvalidTimezone,
+			"InvalidQuietHours" => Error::InvalidQuietHours,
+			"InvalidTelegramHandle" => Error::InvalidTelegramHandle,
+			"InvalidDiscordWebhook" => Error::InvalidDiscordWebhook,
+			"InvalidSlackWebhook" => Error::InvalidSlackWebhook,
+			"InvalidMatrixRoom" => Error::InvalidMatrixRoom,
+			"InvalidWebhookUrl" => Error::InvalidWebhookUrl,
+			"WebhookNotConfigured" => Error::WebhookNotConfigured,
+			"InvalidEscalation" => Error::InvalidEscalation,
+			"AlertNotFound" => Error::AlertNotFound,
+			"ContactNotFound" => Error::ContactNotFound,
+			"InvalidVerificationToken" => Error::InvalidVerificationToken,
+			"VerificationTokenExpired" => Error::VerificationTokenExpired,
+			"InvalidAccount" => Error::InvalidAccount,
+			"InvalidSignature" => Error::InvalidSignature,
+			"Forbidden" => Error::Forbidden,
+			"AccountInUse" => Error::AccountInUse,
+			"InvalidTelegramLogin" => Error::InvalidTelegramLogin,
+			"TelegramLoginExpired" => Error::TelegramLoginExpired,
+			"InvalidEmail" => Error::InvalidEmail,
+			"InvalidLoginLink" => Error::InvalidLoginLink,
+			"TooManyRequests" => Error::TooManyRequests,
+			"InsufficientScope" => Error::InsufficientScope,
+			"InvalidApiKeyName" => Error::InvalidApiKeyName,
+			"InvalidApiKeyExpiry" => Error::InvalidApiKeyExpiry,
+			"ApiKeyNotFound" => Error::ApiKeyNotFound,
+			"OrganizationNotFound" => Error::OrganizationNotFound,
+			"InvalidOrganizationName" => Error::InvalidOrganizationName,
+			"InsufficientRole" => Error::InsufficientRole,
+			"InvalidOrganizationSubscription" => Error::InvalidOrganizationSubscription,
+			"ChannelNotConfigured" => Error::ChannelNotConfigured,
+			"InvalidInvite" => Error::InvalidInvite,
+			"AlreadyMember" => Error::AlreadyMember,
+			"MemberNotFound" => Error::MemberNotFound,
+			"LastOwner" => Error::LastOwner,
+			"InvalidParaId" => Error::InvalidParaId,
+			"InvalidPhaseOffset" => Error::InvalidPhaseOffset,
+			"AlreadySubscribed" => Error::AlreadySubscribed,
+			"SubscriptionNotFound" => Error::SubscriptionNotFound,
+			"InvalidUnsubscribeToken" => Error::InvalidUnsubscribeToken,
 			_ => panic!("UnknownError"),
 		}
 	}
@@ -0,0 +1,172 @@
+//! ## Data Export Route
+//!
+//! `GET /user/export` returns everything stored about the user of the session as JSON: the
+//! settings, channels and subscriptions of the user, the organizations and API keys of the user,
+//! every delivery to the user, including the ones which were already made, and the audit log of
+//! the user.
+//!
+//! Secrets, e.g. the hashes of API keys and the webhook secret, aren't part of the export.
+
+use crate::{
+	api_keys,
+	auth::{session_user, Authenticated, READ},
+	errors::{custom_error, Error},
+	organizations,
+	query::UserResponse,
+	LOG_TARGET,
+};
+use rocket::{
+	get,
+	http::Status,
+	response::status,
+	serde::{json::Json, Deserialize, Serialize},
+	State,
+};
+use storage::{
+	audit::AuditEntry,
+	auth::ApiKey,
+	channels, escalations,
+	organizations::Organization,
+	outbox::{JobStatus, OutboxJob},
+	subscriptions::Subscription,
+	telegram::TelegramChat,
+	users::User,
+	DbConn,
+};
+use types::{
+	api::{self, ErrorResponse},
+	EscalationStep, Notifications, Notifier, Timestamp,
+};
+
+/// Everything stored about a user.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserExport {
+	pub user: UserResponse,
+	/// The account the user signs in with.
+	pub account: Option<String>,
+	pub channels: Vec<ChannelExport>,
+	pub subscriptions: Vec<SubscriptionExport>,
+	/// The id of the Telegram chat linked to the user.
+	pub telegram_chat: Option<i64>,
+	pub organizations: Vec<api::Organization>,
+	pub api_keys: Vec<api::ApiKey>,
+	pub deliveries: Vec<DeliveryExport>,
+	/// Changes made on behalf of the user without a session, e.g. through unsubscribe links.
+	pub audit_log: Vec<AuditEntry>,
+}
+
+/// A channel of the user.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ChannelExport {
+	pub kind: Notifier,
+	pub address: String,
+	pub verified_at: Option<Timestamp>,
+}
+
+/// A subscription of the user, along with the channels and escalation steps of it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SubscriptionExport {
+	pub notification: Notifications,
+	pub channels: Vec<Notifier>,
+	pub escalation: Vec<EscalationStep>,
+}
+
+/// A delivery to the user.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DeliveryExport {
+	pub channel: Notifier,
+	/// The address the delivery went to, if not the user's own address of the channel.
+	pub address: Option<String>,
+	pub status: JobStatus,
+	pub attempts: u32,
+	/// When the delivery is attempted next, or when it was last attempted.
+	pub next_attempt_at: Timestamp,
+	pub last_error: Option<String>,
+	/// The event the user was notified about.
+	pub event: serde_json::Value,
+}
+
+#[get("/user/export")]
+pub async fn export_user(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<Json<UserExport>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(READ)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to export user: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let user = session_user(&conn, &auth)?;
+	let id = user.id;
+
+	let mut channels = vec![];
+	for kind in user.channels() {
+		let address = user.address(&kind).cloned().unwrap_or_default();
+		let verified_at = channels::verified_at(&conn, id, &kind).map_err(db_error)?;
+		channels.push(ChannelExport { kind, address, verified_at });
+	}
+
+	let mut subscriptions = vec![];
+	for subscription in Subscription::query_by_user(&conn, id).map_err(db_error)? {
+		let escalation =
+			escalations::query_steps(&conn, id, &subscription.notification).map_err(db_error)?;
+		subscriptions.push(SubscriptionExport {
+			notification: subscription.notification,
+			channels: subscription.channels,
+			escalation,
+		});
+	}
+
+	let deliveries = OutboxJob::query_by_user(&conn, id)
+		.map_err(db_error)?
+		.into_iter()
+		.map(|job| DeliveryExport {
+			channel: job.channel,
+			address: job.address,
+			status: job.status,
+			attempts: job.attempts,
+			next_attempt_at: job.next_attempt_at,
+			last_error: job.last_error,
+			event: serde_json::from_str(&job.payload).unwrap_or_default(),
+		})
+		.collect();
+
+	let export = UserExport {
+		account: User::query_account(&conn, id).map_err(db_error)?,
+		channels,
+		subscriptions,
+		telegram_chat: TelegramChat::query_by_user(&conn, id)
+			.map_err(db_error)?
+			.map(|chat| chat.chat_id),
+		organizations: Organization::query_by_member(&conn, id)
+			.map_err(db_error)?
+			.into_iter()
+			.map(|(organization, role)| organizations::to_response(organization, role))
+			.collect(),
+		api_keys: ApiKey::query_by_user(&conn, id)
+			.map_err(db_error)?
+			.into_iter()
+			.map(api_keys::to_response)
+			.collect(),
+		deliveries,
+		audit_log: AuditEntry::query_by_user(&conn, id).map_err(db_error)?,
+		user: UserResponse {
+			id: User::query_public_id(&conn, id).map_err(db_error)?.unwrap_or_default(),
+			user,
+		},
+	};
+
+	log::info!(target: LOG_TARGET, "Exported user {}", id);
+	Ok(Json(export))
+}
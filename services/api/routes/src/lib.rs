@@ -1,8 +1,23 @@
+pub mod admin;
+pub mod alerts;
+pub mod api_keys;
+pub mod auth;
+pub mod contacts;
+pub mod delete;
+pub mod export;
+pub mod login_links;
+pub mod organizations;
 pub mod query;
 pub mod register;
+pub mod subscriptions;
+pub mod telegram;
+pub mod unsubscribe;
 pub mod update;
+pub mod verification;
+pub mod webhooks;
 
 mod errors;
+mod pages;
 
 #[cfg(test)]
 mod tests;
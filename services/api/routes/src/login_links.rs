@@ -0,0 +1,187 @@
+//! ## Email Sign-In Routes
+//!
+//! Users without a Substrate account sign in through their email address:
+//! 1. `POST /auth/email` with the `email` address sends a link to it.
+//! 2. Opening the link, `GET /auth/email/<token>`, returns a page on which the user signs in, see
+//!    [`crate::pages`].
+//! 3. `POST /auth/email/<token>`, e.g. by the page, returns a session token.
+//!
+//! A link can only be used once and expires after [`LINK_LIFETIME`]. Only [`MAX_LINKS`] links
+//! are sent to an address within [`RATE_LIMIT_WINDOW`], so that the route can't be used to flood
+//! an inbox.
+//!
+//! Mail scanners open the link before the user does, so the link is only used up by the `POST`.
+//! Signing in verifies the address. The account is bound to the user with the address, or
+//! to a new user if there is none.
+
+use crate::{
+	auth::start_session,
+	errors::{custom_error, Error},
+	pages,
+	register::{normalize_email, validate_email},
+	verification::EmailVerification,
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use notification::message::Message;
+use rocket::{
+	get,
+	http::Status,
+	post,
+	response::{content::RawHtml, status},
+	serde::json::Json,
+	State,
+};
+use rusqlite::Connection;
+use storage::{
+	auth::LoginLink,
+	channels,
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	users::User,
+	DbConn,
+};
+use types::{
+	api::{self, ErrorResponse},
+	Notifier, Timestamp,
+};
+
+/// How long a sign-in link is valid, in seconds.
+pub const LINK_LIFETIME: u64 = 15 * 60;
+/// The window in which at most [`MAX_LINKS`] links are sent to an address, in seconds.
+pub const RATE_LIMIT_WINDOW: u64 = 60 * 60;
+/// How many links are sent to an address within [`RATE_LIMIT_WINDOW`].
+pub const MAX_LINKS: u32 = 3;
+
+#[post("/auth/email", data = "<request>")]
+pub async fn request_login_link(
+	conn: &State<DbConn>,
+	verification: &State<EmailVerification>,
+	request: Json<api::EmailLoginRequest>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let email = normalize_email(&request.email);
+	validate_email(&email).map_err(|err| custom_error(Status::BadRequest, err))?;
+
+	// The connection is released before the link is sent.
+	let token = {
+		let conn = conn.lock().map_err(|err| {
+			log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+		})?;
+		let db_error = |err| {
+			log::error!(target: LOG_TARGET, "Failed to create login link: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		};
+
+		let now = notification::timestamp();
+		let sent =
+			LoginLink::count_since(&conn, &email, now - RATE_LIMIT_WINDOW).map_err(db_error)?;
+		ensure!(sent < MAX_LINKS, custom_error(Status::TooManyRequests, Error::TooManyRequests));
+
+		let token = random_token(TOKEN_BYTES);
+		let link = LoginLink {
+			token_hash: hash_token(&token),
+			email: email.clone(),
+			created_at: now,
+			expires_at: now + LINK_LIFETIME,
+		};
+		LoginLink::create(&conn, &link).map_err(db_error)?;
+		token
+	};
+
+	let message = Message::plain(
+		"Sign in to coretime notifications",
+		"Open the link to sign in and manage your coretime notifications. The link expires in 15 \
+			minutes and can only be used once. If you didn't request it, you can ignore this email.",
+	);
+	let path = format!("/auth/email/{}", token);
+	verification.mail_link(&email, message, "Sign in", &path).await;
+
+	// Whether the address belongs to a user isn't revealed.
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+/// Returns the page on which the user signs in through the link.
+#[get("/auth/email/<_>")]
+pub async fn email_login_page() -> RawHtml<String> {
+	pages::confirmation(
+		"Sign in",
+		"Do you want to sign in to manage your coretime notifications?",
+		"Sign in",
+	)
+}
+
+#[post("/auth/email/<token>")]
+pub async fn email_login(
+	conn: &State<DbConn>,
+	token: &str,
+) -> Result<Json<api::SessionToken>, status::Custom<Json<ErrorResponse>>> {
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let now = notification::timestamp();
+	let link = LoginLink::take(&conn, &hash_token(token), now).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query login link: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	let Some(link) = link else {
+		return Err(custom_error(Status::Unauthorized, Error::InvalidLoginLink));
+	};
+
+	let account = email_account(&link.email);
+	link_user(&conn, &link.email, &account, now)?;
+
+	start_session(&conn, account, now).map(Json)
+}
+
+/// Returns the account an email address signs in as.
+pub fn email_account(email: &str) -> String {
+	format!("email:{}", email)
+}
+
+/// Loads or creates the user bound to the email account, and verifies the address of the user.
+///
+/// Users with the address who aren't bound to any account yet are bound to it. The address is
+/// taken over from users of other accounts who didn't verify it.
+fn link_user(
+	conn: &Connection,
+	email: &str,
+	account: &str,
+	now: Timestamp,
+) -> Result<u32, status::Custom<Json<ErrorResponse>>> {
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to link email account: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	if let Some(user) = User::query_by_account(conn, account).map_err(db_error)? {
+		// The user may have changed their address since.
+		channels::verify(conn, user.id, &Notifier::Email, email, now).map_err(db_error)?;
+		return Ok(user.id);
+	}
+
+	if let Some(other) = User::query_by_email(conn, email.to_string()).map_err(db_error)? {
+		if User::query_account(conn, other.id).map_err(db_error)?.is_none() {
+			User::bind_account(conn, other.id, account).map_err(db_error)?;
+			channels::verify(conn, other.id, &Notifier::Email, email, now).map_err(db_error)?;
+			return Ok(other.id);
+		}
+
+		let verified = channels::is_verified(conn, other.id, &Notifier::Email).map_err(db_error)?;
+		ensure!(!verified, custom_error(Status::Conflict, Error::NotifierNotUnique));
+		log::info!(target: LOG_TARGET, "Removing unverified email of user {}", other.id);
+		channels::set(conn, other.id, &Notifier::Email, None).map_err(db_error)?;
+	}
+
+	let user = User {
+		email: Some(email.to_string()),
+		..User::new(User::next_id(conn).map_err(db_error)?)
+	};
+	User::create_user(conn, &user).map_err(db_error)?;
+	User::bind_account(conn, user.id, account).map_err(db_error)?;
+	channels::verify(conn, user.id, &Notifier::Email, email, now).map_err(db_error)?;
+	log::info!(target: LOG_TARGET, "Registered user {} for {}", user.id, account);
+
+	Ok(user.id)
+}
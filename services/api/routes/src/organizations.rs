@@ -0,0 +1,372 @@
+//! ## Organization Routes
+//!
+//! A parachain is usually operated by a team. Teams create an organization, which subscribes to
+//! the state of their parachains on behalf of all of its members:
+//! - `POST /organizations` creates an organization, of which the user becomes the owner.
+//! - `GET /organizations` lists the organizations of the user, `GET /organizations/<id>` returns
+//!   the members and subscriptions of one of them.
+//! - `PUT /organizations/<id>/subscriptions` replaces the subscriptions of the organization.
+//! - `PUT /organizations/<id>/channels` sets the channels through which the user receives them.
+//! - `POST /organizations/<id>/invites` creates an invite, which another user accepts at `POST
+//!   /organizations/invites/<token>/accept`.
+//! - `DELETE /organizations/<id>/members/<user id>` removes a member, or lets a user leave.
+//!
+//! Owners and admins manage the subscriptions and members of the organization, only owners can
+//! make others owners or remove them. An organization always keeps at least one owner.
+
+use crate::{
+	auth::{session_user, Authenticated, READ, SUBSCRIPTIONS, WRITE},
+	errors::{custom_error, Error},
+	subscriptions::validate_notification,
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use rocket::{delete, get, http::Status, post, put, response::status, serde::json::Json, State};
+use rusqlite::Connection;
+use storage::{
+	organizations::{Invite, Member, Organization, OrganizationSubscription},
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	users::User,
+	DbConn,
+};
+use types::{
+	api::{self, ErrorResponse, OrganizationRole},
+	Notifications, Notifier,
+};
+
+/// The maximum length of the name of an organization.
+pub const MAX_NAME_LENGTH: usize = 64;
+/// How long an invite can be accepted, in seconds.
+pub const INVITE_LIFETIME: u64 = 7 * 24 * 60 * 60;
+
+#[post("/organizations", data = "<request>")]
+pub async fn create_organization(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	request: Json<api::OrganizationRequest>,
+) -> Result<Json<api::Organization>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	let name = request.name.trim();
+	ensure!(
+		!name.is_empty() && name.chars().count() <= MAX_NAME_LENGTH,
+		custom_error(Status::BadRequest, Error::InvalidOrganizationName)
+	);
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = session_user(&conn, &auth)?;
+	let public_id = random_token(16);
+	let now = notification::timestamp();
+	let organization =
+		Organization::create(&conn, &public_id, name, user.id, now).map_err(|err| {
+			log::error!(target: LOG_TARGET, "Failed to create organization: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		})?;
+
+	log::info!(target: LOG_TARGET, "User {} created organization {}", user.id, organization.id);
+	Ok(Json(to_response(organization, OrganizationRole::Owner)))
+}
+
+#[get("/organizations")]
+pub async fn organizations(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<Json<Vec<api::Organization>>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(READ)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = session_user(&conn, &auth)?;
+	let organizations = Organization::query_by_member(&conn, user.id).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query organizations: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(Json(
+		organizations
+			.into_iter()
+			.map(|(organization, role)| to_response(organization, role))
+			.collect(),
+	))
+}
+
+#[get("/organizations/<id>")]
+pub async fn organization(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	id: &str,
+) -> Result<Json<api::OrganizationDetails>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(READ)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let (_, organization, member) = membership(&conn, &auth, id)?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to query organization: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let mut members = vec![];
+	for member in Member::query_by_organization(&conn, organization.id).map_err(db_error)? {
+		let id = User::query_public_id(&conn, member.user_id).map_err(db_error)?;
+		members.push(api::OrganizationMember { id: id.unwrap_or_default(), role: member.role });
+	}
+	let subscriptions = OrganizationSubscription::query_by_organization(&conn, organization.id)
+		.map_err(db_error)?;
+
+	Ok(Json(api::OrganizationDetails {
+		organization: to_response(organization, member.role),
+		members,
+		subscriptions,
+	}))
+}
+
+#[put("/organizations/<id>/subscriptions", data = "<notifications>")]
+pub async fn set_organization_subscriptions(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	id: &str,
+	notifications: Json<Vec<Notifications>>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SUBSCRIPTIONS)?;
+
+	ensure!(
+		notifications
+			.iter()
+			.all(|notification| matches!(notification, Notifications::ParachainState(_))),
+		custom_error(Status::BadRequest, Error::InvalidOrganizationSubscription)
+	);
+	for notification in notifications.iter() {
+		validate_notification(notification).map_err(|err| custom_error(Status::BadRequest, err))?;
+	}
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let (_, organization, member) = membership(&conn, &auth, id)?;
+	ensure!(
+		member.role != OrganizationRole::Member,
+		custom_error(Status::Forbidden, Error::InsufficientRole)
+	);
+
+	OrganizationSubscription::set(&conn, organization.id, &notifications).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to set organization subscriptions: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+#[put("/organizations/<id>/channels", data = "<channels>")]
+pub async fn set_organization_channels(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	id: &str,
+	channels: Json<Vec<Notifier>>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let (user, organization, _) = membership(&conn, &auth, id)?;
+	ensure!(
+		channels.iter().all(|channel| user.address(channel).is_some()),
+		custom_error(Status::BadRequest, Error::ChannelNotConfigured)
+	);
+
+	Member::set_channels(&conn, organization.id, user.id, &channels).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to set organization channels: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+#[post("/organizations/<id>/invites", data = "<request>")]
+pub async fn invite_member(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	id: &str,
+	request: Json<api::InviteRequest>,
+) -> Result<Json<api::Invite>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let (_, organization, member) = membership(&conn, &auth, id)?;
+	ensure!(
+		can_manage(member.role, request.role),
+		custom_error(Status::Forbidden, Error::InsufficientRole)
+	);
+
+	let token = random_token(TOKEN_BYTES);
+	let invite = Invite {
+		token_hash: hash_token(&token),
+		organization_id: organization.id,
+		role: request.role,
+		expires_at: notification::timestamp() + INVITE_LIFETIME,
+	};
+	Invite::create(&conn, &invite).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to create invite: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(Json(api::Invite { token, expires_at: invite.expires_at }))
+}
+
+#[post("/organizations/invites/<token>/accept")]
+pub async fn accept_invite(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	token: &str,
+) -> Result<Json<api::Organization>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to accept invite: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let user = session_user(&conn, &auth)?;
+	let now = notification::timestamp();
+	// The invite stays valid if it can't be accepted, since dropping the transaction rolls back.
+	let tx = conn.unchecked_transaction().map_err(db_error)?;
+	let Some(invite) = Invite::take(&tx, &hash_token(token), now).map_err(db_error)? else {
+		return Err(custom_error(Status::NotFound, Error::InvalidInvite));
+	};
+	ensure!(
+		Member::query(&tx, invite.organization_id, user.id).map_err(db_error)?.is_none(),
+		custom_error(Status::Conflict, Error::AlreadyMember)
+	);
+
+	Member::add(&tx, invite.organization_id, user.id, invite.role, now)
+		.and_then(|_| tx.commit())
+		.map_err(db_error)?;
+	let organizations = Organization::query_by_member(&conn, user.id).map_err(db_error)?;
+	let Some((organization, role)) = organizations
+		.into_iter()
+		.find(|(organization, _)| organization.id == invite.organization_id)
+	else {
+		return Err(custom_error(Status::NotFound, Error::OrganizationNotFound));
+	};
+
+	log::info!(target: LOG_TARGET, "User {} joined organization {}", user.id, organization.id);
+	Ok(Json(to_response(organization, role)))
+}
+
+#[delete("/organizations/<id>/members/<member_id>")]
+pub async fn remove_member(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	id: &str,
+	member_id: &str,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to remove member: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let (user, organization, member) = membership(&conn, &auth, id)?;
+	let not_found = || custom_error(Status::NotFound, Error::MemberNotFound);
+	let removed = User::query_by_public_id(&conn, member_id)
+		.map_err(db_error)?
+		.ok_or_else(not_found)?;
+	let removed = Member::query(&conn, organization.id, removed.id)
+		.map_err(db_error)?
+		.ok_or_else(not_found)?;
+
+	// Anyone can leave, only those who could have invited a member can remove them.
+	ensure!(
+		removed.user_id == user.id || can_manage(member.role, removed.role),
+		custom_error(Status::Forbidden, Error::InsufficientRole)
+	);
+	if removed.role == OrganizationRole::Owner {
+		let owners = Member::count_owners(&conn, organization.id).map_err(db_error)?;
+		ensure!(owners > 1, custom_error(Status::Conflict, Error::LastOwner));
+	}
+
+	Member::remove(&conn, organization.id, removed.user_id).map_err(db_error)?;
+	log::info!(
+		target: LOG_TARGET,
+		"Removed user {} from organization {}",
+		removed.user_id,
+		organization.id
+	);
+
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+/// Returns the user of the session along with the organization and their membership in it.
+///
+/// Organizations of which the user isn't a member aren't found, so that their ids aren't
+/// revealed.
+fn membership(
+	conn: &Connection,
+	auth: &Authenticated,
+	public_id: &str,
+) -> Result<(User, Organization, Member), status::Custom<Json<ErrorResponse>>> {
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to query organization: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+	let not_found = || custom_error(Status::NotFound, Error::OrganizationNotFound);
+
+	let user = session_user(conn, auth)?;
+	let organization = Organization::query_by_public_id(conn, public_id)
+		.map_err(db_error)?
+		.ok_or_else(not_found)?;
+	let member = Member::query(conn, organization.id, user.id)
+		.map_err(db_error)?
+		.ok_or_else(not_found)?;
+
+	Ok((user, organization, member))
+}
+
+/// Whether a member with the role can invite or remove members with the other role.
+fn can_manage(role: OrganizationRole, other: OrganizationRole) -> bool {
+	match role {
+		OrganizationRole::Owner => true,
+		OrganizationRole::Admin => other != OrganizationRole::Owner,
+		OrganizationRole::Member => false,
+	}
+}
+
+pub(crate) fn to_response(organization: Organization, role: OrganizationRole) -> api::Organization {
+	api::Organization { id: organization.public_id, name: organization.name, role }
+}
@@ -0,0 +1,24 @@
+//! Pages on which links from notifications are confirmed.
+//!
+//! Mail scanners and link previews open every link in a message, so opening a link must not
+//! change anything. The link returns a page instead, whose button posts to the same url.
+
+use rocket::response::content::RawHtml;
+
+/// Returns a page asking the question, with a button which posts to the url of the page.
+///
+/// The form has no action, so that the token in the url doesn't have to be embedded.
+pub(crate) fn confirmation(title: &str, question: &str, button: &str) -> RawHtml<String> {
+	RawHtml(format!(
+		"<!DOCTYPE html>
+<html>
+	<head><meta charset=\"utf-8\"><title>{}</title></head>
+	<body>
+		<p>{}</p>
+		<form method=\"post\"><button type=\"submit\">{}</button></form>
+	</body>
+</html>
+",
+		title, question, button
+	))
+}
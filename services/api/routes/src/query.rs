@@ -1,33 +1,44 @@
 use crate::{
+	auth::{session_user, Authenticated, READ},
 	errors::{custom_error, Error},
 	LOG_TARGET,
 };
 use rocket::{get, http::Status, response::status, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
 use storage::{users::User, DbConn};
 use types::api::ErrorResponse;
 
-#[get("/user/<user_id>")]
+/// The user as returned to clients, identified by its public id.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserResponse {
+	pub id: String,
+	#[serde(flatten)]
+	pub user: User,
+}
+
+#[get("/user")]
 pub async fn user(
 	conn: &State<DbConn>,
-	user_id: u32,
+	auth: Result<Authenticated, Error>,
 ) -> Result<status::Custom<String>, status::Custom<Json<ErrorResponse>>> {
-	log::info!(target: LOG_TARGET, "Querying user: {}", user_id);
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(READ)?;
+	log::info!(target: LOG_TARGET, "Querying user of: {}", auth.account);
 
 	let conn = conn.lock().map_err(|err| {
 		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
 		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
 	})?;
 
-	let maybe_user = User::query_by_id(&conn, user_id).map_err(|err| {
-		log::error!(target: LOG_TARGET, "Failed to search user by id: {:?}", err);
+	let user = session_user(&conn, &auth)?;
+	let public_id = User::query_public_id(&conn, user.id).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query public id: {:?}", err);
 		custom_error(Status::InternalServerError, Error::DbError)
 	})?;
 
-	let Some(user) = maybe_user else {
-		return Err(custom_error(Status::BadRequest, Error::UserNotFound));
-	};
-
-	let serialized = serde_json::to_string(&user).map_err(|err| {
+	let response = UserResponse { id: public_id.unwrap_or_default(), user };
+	let serialized = serde_json::to_string(&response).map_err(|err| {
 		log::error!(target: LOG_TARGET, "Failed to serialize: {:?}", err);
 		custom_error(Status::InternalServerError, Error::FailedToSerialize)
 	})?;
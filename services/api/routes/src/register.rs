@@ -1,92 +1,474 @@
 use crate::{
+	auth::{Authenticated, WRITE},
+	contacts::ContactConfirmation,
 	errors::{custom_error, Error},
+	subscriptions::validate_notification,
+	verification::EmailVerification,
+	webhooks::ensure_webhook_secret,
 	LOG_TARGET,
 };
+use chrono_tz::Tz;
 use common_macros::ensure;
-use rocket::{http::Status, post, response::status, serde::json::Json, State};
+use notification::{bot::canonical_handle, channels::webhook};
+use rocket::{
+	http::{uri::Absolute, Status},
+	post,
+	response::status,
+	serde::json::Json,
+	State,
+};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use storage::DbConn;
-use types::{api::ErrorResponse, Notifications, Notifier};
+use types::{
+	api::{self, ErrorResponse},
+	DeliveryMode, EscalationStep, Notifications, Notifier, QuietHours,
+};
+
+use storage::{
+	escalations,
+	subscriptions::Subscription,
+	users::{User, DEFAULT_LANGUAGE, DEFAULT_TIMEZONE},
+};
 
-use storage::users::User;
+/// The maximum number of escalation steps of a subscription.
+pub const MAX_ESCALATION_STEPS: usize = 5;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct RegistrationData {
-	// TODO, for now we are using a u32 for identification, however, this will likely change once
-	// we do some form of user authentication.
-	pub id: u32,
-	/// Defines how the user wants to receive their notifications.
-	pub notifier: Notifier,
-	// The user's email, will be used if notifier == `Notifier::Telegram`
+	// The user's email, enables the `Notifier::Email` channel.
 	pub email: Option<String>,
-	// The user's telegram handle, used if tg_handle == `Notifier::Email`
+	// The user's telegram handle, enables the `Notifier::Telegram` channel.
 	#[serde(rename = "tgHandle")]
 	pub tg_handle: Option<String>,
+	/// The url of the user's Discord webhook, enables the `Notifier::Discord` channel.
+	#[serde(default, rename = "discordWebhook")]
+	pub discord_webhook: Option<String>,
+	/// The url of the user's Slack incoming webhook, enables the `Notifier::Slack` channel.
+	#[serde(default, rename = "slackWebhook")]
+	pub slack_webhook: Option<String>,
+	/// The id of the Matrix room the user is notified in, enables the `Notifier::Matrix` channel.
+	#[serde(default, rename = "matrixRoom")]
+	pub matrix_room: Option<String>,
+	/// The https endpoint the events are posted to, enables the `Notifier::Webhook` channel.
+	#[serde(default, rename = "webhookUrl")]
+	pub webhook_url: Option<String>,
 	// Notifications the user enabled.
 	#[serde(rename = "enabledNotifications")]
-	pub enabled_notifications: Vec<Notifications>,
+	pub enabled_notifications: Vec<EnabledNotification>,
+	/// The language of the notifications, e.g. `en` or `pt-BR`. Defaults to English.
+	#[serde(default)]
+	pub language: Option<String>,
+	/// The IANA time zone of the user, e.g. `Europe/Berlin`. Defaults to UTC.
+	#[serde(default)]
+	pub timezone: Option<String>,
+	/// The hours during which the user doesn't want to receive non-critical notifications.
+	#[serde(default, rename = "quietHours")]
+	pub quiet_hours: Option<QuietHours>,
+	/// Whether notifications are sent right away or collected into digests. Defaults to
+	/// immediate delivery.
+	#[serde(default, rename = "deliveryMode")]
+	pub delivery_mode: Option<DeliveryMode>,
+}
+
+/// A notification the user enabled, along with the channels it is delivered through.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum EnabledNotification {
+	/// Delivered through all channels of the user.
+	All(Notifications),
+	/// Delivered through the listed channels only.
+	///
+	/// Critical alerts of the notification are escalated through the steps until they are
+	/// acknowledged.
+	Routed {
+		notification: Notifications,
+		channels: Vec<Notifier>,
+		#[serde(default)]
+		escalation: Vec<EscalationStep>,
+	},
+}
+
+impl EnabledNotification {
+	pub fn notification(&self) -> &Notifications {
+		match self {
+			EnabledNotification::All(notification) => notification,
+			EnabledNotification::Routed { notification, .. } => notification,
+		}
+	}
+
+	/// Returns the channels of the user the notification is delivered through.
+	pub fn channels(&self, user: &User) -> Vec<Notifier> {
+		match self {
+			EnabledNotification::All(_) => user.channels(),
+			EnabledNotification::Routed { channels, .. } => channels.clone(),
+		}
+	}
+
+	pub fn escalation(&self) -> &[EscalationStep] {
+		match self {
+			EnabledNotification::All(_) => &[],
+			EnabledNotification::Routed { escalation, .. } => escalation,
+		}
+	}
+}
+
+/// Ensures every enabled notification is delivered through at least one channel, and only
+/// through channels the user configured.
+pub(crate) fn validate_routing(
+	user: &User,
+	notifications: &[EnabledNotification],
+) -> Result<(), Error> {
+	for notification in notifications {
+		let channels = notification.channels(user);
+		ensure!(!channels.is_empty(), Error::NotifierEmpty);
+		ensure!(
+			channels.iter().all(|channel| user.address(channel).is_some()),
+			Error::NotifierEmpty
+		);
+		validate_escalation(user, notification.notification(), notification.escalation())?;
+	}
+	Ok(())
+}
+
+/// Ensures the escalation steps are ordered by time and escalate to reachable channels.
+///
+/// Only the parachain state raises critical alerts, so other notifications can't be escalated.
+fn validate_escalation(
+	user: &User,
+	notification: &Notifications,
+	steps: &[EscalationStep],
+) -> Result<(), Error> {
+	if steps.is_empty() {
+		return Ok(());
+	}
+	ensure!(matches!(notification, Notifications::ParachainState(_)), Error::InvalidEscalation);
+	ensure!(steps.len() <= MAX_ESCALATION_STEPS, Error::InvalidEscalation);
+
+	let mut after_minutes = 0;
+	for step in steps {
+		ensure!(step.after_minutes > after_minutes, Error::InvalidEscalation);
+		after_minutes = step.after_minutes;
+
+		match &step.contact {
+			Some(contact) => validate_contact(&step.channel, contact)?,
+			None => ensure!(user.address(&step.channel).is_some(), Error::InvalidEscalation),
+		}
+	}
+	Ok(())
+}
+
+/// Ensures the contact is a valid address of the channel.
+///
+/// Alerts can't be escalated to the webhooks of contacts, since they don't know the secret the
+/// deliveries are signed with.
+fn validate_contact(channel: &Notifier, contact: &str) -> Result<(), Error> {
+	match channel {
+		Notifier::Email => validate_email(contact).map_err(|_| Error::InvalidEscalation)?,
+		Notifier::Telegram => validate_tg_handle(contact).map_err(|_| Error::InvalidEscalation)?,
+		Notifier::Discord => validate_discord_webhook(contact)?,
+		Notifier::Slack => validate_slack_webhook(contact)?,
+		Notifier::Matrix => validate_matrix_room(contact)?,
+		Notifier::Webhook | Notifier::Null => return Err(Error::InvalidEscalation),
+	}
+	Ok(())
 }
 
 impl RegistrationData {
+	fn user(&self, id: u32) -> User {
+		User {
+			id,
+			email: self.email.clone(),
+			tg_handle: self.tg_handle.clone(),
+			discord_webhook: self.discord_webhook.clone(),
+			slack_webhook: self.slack_webhook.clone(),
+			matrix_room: self.matrix_room.clone(),
+			webhook_url: self.webhook_url.clone(),
+			language: self.language.clone().unwrap_or(DEFAULT_LANGUAGE.to_string()),
+			timezone: self.timezone.clone().unwrap_or(DEFAULT_TIMEZONE.to_string()),
+			quiet_hours: self.quiet_hours,
+			delivery_mode: self.delivery_mode.unwrap_or_default(),
+		}
+	}
+
+	/// Brings the addresses into the form they are stored in.
+	fn normalize(&mut self) {
+		self.email = self.email.as_deref().map(normalize_email);
+		self.tg_handle = self.tg_handle.as_deref().map(normalize_tg_handle);
+	}
+
 	fn validate(&self) -> Result<(), Error> {
-		// Ensure the configured notifier is set.
-		match self.notifier {
-			Notifier::Email if self.email.is_none() => Err(Error::NotifierEmpty),
-			Notifier::Telegram if self.tg_handle.is_none() => Err(Error::NotifierEmpty),
-			_ => Ok(()),
+		if let Some(email) = &self.email {
+			validate_email(email)?;
+		}
+		if let Some(handle) = &self.tg_handle {
+			validate_tg_handle(handle)?;
+		}
+		if let Some(language) = &self.language {
+			validate_language(language)?;
+		}
+		if let Some(timezone) = &self.timezone {
+			validate_timezone(timezone)?;
+		}
+		if let Some(quiet_hours) = &self.quiet_hours {
+			ensure!(quiet_hours.is_valid(), Error::InvalidQuietHours);
 		}
+		if let Some(webhook) = &self.discord_webhook {
+			validate_discord_webhook(webhook)?;
+		}
+		if let Some(webhook) = &self.slack_webhook {
+			validate_slack_webhook(webhook)?;
+		}
+		if let Some(room) = &self.matrix_room {
+			validate_matrix_room(room)?;
+		}
+		if let Some(url) = &self.webhook_url {
+			validate_webhook_url(url)?;
+		}
+
+		for notification in &self.enabled_notifications {
+			validate_notification(notification.notification())?;
+		}
+
+		// Ensure the enabled notifications can be delivered, which only depends on the addresses
+		// of the user.
+		validate_routing(&self.user(0), &self.enabled_notifications)
 	}
 }
 
+/// Ensures the language is a language tag such as `en` or `pt-BR`.
+///
+/// Languages without translations are accepted, notifications fall back to English for them.
+pub(crate) fn validate_language(language: &str) -> Result<(), Error> {
+	let mut parts = language.split('-');
+	let primary = parts.next().unwrap_or_default();
+	let region = parts.next();
+
+	let valid = (2..=3).contains(&primary.len()) &&
+		primary.chars().all(|c| c.is_ascii_lowercase()) &&
+		region.map_or(true, |region| {
+			(2..=4).contains(&region.len()) && region.chars().all(|c| c.is_ascii_alphanumeric())
+		}) && parts.next().is_none();
+
+	ensure!(valid, Error::InvalidLanguage);
+	Ok(())
+}
+
+/// Ensures the time zone is known, e.g. `Europe/Berlin`.
+pub(crate) fn validate_timezone(timezone: &str) -> Result<(), Error> {
+	ensure!(timezone.parse::<Tz>().is_ok(), Error::InvalidTimezone);
+	Ok(())
+}
+
+/// Returns the form email addresses are stored in, so that an address is found however its
+/// owner types it.
+pub(crate) fn normalize_email(email: &str) -> String {
+	email.trim().to_lowercase()
+}
+
+/// Ensures the address has a local part and a domain, e.g. `alice@mail.com`.
+///
+/// Whether the address exists is only known once it is verified, see [`crate::verification`].
+pub(crate) fn validate_email(email: &str) -> Result<(), Error> {
+	let valid = match email.split_once('@') {
+		Some((local, domain)) =>
+			!local.is_empty() &&
+				!domain.is_empty() &&
+				!domain.contains('@') &&
+				!email.chars().any(|c| c.is_whitespace() || c.is_control()),
+		None => false,
+	};
+
+	ensure!(valid, Error::InvalidEmail);
+	Ok(())
+}
+
+/// Returns the form Telegram handles are stored in, so that a handle can't be registered twice
+/// with and without the leading `@`.
+pub(crate) fn normalize_tg_handle(handle: &str) -> String {
+	canonical_handle(handle.trim())
+}
+
+/// Ensures the handle is a Telegram username, with or without the leading `@`.
+///
+/// Accounts without a username are identified by their numeric id, see [`crate::telegram`].
+pub(crate) fn validate_tg_handle(handle: &str) -> Result<(), Error> {
+	let username = handle.strip_prefix('@').unwrap_or(handle);
+	let valid = (5..=32).contains(&username.len()) &&
+		username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+	ensure!(valid, Error::InvalidTelegramHandle);
+	Ok(())
+}
+
+/// Ensures the url is a Discord webhook url, i.e. `https://discord.com/api/webhooks/<id>/<token>`.
+pub(crate) fn validate_discord_webhook(webhook: &str) -> Result<(), Error> {
+	let path = ["https://discord.com/api/webhooks/", "https://discordapp.com/api/webhooks/"]
+		.into_iter()
+		.find_map(|prefix| webhook.strip_prefix(prefix))
+		.ok_or(Error::InvalidDiscordWebhook)?;
+
+	let valid = match path.split_once('/') {
+		Some((id, token)) =>
+			!id.is_empty() &&
+				id.chars().all(|c| c.is_ascii_digit()) &&
+				!token.is_empty() &&
+				token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+		None => false,
+	};
+
+	ensure!(valid, Error::InvalidDiscordWebhook);
+	Ok(())
+}
+
+/// Ensures the url is a Slack incoming webhook url, i.e.
+/// `https://hooks.slack.com/services/<team>/<bot>/<token>`.
+pub(crate) fn validate_slack_webhook(webhook: &str) -> Result<(), Error> {
+	let path = webhook
+		.strip_prefix("https://hooks.slack.com/services/")
+		.ok_or(Error::InvalidSlackWebhook)?;
+
+	let parts = path.split('/').collect::<Vec<_>>();
+	let valid = parts.len() == 3 &&
+		parts
+			.iter()
+			.all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+
+	ensure!(valid, Error::InvalidSlackWebhook);
+	Ok(())
+}
+
+/// Ensures the id is a Matrix room id, i.e. `!<opaque id>:<server name>`.
+pub(crate) fn validate_matrix_room(room: &str) -> Result<(), Error> {
+	let valid = match room.strip_prefix('!').and_then(|room| room.split_once(':')) {
+		Some((id, server)) =>
+			!id.is_empty() &&
+				!server.is_empty() &&
+				!room.chars().any(|c| c.is_whitespace() || c.is_control() || c == '/'),
+		None => false,
+	};
+
+	ensure!(valid, Error::InvalidMatrixRoom);
+	Ok(())
+}
+
+/// Ensures the url is an absolute https url of a public host.
+///
+/// Hosts which obviously aren't public, like `localhost` or private addresses, are rejected right
+/// away. Names are resolved when delivering, see [`notification::channels::webhook`].
+pub(crate) fn validate_webhook_url(url: &str) -> Result<(), Error> {
+	let is_public = |host: &str| {
+		let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+		match host.parse::<IpAddr>() {
+			Ok(ip) => webhook::is_public(ip),
+			Err(_) => !host.is_empty() && host != "localhost" && !host.ends_with(".localhost"),
+		}
+	};
+	let valid = match Absolute::parse(url) {
+		Ok(uri) =>
+			uri.scheme() == "https" &&
+				uri.authority().map_or(false, |authority| is_public(authority.host())),
+		Err(_) => false,
+	};
+
+	ensure!(valid, Error::InvalidWebhookUrl);
+	Ok(())
+}
+
 #[post("/register_user", data = "<registration_data>")]
 pub async fn register_user(
 	conn: &State<DbConn>,
-	registration_data: Json<RegistrationData>,
-) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	verification: &State<EmailVerification>,
+	contacts: &State<ContactConfirmation>,
+	auth: Result<Authenticated, Error>,
+	mut registration_data: Json<RegistrationData>,
+) -> Result<Json<api::UserId>, status::Custom<Json<ErrorResponse>>> {
 	log::info!(target: LOG_TARGET, "Registration request: {:?}", registration_data);
 
-	// Get connection:
-	let conn = conn.lock().map_err(|err| {
-		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
-		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
-	})?;
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	// The connection is released before the confirmation links are sent.
+	let (user, public_id, requests) = {
+		// Get connection:
+		let conn = conn.lock().map_err(|err| {
+			log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+		})?;
 
-	// Validate registration data:
-	registration_data
-		.validate()
-		.map_err(|error| custom_error(Status::BadRequest, error))?;
+		// Validate registration data:
+		registration_data.normalize();
+		registration_data
+			.validate()
+			.map_err(|error| custom_error(Status::BadRequest, error))?;
 
-	ensure_unique_data(&conn, &registration_data)?;
+		ensure_unique_data(&conn, &registration_data)?;
+
+		// Every account manages a single user.
+		let maybe_user = User::query_by_account(&conn, &auth.account).map_err(|err| {
+			log::error!(target: LOG_TARGET, "Failed to search user by account: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		})?;
+		ensure!(maybe_user.is_none(), custom_error(Status::Conflict, Error::AccountInUse));
 
-	let user = User {
-		id: registration_data.id,
-		email: registration_data.email.clone(),
-		tg_handle: registration_data.tg_handle.clone(),
-		notifier: registration_data.notifier.clone(),
+		let db_error = |err| {
+			log::error!(target: LOG_TARGET, "Failed to create user: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		};
+
+		// Register user, the ids are assigned by the service. Either the user is registered along
+		// with all of its subscriptions, or not at all.
+		let tx = conn.unchecked_transaction().map_err(db_error)?;
+		let user = registration_data.user(User::next_id(&tx).map_err(db_error)?);
+		User::create_user(&tx, &user).map_err(db_error)?;
+		let public_id = User::query_public_id(&tx, user.id).map_err(db_error)?;
+		User::bind_account(&tx, user.id, &auth.account).map_err(|err| {
+			log::error!(target: LOG_TARGET, "Failed to bind account: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		})?;
+
+		if user.webhook_url.is_some() {
+			ensure_webhook_secret(&tx, user.id)?;
+		}
+
+		let mut requests = vec![];
+		for notification in registration_data.enabled_notifications.iter() {
+			let channels = notification.channels(&user);
+			Subscription::create(&tx, user.id, notification.notification(), &channels)
+				.and_then(|_| {
+					escalations::set_steps(
+						&tx,
+						user.id,
+						notification.notification(),
+						notification.escalation(),
+					)
+				})
+				.and_then(|_| ContactConfirmation::request(&tx, user.id, notification.escalation()))
+				.map(|new_requests| requests.extend(new_requests))
+				.map_err(|err| {
+					log::error!(target: LOG_TARGET, "Failed to create subscription: {:?}", err);
+					custom_error(Status::InternalServerError, Error::DbError)
+				})?;
+		}
+		tx.commit().map_err(db_error)?;
+
+		(user, public_id.unwrap_or_default(), requests)
 	};
-	// Register user
-	User::create_user(&conn, &user).map_err(|err| {
-		log::error!(target: LOG_TARGET, "Failed to create user: {:?}", err);
-		custom_error(Status::InternalServerError, Error::DbError)
-	})?;
 
-	Ok(status::Custom(Status::Ok, ()))
+	if let Some(email) = &user.email {
+		verification.send_link(user.id, email).await;
+	}
+	contacts.send_links(requests).await;
+
+	Ok(Json(api::UserId { id: public_id }))
 }
 
 fn ensure_unique_data(
 	conn: &Connection,
 	registration_data: &Json<RegistrationData>,
 ) -> Result<(), status::Custom<Json<ErrorResponse>>> {
-	let maybe_user = User::query_by_id(&conn, registration_data.id).map_err(|err| {
-		log::error!(target: LOG_TARGET, "Failed to search user by id: {:?}", err);
-		custom_error(Status::InternalServerError, Error::DbError)
-	})?;
-
-	// Ensure that the id is unique.
-	ensure!(maybe_user.is_none(), custom_error(Status::Conflict, Error::UserExists));
-
 	let error = custom_error(Status::Conflict, Error::NotifierNotUnique);
 
 	if let Some(email) = registration_data.email.clone() {
@@ -0,0 +1,163 @@
+//! ## Subscription Routes
+//!
+//! Users subscribe to notifications when registering, and manage the subscriptions one by one
+//! afterwards:
+//! - `GET /subscriptions` lists the subscriptions of the user, along with their channels and
+//!   escalation steps.
+//! - `POST /subscriptions` subscribes the user to a notification. The body has the format of the
+//!   enabled notifications of the registration, see [`EnabledNotification`].
+//! - `DELETE /subscriptions` unsubscribes the user from the notification in the body.
+//!
+//! Subscribing to a notification twice is rejected, unsubscribe first to change the channels of
+//! a subscription.
+
+use crate::{
+	auth::{session_user, Authenticated, READ, SUBSCRIPTIONS},
+	contacts::ContactConfirmation,
+	errors::{custom_error, Error},
+	register::{validate_routing, EnabledNotification},
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use rocket::{delete, get, http::Status, post, response::status, serde::json::Json, State};
+use storage::{escalations, subscriptions::Subscription, DbConn};
+use types::{api::ErrorResponse, Notifications, PhaseNotification, MIN_PARA_ID};
+
+/// Sales repeat every 28 days, so a notification can't be due further ahead of a phase.
+pub const MAX_PHASE_OFFSET: u64 = 28 * 24 * 60 * 60;
+
+#[get("/subscriptions")]
+pub async fn subscriptions(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<Json<Vec<EnabledNotification>>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(READ)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to query subscriptions: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let user = session_user(&conn, &auth)?;
+	let mut enabled = vec![];
+	for subscription in Subscription::query_by_user(&conn, user.id).map_err(db_error)? {
+		let escalation = escalations::query_steps(&conn, user.id, &subscription.notification)
+			.map_err(db_error)?;
+		enabled.push(EnabledNotification::Routed {
+			notification: subscription.notification,
+			channels: subscription.channels,
+			escalation,
+		});
+	}
+
+	Ok(Json(enabled))
+}
+
+#[post("/subscriptions", data = "<notification>")]
+pub async fn subscribe(
+	conn: &State<DbConn>,
+	contacts: &State<ContactConfirmation>,
+	auth: Result<Authenticated, Error>,
+	notification: Json<EnabledNotification>,
+) -> Result<Json<EnabledNotification>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SUBSCRIPTIONS)?;
+
+	validate_notification(notification.notification())
+		.map_err(|err| custom_error(Status::BadRequest, err))?;
+
+	// The connection is released before the confirmation links are sent.
+	let (channels, requests) = {
+		let conn = conn.lock().map_err(|err| {
+			log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+		})?;
+		let db_error = |err| {
+			log::error!(target: LOG_TARGET, "Failed to create subscription: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		};
+
+		let user = session_user(&conn, &auth)?;
+		validate_routing(&user, std::slice::from_ref(&notification))
+			.map_err(|err| custom_error(Status::BadRequest, err))?;
+
+		let subscribed = Subscription::query_by_user(&conn, user.id)
+			.map_err(db_error)?
+			.into_iter()
+			.any(|subscription| &subscription.notification == notification.notification());
+		ensure!(!subscribed, custom_error(Status::Conflict, Error::AlreadySubscribed));
+
+		let channels = notification.channels(&user);
+		let tx = conn.unchecked_transaction().map_err(db_error)?;
+		let requests = Subscription::create(&tx, user.id, notification.notification(), &channels)
+			.and_then(|_| {
+				escalations::set_steps(
+					&tx,
+					user.id,
+					notification.notification(),
+					notification.escalation(),
+				)
+			})
+			.and_then(|_| ContactConfirmation::request(&tx, user.id, notification.escalation()))
+			.and_then(|requests| tx.commit().map(|_| requests))
+			.map_err(db_error)?;
+
+		log::info!(target: LOG_TARGET, "User {} subscribed to {:?}", user.id, notification.notification());
+		(channels, requests)
+	};
+
+	contacts.send_links(requests).await;
+	Ok(Json(EnabledNotification::Routed {
+		notification: notification.notification().clone(),
+		channels,
+		escalation: notification.escalation().to_vec(),
+	}))
+}
+
+#[delete("/subscriptions", data = "<notification>")]
+pub async fn unsubscribe(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+	notification: Json<Notifications>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(SUBSCRIPTIONS)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = session_user(&conn, &auth)?;
+	let removed = Subscription::remove(&conn, user.id, &notification).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to remove subscription: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	ensure!(removed, custom_error(Status::NotFound, Error::SubscriptionNotFound));
+
+	log::info!(target: LOG_TARGET, "User {} unsubscribed from {:?}", user.id, *notification);
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+/// Ensures the parachain of the notification may exist, and a phase isn't notified about too far
+/// ahead.
+pub(crate) fn validate_notification(notification: &Notifications) -> Result<(), Error> {
+	match notification {
+		Notifications::InterludePhase(when) |
+		Notifications::LeadinPhaseStart(when) |
+		Notifications::FixedPhaseStart(when) => {
+			let (PhaseNotification::PriorStart(offset) | PhaseNotification::PriorEnd(offset)) =
+				when;
+			ensure!(*offset <= MAX_PHASE_OFFSET, Error::InvalidPhaseOffset);
+		},
+		Notifications::ParachainState(para_id) =>
+			ensure!(*para_id >= MIN_PARA_ID, Error::InvalidParaId),
+		Notifications::CoretimeSale => {},
+	}
+	Ok(())
+}
@@ -0,0 +1,208 @@
+//! ## Telegram Routes
+//!
+//! Telegram pushes the updates of the bot to the webhook route when the bot runs in webhook mode,
+//! see [`notification::bot`]. The reply to a command is returned in the response, which Telegram
+//! sends on behalf of the bot.
+//!
+//! Telegram authenticates with the `X-Telegram-Bot-Api-Secret-Token` header, which has to match
+//! the configured webhook secret. If no secret is configured, the route is disabled.
+//!
+//! Users who come in through Telegram sign in with the Telegram Login Widget. Telegram signs the
+//! data of the account with the token of the bot, which `POST /auth/telegram` exchanges for a
+//! session token. The account is bound to a user of its own, whose Telegram channel is addressed
+//! through the verified chat with the bot rather than a handle anyone could claim.
+
+use crate::{
+	auth::{secrets_match, start_session},
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use hmac::{Hmac, Mac};
+use notification::bot::{canonical_handle, handle_update, Update};
+use rocket::{
+	http::Status,
+	post,
+	request::{FromRequest, Outcome, Request},
+	response::status,
+	serde::json::Json,
+	State,
+};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use storage::{channels, telegram::TelegramChat, users::User, DbConn};
+use types::{
+	api::{self, ErrorResponse},
+	Notifier, Timestamp,
+};
+
+/// The header in which Telegram passes the webhook secret.
+pub const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+/// How long the data of the Telegram Login Widget is accepted, in seconds.
+pub const LOGIN_LIFETIME: u64 = 24 * 60 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct TelegramConfig {
+	/// The secret Telegram authenticates its webhook requests with.
+	pub webhook_secret: Option<String>,
+	/// The token of the bot, with which Telegram signs the data of the Login Widget. If no token
+	/// is configured, signing in through Telegram is disabled.
+	pub bot_token: Option<String>,
+}
+
+/// Request guard which succeeds if the request is made by Telegram.
+pub struct Telegram;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Telegram {
+	type Error = Error;
+
+	async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+		let expected = request
+			.rocket()
+			.state::<TelegramConfig>()
+			.and_then(|c| c.webhook_secret.as_ref());
+		let provided = request.headers().get_one(SECRET_HEADER);
+
+		match (expected, provided) {
+			(Some(expected), Some(provided)) if secrets_match(expected, provided) =>
+				Outcome::Success(Telegram),
+			_ => Outcome::Error((Status::Unauthorized, Error::Unauthorized)),
+		}
+	}
+}
+
+#[post("/telegram/webhook", data = "<update>")]
+pub async fn telegram_webhook(
+	conn: &State<DbConn>,
+	telegram: Result<Telegram, Error>,
+	update: Json<Update>,
+) -> Result<Json<Value>, status::Custom<Json<ErrorResponse>>> {
+	telegram.map_err(|err| custom_error(Status::Unauthorized, err))?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	// Telegram retries the update if the request fails.
+	let reply = handle_update(&conn, &update, notification::timestamp()).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to handle telegram update: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(Json(reply.map(|reply| reply.to_webhook_response()).unwrap_or_else(|| json!({}))))
+}
+
+#[post("/auth/telegram", data = "<login>")]
+pub async fn telegram_login(
+	conn: &State<DbConn>,
+	config: &State<TelegramConfig>,
+	login: Json<api::TelegramLogin>,
+) -> Result<Json<api::SessionToken>, status::Custom<Json<ErrorResponse>>> {
+	let invalid = || custom_error(Status::Unauthorized, Error::InvalidTelegramLogin);
+	let bot_token = config.bot_token.as_ref().ok_or_else(invalid)?;
+	ensure!(verify_login(bot_token, &login), invalid());
+
+	let now = notification::timestamp();
+	ensure!(
+		login.auth_date + LOGIN_LIFETIME >= now,
+		custom_error(Status::Unauthorized, Error::TelegramLoginExpired)
+	);
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let account = telegram_account(login.id);
+	link_user(&conn, &login, &account, now)?;
+
+	start_session(&conn, account, now).map(Json)
+}
+
+/// Returns the account a Telegram account signs in as.
+pub fn telegram_account(telegram_id: i64) -> String {
+	format!("telegram:{}", telegram_id)
+}
+
+/// Whether the data was signed with the token of the bot, see
+/// <https://core.telegram.org/widgets/login#checking-authorization>.
+pub fn verify_login(bot_token: &str, login: &api::TelegramLogin) -> bool {
+	let Ok(hash) = hex::decode(&login.hash) else { return false };
+	login_mac(bot_token, login).verify_slice(&hash).is_ok()
+}
+
+/// Returns the MAC of the fields of the login, sorted by name.
+pub(crate) fn login_mac(bot_token: &str, login: &api::TelegramLogin) -> HmacSha256 {
+	let fields = [
+		("auth_date", Some(login.auth_date.to_string())),
+		("first_name", Some(login.first_name.clone())),
+		("id", Some(login.id.to_string())),
+		("last_name", login.last_name.clone()),
+		("photo_url", login.photo_url.clone()),
+		("username", login.username.clone()),
+	];
+	let data_check_string = fields
+		.iter()
+		.filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	let mut mac = HmacSha256::new_from_slice(&Sha256::digest(bot_token.as_bytes()))
+		.expect("any key length is valid");
+	mac.update(data_check_string.as_bytes());
+	mac
+}
+
+/// Loads or creates the user bound to the Telegram account, and links the chat with the bot to it.
+///
+/// The handle of the account is taken over from any other user who claimed it without verifying
+/// it through the bot.
+fn link_user(
+	conn: &Connection,
+	login: &api::TelegramLogin,
+	account: &str,
+	now: Timestamp,
+) -> Result<u32, status::Custom<Json<ErrorResponse>>> {
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to link telegram account: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+	// Accounts without a username are identified by their id. Either way, notifications are
+	// delivered to the chat linked below rather than to the handle.
+	let handle = match &login.username {
+		Some(username) => canonical_handle(username),
+		None => login.id.to_string(),
+	};
+
+	if let Some(other) = User::query_by_tg_handle(conn, handle.clone()).map_err(db_error)? {
+		if User::query_account(conn, other.id).map_err(db_error)?.as_deref() != Some(account) {
+			let verified =
+				channels::is_verified(conn, other.id, &Notifier::Telegram).map_err(db_error)?;
+			ensure!(!verified, custom_error(Status::Conflict, Error::NotifierNotUnique));
+			log::info!(target: LOG_TARGET, "Removing unverified handle {} of user {}", handle, other.id);
+			channels::set(conn, other.id, &Notifier::Telegram, None).map_err(db_error)?;
+		}
+	}
+
+	let user_id = match User::query_by_account(conn, account).map_err(db_error)? {
+		Some(user) => user.id,
+		None => {
+			let user = User::new(User::next_id(conn).map_err(db_error)?);
+			User::create_user(conn, &user).map_err(db_error)?;
+			User::bind_account(conn, user.id, account).map_err(db_error)?;
+			log::info!(target: LOG_TARGET, "Registered user {} for {}", user.id, account);
+			user.id
+		},
+	};
+
+	// The private chat of a Telegram account with the bot has the id of the account.
+	channels::set(conn, user_id, &Notifier::Telegram, Some(&handle)).map_err(db_error)?;
+	channels::verify(conn, user_id, &Notifier::Telegram, &handle, now).map_err(db_error)?;
+	TelegramChat::link(conn, user_id, login.id, &handle).map_err(db_error)?;
+
+	Ok(user_id)
+}
@@ -0,0 +1,87 @@
+use crate::{
+	admin::{dead_letters, requeue_dead_letter, AdminConfig},
+	errors::Error,
+	tests::mock::{execute_with, parse_err_response},
+};
+use rocket::{
+	http::{Header, Status},
+	local::blocking::Client,
+	routes,
+};
+use storage::{
+	init_db,
+	outbox::{JobStatus, OutboxJob},
+	users::User,
+};
+use types::{DeliveryMode, Notifier};
+
+pub const DB_PATH: &'static str = "admin-tests.db";
+
+#[test]
+fn dead_letters_can_be_requeued() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		{
+			let conn = conn.lock().unwrap();
+			let user = User {
+				id: 0,
+				email: None,
+				tg_handle: None,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
+			};
+			User::create_user(&conn, &user).unwrap();
+			OutboxJob::enqueue(&conn, "key", 0, &Notifier::Telegram, "{}", 0, false).unwrap();
+			OutboxJob::claim_due(&conn, 0, 60, 1).unwrap();
+			OutboxJob::dead_letter(&conn, 1, "Bot blocked").unwrap();
+		}
+
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(AdminConfig { token: Some("secret".to_string()) })
+			.mount("/", routes![dead_letters, requeue_dead_letter]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		// CASE 1: not an admin.
+		let response = client.get("/admin/dead_letters").dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::Unauthorized);
+
+		let response = client
+			.get("/admin/dead_letters")
+			.header(Header::new("Authorization", "Bearer wrong"))
+			.dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+
+		// CASE 2: admins can inspect dead letters.
+		let response = client.get("/admin/dead_letters").header(auth()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let jobs: Vec<OutboxJob> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+		assert_eq!(jobs.len(), 1);
+		assert_eq!(jobs[0].status, JobStatus::Dead);
+		assert_eq!(jobs[0].last_error, Some("Bot blocked".to_string()));
+
+		// CASE 3: requeue a dead letter.
+		let response = client.post("/admin/dead_letters/1/requeue").header(auth()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/admin/dead_letters").header(auth()).dispatch();
+		let jobs: Vec<OutboxJob> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+		assert!(jobs.is_empty());
+
+		// CASE 4: the job is no longer dead.
+		let response = client.post("/admin/dead_letters/1/requeue").header(auth()).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::JobNotFound);
+	});
+}
+
+fn auth() -> Header<'static> {
+	Header::new("Authorization", "Bearer secret")
+}
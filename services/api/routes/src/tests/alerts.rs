@@ -0,0 +1,85 @@
+use crate::{
+	alerts::{acknowledge_alert, acknowledgement_page},
+	errors::Error,
+	tests::mock::{execute_with, parse_err_response},
+};
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::Client,
+	routes,
+};
+use storage::{escalations::Alert, init_db, outbox::OutboxJob, tokens::hash_token, users::User};
+use types::{DeliveryMode, Notifier};
+
+pub const DB_PATH: &'static str = "alert-tests.db";
+
+#[test]
+fn alerts_can_be_acknowledged() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		{
+			let conn = conn.lock().unwrap();
+			let user = User {
+				id: 0,
+				email: Some("dummy@mail.com".to_string()),
+				tg_handle: None,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
+			};
+			User::create_user(&conn, &user).unwrap();
+			let alert = Alert::raise(&conn, "alert", 0, 0).unwrap();
+			Alert::add_token(&conn, alert.id, &hash_token("token")).unwrap();
+			OutboxJob::enqueue_alert(&conn, "step", &alert, &Notifier::Email, None, "{}", 600)
+				.unwrap();
+		}
+
+		let rocket = rocket::build()
+			.manage(conn)
+			.mount("/", routes![acknowledgement_page, acknowledge_alert]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+		let query_alert = || {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			Alert::query_by_token(&conn, &hash_token("token")).unwrap().unwrap()
+		};
+
+		// CASE 1: unknown token.
+		for response in [
+			client.get("/alert/unknown/acknowledge").dispatch(),
+			client.post("/alert/unknown/acknowledge").dispatch(),
+		] {
+			assert_eq!(response.status(), Status::NotFound);
+			assert_eq!(parse_err_response(response), Error::AlertNotFound);
+		}
+		assert_eq!(query_alert().acknowledged_at, None);
+
+		// CASE 2: opening the link only asks for a confirmation.
+		let response = client.get("/alert/token/acknowledge").dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(response.content_type(), Some(ContentType::HTML));
+		assert!(response.into_string().unwrap().contains("<form method=\"post\">"));
+		assert_eq!(query_alert().acknowledged_at, None);
+
+		// CASE 3: acknowledging stops the escalation.
+		let response = client.post("/alert/token/acknowledge").dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let acknowledged_at = query_alert().acknowledged_at;
+		assert!(acknowledged_at.is_some());
+
+		let job = {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			OutboxJob::query_by_id(&conn, 1).unwrap()
+		};
+		assert_eq!(job, None);
+
+		// CASE 4: acknowledging again keeps the first acknowledgement.
+		let response = client.post("/alert/token/acknowledge").dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(query_alert().acknowledged_at, acknowledged_at);
+	})
+}
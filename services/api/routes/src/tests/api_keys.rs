@@ -0,0 +1,189 @@
+use crate::{
+	api_keys::{api_keys, create_api_key, revoke_api_key},
+	auth::logout,
+	errors::Error,
+	query::user,
+	register::{register_user, RegistrationData},
+	tests::mock::{contacts, execute_with, parse_err_response, sign_in, verification},
+	update::{update_user, UpdateData},
+};
+use rocket::{
+	http::{ContentType, Header, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use storage::{auth::ApiKey, init_db, tokens::hash_token};
+use types::api::{self, ApiKeyScope};
+
+pub const DB_PATH: &'static str = "api-key-tests.db";
+
+fn create<'a>(
+	client: &'a Client,
+	auth: Header<'static>,
+	name: &str,
+	scope: ApiKeyScope,
+	expires_at: Option<u64>,
+) -> LocalResponse<'a> {
+	let request = api::ApiKeyRequest { name: name.to_string(), scope, expires_at };
+	client
+		.post("/user/api_keys")
+		.header(ContentType::JSON)
+		.header(auth)
+		.body(serde_json::to_string(&request).unwrap())
+		.dispatch()
+}
+
+fn bearer(key: &str) -> Header<'static> {
+	Header::new("Authorization", format!("Bearer {}", key))
+}
+
+fn update(client: &Client, auth: Header<'static>) -> Status {
+	let data = UpdateData {
+		email: None,
+		tg_handle: Some("@alice".to_string()),
+		discord_webhook: None,
+		slack_webhook: None,
+		matrix_room: None,
+		webhook_url: None,
+		language: None,
+		timezone: None,
+		quiet_hours: None,
+		delivery_mode: None,
+	};
+	client
+		.put("/update_user")
+		.header(ContentType::JSON)
+		.header(auth)
+		.body(serde_json::to_string(&data).unwrap())
+		.dispatch()
+		.status()
+}
+
+#[test]
+fn api_keys_act_on_behalf_of_users() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let rocket = rocket::build().manage(conn).manage(verification()).manage(contacts()).mount(
+			"/",
+			routes![
+				register_user,
+				user,
+				update_user,
+				logout,
+				create_api_key,
+				api_keys,
+				revoke_api_key
+			],
+		);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let registration = RegistrationData {
+			email: None,
+			tg_handle: Some("@alice".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&registration).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let new_key = |response: LocalResponse| -> api::NewApiKey {
+			assert_eq!(response.status(), Status::Ok);
+			serde_json::from_str(&response.into_string().unwrap()).unwrap()
+		};
+
+		// CASE 1: keys are created within a session of a registered user.
+		let response = create(&client, bearer("invalid"), "ci", ApiKeyScope::Full, None);
+		assert_eq!(response.status(), Status::Unauthorized);
+		let response = create(&client, sign_in(&client, 1), "ci", ApiKeyScope::Full, None);
+		assert_eq!(response.status(), Status::NotFound);
+
+		let response = create(&client, sign_in(&client, 0), " ", ApiKeyScope::Full, None);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidApiKeyName);
+		let response = create(&client, sign_in(&client, 0), "ci", ApiKeyScope::Full, Some(1));
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidApiKeyExpiry);
+
+		let full = new_key(create(&client, sign_in(&client, 0), "ci", ApiKeyScope::Full, None));
+		let read_only =
+			new_key(create(&client, sign_in(&client, 0), "bot", ApiKeyScope::ReadOnly, None));
+		assert_eq!(full.api_key.name, "ci");
+		assert_ne!(full.key, read_only.key);
+
+		// CASE 2: keys authenticate requests in place of a session, within their scope.
+		let response = client.get("/user").header(bearer(&full.key)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(update(&client, bearer(&full.key)), Status::Ok);
+
+		let response = client.get("/user").header(bearer(&read_only.key)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let response = client
+			.put("/update_user")
+			.header(ContentType::JSON)
+			.header(bearer(&read_only.key))
+			.body("{}")
+			.dispatch();
+		assert_eq!(response.status(), Status::Forbidden);
+		assert_eq!(parse_err_response(response), Error::InsufficientScope);
+
+		// CASE 3: keys can't manage keys or sign out.
+		let response = client.get("/user/api_keys").header(bearer(&full.key)).dispatch();
+		assert_eq!(response.status(), Status::Forbidden);
+		let response = client.post("/auth/logout").header(bearer(&full.key)).dispatch();
+		assert_eq!(response.status(), Status::Forbidden);
+
+		// CASE 4: the keys are listed without the keys themselves, along with their last use.
+		let response = client.get("/user/api_keys").header(sign_in(&client, 0)).dispatch();
+		let body = response.into_string().unwrap();
+		assert!(!body.contains(&full.key));
+		let keys: Vec<api::ApiKey> = serde_json::from_str(&body).unwrap();
+		assert_eq!(keys.len(), 2);
+		assert!(keys.iter().all(|key| key.last_used_at.is_some()));
+
+		// CASE 5: expired and unknown keys are rejected.
+		{
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			let expired = ApiKey {
+				id: "expired".to_string(),
+				user_id: 0,
+				name: "old".to_string(),
+				key_hash: hash_token("key_expired"),
+				scope: ApiKeyScope::Full,
+				created_at: 0,
+				expires_at: Some(1),
+				last_used_at: None,
+			};
+			ApiKey::create(&conn, &expired).unwrap();
+		}
+		let response = client.get("/user").header(bearer("key_expired")).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+		let response = client.get("/user").header(bearer("key_unknown")).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+
+		// CASE 6: revoked keys can't be used anymore, keys of other users can't be revoked.
+		let path = format!("/user/api_keys/{}", full.api_key.id);
+		let response = client.delete(path.clone()).header(sign_in(&client, 1)).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		let response = client.delete(path.clone()).header(sign_in(&client, 0)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let response = client.delete(path).header(sign_in(&client, 0)).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::ApiKeyNotFound);
+
+		let response = client.get("/user").header(bearer(&full.key)).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+		let response = client.get("/user").header(bearer(&read_only.key)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+	})
+}
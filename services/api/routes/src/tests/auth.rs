@@ -0,0 +1,291 @@
+use crate::{
+	auth::{challenge, decode_address, encode_address, login, logout, AuthConfig},
+	errors::Error,
+	query::user,
+	register::{register_user, RegistrationData},
+	tests::mock::{contacts, execute_with, parse_err_response, verification},
+	update::{update_user, UpdateData},
+};
+use rocket::{
+	http::{ContentType, Header, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use schnorrkel::{ExpansionMode, MiniSecretKey};
+use storage::{init_db, users::User};
+use types::api;
+
+pub const DB_PATH: &'static str = "auth-tests.db";
+pub const OWNER_DB_PATH: &'static str = "auth-owner-tests.db";
+
+/// Signs messages the way wallets do, with either sr25519 or ed25519.
+enum Signer {
+	Sr25519(schnorrkel::Keypair),
+	Ed25519(ed25519_zebra::SigningKey),
+}
+
+impl Signer {
+	fn sr25519(seed: u8) -> Self {
+		let secret = MiniSecretKey::from_bytes(&[seed; 32]).unwrap();
+		Self::Sr25519(secret.expand_to_keypair(ExpansionMode::Ed25519))
+	}
+
+	fn ed25519(seed: u8) -> Self {
+		Self::Ed25519(ed25519_zebra::SigningKey::from([seed; 32]))
+	}
+
+	fn address(&self) -> String {
+		self.address_on(42)
+	}
+
+	fn address_on(&self, prefix: u8) -> String {
+		let public_key = match self {
+			Self::Sr25519(keypair) => keypair.public.to_bytes(),
+			Self::Ed25519(key) => ed25519_zebra::VerificationKey::from(key).into(),
+		};
+		encode_address(prefix, &public_key)
+	}
+
+	fn sign(&self, message: &str) -> String {
+		let signature: [u8; 64] = match self {
+			Self::Sr25519(keypair) =>
+				keypair.sign_simple(b"substrate", message.as_bytes()).to_bytes(),
+			Self::Ed25519(key) => key.sign(message.as_bytes()).into(),
+		};
+		format!("0x{}", hex::encode(signature))
+	}
+}
+
+fn client(db_path: &'static str) -> Client {
+	let conn = init_db(db_path).unwrap();
+	let rocket = rocket::build()
+		.manage(conn)
+		.manage(verification())
+		.manage(contacts())
+		.manage(AuthConfig::for_url("https://api.example.com/"))
+		.mount("/", routes![challenge, login, logout, register_user, user, update_user]);
+	Client::tracked(rocket).expect("failed to create a client")
+}
+
+fn request_challenge(client: &Client, address: &str) -> api::Challenge {
+	let response = client
+		.post("/auth/challenge")
+		.header(ContentType::JSON)
+		.body(serde_json::to_string(&api::ChallengeRequest { address: address.into() }).unwrap())
+		.dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+fn submit<'a>(client: &'a Client, nonce: &str, signature: &str) -> LocalResponse<'a> {
+	let request = api::LoginRequest { nonce: nonce.into(), signature: signature.into() };
+	client
+		.post("/auth/login")
+		.header(ContentType::JSON)
+		.body(serde_json::to_string(&request).unwrap())
+		.dispatch()
+}
+
+fn sign_in_with(client: &Client, signer: &Signer) -> Header<'static> {
+	sign_in_on(client, signer, 42)
+}
+
+fn sign_in_on(client: &Client, signer: &Signer, prefix: u8) -> Header<'static> {
+	let challenge = request_challenge(client, &signer.address_on(prefix));
+	let response = submit(client, &challenge.nonce, &signer.sign(&challenge.message));
+	assert_eq!(response.status(), Status::Ok);
+	let session: api::SessionToken =
+		serde_json::from_str(&response.into_string().unwrap()).unwrap();
+	Header::new("Authorization", format!("Bearer {}", session.token))
+}
+
+fn registration() -> RegistrationData {
+	RegistrationData {
+		email: None,
+		tg_handle: None,
+		discord_webhook: None,
+		slack_webhook: None,
+		matrix_room: None,
+		webhook_url: None,
+		enabled_notifications: vec![],
+		language: None,
+		timezone: None,
+		quiet_hours: None,
+		delivery_mode: None,
+	}
+}
+
+#[test]
+fn addresses_are_decoded() {
+	let public_key = [7; 32];
+	assert_eq!(decode_address(&encode_address(0, &public_key)), Some(public_key));
+	assert_eq!(decode_address(&encode_address(42, &public_key)), Some(public_key));
+	// Alice on the generic Substrate network.
+	assert!(decode_address("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY").is_some());
+
+	assert_eq!(decode_address("invalid"), None);
+	assert_eq!(decode_address("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQZ"), None);
+}
+
+#[test]
+fn accounts_sign_in_with_a_signed_challenge() {
+	execute_with(DB_PATH, || {
+		let client = client(DB_PATH);
+		let alice = Signer::sr25519(1);
+
+		// CASE 1: invalid addresses don't get a challenge.
+		let response = client
+			.post("/auth/challenge")
+			.header(ContentType::JSON)
+			.body(r#"{"address": "invalid"}"#)
+			.dispatch();
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidAccount);
+
+		// CASE 2: the message follows Sign-In with Substrate.
+		let challenge = request_challenge(&client, &alice.address());
+		assert!(challenge.message.starts_with(&format!(
+			"api.example.com wants you to sign in with your Substrate account:\n{}\n",
+			alice.address()
+		)));
+		assert!(challenge.message.contains("URI: https://api.example.com\n"));
+		assert!(challenge.message.contains(&format!("Nonce: {}\n", challenge.nonce)));
+
+		// CASE 3: signatures of another account are rejected and use up the challenge.
+		let mallory = Signer::sr25519(2);
+		let response = submit(&client, &challenge.nonce, &mallory.sign(&challenge.message));
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::InvalidSignature);
+
+		let response = submit(&client, &challenge.nonce, &alice.sign(&challenge.message));
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::Unauthorized);
+
+		// CASE 4: a valid signature opens a session, the nonce can't be reused.
+		let challenge = request_challenge(&client, &alice.address());
+		let signature = alice.sign(&challenge.message);
+		assert_eq!(submit(&client, &challenge.nonce, &signature).status(), Status::Ok);
+		assert_eq!(submit(&client, &challenge.nonce, &signature).status(), Status::Unauthorized);
+
+		// CASE 5: messages wrapped by wallets and ed25519 accounts are accepted.
+		let challenge = request_challenge(&client, &alice.address());
+		let signature = alice.sign(&format!("<Bytes>{}</Bytes>", challenge.message));
+		assert_eq!(submit(&client, &challenge.nonce, &signature).status(), Status::Ok);
+
+		let bob = Signer::ed25519(3);
+		let challenge = request_challenge(&client, &bob.address());
+		assert_eq!(
+			submit(&client, &challenge.nonce, &bob.sign(&challenge.message)).status(),
+			Status::Ok
+		);
+
+		// CASE 6: signing out ends the session.
+		let session = sign_in_with(&client, &alice);
+		let response = client.post("/auth/logout").header(session.clone()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let response = client.post("/auth/logout").header(session).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+	})
+}
+
+#[test]
+fn users_are_managed_by_their_account() {
+	execute_with(OWNER_DB_PATH, || {
+		let client = client(OWNER_DB_PATH);
+		let alice = Signer::sr25519(1);
+		let bob = Signer::ed25519(2);
+		let alice_session = sign_in_with(&client, &alice);
+		let bob_session = sign_in_with(&client, &bob);
+
+		let register = |session: Option<&Header<'static>>, data: &RegistrationData| {
+			let mut request = client
+				.post("/register_user")
+				.header(ContentType::JSON)
+				.body(serde_json::to_string(data).unwrap());
+			if let Some(session) = session {
+				request = request.header(session.clone());
+			}
+			request.dispatch().status()
+		};
+
+		// CASE 1: registering requires a session and binds the user to the account.
+		assert_eq!(register(None, &registration()), Status::Unauthorized);
+		assert_eq!(register(Some(&alice_session), &registration()), Status::Ok);
+		{
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			assert_eq!(User::query_account(&conn, 0).unwrap(), Some(alice.address()));
+		}
+
+		// CASE 2: an account can only register one user.
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(alice_session.clone())
+			.body(serde_json::to_string(&registration()).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Conflict);
+		assert_eq!(parse_err_response(response), Error::AccountInUse);
+
+		// CASE 3: the session acts on the user of its account.
+		assert_eq!(client.get("/user").dispatch().status(), Status::Unauthorized);
+		let response =
+			client.get("/user").header(Header::new("Authorization", "Bearer x")).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+		let response = client.get("/user").header(bob_session.clone()).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::UserNotFound);
+		let response = client.get("/user").header(alice_session.clone()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 4: accounts without a user can't update any.
+		let update = UpdateData {
+			email: None,
+			tg_handle: Some("@alice".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.put("/update_user")
+			.header(ContentType::JSON)
+			.header(bob_session)
+			.body(serde_json::to_string(&update).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		let response = client
+			.put("/update_user")
+			.header(ContentType::JSON)
+			.header(alice_session)
+			.body(serde_json::to_string(&update).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 5: the address of the account on another network signs in as the same account.
+		let polkadot_session = sign_in_on(&client, &alice, 0);
+		let response = client.get("/user").header(polkadot_session).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in_on(&client, &alice, 2))
+			.body(serde_json::to_string(&registration()).unwrap())
+			.dispatch();
+		assert_eq!(parse_err_response(response), Error::AccountInUse);
+
+		// CASE 6: users which were bound to the address they signed in with before are rebound.
+		let charlie = Signer::sr25519(4);
+		assert_eq!(register(Some(&sign_in_with(&client, &charlie)), &registration()), Status::Ok);
+		let conn = || client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+		User::bind_account(&conn(), 1, &charlie.address_on(0)).unwrap();
+		let response = client.get("/user").header(sign_in_on(&client, &charlie, 0)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(User::query_account(&conn(), 1).unwrap(), Some(charlie.address()));
+		let response = client.get("/user").header(sign_in_with(&client, &charlie)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+	})
+}
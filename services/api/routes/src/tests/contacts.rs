@@ -0,0 +1,109 @@
+use crate::{
+	contacts::{confirm_contact, contact_confirmation_page, ContactConfirmation},
+	errors::Error,
+	register::{register_user, EnabledNotification, RegistrationData},
+	subscriptions::subscribe,
+	tests::mock::{execute_with, parse_err_response, sign_in, verification, MockMailer},
+};
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::Client,
+	routes,
+};
+use storage::{escalations::Contact, init_db};
+use types::{EscalationStep, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "contact-tests.db";
+
+#[test]
+fn contacts_are_confirmed_through_the_sent_link() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let mailer = MockMailer::default();
+		let mut channels = notification::channels::Channels::new();
+		channels.insert(Notifier::Email, Box::new(mailer.clone()));
+		let contacts =
+			ContactConfirmation { api_url: Some("https://api.example.com/".to_string()), channels };
+		let rocket = rocket::build().manage(conn).manage(verification()).manage(contacts).mount(
+			"/",
+			routes![register_user, subscribe, contact_confirmation_page, confirm_contact],
+		);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let is_confirmed = || {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			Contact::is_confirmed(&conn, 0, &Notifier::Email, "team@mail.com").unwrap()
+		};
+		let escalated = |para_id| EnabledNotification::Routed {
+			notification: Notifications::ParachainState(para_id),
+			channels: vec![Notifier::Telegram],
+			escalation: vec![EscalationStep {
+				after_minutes: 10,
+				channel: Notifier::Email,
+				contact: Some("team@mail.com".to_string()),
+			}],
+		};
+
+		let data = RegistrationData {
+			email: None,
+			tg_handle: Some("@dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![escalated(2000)],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&data).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 1: the contact is asked to confirm.
+		let link = {
+			let sent = mailer.sent.lock().unwrap();
+			assert_eq!(sent.len(), 1);
+			assert_eq!(sent[0].0, "team@mail.com");
+			let url = sent[0].1.action.as_ref().unwrap().url.clone();
+			url.strip_prefix("https://api.example.com").unwrap().to_string()
+		};
+		assert!(!is_confirmed());
+
+		// CASE 2: escalating to the contact again doesn't ask it again right away.
+		let response = client
+			.post("/subscriptions")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&escalated(2001)).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+
+		// CASE 3: unknown tokens don't confirm anything.
+		for response in [
+			client.get("/escalation_contacts/unknown/confirm").dispatch(),
+			client.post("/escalation_contacts/unknown/confirm").dispatch(),
+		] {
+			assert_eq!(response.status(), Status::NotFound);
+			assert_eq!(parse_err_response(response), Error::ContactNotFound);
+		}
+		assert!(!is_confirmed());
+
+		// CASE 4: opening the link only asks for a confirmation.
+		let response = client.get(link.clone()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(response.content_type(), Some(ContentType::HTML));
+		assert!(!is_confirmed());
+
+		// CASE 5: confirming on the page confirms the contact.
+		let response = client.post(link).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert!(is_confirmed());
+	})
+}
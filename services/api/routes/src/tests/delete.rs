@@ -0,0 +1,113 @@
+use crate::{
+	delete::delete_user,
+	errors::Error,
+	export::{export_user, UserExport},
+	query::user,
+	register::{register_user, EnabledNotification, RegistrationData},
+	tests::mock::{account, contacts, execute_with, parse_err_response, sign_in, verification},
+};
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::Client,
+	routes,
+};
+use storage::{
+	init_db,
+	organizations::{Member, Organization},
+	outbox::{JobStatus, OutboxJob},
+	users::User,
+	DbConn,
+};
+use types::{api::OrganizationRole, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "delete-tests.db";
+
+fn register(client: &Client, account_id: u32) -> u32 {
+	let data = RegistrationData {
+		email: None,
+		tg_handle: Some(format!("@user{}", account_id)),
+		discord_webhook: None,
+		slack_webhook: None,
+		matrix_room: None,
+		webhook_url: None,
+		enabled_notifications: vec![EnabledNotification::All(Notifications::ParachainState(2000))],
+		language: None,
+		timezone: None,
+		quiet_hours: None,
+		delivery_mode: None,
+	};
+	let response = client
+		.post("/register_user")
+		.header(ContentType::JSON)
+		.header(sign_in(client, account_id))
+		.body(serde_json::to_string(&data).unwrap())
+		.dispatch();
+	assert_eq!(response.status(), Status::Ok);
+
+	let conn = client.rocket().state::<DbConn>().unwrap().lock().unwrap();
+	User::query_by_account(&conn, &account(account_id)).unwrap().unwrap().id
+}
+
+#[test]
+fn users_export_and_delete_their_data() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
+			.mount("/", routes![register_user, user, export_user, delete_user]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+		let (alice, bob) = (register(&client, 0), register(&client, 1));
+
+		let organization = {
+			let conn = client.rocket().state::<DbConn>().unwrap().lock().unwrap();
+			OutboxJob::enqueue(&conn, "a", alice, &Notifier::Telegram, "{\"id\":1}", 0, false)
+				.unwrap();
+			OutboxJob::enqueue(&conn, "b", bob, &Notifier::Telegram, "{\"id\":2}", 0, false)
+				.unwrap();
+			let organization = Organization::create(&conn, "team", "Team", alice, 0).unwrap();
+			Member::add(&conn, organization.id, bob, OrganizationRole::Member, 0).unwrap();
+			organization
+		};
+
+		// CASE 1: the export holds the data of the user only.
+		let response = client.get("/user/export").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let export: UserExport = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+		assert_eq!(export.account, Some(account(0)));
+		assert_eq!(export.user.user.tg_handle, Some("@user0".to_string()));
+		assert_eq!(export.channels.len(), 1);
+		assert_eq!(export.channels[0].kind, Notifier::Telegram);
+		assert_eq!(export.subscriptions.len(), 1);
+		assert_eq!(export.subscriptions[0].notification, Notifications::ParachainState(2000));
+		assert_eq!(export.organizations.len(), 1);
+		assert_eq!(export.organizations[0].role, OrganizationRole::Owner);
+		assert_eq!(export.deliveries.len(), 1);
+		assert_eq!(export.deliveries[0].status, JobStatus::Pending);
+		assert_eq!(export.deliveries[0].event, serde_json::json!({ "id": 1 }));
+
+		// CASE 2: the last owner of an organization with other members can't leave it behind.
+		let response = client.delete("/user").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(response.status(), Status::Conflict);
+		assert_eq!(parse_err_response(response), Error::LastOwner);
+
+		// CASE 3: once the other members are gone, the user and the organization are deleted.
+		{
+			let conn = client.rocket().state::<DbConn>().unwrap().lock().unwrap();
+			Member::remove(&conn, organization.id, bob).unwrap();
+		}
+		let auth = sign_in(&client, 0);
+		let response = client.delete("/user").header(auth.clone()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let response = client.get("/user").header(auth).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+
+		let conn = client.rocket().state::<DbConn>().unwrap().lock().unwrap();
+		assert_eq!(User::query_by_id(&conn, alice).unwrap(), None);
+		assert_eq!(Organization::query_by_public_id(&conn, "team").unwrap(), None);
+		assert!(OutboxJob::query_by_user(&conn, alice).unwrap().is_empty());
+		assert_eq!(OutboxJob::query_by_user(&conn, bob).unwrap().len(), 1);
+		assert!(User::query_by_id(&conn, bob).unwrap().is_some());
+	})
+}
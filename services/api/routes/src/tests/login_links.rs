@@ -0,0 +1,118 @@
+use crate::{
+	errors::Error,
+	login_links::{email_account, email_login, email_login_page, request_login_link, MAX_LINKS},
+	query::user,
+	tests::mock::{execute_with, parse_err_response, MockMailer},
+	verification::EmailVerification,
+};
+use rocket::{
+	http::{ContentType, Header, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use storage::{channels, init_db, users::User};
+use types::{api, Notifier};
+
+pub const DB_PATH: &'static str = "login-link-tests.db";
+
+fn request_link<'a>(client: &'a Client, email: &str) -> LocalResponse<'a> {
+	let request = api::EmailLoginRequest { email: email.to_string() };
+	client
+		.post("/auth/email")
+		.header(ContentType::JSON)
+		.body(serde_json::to_string(&request).unwrap())
+		.dispatch()
+}
+
+#[test]
+fn users_sign_in_through_emailed_links() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		{
+			// A user which was registered before users were bound to accounts.
+			let conn = conn.lock().unwrap();
+			let user = User { email: Some("bob@mail.com".to_string()), ..User::new(0) };
+			User::create_user(&conn, &user).unwrap();
+		}
+		let mailer = MockMailer::default();
+		let verification = EmailVerification {
+			secret: "secret".to_string(),
+			api_url: Some("https://api.example.com".to_string()),
+			mailer: Some(Box::new(mailer.clone())),
+		};
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification)
+			.mount("/", routes![request_login_link, email_login_page, email_login, user]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let last_link = || {
+			let sent = mailer.sent.lock().unwrap();
+			let (_, message) = sent.last().unwrap();
+			let url = message.action.as_ref().unwrap().url.clone();
+			url.strip_prefix("https://api.example.com").unwrap().to_string()
+		};
+		let session = |response: LocalResponse| {
+			let session: api::SessionToken =
+				serde_json::from_str(&response.into_string().unwrap()).unwrap();
+			Header::new("Authorization", format!("Bearer {}", session.token))
+		};
+		let account_user = |email: &str| {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			let user = User::query_by_account(&conn, &email_account(email)).unwrap().unwrap();
+			(user.id, channels::is_verified(&conn, user.id, &Notifier::Email).unwrap())
+		};
+
+		// CASE 1: malformed addresses are rejected.
+		let response = request_link(&client, "alice");
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidEmail);
+
+		// CASE 2: the link creates a verified user and signs in as it.
+		assert_eq!(request_link(&client, "alice@mail.com").status(), Status::Ok);
+		assert_eq!(mailer.sent.lock().unwrap()[0].0, "alice@mail.com");
+		let link = last_link();
+		assert!(link.starts_with("/auth/email/"));
+
+		// Opening the link only shows the page which signs in.
+		let response = client.get(&link).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(response.content_type(), Some(ContentType::HTML));
+		assert!(response.into_string().unwrap().contains("<form method=\"post\">"));
+
+		let response = client.post(&link).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let alice = session(response);
+		assert_eq!(account_user("alice@mail.com"), (1, true));
+		assert_eq!(client.get("/user").header(alice).dispatch().status(), Status::Ok);
+
+		// CASE 3: links can only be used once.
+		let response = client.post(&link).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::InvalidLoginLink);
+
+		let response = client.post("/auth/email/invalid").dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+
+		// CASE 4: users which aren't bound to an account yet are bound to the address, however it
+		// is typed.
+		assert_eq!(request_link(&client, " Bob@Mail.com ").status(), Status::Ok);
+		let response = client.post(last_link()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(account_user("bob@mail.com"), (0, true));
+		assert_eq!(client.get("/user").header(session(response)).dispatch().status(), Status::Ok);
+
+		// CASE 5: only a few links are sent to an address.
+		for _ in 1..MAX_LINKS {
+			assert_eq!(request_link(&client, "alice@mail.com").status(), Status::Ok);
+		}
+		let response = request_link(&client, "Alice@mail.com");
+		assert_eq!(response.status(), Status::TooManyRequests);
+		assert_eq!(parse_err_response(response), Error::TooManyRequests);
+		assert_eq!(mailer.sent.lock().unwrap().len(), 1 + 1 + (MAX_LINKS - 1) as usize);
+
+		// The links sent before the limit was reached still work.
+		assert_eq!(client.post(last_link()).dispatch().status(), Status::Ok);
+		assert_eq!(account_user("alice@mail.com"), (1, true));
+	})
+}
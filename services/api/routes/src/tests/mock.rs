@@ -1,8 +1,24 @@
-use rocket::local::blocking::LocalResponse;
-use storage::users::User;
+use notification::{
+	channels::{Channel, ChannelError},
+	message::Message,
+};
+use rocket::{
+	http::Header,
+	local::blocking::{Client, LocalResponse},
+};
+use std::sync::{Arc, Mutex};
+use storage::{
+	auth::Session,
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	users::User,
+	DbConn,
+};
 use types::api::ErrorResponse;
 
-use crate::errors::Error;
+use crate::{
+	contacts::ContactConfirmation, errors::Error, query::UserResponse,
+	verification::EmailVerification,
+};
 
 pub fn execute_with<R>(db_path: &str, f: impl Fn() -> R) -> R {
 	// Don't check the result since it will error if the db already doesn't exist which isn't an
@@ -12,9 +28,11 @@ pub fn execute_with<R>(db_path: &str, f: impl Fn() -> R) -> R {
 	f()
 }
 
+/// Parses the queried user. Its internal id isn't part of the response and is always 0.
 pub fn parse_ok_response<'a>(response: LocalResponse<'a>) -> User {
 	let body = response.into_string().unwrap();
-	serde_json::from_str(&body).expect("can't parse value")
+	let response: UserResponse = serde_json::from_str(&body).expect("can't parse value");
+	response.user
 }
 
 pub fn parse_err_response<'a>(response: LocalResponse<'a>) -> Error {
@@ -22,3 +40,46 @@ pub fn parse_err_response<'a>(response: LocalResponse<'a>) -> Error {
 	let error: ErrorResponse = serde_json::from_str(&body).unwrap();
 	error.message.into()
 }
+
+/// Doesn't send any confirmation links.
+pub fn verification() -> EmailVerification {
+	EmailVerification { secret: "secret".to_string(), api_url: None, mailer: None }
+}
+
+/// Doesn't send any confirmation links to contacts.
+pub fn contacts() -> ContactConfirmation {
+	ContactConfirmation { api_url: None, channels: Default::default() }
+}
+
+/// Returns the account which registers the user in tests.
+pub fn account(user_id: u32) -> String {
+	format!("account{}", user_id)
+}
+
+/// Signs in with the account of the user, as if the account signed a challenge.
+pub fn sign_in(client: &Client, user_id: u32) -> Header<'static> {
+	let token = random_token(TOKEN_BYTES);
+	let session = Session {
+		token_hash: hash_token(&token),
+		account: account(user_id),
+		expires_at: notification::timestamp() + 3600,
+	};
+	let conn = client.rocket().state::<DbConn>().unwrap().lock().unwrap();
+	Session::create(&conn, &session).unwrap();
+
+	Header::new("Authorization", format!("Bearer {}", token))
+}
+
+/// Records every sent message.
+#[derive(Clone, Default)]
+pub struct MockMailer {
+	pub sent: Arc<Mutex<Vec<(String, Message)>>>,
+}
+
+#[rocket::async_trait]
+impl Channel for MockMailer {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		self.sent.lock().unwrap().push((address.to_string(), message.clone()));
+		Ok(())
+	}
+}
@@ -1,4 +1,17 @@
+mod admin;
+mod alerts;
+mod api_keys;
+mod auth;
+mod contacts;
+mod delete;
+mod login_links;
 mod mock;
+mod organizations;
 mod query;
 mod register;
+mod subscriptions;
+mod telegram;
+mod unsubscribe;
 mod update;
+mod verification;
+mod webhooks;
@@ -0,0 +1,206 @@
+use crate::{
+	errors::Error,
+	organizations::{
+		accept_invite, create_organization, invite_member, organization, organizations,
+		remove_member, set_organization_channels, set_organization_subscriptions,
+	},
+	query::{user, UserResponse},
+	register::{register_user, RegistrationData},
+	tests::mock::{contacts, execute_with, parse_err_response, sign_in, verification},
+};
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use storage::init_db;
+use types::{
+	api::{self, OrganizationRole},
+	Notifications, Notifier,
+};
+
+pub const DB_PATH: &'static str = "organization-tests.db";
+
+fn register(client: &Client, account: u32) -> String {
+	let data = RegistrationData {
+		email: None,
+		tg_handle: Some(format!("@user{}", account)),
+		discord_webhook: None,
+		slack_webhook: None,
+		matrix_room: None,
+		webhook_url: None,
+		enabled_notifications: vec![],
+		language: None,
+		timezone: None,
+		quiet_hours: None,
+		delivery_mode: None,
+	};
+	let response = client
+		.post("/register_user")
+		.header(ContentType::JSON)
+		.header(sign_in(client, account))
+		.body(serde_json::to_string(&data).unwrap())
+		.dispatch();
+	assert_eq!(response.status(), Status::Ok);
+
+	let response = client.get("/user").header(sign_in(client, account)).dispatch();
+	let user: UserResponse = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+	user.id
+}
+
+fn post<'a>(client: &'a Client, account: u32, path: &str, body: String) -> LocalResponse<'a> {
+	client
+		.post(path.to_string())
+		.header(ContentType::JSON)
+		.header(sign_in(client, account))
+		.body(body)
+		.dispatch()
+}
+
+fn put<'a>(client: &'a Client, account: u32, path: &str, body: String) -> LocalResponse<'a> {
+	client
+		.put(path.to_string())
+		.header(ContentType::JSON)
+		.header(sign_in(client, account))
+		.body(body)
+		.dispatch()
+}
+
+fn invite<'a>(
+	client: &'a Client,
+	account: u32,
+	id: &str,
+	role: OrganizationRole,
+) -> LocalResponse<'a> {
+	let request = api::InviteRequest { role };
+	post(client, account, &format!("/organizations/{}/invites", id), to_json(&request))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+	serde_json::to_string(value).unwrap()
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(response: LocalResponse) -> T {
+	assert_eq!(response.status(), Status::Ok);
+	serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn organizations_share_subscriptions_between_members() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let rocket = rocket::build().manage(conn).manage(verification()).manage(contacts()).mount(
+			"/",
+			routes![
+				register_user,
+				user,
+				create_organization,
+				organizations,
+				organization,
+				set_organization_subscriptions,
+				set_organization_channels,
+				invite_member,
+				accept_invite,
+				remove_member
+			],
+		);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+		let (alice, bob, carol) = (0, 1, 2);
+		let ids = [register(&client, alice), register(&client, bob), register(&client, carol)];
+		register(&client, 3);
+
+		// CASE 1: the user who creates the organization becomes its owner.
+		let request = api::OrganizationRequest { name: " ".to_string() };
+		let response = post(&client, alice, "/organizations", to_json(&request));
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidOrganizationName);
+
+		let request = api::OrganizationRequest { name: "Team".to_string() };
+		let team: api::Organization =
+			from_json(post(&client, alice, "/organizations", to_json(&request)));
+		assert_eq!(team.role, OrganizationRole::Owner);
+		let path = format!("/organizations/{}", team.id);
+
+		// CASE 2: members join through single-use invites.
+		let token =
+			from_json::<api::Invite>(invite(&client, alice, &team.id, OrganizationRole::Admin))
+				.token;
+		let accept = format!("/organizations/invites/{}/accept", token);
+		assert_eq!(post(&client, bob, &accept, String::new()).status(), Status::Ok);
+		let response = post(&client, carol, &accept, String::new());
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::InvalidInvite);
+
+		// Admins can't invite owners, members can't invite anyone.
+		let response = invite(&client, bob, &team.id, OrganizationRole::Owner);
+		assert_eq!(response.status(), Status::Forbidden);
+		assert_eq!(parse_err_response(response), Error::InsufficientRole);
+		let token =
+			from_json::<api::Invite>(invite(&client, bob, &team.id, OrganizationRole::Member))
+				.token;
+		let accept = format!("/organizations/invites/{}/accept", token);
+		// Members can't accept invites, which stay valid for others.
+		let response = post(&client, bob, &accept, String::new());
+		assert_eq!(response.status(), Status::Conflict);
+		assert_eq!(parse_err_response(response), Error::AlreadyMember);
+		assert_eq!(post(&client, carol, &accept, String::new()).status(), Status::Ok);
+		let response = invite(&client, carol, &team.id, OrganizationRole::Member);
+		assert_eq!(response.status(), Status::Forbidden);
+
+		// CASE 3: admins manage the subscriptions, which only cover the state of parachains.
+		let subscriptions = format!("{}/subscriptions", path);
+		let parachain = vec![Notifications::ParachainState(2000)];
+		let response = put(&client, carol, &subscriptions, to_json(&parachain));
+		assert_eq!(response.status(), Status::Forbidden);
+		let response =
+			put(&client, bob, &subscriptions, to_json(&vec![Notifications::CoretimeSale]));
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidOrganizationSubscription);
+		assert_eq!(put(&client, bob, &subscriptions, to_json(&parachain)).status(), Status::Ok);
+
+		// CASE 4: members see the organization, others don't.
+		let response = client.get(path.clone()).header(sign_in(&client, 3)).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::OrganizationNotFound);
+
+		let details: api::OrganizationDetails =
+			from_json(client.get(path.clone()).header(sign_in(&client, carol)).dispatch());
+		assert_eq!(details.organization.role, OrganizationRole::Member);
+		assert_eq!(details.subscriptions, parachain);
+		let members: Vec<_> =
+			details.members.into_iter().map(|member| (member.id, member.role)).collect();
+		assert_eq!(
+			members,
+			vec![
+				(ids[0].clone(), OrganizationRole::Owner),
+				(ids[1].clone(), OrganizationRole::Admin),
+				(ids[2].clone(), OrganizationRole::Member),
+			]
+		);
+		let listed: Vec<api::Organization> =
+			from_json(client.get("/organizations").header(sign_in(&client, bob)).dispatch());
+		assert_eq!(listed, vec![api::Organization { role: OrganizationRole::Admin, ..team }]);
+
+		// CASE 5: members choose among their own channels.
+		let channels = format!("{}/channels", path);
+		let response = put(&client, carol, &channels, to_json(&vec![Notifier::Discord]));
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::ChannelNotConfigured);
+		let response = put(&client, carol, &channels, to_json(&vec![Notifier::Telegram]));
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 6: admins can't remove owners, members can only leave, and owners remain.
+		let member = |id: &String| format!("{}/members/{}", path, id);
+		let remove =
+			|account, id| client.delete(member(id)).header(sign_in(&client, account)).dispatch();
+		assert_eq!(remove(bob, &ids[0]).status(), Status::Forbidden);
+		assert_eq!(remove(carol, &ids[1]).status(), Status::Forbidden);
+		assert_eq!(remove(alice, &ids[1]).status(), Status::Ok);
+		assert_eq!(remove(alice, &ids[1]).status(), Status::NotFound);
+		assert_eq!(remove(carol, &ids[2]).status(), Status::Ok);
+
+		let response = remove(alice, &ids[0]);
+		assert_eq!(response.status(), Status::Conflict);
+		assert_eq!(parse_err_response(response), Error::LastOwner);
+	})
+}
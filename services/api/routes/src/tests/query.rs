@@ -2,7 +2,9 @@ use crate::{
 	errors::Error,
 	query::user,
 	register::{register_user, RegistrationData},
-	tests::mock::{execute_with, parse_err_response, parse_ok_response},
+	tests::mock::{
+		contacts, execute_with, parse_err_response, parse_ok_response, sign_in, verification,
+	},
 };
 use rocket::{
 	http::{ContentType, Status},
@@ -11,7 +13,7 @@ use rocket::{
 };
 use serde_json::from_str;
 use storage::{init_db, users::User};
-use types::{api::ErrorResponse, Notifier};
+use types::{api::ErrorResponse, DeliveryMode};
 
 pub const DB_PATH: &'static str = "query-tests.db";
 
@@ -19,46 +21,64 @@ pub const DB_PATH: &'static str = "query-tests.db";
 fn register_works() {
 	execute_with(DB_PATH, || {
 		let conn = init_db(DB_PATH).unwrap();
-		let rocket = rocket::build().manage(conn).mount("/", routes![register_user, user]);
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
+			.mount("/", routes![register_user, user]);
 
 		let client = Client::tracked(rocket).expect("failed to create a client");
 
-		// CASE 1: user doesn't exist.
-		let response = client.get("/user/0").dispatch();
-		assert_eq!(response.status(), Status::BadRequest);
+		// CASE 1: the account didn't register a user.
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
 		assert_eq!(parse_err_response(response), Error::UserNotFound);
 
 		// CASE 2: user exists:
 
 		// Register a user:
 		let registration_data = RegistrationData {
-			id: 0,
-			notifier: Notifier::Email,
 			email: Some("dummy@gmail.com".to_string()),
 			tg_handle: None,
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
 			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
 		};
-		let response = register(&client, &registration_data);
+		let response = register(&client, 0, &registration_data);
 		assert_eq!(response.status(), Status::Ok);
 
-		let response = client.get("/user/0").dispatch();
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
 		// After registering we should be able to get the user:
 		assert_eq!(
 			parse_ok_response(response),
 			User {
 				id: 0,
-				notifier: Notifier::Email,
 				email: Some("dummy@gmail.com".to_string()),
 				tg_handle: None,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
 			}
 		);
 	});
 }
 
-fn register<'a>(client: &'a Client, data: &'a RegistrationData) -> LocalResponse<'a> {
+fn register<'a>(client: &'a Client, account: u32, data: &'a RegistrationData) -> LocalResponse<'a> {
 	client
 		.post("/register_user")
 		.header(ContentType::JSON)
+		.header(sign_in(client, account))
 		.body(serde_json::to_string(&data).unwrap())
 		.dispatch()
 }
@@ -1,8 +1,10 @@
 use crate::{
 	errors::Error,
 	query::user,
-	register::{register_user, RegistrationData},
-	tests::mock::{execute_with, parse_err_response, parse_ok_response},
+	register::{register_user, EnabledNotification, RegistrationData},
+	tests::mock::{
+		contacts, execute_with, parse_err_response, parse_ok_response, sign_in, verification,
+	},
 };
 use rocket::{
 	http::{ContentType, Status},
@@ -10,8 +12,11 @@ use rocket::{
 	routes,
 };
 use serde_json::from_str;
-use storage::{init_db, users::User};
-use types::{api::ErrorResponse, Notifier};
+use storage::{escalations, init_db, subscriptions::Subscription, users::User};
+use types::{
+	api::{self, ErrorResponse},
+	DeliveryMode, EscalationStep, Notifications, Notifier, QuietHours,
+};
 
 pub const DB_PATH: &'static str = "registration-tests.db";
 
@@ -27,19 +32,29 @@ fn no_db_connection_errors() {
 fn register_works() {
 	execute_with(DB_PATH, || {
 		let conn = init_db(DB_PATH).unwrap();
-		let rocket = rocket::build().manage(conn).mount("/", routes![register_user, user]);
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
+			.mount("/", routes![register_user, user]);
 
 		let client = Client::tracked(rocket).expect("failed to create a client");
 
 		let mut registration_data = RegistrationData {
-			id: 0,
-			notifier: Notifier::Email,
 			email: None,
 			tg_handle: None,
-			enabled_notifications: vec![],
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![EnabledNotification::All(Notifications::CoretimeSale)],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
 		};
-		// CASE 1: the user did not set the notifier.
-		let response = register(&client, &registration_data);
+		// CASE 1: the user did not configure a channel for the notification.
+		let response = register(&client, 0, &registration_data);
 
 		assert_eq!(response.status(), Status::BadRequest);
 		assert_eq!(parse_err_response(response), Error::NotifierEmpty);
@@ -48,10 +63,12 @@ fn register_works() {
 		registration_data.email = Some("dummy@gmail.com".to_string());
 		registration_data.tg_handle = Some("@dummy".to_string());
 
-		let response = register(&client, &registration_data);
+		let response = register(&client, 0, &registration_data);
 		assert_eq!(response.status(), Status::Ok);
+		let registered: api::UserId = from_str(&response.into_string().unwrap()).unwrap();
+		assert_eq!(registered.id.len(), 32);
 
-		let response = client.get("/user/0").dispatch();
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
 		// After registering we should be able to get the user:
 		assert_eq!(
 			parse_ok_response(response),
@@ -59,47 +76,361 @@ fn register_works() {
 				id: 0,
 				email: Some("dummy@gmail.com".to_string()),
 				tg_handle: Some("@dummy".to_string()),
-				notifier: Notifier::Email,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
 			}
 		);
 
-		// CASE 3: user with the same id exists
-		let response = register(&client, &registration_data);
+		// CASE 3: the account already registered a user.
+		let other_data = RegistrationData {
+			email: Some("other@gmail.com".to_string()),
+			tg_handle: None,
+			..registration_data.clone()
+		};
+		let response = register(&client, 0, &other_data);
 		assert_eq!(response.status(), Status::Conflict);
-		assert_eq!(parse_err_response(response), Error::UserExists);
+		assert_eq!(parse_err_response(response), Error::AccountInUse);
 
-		// CASE 4: user with the same email exists:
+		// CASE 4: user with the same email exists, however it is typed:
 		let registration_data = RegistrationData {
-			id: 1,
-			notifier: Notifier::Email,
-			email: Some("dummy@gmail.com".to_string()),
+			email: Some(" Dummy@Gmail.com".to_string()),
 			tg_handle: None,
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
 			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
 		};
-		let response = register(&client, &registration_data);
+		let response = register(&client, 1, &registration_data);
 
 		assert_eq!(response.status(), Status::Conflict);
 		assert_eq!(parse_err_response(response), Error::NotifierNotUnique);
 
-		// CASE 5: user with the same telegram exists:
+		// CASE 5: user with the same telegram exists, with or without the leading `@`:
 		let registration_data = RegistrationData {
-			id: 1,
-			notifier: Notifier::Telegram,
 			email: None,
-			tg_handle: Some("@dummy".to_string()),
+			tg_handle: Some("dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
 			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
 		};
 
-		let response = register(&client, &registration_data);
+		let response = register(&client, 1, &registration_data);
 		assert_eq!(response.status(), Status::Conflict);
 		assert_eq!(parse_err_response(response), Error::NotifierNotUnique);
+
+		// CASE 6: invalid language.
+		let mut registration_data = RegistrationData {
+			email: None,
+			tg_handle: Some("@dummy2".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![],
+			language: Some("German".to_string()),
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+
+		let response = register(&client, 1, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidLanguage);
+
+		// CASE 7: the user picked a language.
+		registration_data.language = Some("de".to_string());
+		let response = register(&client, 1, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 1)).dispatch();
+		assert_eq!(parse_ok_response(response).language, "de".to_string());
+
+		// CASE 8: invalid time zone.
+		let mut registration_data = RegistrationData {
+			tg_handle: Some("@dummy3".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			timezone: Some("Berlin".to_string()),
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 2, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidTimezone);
+
+		// CASE 9: invalid quiet hours.
+		registration_data.timezone = Some("Europe/Berlin".to_string());
+		registration_data.quiet_hours = Some(QuietHours { start: 7, end: 7 });
+
+		let response = register(&client, 2, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidQuietHours);
+
+		// CASE 10: the user set a time zone and quiet hours.
+		registration_data.quiet_hours = Some(QuietHours { start: 22, end: 7 });
+		let response = register(&client, 2, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 2)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.timezone, "Europe/Berlin".to_string());
+		assert_eq!(user.quiet_hours, Some(QuietHours { start: 22, end: 7 }));
+
+		// CASE 11: notification routed to Discord without a webhook.
+		let mut registration_data = RegistrationData {
+			tg_handle: None,
+			enabled_notifications: vec![EnabledNotification::Routed {
+				notification: Notifications::CoretimeSale,
+				channels: vec![Notifier::Discord],
+				escalation: vec![],
+			}],
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 3, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::NotifierEmpty);
+
+		// CASE 12: invalid Discord webhook.
+		registration_data.discord_webhook = Some("https://example.com/api/webhooks/1/token".into());
+		let response = register(&client, 3, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidDiscordWebhook);
+
+		// CASE 13: valid Discord webhook.
+		let webhook = "https://discord.com/api/webhooks/123456/abc-DEF_789".to_string();
+		registration_data.discord_webhook = Some(webhook.clone());
+		let response = register(&client, 3, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 3)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.channels(), vec![Notifier::Discord]);
+		assert_eq!(user.discord_webhook, Some(webhook));
+
+		// CASE 14: invalid Slack webhook.
+		let mut registration_data = RegistrationData {
+			discord_webhook: None,
+			enabled_notifications: vec![EnabledNotification::All(Notifications::CoretimeSale)],
+			slack_webhook: Some("https://hooks.slack.com/services/T000/B000".to_string()),
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 4, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidSlackWebhook);
+
+		// CASE 15: valid Slack webhook.
+		let webhook = "https://hooks.slack.com/services/T000/B000/XXXX".to_string();
+		registration_data.slack_webhook = Some(webhook.clone());
+		let response = register(&client, 4, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 4)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.channels(), vec![Notifier::Slack]);
+		assert_eq!(user.slack_webhook, Some(webhook));
+
+		// CASE 16: invalid Matrix room.
+		let mut registration_data = RegistrationData {
+			slack_webhook: None,
+			matrix_room: Some("#coretime:matrix.org".to_string()),
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 5, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidMatrixRoom);
+
+		// CASE 17: valid Matrix room.
+		registration_data.matrix_room = Some("!coretime:matrix.org".to_string());
+		let response = register(&client, 5, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 5)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.channels(), vec![Notifier::Matrix]);
+		assert_eq!(user.matrix_room, Some("!coretime:matrix.org".to_string()));
+
+		// CASE 18: webhooks have to use https.
+		let mut registration_data = RegistrationData {
+			matrix_room: None,
+			webhook_url: Some("http://example.com/hooks".to_string()),
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 6, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidWebhookUrl);
+
+		// CASE 19: webhooks can't post to internal services.
+		for url in [
+			"https://localhost/hooks",
+			"https://127.0.0.1/hooks",
+			"https://169.254.169.254/latest",
+			"https://10.0.0.1:8443/hooks",
+			"https://[::1]/hooks",
+		] {
+			registration_data.webhook_url = Some(url.to_string());
+			let response = register(&client, 6, &registration_data);
+			assert_eq!(response.status(), Status::BadRequest, "{} was accepted", url);
+			assert_eq!(parse_err_response(response), Error::InvalidWebhookUrl);
+		}
+
+		// CASE 20: valid webhook.
+		registration_data.webhook_url = Some("https://example.com/hooks".to_string());
+		let response = register(&client, 6, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 6)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.channels(), vec![Notifier::Webhook]);
+		assert_eq!(user.webhook_url, Some("https://example.com/hooks".to_string()));
+
+		// CASE 21: notifications are routed to some of the channels of the user.
+		let registration_data = RegistrationData {
+			email: Some("routed@gmail.com".to_string()),
+			tg_handle: Some("@routed".to_string()),
+			webhook_url: None,
+			enabled_notifications: vec![
+				EnabledNotification::Routed {
+					notification: Notifications::CoretimeSale,
+					channels: vec![Notifier::Telegram],
+					escalation: vec![],
+				},
+				EnabledNotification::All(Notifications::ParachainState(2000)),
+			],
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 7, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let subscriptions = {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			Subscription::query_all(&conn).unwrap()
+		};
+		let routed = subscriptions.iter().filter(|s| s.user_id == 7).collect::<Vec<_>>();
+		assert_eq!(
+			routed,
+			vec![
+				&Subscription {
+					user_id: 7,
+					notification: Notifications::CoretimeSale,
+					channels: vec![Notifier::Telegram],
+				},
+				&Subscription {
+					user_id: 7,
+					notification: Notifications::ParachainState(2000),
+					channels: vec![Notifier::Email, Notifier::Telegram],
+				},
+			]
+		);
+
+		// CASE 22: only the parachain state raises alerts which can be escalated.
+		let step = |after_minutes, channel, contact: Option<&str>| EscalationStep {
+			after_minutes,
+			channel,
+			contact: contact.map(|contact| contact.to_string()),
+		};
+		let escalated = |notification, escalation| EnabledNotification::Routed {
+			notification,
+			channels: vec![Notifier::Telegram],
+			escalation,
+		};
+		let mut registration_data = RegistrationData {
+			email: Some("escalated@gmail.com".to_string()),
+			tg_handle: Some("@escalated".to_string()),
+			enabled_notifications: vec![escalated(
+				Notifications::CoretimeSale,
+				vec![step(10, Notifier::Email, None)],
+			)],
+			..registration_data.clone()
+		};
+
+		let response = register(&client, 8, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidEscalation);
+
+		// CASE 23: steps have to be ordered by time.
+		let steps = vec![step(30, Notifier::Email, None), step(10, Notifier::Email, None)];
+		registration_data.enabled_notifications =
+			vec![escalated(Notifications::ParachainState(2000), steps)];
+
+		let response = register(&client, 8, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidEscalation);
+
+		// CASE 24: alerts can't be escalated to the webhooks of contacts.
+		let steps = vec![step(10, Notifier::Webhook, Some("https://example.com/hooks"))];
+		registration_data.enabled_notifications =
+			vec![escalated(Notifications::ParachainState(2000), steps)];
+
+		let response = register(&client, 8, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidEscalation);
+
+		// CASE 25: escalation to the user's email and then to a team member.
+		let steps =
+			vec![step(10, Notifier::Email, None), step(30, Notifier::Telegram, Some("@teammate"))];
+		registration_data.enabled_notifications =
+			vec![escalated(Notifications::ParachainState(2000), steps.clone())];
+
+		let response = register(&client, 8, &registration_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let stored = {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			escalations::query_steps(&conn, 8, &Notifications::ParachainState(2000)).unwrap()
+		};
+		assert_eq!(stored, steps);
+
+		// CASE 26: malformed addresses are rejected.
+		let registration_data = RegistrationData {
+			email: Some("malformed".to_string()),
+			tg_handle: None,
+			enabled_notifications: vec![],
+			..registration_data.clone()
+		};
+		let response = register(&client, 9, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidEmail);
+
+		let registration_data = RegistrationData {
+			email: None,
+			tg_handle: Some("@with space".to_string()),
+			..registration_data.clone()
+		};
+		let response = register(&client, 9, &registration_data);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidTelegramHandle);
 	});
 }
 
-fn register<'a>(client: &'a Client, data: &'a RegistrationData) -> LocalResponse<'a> {
+fn register<'a>(client: &'a Client, account: u32, data: &'a RegistrationData) -> LocalResponse<'a> {
 	client
 		.post("/register_user")
 		.header(ContentType::JSON)
+		.header(sign_in(client, account))
 		.body(serde_json::to_string(&data).unwrap())
 		.dispatch()
 }
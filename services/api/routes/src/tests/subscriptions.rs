@@ -0,0 +1,136 @@
+use crate::{
+	errors::Error,
+	register::{register_user, EnabledNotification, RegistrationData},
+	subscriptions::{subscribe, subscriptions, unsubscribe, MAX_PHASE_OFFSET},
+	tests::mock::{contacts, execute_with, parse_err_response, sign_in, verification},
+};
+use rocket::{
+	http::{ContentType, Header, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use storage::init_db;
+use types::{Notifications, Notifier, PhaseNotification};
+
+pub const DB_PATH: &'static str = "subscription-tests.db";
+
+fn list(client: &Client, auth: Header<'static>) -> Vec<EnabledNotification> {
+	let response = client.get("/subscriptions").header(auth).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+fn post<'a>(
+	client: &'a Client,
+	auth: Header<'static>,
+	notification: &EnabledNotification,
+) -> LocalResponse<'a> {
+	client
+		.post("/subscriptions")
+		.header(ContentType::JSON)
+		.header(auth)
+		.body(serde_json::to_string(notification).unwrap())
+		.dispatch()
+}
+
+fn delete<'a>(
+	client: &'a Client,
+	auth: Header<'static>,
+	notification: &Notifications,
+) -> LocalResponse<'a> {
+	client
+		.delete("/subscriptions")
+		.header(ContentType::JSON)
+		.header(auth)
+		.body(serde_json::to_string(notification).unwrap())
+		.dispatch()
+}
+
+#[test]
+fn subscriptions_are_managed_one_by_one() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
+			.mount("/", routes![register_user, subscriptions, subscribe, unsubscribe]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let parachain = Notifications::ParachainState(2000);
+		let data = RegistrationData {
+			email: None,
+			tg_handle: Some("@dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![EnabledNotification::All(parachain.clone())],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&data).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 1: the subscriptions of the registration are listed with their channels.
+		let routed = |notification: &Notifications| EnabledNotification::Routed {
+			notification: notification.clone(),
+			channels: vec![Notifier::Telegram],
+			escalation: vec![],
+		};
+		assert_eq!(list(&client, sign_in(&client, 0)), vec![routed(&parachain)]);
+
+		// CASE 2: invalid notifications are rejected.
+		let reserved = EnabledNotification::All(Notifications::ParachainState(42));
+		let response = post(&client, sign_in(&client, 0), &reserved);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidParaId);
+
+		let too_early = EnabledNotification::All(Notifications::InterludePhase(
+			PhaseNotification::PriorStart(MAX_PHASE_OFFSET + 1),
+		));
+		let response = post(&client, sign_in(&client, 0), &too_early);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidPhaseOffset);
+
+		let unreachable = EnabledNotification::Routed {
+			notification: Notifications::CoretimeSale,
+			channels: vec![Notifier::Discord],
+			escalation: vec![],
+		};
+		let response = post(&client, sign_in(&client, 0), &unreachable);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::NotifierEmpty);
+
+		// CASE 3: users subscribe to a notification only once.
+		let response =
+			post(&client, sign_in(&client, 0), &EnabledNotification::All(parachain.clone()));
+		assert_eq!(response.status(), Status::Conflict);
+		assert_eq!(parse_err_response(response), Error::AlreadySubscribed);
+
+		let sale = Notifications::CoretimeSale;
+		let response = post(&client, sign_in(&client, 0), &EnabledNotification::All(sale.clone()));
+		assert_eq!(response.status(), Status::Ok);
+		let listed = list(&client, sign_in(&client, 0));
+		assert_eq!(listed.len(), 2);
+		assert!(listed.contains(&routed(&parachain)) && listed.contains(&routed(&sale)));
+
+		// CASE 4: unsubscribing removes the subscription.
+		assert_eq!(delete(&client, sign_in(&client, 0), &parachain).status(), Status::Ok);
+		let response = delete(&client, sign_in(&client, 0), &parachain);
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::SubscriptionNotFound);
+		assert_eq!(list(&client, sign_in(&client, 0)), vec![routed(&sale)]);
+
+		// CASE 5: users who didn't register have no subscriptions.
+		let response = client.get("/subscriptions").header(sign_in(&client, 1)).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+	})
+}
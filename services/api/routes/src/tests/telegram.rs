@@ -0,0 +1,207 @@
+use crate::{
+	errors::Error,
+	query::user,
+	telegram::{
+		login_mac, telegram_account, telegram_login, telegram_webhook, TelegramConfig,
+		LOGIN_LIFETIME, SECRET_HEADER,
+	},
+	tests::mock::{execute_with, parse_err_response},
+};
+use hmac::Mac;
+use rocket::{
+	http::{ContentType, Header, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use serde_json::{json, Value};
+use storage::{channels, init_db, telegram::TelegramChat, users::User};
+use types::{api, DeliveryMode, Notifier};
+
+pub const DB_PATH: &'static str = "telegram-tests.db";
+pub const LOGIN_DB_PATH: &'static str = "telegram-login-tests.db";
+
+/// Returns the data of the account, signed like the Telegram Login Widget does.
+fn signed_login(id: i64, username: Option<&str>, auth_date: u64) -> api::TelegramLogin {
+	let mut login = api::TelegramLogin {
+		id,
+		first_name: "Alice".to_string(),
+		last_name: None,
+		username: username.map(str::to_string),
+		photo_url: None,
+		auth_date,
+		hash: String::new(),
+	};
+	login.hash = hex::encode(login_mac("bot-token", &login).finalize().into_bytes());
+	login
+}
+
+fn sign_in<'a>(client: &'a Client, login: &api::TelegramLogin) -> LocalResponse<'a> {
+	client
+		.post("/auth/telegram")
+		.header(ContentType::JSON)
+		.body(serde_json::to_string(login).unwrap())
+		.dispatch()
+}
+
+#[test]
+fn webhook_answers_commands() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		{
+			let conn = conn.lock().unwrap();
+			let user = User {
+				id: 0,
+				email: None,
+				tg_handle: Some("@alice".to_string()),
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
+			};
+			User::create_user(&conn, &user).unwrap();
+		}
+
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(TelegramConfig { webhook_secret: Some("secret".to_string()), bot_token: None })
+			.mount("/", routes![telegram_webhook]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+		let update = |text: &str| {
+			json!({
+				"update_id": 1,
+				"message": {
+					"chat": { "id": 42, "type": "private" },
+					"from": { "username": "alice" },
+					"text": text,
+				},
+			})
+			.to_string()
+		};
+
+		// CASE 1: requests without the secret are rejected.
+		let response = client.post("/telegram/webhook").body(update("/start")).dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::Unauthorized);
+
+		let response = client
+			.post("/telegram/webhook")
+			.header(Header::new(SECRET_HEADER, "wrong"))
+			.body(update("/start"))
+			.dispatch();
+		assert_eq!(response.status(), Status::Unauthorized);
+
+		// CASE 2: the reply to a command is returned as a call of the bot api.
+		let response = client
+			.post("/telegram/webhook")
+			.header(Header::new(SECRET_HEADER, "secret"))
+			.body(update("/start"))
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let reply: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+		assert_eq!(reply["method"], "sendMessage");
+		assert_eq!(reply["chat_id"], 42);
+
+		let chat = {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			TelegramChat::query_by_chat(&conn, 42).unwrap()
+		};
+		assert_eq!(chat.map(|chat| chat.user_id), Some(0));
+
+		// CASE 3: other messages are acknowledged without a reply.
+		let response = client
+			.post("/telegram/webhook")
+			.header(Header::new(SECRET_HEADER, "secret"))
+			.body(update("hello"))
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(response.into_string().unwrap(), "{}");
+	})
+}
+
+#[test]
+fn accounts_sign_in_with_the_login_widget() {
+	execute_with(LOGIN_DB_PATH, || {
+		let conn = init_db(LOGIN_DB_PATH).unwrap();
+		{
+			// The handle was claimed without verification.
+			let conn = conn.lock().unwrap();
+			let user = User {
+				id: 0,
+				email: None,
+				tg_handle: Some("@alice".to_string()),
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
+			};
+			User::create_user(&conn, &user).unwrap();
+		}
+
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(TelegramConfig { webhook_secret: None, bot_token: Some("bot-token".into()) })
+			.mount("/", routes![telegram_login, user]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+		let now = notification::timestamp();
+
+		// CASE 1: data which isn't signed by the bot is rejected.
+		let mut login = signed_login(42, Some("alice"), now);
+		login.username = Some("mallory".to_string());
+		let response = sign_in(&client, &login);
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::InvalidTelegramLogin);
+
+		// CASE 2: old data is rejected.
+		let response = sign_in(&client, &signed_login(42, Some("alice"), now - LOGIN_LIFETIME - 1));
+		assert_eq!(response.status(), Status::Unauthorized);
+		assert_eq!(parse_err_response(response), Error::TelegramLoginExpired);
+
+		// CASE 3: signing in creates a user with the verified chat, the unverified handle of the
+		// other user is removed.
+		let response = sign_in(&client, &signed_login(42, Some("alice"), now));
+		assert_eq!(response.status(), Status::Ok);
+		let session: api::SessionToken =
+			serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+		{
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			let user = User::query_by_account(&conn, &telegram_account(42)).unwrap().unwrap();
+			assert_eq!((user.id, user.tg_handle.as_deref()), (1, Some("@alice")));
+			assert!(channels::is_verified(&conn, 1, &Notifier::Telegram).unwrap());
+			let chat = TelegramChat::query_by_chat(&conn, 42).unwrap().unwrap();
+			assert_eq!((chat.user_id, chat.handle.as_str()), (1, "@alice"));
+			assert_eq!(User::query_by_id(&conn, 0).unwrap().unwrap().tg_handle, None);
+		}
+
+		// CASE 4: the session manages the user of the account.
+		let response = client
+			.get("/user")
+			.header(Header::new("Authorization", format!("Bearer {}", session.token)))
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 5: signing in again loads the user and follows a changed username.
+		let response = sign_in(&client, &signed_login(42, Some("alice_new"), now));
+		assert_eq!(response.status(), Status::Ok);
+		{
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			let user = User::query_by_account(&conn, &telegram_account(42)).unwrap().unwrap();
+			assert_eq!((user.id, user.tg_handle.as_deref()), (1, Some("@alice_new")));
+			assert!(channels::is_verified(&conn, 1, &Notifier::Telegram).unwrap());
+			assert_eq!(TelegramChat::query_by_chat(&conn, 42).unwrap().unwrap().user_id, 1);
+		}
+
+		// CASE 6: verified handles can't be taken over.
+		let response = sign_in(&client, &signed_login(43, Some("alice_new"), now));
+		assert_eq!(response.status(), Status::Conflict);
+		assert_eq!(parse_err_response(response), Error::NotifierNotUnique);
+	})
+}
@@ -0,0 +1,127 @@
+use crate::{
+	errors::Error,
+	register::{register_user, EnabledNotification, RegistrationData},
+	tests::mock::{account, contacts, execute_with, parse_err_response, sign_in, verification},
+	unsubscribe::{unsubscribe_link, unsubscribe_one_click, UnsubscribeTokens},
+};
+use notification::unsubscribe::Scope;
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::Client,
+	routes,
+};
+use storage::{audit::AuditEntry, init_db, subscriptions::Subscription, users::User, DbConn};
+use types::Notifications;
+
+pub const DB_PATH: &'static str = "unsubscribe-tests.db";
+
+#[test]
+fn unsubscribe_links_work_without_signing_in() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
+			.manage(UnsubscribeTokens::new("secret".to_string()))
+			.mount("/", routes![register_user, unsubscribe_link, unsubscribe_one_click]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let (sale, parachain, other) = (
+			Notifications::CoretimeSale,
+			Notifications::ParachainState(2000),
+			Notifications::ParachainState(3000),
+		);
+		let data = RegistrationData {
+			email: None,
+			tg_handle: Some("@dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: [&sale, &parachain, &other]
+				.into_iter()
+				.map(|notification| EnabledNotification::All(notification.clone()))
+				.collect(),
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&data).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		let state = || client.rocket().state::<DbConn>().unwrap().lock().unwrap();
+		let id = User::query_by_account(&state(), &account(0)).unwrap().unwrap().id;
+		let subscribed = || {
+			Subscription::query_by_user(&state(), id)
+				.unwrap()
+				.into_iter()
+				.map(|subscription| subscription.notification)
+				.collect::<Vec<_>>()
+		};
+		let path = |scope: &Scope| {
+			let tokens = client.rocket().state::<UnsubscribeTokens>().unwrap();
+			format!("/unsubscribe/{}", tokens.token(id, scope))
+		};
+
+		// CASE 1: opening the link only asks for a confirmation.
+		let link = path(&Scope::Notification(sale.clone()));
+		let response = client.get(link.clone()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert_eq!(response.content_type(), Some(ContentType::HTML));
+		assert!(response.into_string().unwrap().contains("<form method=\"post\">"));
+		assert_eq!(subscribed().len(), 3);
+
+		// CASE 2: confirming removes the subscription of the notification.
+		let response = client.post(link.clone()).header(ContentType::Form).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert!(!subscribed().contains(&sale));
+		assert_eq!(subscribed().len(), 2);
+
+		// CASE 3: mail clients post to the same link, which succeeds again.
+		let response = client
+			.post(link)
+			.header(ContentType::Form)
+			.body("List-Unsubscribe=One-Click")
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 4: links of digests remove every subscription.
+		let response = client.post(path(&Scope::All)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert!(subscribed().is_empty());
+
+		// CASE 5: forged links are rejected.
+		let forged = path(&Scope::All).replacen(&format!("/{}.", id), "/7.", 1);
+		let response = client.get(forged.clone()).dispatch();
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidUnsubscribeToken);
+		let response = client.post(forged).dispatch();
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidUnsubscribeToken);
+
+		// Every unsubscription is recorded.
+		let entries = AuditEntry::query_by_user(&state(), id).unwrap();
+		let details = entries
+			.iter()
+			.map(|entry| {
+				assert_eq!(entry.action, "unsubscribe");
+				serde_json::from_str(&entry.details).unwrap()
+			})
+			.collect::<Vec<serde_json::Value>>();
+		assert_eq!(
+			details,
+			vec![
+				serde_json::json!({ "removed": [sale], "oneClick": false }),
+				serde_json::json!({ "removed": [], "oneClick": true }),
+				serde_json::json!({ "removed": [parachain, other], "oneClick": false }),
+			]
+		);
+	})
+}
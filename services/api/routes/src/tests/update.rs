@@ -4,19 +4,24 @@ use rocket::{
 	routes,
 };
 use storage::{init_db, users::User};
-use types::Notifier;
+use types::{DeliveryMode, Notifier, QuietHours};
 
 use crate::{
+	errors::Error,
 	query::user,
 	register::{register_user, RegistrationData},
-	tests::mock::parse_ok_response,
-	update::{update_user, UpdateData},
+	tests::mock::{
+		contacts, parse_err_response, parse_ok_response, sign_in, verification, MockMailer,
+	},
+	update::{patch_user, update_user, UpdateData},
+	verification::EmailVerification,
 	LOG_TARGET,
 };
 
 use super::mock::execute_with;
 
 pub const DB_PATH: &'static str = "update-tests.db";
+pub const PATCH_DB_PATH: &'static str = "patch-tests.db";
 
 #[test]
 fn updating_users_works() {
@@ -24,78 +29,300 @@ fn updating_users_works() {
 		let conn = init_db(DB_PATH).unwrap();
 		let rocket = rocket::build()
 			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
 			.mount("/", routes![register_user, user, update_user]);
 		let client = Client::tracked(rocket).expect("failed to create client");
 
 		let registration_data = RegistrationData {
-			id: 0,
-			notifier: Notifier::Telegram,
 			email: None,
 			tg_handle: Some("@dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
 			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
 		};
 
 		// Should register successfully
-		let response = register(&client, &registration_data);
+		let response = register(&client, 0, &registration_data);
 		assert_eq!(response.status(), Status::Ok);
 
-		let response = client.get("/user/0").dispatch();
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
 		assert_eq!(
 			parse_ok_response(response),
 			User {
 				id: 0,
 				email: None,
 				tg_handle: Some("@dummy".to_string()),
-				notifier: Notifier::Telegram,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
 			}
 		);
 
-		// Update to mismatched notifier should not work
-		let mut update_data = UpdateData {
-			id: 0,
-			email: None,
-			tg_handle: Some("@dummy".to_string()),
-			notifier: Some(Notifier::Email),
-		};
-		let response = update(&client, &update_data);
-		assert_eq!(response.status(), Status::BadRequest);
-
-		// Update email with notifier works
+		// Adding an email address works
 		let update_data = UpdateData {
-			id: 0,
 			email: Some("dummy@mail.com".to_string()),
 			tg_handle: Some("@dummy".to_string()),
-			notifier: Some(Notifier::Email),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
 		};
-		let response = update(&client, &update_data);
+		let response = update(&client, 0, &update_data);
 		assert_eq!(response.status(), Status::Ok);
 
 		// Should return the updated user information
-		let response = client.get("/user/0").dispatch();
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
 		assert_eq!(
 			parse_ok_response(response),
 			User {
 				id: 0,
 				email: Some("dummy@mail.com".to_string()),
 				tg_handle: Some("@dummy".to_string()),
-				notifier: Notifier::Email,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: "en".to_string(),
+				timezone: "UTC".to_string(),
+				quiet_hours: None,
+				delivery_mode: DeliveryMode::Immediate,
 			}
 		);
+
+		// Update the language
+		let update_data = UpdateData {
+			email: Some("dummy@mail.com".to_string()),
+			tg_handle: Some("@dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			language: Some("pt_BR".to_string()),
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::BadRequest);
+
+		let update_data = UpdateData { language: Some("pt-BR".to_string()), ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		// The language is kept if it is not passed
+		let update_data = UpdateData { language: None, ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(parse_ok_response(response).language, "pt-BR".to_string());
+
+		// Update the time zone and quiet hours
+		let update_data =
+			UpdateData { timezone: Some("Mars/Olympus".to_string()), ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::BadRequest);
+
+		let update_data = UpdateData {
+			timezone: Some("Europe/Berlin".to_string()),
+			quiet_hours: Some(QuietHours { start: 22, end: 24 }),
+			..update_data.clone()
+		};
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::BadRequest);
+
+		let update_data = UpdateData {
+			quiet_hours: Some(QuietHours { start: 22, end: 7 }),
+			..update_data.clone()
+		};
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.timezone, "Europe/Berlin".to_string());
+		assert_eq!(user.quiet_hours, Some(QuietHours { start: 22, end: 7 }));
+
+		// The time zone is kept if it is not passed, the quiet hours are turned off.
+		let update_data = UpdateData { timezone: None, quiet_hours: None, ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		let user = parse_ok_response(response);
+		assert_eq!(user.timezone, "Europe/Berlin".to_string());
+		assert_eq!(user.quiet_hours, None);
+
+		// Switch to daily digests, the delivery mode is kept if it is not passed.
+		let update_data =
+			UpdateData { delivery_mode: Some(DeliveryMode::DailyDigest), ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let update_data = UpdateData { delivery_mode: None, ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(parse_ok_response(response).delivery_mode, DeliveryMode::DailyDigest);
+
+		// Add a Slack channel, which requires a valid webhook.
+		let update_data = UpdateData {
+			slack_webhook: Some("https://hooks.slack.com/services/T000/B000".to_string()),
+			..update_data.clone()
+		};
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::BadRequest);
+
+		let update_data = UpdateData {
+			slack_webhook: Some("https://hooks.slack.com/services/T000/B000/XXXX".to_string()),
+			..update_data.clone()
+		};
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(
+			parse_ok_response(response).channels(),
+			vec![Notifier::Email, Notifier::Telegram, Notifier::Slack]
+		);
+
+		// Removing the Telegram handle removes the channel.
+		let update_data = UpdateData { tg_handle: None, ..update_data.clone() };
+		let response = update(&client, 0, &update_data);
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client.get("/user").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(parse_ok_response(response).channels(), vec![Notifier::Email, Notifier::Slack]);
+	})
+}
+
+#[test]
+fn patching_users_only_changes_the_given_fields() {
+	execute_with(PATCH_DB_PATH, || {
+		let conn = init_db(PATCH_DB_PATH).unwrap();
+		let mailer = MockMailer::default();
+		let verification = EmailVerification {
+			secret: "secret".to_string(),
+			api_url: Some("https://api.example.com/".to_string()),
+			mailer: Some(Box::new(mailer.clone())),
+		};
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification)
+			.manage(contacts())
+			.mount("/", routes![register_user, user, patch_user]);
+		let client = Client::tracked(rocket).expect("failed to create client");
+
+		let mut data = RegistrationData {
+			email: None,
+			tg_handle: Some("@dummy".to_string()),
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: Some(QuietHours { start: 22, end: 7 }),
+			delivery_mode: None,
+		};
+		assert_eq!(register(&client, 0, &data).status(), Status::Ok);
+		data.email = Some("other@example.com".to_string());
+		data.tg_handle = Some("@other".to_string());
+		assert_eq!(register(&client, 1, &data).status(), Status::Ok);
+
+		let patch = |body: &str| {
+			client
+				.patch("/user")
+				.header(ContentType::JSON)
+				.header(sign_in(&client, 0))
+				.body(body.to_string())
+				.dispatch()
+		};
+		let query =
+			|| parse_ok_response(client.get("/user").header(sign_in(&client, 0)).dispatch());
+		let sent = || mailer.sent.lock().unwrap().len();
+
+		// CASE 1: absent fields are kept, and a new email address has to be confirmed.
+		assert_eq!(patch(r#"{"email": "dummy@example.com"}"#).status(), Status::Ok);
+		let patched = query();
+		assert_eq!(patched.email, Some("dummy@example.com".to_string()));
+		assert_eq!(patched.tg_handle, Some("@dummy".to_string()));
+		assert_eq!(patched.quiet_hours, Some(QuietHours { start: 22, end: 7 }));
+		assert_eq!(sent(), 2);
+
+		// CASE 2: unchanged addresses aren't confirmed again.
+		let body = r#"{"email": "dummy@example.com", "language": "de"}"#;
+		assert_eq!(patch(body).status(), Status::Ok);
+		assert_eq!(query().language, "de".to_string());
+		assert_eq!(sent(), 2);
+
+		// CASE 3: null clears a field.
+		assert_eq!(patch(r#"{"tgHandle": null, "quietHours": null}"#).status(), Status::Ok);
+		let patched = query();
+		assert_eq!(patched.tg_handle, None);
+		assert_eq!(patched.quiet_hours, None);
+		assert_eq!(patched.email, Some("dummy@example.com".to_string()));
+
+		// CASE 4: addresses of other users can't be taken.
+		for body in [r#"{"email": "other@example.com"}"#, r#"{"tgHandle": "@other"}"#] {
+			let response = patch(body);
+			assert_eq!(response.status(), Status::Conflict);
+			assert_eq!(parse_err_response(response), Error::NotifierNotUnique);
+		}
+
+		// CASE 5: values are validated.
+		let response = patch(r#"{"matrixRoom": "room"}"#);
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidMatrixRoom);
+		assert_eq!(query().matrix_room, None);
+
+		// CASE 6: addresses are validated like on registration.
+		for (body, error) in [
+			(r#"{"email": "dummy"}"#, Error::InvalidEmail),
+			(r#"{"email": "dummy @example.com"}"#, Error::InvalidEmail),
+			(r#"{"tgHandle": "@du"}"#, Error::InvalidTelegramHandle),
+			(r#"{"tgHandle": "@dummy/other"}"#, Error::InvalidTelegramHandle),
+		] {
+			let response = patch(body);
+			assert_eq!(response.status(), Status::BadRequest);
+			assert_eq!(parse_err_response(response), error);
+		}
+		assert_eq!(query().email, Some("dummy@example.com".to_string()));
 	})
 }
 
-fn register<'a>(client: &'a Client, data: &'a RegistrationData) -> LocalResponse<'a> {
+fn register<'a>(client: &'a Client, account: u32, data: &'a RegistrationData) -> LocalResponse<'a> {
 	client
 		.post("/register_user")
 		.header(ContentType::JSON)
+		.header(sign_in(client, account))
 		.body(serde_json::to_string(&data).unwrap())
 		.dispatch()
 }
 
-fn update<'a>(client: &'a Client, data: &'a UpdateData) -> LocalResponse<'a> {
+fn update<'a>(client: &'a Client, account: u32, data: &'a UpdateData) -> LocalResponse<'a> {
 	client
 		.put("/update_user")
 		.header(ContentType::JSON)
+		.header(sign_in(client, account))
 		.body(serde_json::to_string(&data).unwrap())
 		.dispatch()
 }
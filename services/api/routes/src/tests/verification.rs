@@ -0,0 +1,188 @@
+use crate::{
+	errors::Error,
+	register::{register_user, EnabledNotification, RegistrationData},
+	tests::mock::{contacts, execute_with, parse_err_response, sign_in, verification, MockMailer},
+	update::{update_user, UpdateData},
+	verification::{verify_email, EmailVerification},
+};
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::Client,
+	routes,
+};
+use storage::{channels, init_db, users::User};
+use types::{DeliveryMode, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "verification-tests.db";
+pub const BACKLOG_DB_PATH: &'static str = "verification-backlog-tests.db";
+
+#[test]
+fn emails_are_verified_through_the_sent_link() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let mailer = MockMailer::default();
+		let verification = EmailVerification {
+			secret: "secret".to_string(),
+			api_url: Some("https://api.example.com/".to_string()),
+			mailer: Some(Box::new(mailer.clone())),
+		};
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification)
+			.manage(contacts())
+			.mount("/", routes![register_user, update_user, verify_email]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let is_verified = || {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			channels::is_verified(&conn, 0, &Notifier::Email).unwrap()
+		};
+		let last_link = || {
+			let sent = mailer.sent.lock().unwrap();
+			let (_, message) = sent.last().unwrap();
+			let url = message.action.as_ref().unwrap().url.clone();
+			url.strip_prefix("https://api.example.com").unwrap().to_string()
+		};
+		let update = |email: &str| {
+			let data = UpdateData {
+				email: Some(email.to_string()),
+				tg_handle: None,
+				discord_webhook: None,
+				slack_webhook: None,
+				matrix_room: None,
+				webhook_url: None,
+				language: None,
+				timezone: None,
+				quiet_hours: None,
+				delivery_mode: None,
+			};
+			let response = client
+				.put("/update_user")
+				.header(ContentType::JSON)
+				.header(sign_in(&client, 0))
+				.body(serde_json::to_string(&data).unwrap())
+				.dispatch();
+			assert_eq!(response.status(), Status::Ok);
+		};
+
+		// CASE 1: registering sends a link to the email address.
+		let registration_data = RegistrationData {
+			email: Some("alice@mail.com".to_string()),
+			tg_handle: None,
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			enabled_notifications: vec![EnabledNotification::All(Notifications::CoretimeSale)],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&registration_data).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		assert_eq!(mailer.sent.lock().unwrap()[0].0, "alice@mail.com");
+		assert!(last_link().starts_with("/verify_email/0."));
+		assert!(!is_verified());
+
+		// CASE 2: invalid and tampered tokens are rejected.
+		let response = client.get("/verify_email/invalid").dispatch();
+		assert_eq!(response.status(), Status::BadRequest);
+		assert_eq!(parse_err_response(response), Error::InvalidVerificationToken);
+
+		let tampered = last_link().replacen("/verify_email/0.", "/verify_email/1.", 1);
+		let response = client.get(tampered).dispatch();
+		assert_eq!(response.status(), Status::BadRequest);
+		assert!(!is_verified());
+
+		// CASE 3: expired tokens are rejected.
+		let token = EmailVerification { secret: "secret".to_string(), api_url: None, mailer: None }
+			.token(0, "alice@mail.com", 1);
+		let response = client.get(format!("/verify_email/{}", token)).dispatch();
+		assert_eq!(response.status(), Status::Gone);
+		assert_eq!(parse_err_response(response), Error::VerificationTokenExpired);
+
+		// CASE 4: opening the link verifies the address.
+		let link = last_link();
+		let response = client.get(&link).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert!(is_verified());
+
+		// CASE 5: updating a verified address doesn't send another link.
+		update("alice@mail.com");
+		assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+
+		// CASE 6: a new address has to be verified again, links to the previous one are invalid.
+		update("bob@mail.com");
+		assert!(!is_verified());
+		assert_eq!(mailer.sent.lock().unwrap()[1].0, "bob@mail.com");
+
+		let response = client.get(&link).dispatch();
+		assert_eq!(response.status(), Status::BadRequest);
+		assert!(!is_verified());
+
+		let response = client.get(last_link()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		assert!(is_verified());
+	})
+}
+
+#[rocket::async_test]
+async fn addresses_registered_before_verification_are_sent_a_link_once() {
+	execute_with(BACKLOG_DB_PATH, || async {
+		let conn = init_db(BACKLOG_DB_PATH).unwrap();
+		{
+			let conn = conn.lock().unwrap();
+			for (id, email) in [(0, "alice@mail.com"), (1, "bob@mail.com")] {
+				let user = User {
+					id,
+					email: Some(email.to_string()),
+					tg_handle: None,
+					discord_webhook: None,
+					slack_webhook: None,
+					matrix_room: None,
+					webhook_url: None,
+					language: "en".to_string(),
+					timezone: "UTC".to_string(),
+					quiet_hours: None,
+					delivery_mode: DeliveryMode::Immediate,
+				};
+				User::create_user(&conn, &user).unwrap();
+			}
+			// As if both addresses were registered before addresses were verified.
+			conn.execute_batch(
+				"INSERT INTO verification_backlog (user_id) VALUES (0);
+				INSERT INTO verification_backlog (user_id) VALUES (1);",
+			)
+			.unwrap();
+			channels::verify(&conn, 1, &Notifier::Email, "bob@mail.com", 0).unwrap();
+		}
+
+		// CASE 1: nothing is sent while email isn't configured.
+		verification().send_backlog(&conn).await;
+
+		// CASE 2: addresses which weren't verified in the meantime are sent a link.
+		let mailer = MockMailer::default();
+		let verification = EmailVerification {
+			secret: "secret".to_string(),
+			api_url: Some("https://api.example.com".to_string()),
+			mailer: Some(Box::new(mailer.clone())),
+		};
+		verification.send_backlog(&conn).await;
+		let sent = mailer.sent.lock().unwrap().clone();
+		assert_eq!(sent.len(), 1);
+		assert_eq!(sent[0].0, "alice@mail.com");
+		assert!(sent[0].1.action.as_ref().unwrap().url.contains("/verify_email/0."));
+
+		// CASE 3: the links are only sent once.
+		verification.send_backlog(&conn).await;
+		assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+	})
+	.await
+}
@@ -0,0 +1,103 @@
+use crate::{
+	errors::Error,
+	register::{register_user, RegistrationData},
+	tests::mock::{contacts, execute_with, parse_err_response, sign_in, verification},
+	webhooks::{rotate_webhook_secret, webhook_secret},
+};
+use rocket::{
+	http::{ContentType, Status},
+	local::blocking::{Client, LocalResponse},
+	routes,
+};
+use storage::{init_db, webhooks::WebhookSecret};
+use types::api;
+
+pub const DB_PATH: &'static str = "webhook-tests.db";
+
+#[test]
+fn webhook_secrets_can_be_rotated() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let rocket = rocket::build()
+			.manage(conn)
+			.manage(verification())
+			.manage(contacts())
+			.mount("/", routes![register_user, webhook_secret, rotate_webhook_secret]);
+		let client = Client::tracked(rocket).expect("failed to create a client");
+
+		let registration_data = RegistrationData {
+			email: None,
+			tg_handle: None,
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: Some("https://example.com/hooks".to_string()),
+			enabled_notifications: vec![],
+			language: None,
+			timezone: None,
+			quiet_hours: None,
+			delivery_mode: None,
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 0))
+			.body(serde_json::to_string(&registration_data).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		// CASE 1: a secret is created when the webhook is registered.
+		let response = client.get("/user/webhook_secret").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let secret = parse_secret(response);
+		assert_eq!(secret.len(), 64);
+
+		// CASE 2: rotating replaces the secret and keeps the previous one.
+		let response = client
+			.post("/user/webhook_secret/rotate")
+			.header(sign_in(&client, 0))
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		let rotated = parse_secret(response);
+		assert_ne!(rotated, secret);
+
+		let response = client.get("/user/webhook_secret").header(sign_in(&client, 0)).dispatch();
+		assert_eq!(parse_secret(response), rotated);
+
+		let stored = {
+			let conn = client.rocket().state::<storage::DbConn>().unwrap().lock().unwrap();
+			WebhookSecret::query_by_user(&conn, 0).unwrap().unwrap()
+		};
+		assert_eq!(stored.previous_secret, Some(secret));
+
+		// CASE 3: users without a webhook don't have a secret.
+		let registration_data = RegistrationData {
+			email: Some("dummy@mail.com".to_string()),
+			webhook_url: None,
+			..registration_data
+		};
+		let response = client
+			.post("/register_user")
+			.header(ContentType::JSON)
+			.header(sign_in(&client, 1))
+			.body(serde_json::to_string(&registration_data).unwrap())
+			.dispatch();
+		assert_eq!(response.status(), Status::Ok);
+
+		let response = client
+			.post("/user/webhook_secret/rotate")
+			.header(sign_in(&client, 1))
+			.dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::WebhookNotConfigured);
+
+		let response = client.get("/user/webhook_secret").header(sign_in(&client, 2)).dispatch();
+		assert_eq!(response.status(), Status::NotFound);
+		assert_eq!(parse_err_response(response), Error::UserNotFound);
+	})
+}
+
+fn parse_secret(response: LocalResponse) -> String {
+	let body = response.into_string().unwrap();
+	serde_json::from_str::<api::WebhookSecret>(&body).unwrap().secret
+}
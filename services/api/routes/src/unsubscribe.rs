@@ -0,0 +1,118 @@
+//! ## Unsubscribe Routes
+//!
+//! Notifications link to `/unsubscribe/<token>`, which unsubscribes the recipient from the
+//! subscription the notification was delivered for, or from all subscriptions of the recipient,
+//! see [`notification::unsubscribe`]. The signed token is the only credential required.
+//!
+//! Opening the link with `GET` doesn't change anything, since mail scanners and link previews
+//! open links too. It returns a page on which the recipient confirms, which posts to the same
+//! link. Mail clients which support the one-click unsubscription of RFC 8058 post to the link
+//! right away. Unsubscribing again succeeds, and every unsubscription is recorded in the audit log
+//! of the user, see [`storage::audit`].
+
+use crate::{
+	errors::{custom_error, Error},
+	pages, LOG_TARGET,
+};
+use notification::unsubscribe::Scope;
+pub use notification::unsubscribe::UnsubscribeTokens;
+use rocket::{
+	form::Form,
+	get,
+	http::Status,
+	post,
+	response::{content::RawHtml, status},
+	serde::json::Json,
+	FromForm, State,
+};
+use storage::{audit::AuditEntry, subscriptions::Subscription, users::User, DbConn};
+use types::api::ErrorResponse;
+
+/// The body mail clients post for the one-click unsubscription of RFC 8058, which is
+/// `List-Unsubscribe=One-Click`.
+#[derive(FromForm)]
+pub struct OneClick<'r> {
+	#[field(name = "List-Unsubscribe")]
+	list_unsubscribe: &'r str,
+}
+
+/// Returns the page on which the recipient confirms the unsubscription.
+#[get("/unsubscribe/<token>")]
+pub async fn unsubscribe_link(
+	tokens: &State<UnsubscribeTokens>,
+	token: &str,
+) -> Result<RawHtml<String>, status::Custom<Json<ErrorResponse>>> {
+	let (_, scope) = tokens
+		.verify(token)
+		.ok_or(custom_error(Status::BadRequest, Error::InvalidUnsubscribeToken))?;
+
+	let question = match scope {
+		Scope::All => "Do you want to unsubscribe from all notifications?",
+		Scope::Notification(_) => "Do you want to unsubscribe from these notifications?",
+	};
+	Ok(pages::confirmation("Unsubscribe", question, "Unsubscribe"))
+}
+
+/// Unsubscribes, either from the confirmation page or through the one-click unsubscription of
+/// RFC 8058.
+#[post("/unsubscribe/<token>", data = "<one_click>")]
+pub async fn unsubscribe_one_click(
+	conn: &State<DbConn>,
+	tokens: &State<UnsubscribeTokens>,
+	token: &str,
+	one_click: Option<Form<OneClick<'_>>>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let one_click = one_click.is_some_and(|form| form.list_unsubscribe == "One-Click");
+	unsubscribe(conn, tokens, token, one_click)
+}
+
+fn unsubscribe(
+	conn: &DbConn,
+	tokens: &UnsubscribeTokens,
+	token: &str,
+	one_click: bool,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let (user_id, scope) = tokens
+		.verify(token)
+		.ok_or(custom_error(Status::BadRequest, Error::InvalidUnsubscribeToken))?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+	let db_error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to unsubscribe: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	let user = User::query_by_id(&conn, user_id).map_err(db_error)?;
+	let user = user.ok_or(custom_error(Status::NotFound, Error::UserNotFound))?;
+
+	let notifications = match scope {
+		Scope::All => Subscription::query_by_user(&conn, user.id)
+			.map_err(db_error)?
+			.into_iter()
+			.map(|subscription| subscription.notification)
+			.collect(),
+		Scope::Notification(notification) => vec![notification],
+	};
+
+	let mut removed = vec![];
+	for notification in notifications {
+		if Subscription::remove(&conn, user.id, &notification).map_err(db_error)? {
+			removed.push(notification);
+		}
+	}
+	let details = serde_json::json!({ "removed": removed, "oneClick": one_click });
+	AuditEntry::record(
+		&conn,
+		user.id,
+		"unsubscribe",
+		&details.to_string(),
+		notification::timestamp(),
+	)
+	.map_err(db_error)?;
+
+	log::info!(target: LOG_TARGET, "User {} unsubscribed from {:?}", user.id, removed);
+	Ok(status::Custom(Status::Ok, ()))
+}
@@ -2,106 +2,389 @@
 //!
 //! Update route should handle updating the information of the existing user.
 //! A user is allowed to update the following information:
-//! - User's notifier (set to null to disable notifications)
 //! - User's email address
 //! - User's telegram handle
-//! - User's enabled notifications
+//! - User's Discord and Slack webhooks
+//! - User's Matrix room
+//! - User's webhook url
+//! - User's language, time zone and quiet hours
+//! - User's delivery mode
 //!
-//! We ensure the user exists before validating.
-//! If the ID exists, then it can be validated.
+//! Every address enables a channel. Setting an address to null removes the channel, and with it
+//! the delivery of notifications through it. A new email address has to be verified, see
+//! [`crate::verification`].
 //!
-//! How can we ensure that the owner of the email | tg is making the update?
+//! `PUT /update_user` replaces the information, so values which should stay the same have to be
+//! passed again. `PATCH /user` only changes the fields present in the request, see [`Patch`].
+//!
+//! The routes update the user bound to the account of the session, see [`crate::auth`]. The
+//! subscriptions of the user are managed through [`crate::subscriptions`].
 
 use crate::{
+	auth::{session_user, Authenticated, WRITE},
 	errors::{custom_error, Error},
-	update, LOG_TARGET,
+	register::{
+		normalize_email, normalize_tg_handle, validate_discord_webhook, validate_email,
+		validate_language, validate_matrix_room, validate_slack_webhook, validate_tg_handle,
+		validate_timezone, validate_webhook_url,
+	},
+	update,
+	verification::EmailVerification,
+	webhooks::ensure_webhook_secret,
+	LOG_TARGET,
 };
 
 use common_macros::ensure;
-use rocket::{http::Status, put, response::status, serde::json::Json, State};
-use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
-use storage::{users::User, DbConn};
-use types::{api::ErrorResponse, Notifier};
+use rocket::{http::Status, patch, put, response::status, serde::json::Json, State};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use storage::{channels, users::User, DbConn};
+use types::{api::ErrorResponse, DeliveryMode, Notifier, QuietHours};
 
 // If there is data that should not be updated, then pass current value.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(crate = "rocket::serde")]
 pub struct UpdateData {
-	// The ID of the user to update
-	pub id: u32,
 	// The email address to update to,
 	pub email: Option<String>,
 	#[serde(rename = "tgHandle")]
 	// The telegram handle to update to
 	pub tg_handle: Option<String>,
-	// The desired notifier to use.
-	// If undefined, notifications will be turned off for user
-	// Pass current value if not to be updated
-	pub notifier: Option<Notifier>,
+	/// The Discord webhook to update to.
+	#[serde(default, rename = "discordWebhook")]
+	pub discord_webhook: Option<String>,
+	/// The Slack webhook to update to.
+	#[serde(default, rename = "slackWebhook")]
+	pub slack_webhook: Option<String>,
+	/// The Matrix room to update to.
+	#[serde(default, rename = "matrixRoom")]
+	pub matrix_room: Option<String>,
+	/// The webhook url to update to.
+	#[serde(default, rename = "webhookUrl")]
+	pub webhook_url: Option<String>,
+	/// The language of the notifications. Stays the same if undefined.
+	#[serde(default)]
+	pub language: Option<String>,
+	/// The IANA time zone of the user. Stays the same if undefined.
+	#[serde(default)]
+	pub timezone: Option<String>,
+	/// The quiet hours of the user.
+	/// If undefined, the user receives notifications at any time.
+	#[serde(default, rename = "quietHours")]
+	pub quiet_hours: Option<QuietHours>,
+	/// Whether notifications are sent right away or collected into digests. Stays the same if
+	/// undefined.
+	#[serde(default, rename = "deliveryMode")]
+	pub delivery_mode: Option<DeliveryMode>,
 }
 
 impl UpdateData {
+	/// Brings the addresses into the form they are stored in.
+	fn normalize(&mut self) {
+		self.email = self.email.as_deref().map(normalize_email);
+		self.tg_handle = self.tg_handle.as_deref().map(normalize_tg_handle);
+	}
+
 	fn validate(&self) -> Result<(), Error> {
-		// Ensure the configured notifier is set.
-		match &self.notifier {
-			Some(Notifier::Email) if self.email.is_none() => Err(Error::NotifierEmpty),
-			Some(Notifier::Telegram) if self.tg_handle.is_none() => Err(Error::NotifierEmpty),
-			_ => Ok(()),
+		if let Some(email) = &self.email {
+			validate_email(email)?;
+		}
+		if let Some(handle) = &self.tg_handle {
+			validate_tg_handle(handle)?;
+		}
+		if let Some(language) = &self.language {
+			validate_language(language)?;
+		}
+		if let Some(timezone) = &self.timezone {
+			validate_timezone(timezone)?;
+		}
+		if let Some(quiet_hours) = &self.quiet_hours {
+			ensure!(quiet_hours.is_valid(), Error::InvalidQuietHours);
+		}
+		if let Some(webhook) = &self.discord_webhook {
+			validate_discord_webhook(webhook)?;
+		}
+		if let Some(webhook) = &self.slack_webhook {
+			validate_slack_webhook(webhook)?;
+		}
+		if let Some(room) = &self.matrix_room {
+			validate_matrix_room(room)?;
 		}
+		if let Some(url) = &self.webhook_url {
+			validate_webhook_url(url)?;
+		}
+
+		Ok(())
 	}
 }
 
 #[put("/update_user", data = "<update_data>")]
 pub async fn update_user(
 	conn: &State<DbConn>,
-	update_data: Json<UpdateData>,
+	verification: &State<EmailVerification>,
+	auth: Result<Authenticated, Error>,
+	mut update_data: Json<UpdateData>,
 ) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
 	// Get data to update and serialize
 	// Update the data
 	log::info!(target: LOG_TARGET, "Update user request {:?}", update_data);
 
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
 	// validate the data passed
+	update_data.normalize();
 	update_data.validate().map_err(|err| custom_error(Status::BadRequest, err))?;
 
-	// Get connection:
-	let conn = conn.lock().map_err(|err| {
-		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
-		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
-	})?;
-
-	// Ensure user exists
-	let db_user = verify_existing_id(&conn, update_data.id.clone())?;
-
-	let user = User {
-		email: update_data.email.clone(),
-		tg_handle: update_data.tg_handle.clone(),
-		id: update_data.id.clone(),
-		notifier: if update_data.notifier.clone().is_some() {
-			update_data.notifier.clone().unwrap()
-		} else {
-			db_user.notifier
-		},
+	// The connection is released before the confirmation link is sent.
+	let (user, verified) = {
+		// Get connection:
+		let conn = conn.lock().map_err(|err| {
+			log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+		})?;
+
+		// Ensure user exists
+		let db_user = session_user(&conn, &auth)?;
+
+		let user = User {
+			email: update_data.email.clone(),
+			tg_handle: update_data.tg_handle.clone(),
+			discord_webhook: update_data.discord_webhook.clone(),
+			slack_webhook: update_data.slack_webhook.clone(),
+			matrix_room: update_data.matrix_room.clone(),
+			webhook_url: update_data.webhook_url.clone(),
+			id: db_user.id,
+			language: update_data.language.clone().unwrap_or(db_user.language),
+			timezone: update_data.timezone.clone().unwrap_or(db_user.timezone),
+			quiet_hours: update_data.quiet_hours,
+			delivery_mode: update_data.delivery_mode.unwrap_or(db_user.delivery_mode),
+		};
+		if user.webhook_url.is_some() {
+			ensure_webhook_secret(&conn, user.id)?;
+		}
+
+		User::update(&conn, &user)
+			.map_err(|_| custom_error(Status::InternalServerError, Error::DbError))?;
+
+		// A changed address has to be verified again.
+		let verified = channels::is_verified(&conn, user.id, &Notifier::Email).map_err(|err| {
+			log::error!(target: LOG_TARGET, "Failed to query email verification: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		})?;
+
+		(user, verified)
 	};
-	let result = User::update(&conn, &user);
 
-	match result {
-		Ok(_) => Ok(status::Custom(Status::Ok, ())),
-		Err(_) => Err(custom_error(Status::InternalServerError, Error::DbError)),
+	match &user.email {
+		Some(email) if !verified => verification.send_link(user.id, email).await,
+		_ => {},
+	}
+
+	Ok(status::Custom(Status::Ok, ()))
+}
+
+/// A field of a partial update.
+///
+/// An absent field keeps the current value, `null` clears it and any other value replaces it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Patch<T> {
+	#[default]
+	Keep,
+	Clear,
+	Set(T),
+}
+
+impl<T> Patch<T> {
+	pub fn is_keep(&self) -> bool {
+		matches!(self, Patch::Keep)
+	}
+
+	/// Returns the value after applying the patch to the current one.
+	pub fn apply(self, current: Option<T>) -> Option<T> {
+		match self {
+			Patch::Keep => current,
+			Patch::Clear => None,
+			Patch::Set(value) => Some(value),
+		}
+	}
+
+	fn value(&self) -> Option<&T> {
+		match self {
+			Patch::Set(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+// Absent fields are handled through `#[serde(default)]`, so only `null` and values are left.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		Ok(match Option::<T>::deserialize(deserializer)? {
+			Some(value) => Patch::Set(value),
+			None => Patch::Clear,
+		})
+	}
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			Patch::Set(value) => serializer.serialize_some(value),
+			_ => serializer.serialize_none(),
+		}
+	}
+}
+
+/// The fields of the user to change. Absent fields stay the same.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PatchData {
+	#[serde(default, skip_serializing_if = "Patch::is_keep")]
+	pub email: Patch<String>,
+	#[serde(default, rename = "tgHandle", skip_serializing_if = "Patch::is_keep")]
+	pub tg_handle: Patch<String>,
+	#[serde(default, rename = "discordWebhook", skip_serializing_if = "Patch::is_keep")]
+	pub discord_webhook: Patch<String>,
+	#[serde(default, rename = "slackWebhook", skip_serializing_if = "Patch::is_keep")]
+	pub slack_webhook: Patch<String>,
+	#[serde(default, rename = "matrixRoom", skip_serializing_if = "Patch::is_keep")]
+	pub matrix_room: Patch<String>,
+	#[serde(default, rename = "webhookUrl", skip_serializing_if = "Patch::is_keep")]
+	pub webhook_url: Patch<String>,
+	/// The language of the notifications. Can't be cleared.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub language: Option<String>,
+	/// The IANA time zone of the user. Can't be cleared.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timezone: Option<String>,
+	/// Clearing the quiet hours lets the user receive notifications at any time.
+	#[serde(default, rename = "quietHours", skip_serializing_if = "Patch::is_keep")]
+	pub quiet_hours: Patch<QuietHours>,
+	/// Whether notifications are sent right away or collected into digests. Can't be cleared.
+	#[serde(default, rename = "deliveryMode", skip_serializing_if = "Option::is_none")]
+	pub delivery_mode: Option<DeliveryMode>,
+}
+
+impl PatchData {
+	/// Brings the addresses into the form they are stored in.
+	fn normalize(&mut self) {
+		if let Patch::Set(email) = &mut self.email {
+			*email = normalize_email(email);
+		}
+		if let Patch::Set(handle) = &mut self.tg_handle {
+			*handle = normalize_tg_handle(handle);
+		}
+	}
+
+	fn validate(&self) -> Result<(), Error> {
+		if let Some(email) = self.email.value() {
+			validate_email(email)?;
+		}
+		if let Some(handle) = self.tg_handle.value() {
+			validate_tg_handle(handle)?;
+		}
+		if let Some(language) = &self.language {
+			validate_language(language)?;
+		}
+		if let Some(timezone) = &self.timezone {
+			validate_timezone(timezone)?;
+		}
+		if let Some(quiet_hours) = self.quiet_hours.value() {
+			ensure!(quiet_hours.is_valid(), Error::InvalidQuietHours);
+		}
+		if let Some(webhook) = self.discord_webhook.value() {
+			validate_discord_webhook(webhook)?;
+		}
+		if let Some(webhook) = self.slack_webhook.value() {
+			validate_slack_webhook(webhook)?;
+		}
+		if let Some(room) = self.matrix_room.value() {
+			validate_matrix_room(room)?;
+		}
+		if let Some(url) = self.webhook_url.value() {
+			validate_webhook_url(url)?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the user after applying the changes.
+	fn apply(&self, user: &User) -> User {
+		User {
+			id: user.id,
+			email: self.email.clone().apply(user.email.clone()),
+			tg_handle: self.tg_handle.clone().apply(user.tg_handle.clone()),
+			discord_webhook: self.discord_webhook.clone().apply(user.discord_webhook.clone()),
+			slack_webhook: self.slack_webhook.clone().apply(user.slack_webhook.clone()),
+			matrix_room: self.matrix_room.clone().apply(user.matrix_room.clone()),
+			webhook_url: self.webhook_url.clone().apply(user.webhook_url.clone()),
+			language: self.language.clone().unwrap_or(user.language.clone()),
+			timezone: self.timezone.clone().unwrap_or(user.timezone.clone()),
+			quiet_hours: self.quiet_hours.clone().apply(user.quiet_hours),
+			delivery_mode: self.delivery_mode.unwrap_or(user.delivery_mode),
+		}
 	}
 }
 
-fn verify_existing_id(
-	conn: &Connection,
-	user_id: u32,
-) -> Result<User, status::Custom<Json<ErrorResponse>>> {
-	let maybe_user = User::query_by_id(&conn, user_id).map_err(|err| {
-		log::error!(target: LOG_TARGET, "Failed to search user by id: {:?}", err);
-		custom_error(Status::InternalServerError, Error::DbError)
-	})?;
-
-	match maybe_user {
-		Some(user) => Ok(user),
-		None => Err(custom_error(Status::NotFound, Error::UserNotFound)),
+#[patch("/user", data = "<patch_data>")]
+pub async fn patch_user(
+	conn: &State<DbConn>,
+	verification: &State<EmailVerification>,
+	auth: Result<Authenticated, Error>,
+	mut patch_data: Json<PatchData>,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	patch_data.normalize();
+	patch_data.validate().map_err(|err| custom_error(Status::BadRequest, err))?;
+
+	// The connection is released before the confirmation link is sent.
+	let (user, send_link) = {
+		let conn = conn.lock().map_err(|err| {
+			log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+		})?;
+		let db_error = |err| {
+			log::error!(target: LOG_TARGET, "Failed to patch user: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		};
+
+		let db_user = session_user(&conn, &auth)?;
+		let user = patch_data.apply(&db_user);
+
+		// An address which changes can't belong to another user.
+		let error = custom_error(Status::Conflict, Error::NotifierNotUnique);
+		if let Some(email) = user.email.clone().filter(|_| user.email != db_user.email) {
+			let other = User::query_by_email(&conn, email).map_err(db_error)?;
+			ensure!(other.map_or(true, |other| other.id == user.id), error);
+		}
+		if let Some(tg_handle) =
+			user.tg_handle.clone().filter(|_| user.tg_handle != db_user.tg_handle)
+		{
+			let other = User::query_by_tg_handle(&conn, tg_handle).map_err(db_error)?;
+			ensure!(other.map_or(true, |other| other.id == user.id), error);
+		}
+
+		if user.webhook_url.is_some() {
+			ensure_webhook_secret(&conn, user.id)?;
+		}
+		User::update(&conn, &user).map_err(|err| {
+			log::error!(target: LOG_TARGET, "Failed to patch user: {:?}", err);
+			custom_error(Status::InternalServerError, Error::DbError)
+		})?;
+
+		// Changed addresses lose their verification, a new email address is confirmed again.
+		let verified = channels::is_verified(&conn, user.id, &Notifier::Email).map_err(db_error)?;
+		let email_changed = user.email != db_user.email;
+		(user, email_changed && !verified)
+	};
+
+	log::info!(target: LOG_TARGET, "Patched user {}", user.id);
+	match &user.email {
+		Some(email) if send_link => verification.send_link(user.id, email).await,
+		_ => {},
 	}
+
+	Ok(status::Custom(Status::Ok, ()))
 }
@@ -0,0 +1,193 @@
+//! ## Email Verification Routes
+//!
+//! Notifications are only delivered to verified email addresses, so that nobody can subscribe the
+//! inbox of someone else. Whenever a user is registered or updated with an email address that
+//! isn't verified yet, a confirmation link is sent to it. Opening the link verifies the address.
+//! Updating a user with the same unverified address sends a new link.
+//!
+//! The token in the link is signed with the verification secret and expires after
+//! [`TOKEN_LIFETIME`]. It is bound to the address it was sent to, so it can't verify any other
+//! address of the user.
+//!
+//! Addresses which were registered before addresses were verified are sent a confirmation link
+//! once the api is started, see [`EmailVerification::send_backlog`].
+
+use crate::{
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use common_macros::ensure;
+use hmac::{Hmac, Mac};
+use notification::{
+	channels::{email::EmailChannel, Channel},
+	message::{Action, Message},
+};
+use rocket::{get, http::Status, response::status, serde::json::Json, State};
+use sha2::Sha256;
+use std::env;
+use storage::{channels, users::User, DbConn};
+use types::{api::ErrorResponse, Notifier, Timestamp};
+
+/// How long a confirmation link is valid, in seconds.
+pub const TOKEN_LIFETIME: u64 = 24 * 60 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct EmailVerification {
+	/// The secret with which the tokens are signed.
+	pub secret: String,
+	/// The public url of the api, under which the links are opened.
+	pub api_url: Option<String>,
+	/// The channel through which the links are sent.
+	pub mailer: Option<Box<dyn Channel>>,
+}
+
+impl EmailVerification {
+	/// Reads the configuration from `EMAIL_VERIFICATION_SECRET` and `PUBLIC_API_URL`. The links
+	/// are sent through the email channel, if it is configured.
+	///
+	/// Fails if the secret isn't set, since links signed with a random secret would stop working
+	/// after a restart, and links signed with an empty one could be forged.
+	pub fn from_env() -> Result<Self, env::VarError> {
+		let secret = match env::var("EMAIL_VERIFICATION_SECRET")? {
+			secret if secret.is_empty() => return Err(env::VarError::NotPresent),
+			secret => secret,
+		};
+		let mailer = match EmailChannel::from_env() {
+			Some(Ok(channel)) => Some(Box::new(channel) as Box<dyn Channel>),
+			Some(Err(err)) => {
+				log::error!(target: LOG_TARGET, "Failed to configure email: {}", err);
+				None
+			},
+			None => None,
+		};
+
+		Ok(Self { secret, api_url: env::var("PUBLIC_API_URL").ok(), mailer })
+	}
+
+	/// Returns the token which verifies the address of the user until `expires_at`.
+	pub fn token(&self, user_id: u32, address: &str, expires_at: Timestamp) -> String {
+		let signature = self.mac(user_id, address, expires_at).finalize().into_bytes();
+		format!("{}.{}.{}", user_id, expires_at, hex::encode(signature))
+	}
+
+	/// Sends the link which verifies the address to the user.
+	///
+	/// Failures are only logged, since the user can request another link by updating their
+	/// address.
+	pub(crate) async fn send_link(&self, user_id: u32, address: &str) {
+		let expires_at = notification::timestamp() + TOKEN_LIFETIME;
+		let message = Message::plain(
+			"Confirm your email address",
+			&format!(
+				"Confirm that you want to receive coretime notifications at {}. The link expires \
+					in 24 hours.",
+				address
+			),
+		);
+		let path = format!("/verify_email/{}", self.token(user_id, address, expires_at));
+
+		self.mail_link(address, message, "Confirm email address", &path).await;
+	}
+
+	/// Sends a confirmation link to every address which was registered before addresses were
+	/// verified, and didn't get verified since.
+	///
+	/// This happens once, the addresses are kept until links can be sent. Afterwards their users
+	/// can request another link by updating their address.
+	pub async fn send_backlog(&self, conn: &DbConn) {
+		if self.api_url.is_none() || self.mailer.is_none() {
+			log::warn!(target: LOG_TARGET, "Can't send links to unverified addresses yet");
+			return;
+		}
+
+		let backlog = match conn.lock() {
+			Ok(conn) => channels::take_verification_backlog(&conn),
+			Err(err) => {
+				log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+				return;
+			},
+		};
+		let backlog = match backlog {
+			Ok(backlog) => backlog,
+			Err(err) => {
+				log::error!(target: LOG_TARGET, "Failed to query unverified addresses: {:?}", err);
+				return;
+			},
+		};
+
+		for (user_id, address) in backlog {
+			self.send_link(user_id, &address).await;
+		}
+	}
+
+	/// Mails the message to the address, with a link to the path of the api as its action.
+	///
+	/// Failures are only logged.
+	pub(crate) async fn mail_link(&self, address: &str, message: Message, label: &str, path: &str) {
+		let (Some(api_url), Some(mailer)) = (&self.api_url, &self.mailer) else {
+			log::warn!(target: LOG_TARGET, "Can't send links, email isn't configured");
+			return;
+		};
+
+		let url = format!("{}{}", api_url.trim_end_matches('/'), path);
+		let message = message.with_action(Action { label: label.into(), url });
+
+		if let Err(err) = mailer.send(address, &message).await {
+			log::error!(target: LOG_TARGET, "Failed to send link: {}", err);
+		}
+	}
+
+	fn mac(&self, user_id: u32, address: &str, expires_at: Timestamp) -> HmacSha256 {
+		let mut mac =
+			HmacSha256::new_from_slice(self.secret.as_bytes()).expect("any key length is valid");
+		mac.update(format!("{}.{}.{}", user_id, expires_at, address).as_bytes());
+		mac
+	}
+}
+
+#[get("/verify_email/<token>")]
+pub async fn verify_email(
+	conn: &State<DbConn>,
+	verification: &State<EmailVerification>,
+	token: &str,
+) -> Result<status::Custom<()>, status::Custom<Json<ErrorResponse>>> {
+	let invalid = || custom_error(Status::BadRequest, Error::InvalidVerificationToken);
+
+	let mut parts = token.splitn(3, '.');
+	let (Some(user_id), Some(expires_at), Some(signature)) =
+		(parts.next(), parts.next(), parts.next())
+	else {
+		return Err(invalid());
+	};
+	let user_id: u32 = user_id.parse().map_err(|_| invalid())?;
+	let expires_at: Timestamp = expires_at.parse().map_err(|_| invalid())?;
+	let signature = hex::decode(signature).map_err(|_| invalid())?;
+
+	let now = notification::timestamp();
+	ensure!(expires_at >= now, custom_error(Status::Gone, Error::VerificationTokenExpired));
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user = User::query_by_id(&conn, user_id).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to search user by id: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+	// Links sent to a previous address of the user don't match the current one.
+	let Some(address) = user.and_then(|user| user.email) else { return Err(invalid()) };
+	verification
+		.mac(user_id, &address, expires_at)
+		.verify_slice(&signature)
+		.map_err(|_| invalid())?;
+
+	channels::verify(&conn, user_id, &Notifier::Email, &address, now).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to verify email: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	log::info!(target: LOG_TARGET, "Email of user {} was verified", user_id);
+	Ok(status::Custom(Status::Ok, ()))
+}
@@ -0,0 +1,98 @@
+//! ## Webhook Routes
+//!
+//! Deliveries to the webhook of a user are signed with a secret, which is created once the user
+//! configures a webhook. The user can look up the secret to verify the deliveries, and rotate it
+//! in case it got leaked. After a rotation, deliveries are signed with both the new and the
+//! previous secret for a while, so that integrations can switch over without missing deliveries.
+
+use crate::{
+	auth::{session_user, Authenticated, READ, WRITE},
+	errors::{custom_error, Error},
+	LOG_TARGET,
+};
+use rocket::{get, http::Status, post, response::status, serde::json::Json, State};
+use rusqlite::Connection;
+use storage::{webhooks::WebhookSecret, DbConn};
+use types::api::{self, ErrorResponse};
+
+#[get("/user/webhook_secret")]
+pub async fn webhook_secret(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<Json<api::WebhookSecret>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(READ)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user_id = ensure_webhook_configured(&conn, &auth)?;
+
+	let secret = WebhookSecret::query_by_user(&conn, user_id).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to query webhook secret: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	match secret {
+		Some(secret) => Ok(Json(api::WebhookSecret { secret: secret.secret })),
+		None => Err(custom_error(Status::NotFound, Error::WebhookNotConfigured)),
+	}
+}
+
+#[post("/user/webhook_secret/rotate")]
+pub async fn rotate_webhook_secret(
+	conn: &State<DbConn>,
+	auth: Result<Authenticated, Error>,
+) -> Result<Json<api::WebhookSecret>, status::Custom<Json<ErrorResponse>>> {
+	let auth = auth.map_err(|err| custom_error(Status::Unauthorized, err))?;
+	auth.authorize(WRITE)?;
+
+	let conn = conn.lock().map_err(|err| {
+		log::error!(target: LOG_TARGET, "DB connection failed: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbConnectionFailed)
+	})?;
+
+	let user_id = ensure_webhook_configured(&conn, &auth)?;
+	log::info!(target: LOG_TARGET, "Rotating webhook secret of user: {}", user_id);
+
+	let secret = notification::channels::webhook::generate_secret();
+	WebhookSecret::rotate(&conn, user_id, &secret, notification::timestamp()).map_err(|err| {
+		log::error!(target: LOG_TARGET, "Failed to rotate webhook secret: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	})?;
+
+	Ok(Json(api::WebhookSecret { secret }))
+}
+
+/// Creates the webhook secret of the user, unless the user already has one.
+pub(crate) fn ensure_webhook_secret(
+	conn: &Connection,
+	user_id: u32,
+) -> Result<(), status::Custom<Json<ErrorResponse>>> {
+	let error = |err| {
+		log::error!(target: LOG_TARGET, "Failed to create webhook secret: {:?}", err);
+		custom_error(Status::InternalServerError, Error::DbError)
+	};
+
+	if WebhookSecret::query_by_user(conn, user_id).map_err(error)?.is_none() {
+		let secret = notification::channels::webhook::generate_secret();
+		WebhookSecret::rotate(conn, user_id, &secret, notification::timestamp()).map_err(error)?;
+	}
+
+	Ok(())
+}
+
+/// Ensures the user of the session configured a webhook, and returns the id of the user.
+fn ensure_webhook_configured(
+	conn: &Connection,
+	auth: &Authenticated,
+) -> Result<u32, status::Custom<Json<ErrorResponse>>> {
+	let user = session_user(conn, auth)?;
+
+	match user.webhook_url {
+		Some(_) => Ok(user.id),
+		None => Err(custom_error(Status::NotFound, Error::WebhookNotConfigured)),
+	}
+}
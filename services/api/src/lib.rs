@@ -5,10 +5,32 @@
 //! Users will configure coretime notifications through a frontend interface. The frontend will then
 //! send these configurations to the web server exposed by this service for processing and storage.
 
-use rocket::{Build, Rocket};
+use rocket::{fairing::AdHoc, Build, Rocket};
 use rocket_cors::CorsOptions;
-use routes::{query::user, register::register_user, update::update_user};
-use storage_service::init_db;
+use routes::{
+	admin::{dead_letters, requeue_dead_letter, AdminConfig},
+	alerts::{acknowledge_alert, acknowledgement_page},
+	api_keys::{api_keys, create_api_key, revoke_api_key},
+	auth::{challenge, login, logout, AuthConfig},
+	contacts::{confirm_contact, contact_confirmation_page, ContactConfirmation},
+	delete::delete_user,
+	export::export_user,
+	login_links::{email_login, email_login_page, request_login_link},
+	organizations::{
+		accept_invite, create_organization, invite_member, organization, organizations,
+		remove_member, set_organization_channels, set_organization_subscriptions,
+	},
+	query::user,
+	register::register_user,
+	subscriptions::{subscribe, subscriptions, unsubscribe},
+	telegram::{telegram_login, telegram_webhook, TelegramConfig},
+	unsubscribe::{unsubscribe_link, unsubscribe_one_click, UnsubscribeTokens},
+	update::{patch_user, update_user},
+	verification::{verify_email, EmailVerification},
+	webhooks::{rotate_webhook_secret, webhook_secret},
+};
+use std::env;
+use storage_service::{init_db, DbConn};
 
 #[macro_use]
 extern crate rocket;
@@ -19,8 +41,69 @@ pub async fn rocket() -> Rocket<Build> {
 	let connection = init_db("users.db").expect("Failed to init db connection");
 	rocket::build()
 		.attach(CorsOptions::default().to_cors().unwrap())
+		.attach(AdHoc::on_liftoff("Verification backlog", |rocket| {
+			Box::pin(async move {
+				if let (Some(verification), Some(conn)) =
+					(rocket.state::<EmailVerification>(), rocket.state::<DbConn>())
+				{
+					verification.send_backlog(conn).await;
+				}
+			})
+		}))
 		.manage(connection)
-		.mount("/", routes![register_user, user, update_user])
+		.manage(AdminConfig { token: env::var("ADMIN_TOKEN").ok() })
+		.manage(AuthConfig::from_env())
+		.manage(EmailVerification::from_env().expect("EMAIL_VERIFICATION_SECRET must be set"))
+		.manage(ContactConfirmation::from_env())
+		.manage(UnsubscribeTokens::from_env().expect("UNSUBSCRIBE_SECRET must be set"))
+		.manage(TelegramConfig {
+			webhook_secret: env::var("TELEGRAM_WEBHOOK_SECRET").ok(),
+			bot_token: env::var("TELEGRAM_BOT_TOKEN").ok(),
+		})
+		.mount(
+			"/",
+			routes![
+				register_user,
+				user,
+				update_user,
+				patch_user,
+				dead_letters,
+				requeue_dead_letter,
+				webhook_secret,
+				rotate_webhook_secret,
+				acknowledgement_page,
+				acknowledge_alert,
+				contact_confirmation_page,
+				confirm_contact,
+				telegram_webhook,
+				verify_email,
+				challenge,
+				login,
+				logout,
+				telegram_login,
+				request_login_link,
+				email_login_page,
+				email_login,
+				create_api_key,
+				api_keys,
+				revoke_api_key,
+				create_organization,
+				organizations,
+				organization,
+				set_organization_subscriptions,
+				set_organization_channels,
+				invite_member,
+				accept_invite,
+				remove_member,
+				delete_user,
+				export_user,
+				subscriptions,
+				subscribe,
+				unsubscribe,
+				unsubscribe_link,
+				unsubscribe_one_click
+			],
+		)
 }
 
 // There should be three paths: one POST path to set the notification configuration,
@@ -0,0 +1,85 @@
+//! The commands users send to the bot.
+
+use types::ParaId;
+
+/// How long notifications are muted for if the user doesn't pass a number of hours.
+pub const DEFAULT_MUTE_HOURS: u64 = 8;
+/// The longest time notifications can be muted for at once.
+pub const MAX_MUTE_HOURS: u64 = 7 * 24;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+	/// Links the chat to the user registered with the handle of the sender.
+	Start,
+	/// Delivers the state of the parachain to the chat.
+	Subscribe(ParaId),
+	/// Stops delivering the state of the parachain to the chat, or every notification if no
+	/// parachain is given.
+	Unsubscribe(Option<ParaId>),
+	/// Lists the subscriptions of the user.
+	List,
+	/// Shows the status of the ongoing sales.
+	Status,
+	/// Holds back the non-critical notifications of the user for a number of hours.
+	Mute(u64),
+	/// Lifts the mute.
+	Unmute,
+	Help,
+}
+
+impl Command {
+	/// Parses a message sent to the bot.
+	///
+	/// Returns `None` if the message isn't a command, and the usage of the command if its
+	/// arguments are invalid. Unknown commands are answered with the help.
+	pub fn parse(text: &str) -> Option<Result<Command, &'static str>> {
+		let mut words = text.split_whitespace();
+		let command = words.next()?.strip_prefix('/')?;
+		// In group chats commands are addressed to a bot, e.g. `/list@CoretimeBot`.
+		let command = command.split_once('@').map_or(command, |(command, _)| command);
+		let argument = words.next();
+		if words.next().is_some() {
+			return Some(Err(usage(command)));
+		}
+
+		let parsed = match (command, argument) {
+			("start", _) => Ok(Command::Start),
+			("subscribe", Some(para_id)) =>
+				para_id.parse().map(Command::Subscribe).map_err(|_| usage(command)),
+			("unsubscribe", None) => Ok(Command::Unsubscribe(None)),
+			("unsubscribe", Some(para_id)) => para_id
+				.parse()
+				.map(|id| Command::Unsubscribe(Some(id)))
+				.map_err(|_| usage(command)),
+			("list", None) => Ok(Command::List),
+			("status", None) => Ok(Command::Status),
+			("mute", None) => Ok(Command::Mute(DEFAULT_MUTE_HOURS)),
+			("mute", Some(hours)) => match hours.parse() {
+				Ok(hours) if (1..=MAX_MUTE_HOURS).contains(&hours) => Ok(Command::Mute(hours)),
+				_ => Err(usage(command)),
+			},
+			("unmute", None) => Ok(Command::Unmute),
+			("subscribe" | "list" | "status" | "unmute", _) => Err(usage(command)),
+			_ => Ok(Command::Help),
+		};
+		Some(parsed)
+	}
+}
+
+fn usage(command: &str) -> &'static str {
+	match command {
+		"subscribe" => "Usage: /subscribe <para_id>",
+		"unsubscribe" => "Usage: /unsubscribe [para_id]",
+		"mute" => "Usage: /mute [hours], for at most a week",
+		_ => HELP,
+	}
+}
+
+pub const HELP: &str = "Manage your coretime notifications:
+/start - link this chat to your account
+/subscribe <para_id> - get notified about the state of a parachain
+/unsubscribe [para_id] - stop the notifications about a parachain, or all of them
+/list - show your subscriptions
+/status - show the status of the coretime sales
+/mute [hours] - hold back notifications, except critical alerts
+/unmute - receive notifications again";
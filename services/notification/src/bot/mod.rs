@@ -0,0 +1,467 @@
+//! ## Telegram Bot
+//!
+//! Lets users manage their notifications by chatting with the bot, see [`commands::HELP`].
+//!
+//! A chat has to be linked to a user with `/start` before the other commands can be used. It is
+//! linked to the user registered with the Telegram handle of the sender. Commands are only
+//! executed in private chats, whose id belongs to the sender, since any member of a group could
+//! otherwise manage the notifications of the user who linked it. Notifications through
+//! Telegram are only delivered to users whose chat is linked, since the bot can't message anyone
+//! else. Accounts without a username are linked when signing in with the Telegram Login Widget,
+//! see the api.
+//!
+//! Updates are either fetched by long polling, see [`TelegramBot::run`], or pushed by Telegram to
+//! the webhook of the api, which handles them with [`handle_update`] and replies in its response.
+//! Replies are plain English text.
+
+use crate::{
+	channels::ChannelError,
+	templates::{Context, Locale},
+	timestamp, timezone,
+};
+use commands::Command;
+use rusqlite::{Connection, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+	sync::{MutexGuard, PoisonError},
+	time::Duration,
+};
+use storage::{
+	sales::SaleStatus, subscriptions::Subscription, telegram::TelegramChat, users::User, DbConn,
+};
+use types::{
+	event::{Event, EventKind},
+	Notifications, Notifier, PhaseNotification, Timestamp, MIN_PARA_ID,
+};
+
+pub mod commands;
+
+const LOG_TARGET: &str = "telegram-bot";
+const API_URL: &str = "https://api.telegram.org";
+/// How long a request for updates waits for new updates, in seconds.
+const POLL_TIMEOUT: u64 = 30;
+/// How long to wait before polling again after a failed request.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+const NOT_LINKED: &str = "This chat isn't linked to an account yet. Send /start to link it.";
+const NOT_PRIVATE: &str = "Commands only work in a private chat with the bot.";
+
+/// An update received from Telegram. Only messages are of interest to the bot.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Update {
+	pub update_id: i64,
+	pub message: Option<IncomingMessage>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IncomingMessage {
+	pub chat: Chat,
+	pub from: Option<Sender>,
+	pub text: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Chat {
+	pub id: i64,
+	/// Either `private`, `group`, `supergroup` or `channel`.
+	#[serde(rename = "type")]
+	pub kind: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Sender {
+	/// The username of the sender, without the leading `@`.
+	pub username: Option<String>,
+}
+
+/// A message the bot sends in reply to a command.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Reply {
+	pub chat_id: i64,
+	pub text: String,
+}
+
+impl Reply {
+	/// Returns the reply as the response to a webhook request, which Telegram executes as a call
+	/// of the bot api.
+	pub fn to_webhook_response(&self) -> Value {
+		json!({ "method": "sendMessage", "chat_id": self.chat_id, "text": self.text })
+	}
+}
+
+/// Executes the command of the update.
+///
+/// Returns the reply to send, or `None` if the update doesn't contain a command.
+pub fn handle_update(conn: &Connection, update: &Update, now: Timestamp) -> Result<Option<Reply>> {
+	let Some(message) = &update.message else { return Ok(None) };
+	let Some(command) = message.text.as_deref().and_then(Command::parse) else {
+		return Ok(None);
+	};
+	let chat_id = message.chat.id;
+
+	let text = match command {
+		Ok(_) if message.chat.kind != "private" => NOT_PRIVATE.to_string(),
+		Ok(command) => {
+			let username = message.from.as_ref().and_then(|from| from.username.as_deref());
+			execute(conn, chat_id, username, command, now)?
+		},
+		Err(usage) => usage.to_string(),
+	};
+
+	Ok(Some(Reply { chat_id, text }))
+}
+
+fn execute(
+	conn: &Connection,
+	chat_id: i64,
+	username: Option<&str>,
+	command: Command,
+	now: Timestamp,
+) -> Result<String> {
+	match command {
+		Command::Start => return start(conn, chat_id, username),
+		Command::Help => return Ok(commands::HELP.into()),
+		_ => {},
+	}
+
+	let Some(chat) = TelegramChat::query_by_chat(conn, chat_id)? else {
+		return Ok(NOT_LINKED.into());
+	};
+	let Some(user) = User::query_by_id(conn, chat.user_id)? else {
+		return Ok(NOT_LINKED.into());
+	};
+
+	let reply = match command {
+		Command::Subscribe(para_id) if para_id < MIN_PARA_ID =>
+			format!("Parachain ids start at {}, {} isn't a parachain.", MIN_PARA_ID, para_id),
+		Command::Subscribe(para_id) => {
+			let notification = Notifications::ParachainState(para_id);
+			Subscription::create(conn, user.id, &notification, &[Notifier::Telegram])?;
+			format!("Subscribed to the state of parachain {}.", para_id)
+		},
+		Command::Unsubscribe(Some(para_id)) => {
+			let notification = Notifications::ParachainState(para_id);
+			match Subscription::remove_channel(conn, user.id, &notification, &Notifier::Telegram)? {
+				true => format!("Unsubscribed from the state of parachain {}.", para_id),
+				false => format!("You aren't subscribed to the state of parachain {}.", para_id),
+			}
+		},
+		Command::Unsubscribe(None) => {
+			let mut removed = 0;
+			for subscription in Subscription::query_by_user(conn, user.id)? {
+				if Subscription::remove_channel(
+					conn,
+					user.id,
+					&subscription.notification,
+					&Notifier::Telegram,
+				)? {
+					removed += 1;
+				}
+			}
+			match removed {
+				0 => "You don't receive any notifications in this chat.".into(),
+				_ => "Unsubscribed from all notifications in this chat.".into(),
+			}
+		},
+		Command::List => list(conn, &user)?,
+		Command::Status => status(conn, &user, now)?,
+		Command::Mute(hours) => {
+			let until = now + hours * 60 * 60;
+			User::mute(conn, user.id, Some(until))?;
+			format!(
+				"Notifications are muted until {}. Critical alerts are still delivered.",
+				format_time(&user, until)
+			)
+		},
+		Command::Unmute => {
+			User::mute(conn, user.id, None)?;
+			"Notifications are no longer muted.".into()
+		},
+		Command::Start | Command::Help => unreachable!("handled above"),
+	};
+
+	Ok(reply)
+}
+
+/// Returns the form in which the handle of a Telegram username is stored, with the leading `@`.
+pub fn canonical_handle(username: &str) -> String {
+	format!("@{}", username.trim_start_matches('@'))
+}
+
+/// Links the chat to the user registered with the handle of the sender.
+fn start(conn: &Connection, chat_id: i64, username: Option<&str>) -> Result<String> {
+	let Some(username) = username else {
+		if let Some(chat) = TelegramChat::query_by_chat(conn, chat_id)? {
+			return Ok(linked(&chat.handle));
+		}
+		return Ok("Set a username in the settings of Telegram and register it, or sign in with \
+			Telegram, to receive notifications here."
+			.into());
+	};
+	let user = User::query_by_tg_handle(conn, canonical_handle(username))?;
+	let Some(User { id: user_id, tg_handle: Some(handle), .. }) = user else {
+		return Ok(format!(
+			"There is no account with the Telegram handle @{}. Register it to receive \
+				notifications here.",
+			username
+		));
+	};
+
+	TelegramChat::link(conn, user_id, chat_id, &handle)?;
+	Ok(linked(&handle))
+}
+
+fn linked(handle: &str) -> String {
+	format!(
+		"This chat is now linked to the account of {}. Send /help to see what you can do.",
+		handle
+	)
+}
+
+fn list(conn: &Connection, user: &User) -> Result<String> {
+	let subscriptions = Subscription::query_by_user(conn, user.id)?;
+	if subscriptions.is_empty() {
+		return Ok("You aren't subscribed to any notifications.".into());
+	}
+
+	let lines = subscriptions
+		.iter()
+		.map(|subscription| {
+			let channels = subscription
+				.channels
+				.iter()
+				.map(|channel| format!("{:?}", channel))
+				.collect::<Vec<_>>()
+				.join(", ");
+			format!("- {} ({})", describe(&subscription.notification), channels)
+		})
+		.collect::<Vec<_>>();
+
+	Ok(format!("Your subscriptions:\n{}", lines.join("\n")))
+}
+
+fn status(conn: &Connection, user: &User, now: Timestamp) -> Result<String> {
+	let sales = SaleStatus::query_all(conn)?;
+	if sales.is_empty() {
+		return Ok("There is no information about the sales yet.".into());
+	}
+
+	let locale = Locale::for_language("en");
+	let paragraphs = sales
+		.iter()
+		.map(|sale| {
+			let (phase, start, end) = if now < sale.leadin_start {
+				("interlude", sale.interlude_start, Some(sale.leadin_start))
+			} else if now < sale.fixed_start {
+				("leadin", sale.leadin_start, Some(sale.fixed_start))
+			} else {
+				("fixed price", sale.fixed_start, None)
+			};
+			let event = Event {
+				network: sale.network.clone(),
+				kind: EventKind::CoretimeSale,
+				block: 0,
+				timestamp: start,
+				end_timestamp: end,
+				timeslice: None,
+				core: None,
+				price: sale.last_price,
+				cores_left: Some(sale.cores_left),
+				para_id: None,
+			};
+			let context = Context::new(&event, locale, timezone(user));
+			let value = |name| context.get(name).cloned().unwrap_or_default();
+
+			let mut lines = vec![match end {
+				Some(_) => format!(
+					"{}: {} phase from {} until {}",
+					value("network"),
+					phase,
+					value("start"),
+					value("end")
+				),
+				None => format!("{}: {} phase since {}", value("network"), phase, value("start")),
+			}];
+			if sale.last_price.is_some() {
+				lines.push(format!("Price of the last sale: {}", value("price")));
+			}
+			lines.push(format!("Cores left: {}", value("cores_left")));
+			lines.join("\n")
+		})
+		.collect::<Vec<_>>();
+
+	Ok(paragraphs.join("\n\n"))
+}
+
+/// Describes a notification in the words of a user.
+fn describe(notification: &Notifications) -> String {
+	match notification {
+		Notifications::InterludePhase(when) =>
+			format!("Interlude phase, {}", describe_offset(when)),
+		Notifications::LeadinPhaseStart(when) => format!("Leadin phase, {}", describe_offset(when)),
+		Notifications::FixedPhaseStart(when) =>
+			format!("Fixed price phase, {}", describe_offset(when)),
+		Notifications::CoretimeSale => "Coretime sales".into(),
+		Notifications::ParachainState(para_id) => format!("State of parachain {}", para_id),
+	}
+}
+
+fn describe_offset(when: &PhaseNotification) -> String {
+	match when {
+		PhaseNotification::PriorStart(0) => "at the start".into(),
+		PhaseNotification::PriorStart(seconds) =>
+			format!("{} before the start", format_duration(*seconds)),
+		PhaseNotification::PriorEnd(0) => "at the end".into(),
+		PhaseNotification::PriorEnd(seconds) =>
+			format!("{} before the end", format_duration(*seconds)),
+	}
+}
+
+/// Formats a duration in the largest unit which divides it, e.g. `12h`.
+fn format_duration(seconds: u64) -> String {
+	match seconds {
+		s if s % (24 * 60 * 60) == 0 => format!("{}d", s / (24 * 60 * 60)),
+		s if s % (60 * 60) == 0 => format!("{}h", s / (60 * 60)),
+		s if s % 60 == 0 => format!("{}m", s / 60),
+		s => format!("{}s", s),
+	}
+}
+
+fn format_time(user: &User, timestamp: Timestamp) -> String {
+	Locale::for_language("en").format_timestamp(timestamp, timezone(user))
+}
+
+/// The envelope of every response of the bot api.
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+	ok: bool,
+	result: Option<T>,
+	description: Option<String>,
+}
+
+/// Receives the updates of the bot by long polling and replies to them.
+pub struct TelegramBot {
+	conn: DbConn,
+	client: reqwest::Client,
+	api_url: String,
+	token: String,
+	/// The id of the next update to fetch.
+	offset: i64,
+}
+
+impl TelegramBot {
+	pub fn new(conn: DbConn, token: String) -> Self {
+		Self { conn, client: reqwest::Client::new(), api_url: API_URL.into(), token, offset: 0 }
+	}
+
+	/// Uses a custom url for the bot api instead of the official one.
+	pub fn with_api_url(mut self, api_url: String) -> Self {
+		self.api_url = api_url;
+		self
+	}
+
+	/// Keeps fetching updates and replying to them.
+	///
+	/// Telegram doesn't deliver updates by polling while a webhook is set, so it is removed first.
+	pub async fn run(mut self) {
+		if let Err(err) = self.call::<bool>("deleteWebhook", json!({})).await {
+			log::error!(target: LOG_TARGET, "Failed to delete the webhook: {}", err);
+		}
+
+		loop {
+			if let Err(err) = self.process_updates().await {
+				log::error!(target: LOG_TARGET, "Failed to fetch updates: {}", err);
+				tokio::time::sleep(RETRY_INTERVAL).await;
+			}
+		}
+	}
+
+	/// Fetches the next updates and replies to them.
+	///
+	/// Returns the number of fetched updates.
+	pub async fn process_updates(&mut self) -> Result<usize, ChannelError> {
+		let updates: Vec<Update> = self
+			.call(
+				"getUpdates",
+				json!({
+					"offset": self.offset,
+					"timeout": POLL_TIMEOUT,
+					"allowed_updates": ["message"],
+				}),
+			)
+			.await?;
+
+		for update in updates.iter() {
+			// Updates are confirmed by fetching with a higher offset, so failures aren't retried.
+			self.offset = update.update_id + 1;
+
+			let reply = handle_update(&self.conn(), update, timestamp());
+			match reply {
+				Ok(Some(reply)) =>
+					if let Err(err) = self.call::<Value>("sendMessage", json!(reply)).await {
+						log::warn!(target: LOG_TARGET, "Failed to reply to {}: {}", reply.chat_id, err);
+					},
+				Ok(None) => {},
+				Err(err) => log::error!(
+					target: LOG_TARGET,
+					"Failed to handle update {}: {:?}",
+					update.update_id,
+					err
+				),
+			}
+		}
+
+		Ok(updates.len())
+	}
+
+	/// Lets Telegram push the updates to the url, along with the secret in the
+	/// `X-Telegram-Bot-Api-Secret-Token` header.
+	pub async fn set_webhook(&self, url: &str, secret: &str) -> Result<(), ChannelError> {
+		self.call::<bool>(
+			"setWebhook",
+			json!({ "url": url, "secret_token": secret, "allowed_updates": ["message"] }),
+		)
+		.await
+		.map(|_| ())
+	}
+
+	async fn call<T: DeserializeOwned>(
+		&self,
+		method: &str,
+		body: Value,
+	) -> Result<T, ChannelError> {
+		let url = format!("{}/bot{}/{}", self.api_url, self.token, method);
+		let response = self
+			.client
+			.post(url)
+			.json(&body)
+			// Long polling holds the request open for up to the poll timeout.
+			.timeout(Duration::from_secs(POLL_TIMEOUT + 10))
+			.send()
+			.await?;
+
+		let status = response.status();
+		if !status.is_success() {
+			let description = response.text().await.unwrap_or_default();
+			return Err(ChannelError::from_status(
+				status,
+				format!("Telegram responded with {}: {}", status, description),
+			));
+		}
+
+		let response: ApiResponse<T> = response.json().await?;
+		match (response.ok, response.result) {
+			(true, Some(result)) => Ok(result),
+			_ => Err(ChannelError::Permanent(format!(
+				"Telegram failed to execute {}: {}",
+				method,
+				response.description.unwrap_or_default()
+			))),
+		}
+	}
+
+	fn conn(&self) -> MutexGuard<'_, Connection> {
+		self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
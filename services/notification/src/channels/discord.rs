@@ -0,0 +1,116 @@
+use super::{header_seconds, truncate, Channel, ChannelError};
+use crate::message::Message;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+/// The longest time a delivery waits for a rate limit to reset. Deliveries which would have to
+/// wait longer are retried later through the outbox.
+const MAX_WAIT: Duration = Duration::from_secs(10);
+/// The color of the embeds.
+const EMBED_COLOR: u32 = 0xE6007A;
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+
+/// Delivers notifications as embeds through Discord webhooks.
+///
+/// The address of a user is the url of their webhook.
+pub struct DiscordChannel {
+	client: reqwest::Client,
+	/// Webhooks which are rate limited, along with the time at which the limit resets.
+	rate_limits: Mutex<HashMap<String, Instant>>,
+}
+
+impl DiscordChannel {
+	pub fn new() -> Self {
+		Self { client: reqwest::Client::new(), rate_limits: Mutex::new(HashMap::new()) }
+	}
+
+	/// Returns how long to wait until the webhook can be used again.
+	fn rate_limited_for(&self, webhook: &str) -> Option<Duration> {
+		let rate_limits = self.rate_limits.lock().unwrap_or_else(PoisonError::into_inner);
+		rate_limits
+			.get(webhook)
+			.and_then(|reset| reset.checked_duration_since(Instant::now()))
+	}
+
+	fn rate_limit(&self, webhook: &str, reset_after: Duration) {
+		let mut rate_limits = self.rate_limits.lock().unwrap_or_else(PoisonError::into_inner);
+		rate_limits.retain(|_, reset| *reset > Instant::now());
+		rate_limits.insert(webhook.to_string(), Instant::now() + reset_after);
+	}
+}
+
+impl Default for DiscordChannel {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[async_trait]
+impl Channel for DiscordChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		let fields = message
+			.fields
+			.iter()
+			.map(|field| json!({ "name": field.name, "value": field.value, "inline": true }))
+			.collect::<Vec<_>>();
+		let body = json!({
+			"embeds": [{
+				"title": truncate(&message.subject, MAX_TITLE_LENGTH),
+				"description": truncate(&message.text, MAX_DESCRIPTION_LENGTH),
+				"color": EMBED_COLOR,
+				"fields": fields,
+			}],
+		});
+
+		// A delivery which hits the rate limit is attempted once more after the limit resets.
+		for _ in 0..2 {
+			if let Some(wait) = self.rate_limited_for(address) {
+				if wait > MAX_WAIT {
+					return Err(ChannelError::Transient(format!(
+						"Discord webhook is rate limited for {}s",
+						wait.as_secs()
+					)));
+				}
+				tokio::time::sleep(wait).await;
+			}
+
+			let response =
+				self.client.post(address).query(&[("wait", "true")]).json(&body).send().await?;
+
+			// Discord announces when the next request would be rate limited.
+			let headers = response.headers();
+			if header_seconds(headers, "x-ratelimit-remaining") == Some(0.0) {
+				if let Some(reset_after) = header_seconds(headers, "x-ratelimit-reset-after") {
+					self.rate_limit(address, Duration::from_secs_f64(reset_after));
+				}
+			}
+
+			let status = response.status();
+			if status == StatusCode::TOO_MANY_REQUESTS {
+				let retry_after = header_seconds(response.headers(), "retry-after").unwrap_or(1.0);
+				self.rate_limit(address, Duration::from_secs_f64(retry_after));
+				continue;
+			}
+
+			// E.g. a `404` is returned if the webhook was deleted, which is permanent.
+			if !status.is_success() {
+				let description = response.text().await.unwrap_or_default();
+				return Err(ChannelError::from_status(
+					status,
+					format!("Discord responded with {}: {}", status, description),
+				));
+			}
+
+			return Ok(());
+		}
+
+		Err(ChannelError::Transient("Discord webhook is rate limited".into()))
+	}
+}
@@ -0,0 +1,94 @@
+use super::{Channel, ChannelError};
+use crate::message::Message;
+use async_trait::async_trait;
+use lettre::{
+	message::{
+		header::{HeaderName, HeaderValue},
+		Mailbox, MultiPart,
+	},
+	transport::smtp::authentication::Credentials,
+	AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use std::env;
+
+/// Delivers notifications by email over SMTP.
+pub struct EmailChannel {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+impl EmailChannel {
+	pub fn new(host: &str, credentials: Credentials, from: Mailbox) -> Result<Self, ChannelError> {
+		let transport = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+			.map_err(|err| ChannelError::Permanent(err.to_string()))?
+			.credentials(credentials)
+			.build();
+
+		Ok(Self { transport, from })
+	}
+
+	/// Reads the configuration from `SMTP_HOST`, `SMTP_USERNAME`, `SMTP_PASSWORD` and
+	/// `EMAIL_FROM`.
+	///
+	/// Returns `None` if the channel isn't configured.
+	pub fn from_env() -> Option<Result<Self, ChannelError>> {
+		let host = env::var("SMTP_HOST").ok()?;
+		let credentials =
+			Credentials::new(env::var("SMTP_USERNAME").ok()?, env::var("SMTP_PASSWORD").ok()?);
+		let from = match env::var("EMAIL_FROM").ok()?.parse() {
+			Ok(from) => from,
+			Err(err) =>
+				return Some(Err(ChannelError::Permanent(format!("Invalid sender: {}", err)))),
+		};
+
+		Some(Self::new(&host, credentials, from))
+	}
+
+	/// Builds the email of the message to the address.
+	///
+	/// Messages which can be unsubscribed from offer the one-click unsubscription of RFC 8058, so
+	/// that mail clients can show an unsubscribe button.
+	pub fn email(&self, address: &str, message: &Message) -> Result<lettre::Message, ChannelError> {
+		let to: Mailbox =
+			address.parse().map_err(|_| ChannelError::Permanent("Invalid email".into()))?;
+		let mut builder = lettre::Message::builder()
+			.from(self.from.clone())
+			.to(to)
+			.subject(message.subject.clone());
+		if let Some(url) = &message.unsubscribe {
+			builder = builder
+				.raw_header(HeaderValue::new(
+					HeaderName::new_from_ascii_str("List-Unsubscribe"),
+					format!("<{}>", url),
+				))
+				.raw_header(HeaderValue::new(
+					HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+					"List-Unsubscribe=One-Click".into(),
+				));
+		}
+
+		builder
+			.multipart(MultiPart::alternative_plain_html(
+				message.text.clone(),
+				message.html.clone(),
+			))
+			.map_err(|err| ChannelError::Permanent(err.to_string()))
+	}
+}
+
+#[async_trait]
+impl Channel for EmailChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		let email = self.email(address, message)?;
+
+		self.transport.send(email).await.map_err(|err| {
+			// Only a permanent smtp reply, e.g. an unknown mailbox, rules out a later retry.
+			if err.is_permanent() {
+				ChannelError::Permanent(err.to_string())
+			} else {
+				ChannelError::Transient(err.to_string())
+			}
+		})?;
+		Ok(())
+	}
+}
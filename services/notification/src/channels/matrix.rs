@@ -0,0 +1,102 @@
+use super::{Channel, ChannelError};
+use crate::message::Message;
+use async_trait::async_trait;
+use reqwest::Url;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+	sync::atomic::{AtomicU64, Ordering},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const DEFAULT_HOMESERVER_URL: &str = "https://matrix.org";
+
+/// Delivers notifications through the client-server api of a Matrix homeserver.
+///
+/// The address of a user is the id of a room the bot has joined, e.g. `!abc:matrix.org`.
+pub struct MatrixChannel {
+	client: reqwest::Client,
+	homeserver_url: String,
+	access_token: String,
+	/// Prefix of the transaction ids of messages which aren't deliveries, unique to this instance.
+	session: u64,
+	/// Counter which makes the transaction ids of this instance unique.
+	transactions: AtomicU64,
+}
+
+impl MatrixChannel {
+	pub fn new(homeserver_url: String, access_token: String) -> Self {
+		let session = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|time| time.as_millis() as u64)
+			.unwrap_or_default();
+
+		Self {
+			client: reqwest::Client::new(),
+			homeserver_url,
+			access_token,
+			session,
+			transactions: AtomicU64::new(0),
+		}
+	}
+
+	fn send_url(&self, room_id: &str, message: &Message) -> Result<Url, ChannelError> {
+		// The homeserver deduplicates requests with the same transaction id, so a retried delivery
+		// keeps the id of its first attempt.
+		let transaction_id = match &message.delivery {
+			Some(delivery) => {
+				let hash = Sha256::new()
+					.chain_update(room_id.as_bytes())
+					.chain_update(delivery.as_bytes())
+					.finalize();
+				hex::encode(&hash[..16])
+			},
+			None => {
+				let transaction = self.transactions.fetch_add(1, Ordering::Relaxed);
+				format!("{}-{}", self.session, transaction)
+			},
+		};
+
+		let mut url = Url::parse(&self.homeserver_url)
+			.map_err(|err| ChannelError::Permanent(format!("Invalid homeserver url: {}", err)))?;
+		url.path_segments_mut()
+			.map_err(|_| ChannelError::Permanent("Invalid homeserver url".into()))?
+			.pop_if_empty()
+			.extend(["_matrix", "client", "v3", "rooms", room_id, "send", "m.room.message"])
+			.push(&transaction_id);
+
+		Ok(url)
+	}
+}
+
+#[async_trait]
+impl Channel for MatrixChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		let body = json!({
+			"msgtype": "m.text",
+			"body": message.text,
+			"format": "org.matrix.custom.html",
+			"formatted_body": message.html,
+		});
+
+		let response = self
+			.client
+			.put(self.send_url(address, message)?)
+			.bearer_auth(&self.access_token)
+			.json(&body)
+			.send()
+			.await?;
+
+		// E.g. a `403` is returned if the bot isn't part of the room, which is permanent.
+		let status = response.status();
+		if !status.is_success() {
+			let description = response.text().await.unwrap_or_default();
+			return Err(ChannelError::from_status(
+				status,
+				format!("Matrix responded with {}: {}", status, description),
+			));
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,128 @@
+//! Channels through which notifications are delivered to users.
+
+use crate::message::Message;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use std::{collections::HashMap, env, fmt};
+use types::Notifier;
+
+pub mod discord;
+pub mod email;
+pub mod matrix;
+pub mod slack;
+pub mod telegram;
+pub mod webhook;
+
+/// The channels configured for delivery, keyed by the notifier they serve.
+pub type Channels = HashMap<Notifier, Box<dyn Channel>>;
+
+/// Error returned when a channel failed to deliver a message.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ChannelError {
+	/// The delivery might succeed when retried later, e.g. on timeouts or rate limiting.
+	Transient(String),
+	/// Retrying the delivery won't help, e.g. the address is invalid or the user blocked the bot.
+	Permanent(String),
+}
+
+impl ChannelError {
+	pub fn is_transient(&self) -> bool {
+		matches!(self, ChannelError::Transient(_))
+	}
+
+	/// Classifies an error response of an http api.
+	pub fn from_status(status: reqwest::StatusCode, description: String) -> Self {
+		if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+			ChannelError::Transient(description)
+		} else {
+			ChannelError::Permanent(description)
+		}
+	}
+}
+
+impl From<reqwest::Error> for ChannelError {
+	fn from(err: reqwest::Error) -> Self {
+		// Failing to reach the api is expected to resolve on its own.
+		match err.status() {
+			Some(status) => Self::from_status(status, err.without_url().to_string()),
+			None => ChannelError::Transient(err.without_url().to_string()),
+		}
+	}
+}
+
+impl fmt::Display for ChannelError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ChannelError::Transient(error) => write!(f, "{}", error),
+			ChannelError::Permanent(error) => write!(f, "{}", error),
+		}
+	}
+}
+
+#[async_trait]
+pub trait Channel: Send + Sync {
+	/// Delivers the message to `address`.
+	///
+	/// The meaning of the address depends on the channel, e.g. an email address or a telegram
+	/// chat.
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError>;
+}
+
+/// Configures all channels for which the required environment variables are set.
+pub fn from_env() -> Channels {
+	let mut channels: Channels = HashMap::new();
+
+	match email::EmailChannel::from_env() {
+		Some(Ok(channel)) => {
+			channels.insert(Notifier::Email, Box::new(channel));
+		},
+		Some(Err(err)) =>
+			log::error!(target: crate::LOG_TARGET, "Failed to configure email: {}", err),
+		None => log::warn!(target: crate::LOG_TARGET, "Email channel is not configured"),
+	}
+
+	match env::var("TELEGRAM_BOT_TOKEN") {
+		Ok(token) => {
+			channels.insert(Notifier::Telegram, Box::new(telegram::TelegramChannel::new(token)));
+		},
+		Err(_) => log::warn!(target: crate::LOG_TARGET, "Telegram channel is not configured"),
+	}
+
+	match env::var("MATRIX_ACCESS_TOKEN") {
+		Ok(token) => {
+			let homeserver_url = env::var("MATRIX_HOMESERVER_URL")
+				.unwrap_or(matrix::DEFAULT_HOMESERVER_URL.to_string());
+			channels.insert(
+				Notifier::Matrix,
+				Box::new(matrix::MatrixChannel::new(homeserver_url, token)),
+			);
+		},
+		Err(_) => log::warn!(target: crate::LOG_TARGET, "Matrix channel is not configured"),
+	}
+
+	// Webhooks don't require any configuration.
+	channels.insert(Notifier::Discord, Box::new(discord::DiscordChannel::new()));
+	channels.insert(Notifier::Slack, Box::new(slack::SlackChannel::new()));
+	channels.insert(Notifier::Webhook, Box::new(webhook::WebhookChannel::new()));
+
+	channels
+}
+
+/// Parses a header which holds a number of seconds, e.g. `Retry-After`.
+pub(crate) fn header_seconds(headers: &HeaderMap, name: &str) -> Option<f64> {
+	headers
+		.get(name)?
+		.to_str()
+		.ok()?
+		.parse::<f64>()
+		.ok()
+		.filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+}
+
+/// Shortens the value to at most `max` characters.
+pub(crate) fn truncate(value: &str, max: usize) -> String {
+	if value.chars().count() <= max {
+		return value.to_string();
+	}
+	value.chars().take(max - 1).chain(['…']).collect()
+}
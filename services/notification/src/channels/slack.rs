@@ -0,0 +1,108 @@
+use super::{header_seconds, truncate, Channel, ChannelError};
+use crate::message::Message;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// How often a rate limited delivery is attempted again before it is left to the outbox.
+const MAX_RETRIES: usize = 2;
+/// The longest time a delivery waits for a rate limit to reset.
+const MAX_WAIT: Duration = Duration::from_secs(10);
+const MAX_HEADER_LENGTH: usize = 150;
+const MAX_TEXT_LENGTH: usize = 3000;
+/// Slack doesn't display more than ten fields in a section.
+const MAX_FIELDS: usize = 10;
+
+/// Delivers notifications as Block Kit messages through Slack incoming webhooks.
+///
+/// The address of a user is the url of their webhook.
+pub struct SlackChannel {
+	client: reqwest::Client,
+}
+
+impl SlackChannel {
+	pub fn new() -> Self {
+		Self { client: reqwest::Client::new() }
+	}
+}
+
+impl Default for SlackChannel {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[async_trait]
+impl Channel for SlackChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		let body = blocks(message);
+
+		for _ in 0..=MAX_RETRIES {
+			let response = self.client.post(address).json(&body).send().await?;
+
+			let status = response.status();
+			if status == StatusCode::TOO_MANY_REQUESTS {
+				let retry_after = header_seconds(response.headers(), "retry-after").unwrap_or(1.0);
+				let retry_after = Duration::from_secs_f64(retry_after);
+				if retry_after > MAX_WAIT {
+					return Err(ChannelError::Transient(format!(
+						"Slack webhook is rate limited for {}s",
+						retry_after.as_secs()
+					)));
+				}
+				tokio::time::sleep(retry_after).await;
+				continue;
+			}
+
+			// E.g. a `404` is returned if the webhook was removed, which is permanent.
+			if !status.is_success() {
+				let description = response.text().await.unwrap_or_default();
+				return Err(ChannelError::from_status(
+					status,
+					format!("Slack responded with {}: {}", status, description),
+				));
+			}
+
+			return Ok(());
+		}
+
+		Err(ChannelError::Transient("Slack webhook is rate limited".into()))
+	}
+}
+
+/// Builds the Block Kit payload of the message.
+///
+/// The `text` is shown in notifications of the Slack clients.
+fn blocks(message: &Message) -> Value {
+	let mut blocks = vec![
+		json!({
+			"type": "header",
+			"text": { "type": "plain_text", "text": truncate(&message.subject, MAX_HEADER_LENGTH) },
+		}),
+		json!({
+			"type": "section",
+			"text": { "type": "mrkdwn", "text": truncate(&escape(&message.text), MAX_TEXT_LENGTH) },
+		}),
+	];
+
+	if !message.fields.is_empty() {
+		let fields = message
+			.fields
+			.iter()
+			.take(MAX_FIELDS)
+			.map(|field| {
+				let text = format!("*{}*\n{}", escape(&field.name), escape(&field.value));
+				json!({ "type": "mrkdwn", "text": text })
+			})
+			.collect::<Vec<_>>();
+		blocks.push(json!({ "type": "section", "fields": fields }));
+	}
+
+	json!({ "text": message.subject, "blocks": blocks })
+}
+
+/// Escapes the characters which have a special meaning in Slack's mrkdwn.
+fn escape(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,55 @@
+use super::{Channel, ChannelError};
+use crate::message::Message;
+use async_trait::async_trait;
+use serde_json::json;
+
+const API_URL: &str = "https://api.telegram.org";
+
+/// Delivers notifications through a telegram bot.
+pub struct TelegramChannel {
+	client: reqwest::Client,
+	api_url: String,
+	token: String,
+}
+
+impl TelegramChannel {
+	pub fn new(token: String) -> Self {
+		Self::with_api_url(API_URL.to_string(), token)
+	}
+
+	/// Uses a custom url for the bot api instead of the official one.
+	pub fn with_api_url(api_url: String, token: String) -> Self {
+		Self { client: reqwest::Client::new(), api_url, token }
+	}
+}
+
+#[async_trait]
+impl Channel for TelegramChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		let url = format!("{}/bot{}/sendMessage", self.api_url, self.token);
+		let mut body = json!({
+			"chat_id": address,
+			"text": message.markdown,
+			"parse_mode": "MarkdownV2",
+		});
+		if let Some(action) = &message.action {
+			body["reply_markup"] = json!({
+				"inline_keyboard": [[{ "text": action.label, "url": action.url }]],
+			});
+		}
+
+		let response = self.client.post(url).json(&body).send().await?;
+
+		// E.g. a `403` is returned if the user blocked the bot, which is permanent.
+		let status = response.status();
+		if !status.is_success() {
+			let description = response.text().await.unwrap_or_default();
+			return Err(ChannelError::from_status(
+				status,
+				format!("Telegram responded with {}: {}", status, description),
+			));
+		}
+
+		Ok(())
+	}
+}
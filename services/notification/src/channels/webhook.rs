@@ -0,0 +1,305 @@
+//! Delivers the events as signed JSON payloads to endpoints of the users, so they can be processed
+//! by their own integrations.
+//!
+//! ## Payload
+//!
+//! The body is a JSON object of the following schema. Fields are only ever added to a version of
+//! the schema, any other change results in a new `version`.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "id": "<unique id of the delivery>",
+//!   "events": [{
+//!     "network": "kusama",
+//!     "kind": "coretime_sale",
+//!     "block": 100,
+//!     "timestamp": 1714564800,
+//!     "endTimestamp": null,
+//!     "timeslice": 42,
+//!     "core": 3,
+//!     "price": "1000000000000",
+//!     "coresLeft": 5,
+//!     "paraId": null
+//!   }],
+//!   "ackUrl": "https://..."
+//! }
+//! ```
+//!
+//! The `events` contain multiple events if the user receives digests. Prices are strings, since
+//! they don't fit into the numbers of most JSON parsers. `ackUrl` is only present for alerts
+//! which are escalated until they are acknowledged. A `POST` to it acknowledges the alert, a `GET`
+//! only returns a page to confirm on.
+//!
+//! ## Signature
+//!
+//! Every delivery carries the `X-Coretime-Signature` header of the form `t=<timestamp>,v1=<hex>`,
+//! where `v1` is the HMAC-SHA256 over `<timestamp>.<body>` keyed with the secret of the user.
+//! Right after a secret was rotated, the header contains a `v1` signature for both the new and
+//! the previous secret.
+//!
+//! To protect against replayed deliveries, receivers should reject signatures which are older than
+//! [`TOLERANCE`] and ignore ids they already processed. The id stays the same when a delivery is
+//! retried. See [`verify`].
+//!
+//! ## Addresses
+//!
+//! Deliveries are only made to public addresses, so that users can't make the service post to
+//! internal services of the network it runs in, see [`is_public`]. Redirects aren't followed.
+
+use super::{Channel, ChannelError};
+use crate::{message::Message, templates::kind_key, timestamp};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use types::{event::Event, BlockNumber, CoreIndex, ParaId, Timeslice, Timestamp};
+
+/// The version of the payload schema.
+pub const SCHEMA_VERSION: u32 = 1;
+/// The header which holds the signature.
+pub const SIGNATURE_HEADER: &str = "X-Coretime-Signature";
+/// The maximum age of a signature which receivers should accept, in seconds.
+pub const TOLERANCE: u64 = 5 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Serialize)]
+struct Payload<'a> {
+	version: u32,
+	id: String,
+	events: Vec<PayloadEvent<'a>>,
+	#[serde(rename = "ackUrl", skip_serializing_if = "Option::is_none")]
+	ack_url: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PayloadEvent<'a> {
+	network: &'a str,
+	kind: &'static str,
+	block: BlockNumber,
+	timestamp: Timestamp,
+	end_timestamp: Option<Timestamp>,
+	timeslice: Option<Timeslice>,
+	core: Option<CoreIndex>,
+	price: Option<String>,
+	cores_left: Option<u16>,
+	para_id: Option<ParaId>,
+}
+
+impl<'a> From<&'a Event> for PayloadEvent<'a> {
+	fn from(event: &'a Event) -> Self {
+		Self {
+			network: &event.network,
+			kind: kind_key(event.kind),
+			block: event.block,
+			timestamp: event.timestamp,
+			end_timestamp: event.end_timestamp,
+			timeslice: event.timeslice,
+			core: event.core,
+			price: event.price.map(|price| price.to_string()),
+			cores_left: event.cores_left,
+			para_id: event.para_id,
+		}
+	}
+}
+
+/// Posts the events of a message to the url of a user.
+///
+/// The address of a user is the url of their endpoint. Messages are signed with their
+/// [`Message::secrets`].
+pub struct WebhookChannel {
+	client: reqwest::Client,
+	/// Whether deliveries to addresses which aren't public are allowed.
+	private_addresses: bool,
+}
+
+impl WebhookChannel {
+	pub fn new() -> Self {
+		Self {
+			client: Self::client().build().expect("the client is valid"),
+			private_addresses: false,
+		}
+	}
+
+	/// Allows deliveries to private addresses, e.g. to endpoints on the same host in tests.
+	pub fn with_private_addresses(self) -> Self {
+		Self { private_addresses: true, ..self }
+	}
+
+	fn client() -> reqwest::ClientBuilder {
+		reqwest::Client::builder().redirect(reqwest::redirect::Policy::none())
+	}
+
+	/// Returns the client through which the url is posted to, which only connects to the address
+	/// the host of the url was checked to resolve to.
+	///
+	/// Otherwise the host could resolve to another address once connecting.
+	async fn client_for(&self, url: &str) -> Result<reqwest::Client, ChannelError> {
+		if self.private_addresses {
+			return Ok(self.client.clone());
+		}
+
+		let url = reqwest::Url::parse(url)
+			.map_err(|err| ChannelError::Permanent(format!("Invalid webhook url: {}", err)))?;
+		let host = url
+			.host_str()
+			.ok_or(ChannelError::Permanent("Webhook url has no host".into()))?;
+		let port = url.port_or_known_default().unwrap_or(443);
+
+		if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+			return match is_public(ip) {
+				true => Ok(self.client.clone()),
+				false => Err(ChannelError::Permanent("Webhook address isn't public".into())),
+			};
+		}
+
+		// Failing to resolve the host is expected to resolve on its own, like failing to connect.
+		let addresses = tokio::net::lookup_host((host, port))
+			.await
+			.map_err(|err| ChannelError::Transient(format!("Failed to resolve {}: {}", host, err)))?
+			.collect::<Vec<SocketAddr>>();
+		let address = match addresses.as_slice() {
+			[] => return Err(ChannelError::Transient(format!("{} didn't resolve", host))),
+			addresses if addresses.iter().all(|address| is_public(address.ip())) => addresses[0],
+			_ => return Err(ChannelError::Permanent("Webhook address isn't public".into())),
+		};
+
+		Self::client()
+			.resolve(host, address)
+			.build()
+			.map_err(|err| ChannelError::Transient(err.to_string()))
+	}
+}
+
+/// Returns whether the address is reachable from the internet, rather than e.g. a loopback,
+/// private or link-local address of the network the service runs in.
+pub fn is_public(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => {
+			let [first, second, ..] = ip.octets();
+			!(ip.is_loopback() ||
+				ip.is_private() ||
+				ip.is_link_local() ||
+				ip.is_unspecified() ||
+				ip.is_broadcast() ||
+				ip.is_documentation() ||
+				// `0.0.0.0/8` and the shared address space of carrier-grade NAT, `100.64.0.0/10`.
+				first == 0 || (first == 100 && second & 0xc0 == 64))
+		},
+		IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+			Some(ip) => is_public(IpAddr::V4(ip)),
+			// Unique local addresses are `fc00::/7`, link-local ones `fe80::/10`.
+			None =>
+				!(ip.is_loopback() ||
+					ip.is_unspecified() ||
+					ip.segments()[0] & 0xfe00 == 0xfc00 ||
+					ip.segments()[0] & 0xffc0 == 0xfe80),
+		},
+	}
+}
+
+impl Default for WebhookChannel {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[async_trait]
+impl Channel for WebhookChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		if message.secrets.is_empty() {
+			return Err(ChannelError::Permanent("Webhook has no secret".into()));
+		}
+
+		let payload = Payload {
+			version: SCHEMA_VERSION,
+			id: delivery_id(address, &message.events),
+			events: message.events.iter().map(PayloadEvent::from).collect(),
+			ack_url: message.action.as_ref().map(|action| action.url.as_str()),
+		};
+		let body = serde_json::to_string(&payload)
+			.map_err(|err| ChannelError::Permanent(format!("Invalid payload: {}", err)))?;
+
+		let now = timestamp();
+		let signatures = message
+			.secrets
+			.iter()
+			.map(|secret| format!("v1={}", sign(secret, now, &body)))
+			.collect::<Vec<_>>();
+
+		let response = self
+			.client_for(address)
+			.await?
+			.post(address)
+			.header(reqwest::header::CONTENT_TYPE, "application/json")
+			.header(SIGNATURE_HEADER, format!("t={},{}", now, signatures.join(",")))
+			.body(body)
+			.send()
+			.await?;
+
+		// Endpoints which reject the delivery, e.g. with a `404`, won't accept it when retried.
+		let status = response.status();
+		if !status.is_success() {
+			return Err(ChannelError::from_status(
+				status,
+				format!("Webhook responded with {}", status),
+			));
+		}
+
+		Ok(())
+	}
+}
+
+/// Generates a new random secret.
+pub fn generate_secret() -> String {
+	storage::tokens::random_token(storage::tokens::TOKEN_BYTES)
+}
+
+/// Computes the hex encoded signature of a delivery.
+pub fn sign(secret: &str, timestamp: Timestamp, body: &str) -> String {
+	let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("any key length is valid");
+	mac.update(format!("{}.{}", timestamp, body).as_bytes());
+	hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks the signature header of a delivery the way receivers are expected to.
+///
+/// Fails if none of the signatures were made with the secret, or if they are older than
+/// [`TOLERANCE`] at `now`.
+pub fn verify(secret: &str, header: &str, body: &str, now: Timestamp) -> bool {
+	let mut timestamp = None;
+	let mut signatures = vec![];
+	for part in header.split(',') {
+		match part.split_once('=') {
+			Some(("t", value)) => timestamp = value.parse::<Timestamp>().ok(),
+			Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+			_ => {},
+		}
+	}
+
+	let Some(timestamp) = timestamp else { return false };
+	if now.abs_diff(timestamp) > TOLERANCE {
+		return false;
+	}
+
+	signatures.iter().any(|signature| {
+		let mut mac =
+			HmacSha256::new_from_slice(secret.as_bytes()).expect("any key length is valid");
+		mac.update(format!("{}.{}", timestamp, body).as_bytes());
+		// Compares in constant time.
+		mac.verify_slice(signature).is_ok()
+	})
+}
+
+/// Derives the id of a delivery, which stays the same when the delivery is retried.
+fn delivery_id(address: &str, events: &[Event]) -> String {
+	let mut hasher = Sha256::new();
+	hasher.update(address.as_bytes());
+	for event in events {
+		hasher.update(serde_json::to_string(event).unwrap_or_default().as_bytes());
+	}
+	hex::encode(&hasher.finalize()[..16])
+}
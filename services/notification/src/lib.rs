@@ -1,6 +1,255 @@
-/// ## Coretime Notification Service
+//! ## Coretime Notification Service
+//!
+//! Responsible for sending out notifications. Notifications are triggered by the tracker
+//! service.
+//!
+//! Triggering a notification doesn't deliver it right away. Instead, a delivery for each subscribed
+//! user and each channel the subscription is routed to is written to the outbox, from which the
+//! [`worker::Worker`] claims, sends and acknowledges them. This way no notification gets lost if a
+//! channel is down or the process crashes. Nothing is delivered to email addresses which weren't
+//! verified yet, nor to Telegram users who didn't start a chat with the bot.
+//!
+//! Users can receive digests instead of a message per event. Their deliveries are due at the time
+//! of the next digest, at which the worker combines them into a single message. Deliveries which
+//! are due during the quiet hours of a user, or while the user muted their notifications, are
+//! deferred to the end of them. Neither applies to critical events, which are delivered right
+//! away.
+//!
+//! Notifications an organization subscribed to are delivered to each of its members, through the
+//! channels the member prefers for the organization, see [`storage::organizations`]. A member who
+//! also subscribed to the same notification is only notified once.
+//!
+//! Critical events of subscriptions with escalation steps raise an alert, which is sent again
+//! through further channels and to further contacts until it is acknowledged, see
+//! [`storage::escalations`].
+//!
+//! Triggering the same event multiple times is safe, as each delivery is identified by a
+//! deterministic key. Deliveries which were already enqueued are skipped.
+//!
+//! Deliveries of subscriptions link to the api, through which the user unsubscribes from them
+//! without signing in, see [`unsubscribe`].
+
+use chrono_tz::Tz;
+use rusqlite::{Connection, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+use storage::{
+	escalations::{self, Alert, Contact},
+	organizations::{Member, OrganizationSubscription},
+	outbox::OutboxJob,
+	subscriptions::Subscription,
+	telegram::TelegramChat,
+	users::User,
+};
+use types::{event::Event, DeliveryMode, Notifications, Notifier, Timestamp};
+
+pub mod bot;
+pub mod channels;
+pub mod message;
+pub mod retry;
+pub mod scheduler;
+pub mod templates;
+pub mod unsubscribe;
+pub mod worker;
+
+#[cfg(test)]
+mod tests;
+
+pub const LOG_TARGET: &str = "notification";
+
+/// Enqueues a delivery of `event` for every user subscribed to it.
 ///
-/// Responsible for sending out notifications. Notifications should be triggered by the 
-/// tracker service.
+/// Returns the number of enqueued deliveries, which doesn't include deliveries that had already
+/// been enqueued before.
+pub fn notify(conn: &Connection, event: &Event) -> Result<usize> {
+	let now = timestamp();
+	let payload = serde_json::to_string(event)
+		.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+	let mut enqueued = 0;
+	for subscription in Subscription::query_all(conn)? {
+		let Some(due) = scheduler::due_at(&subscription.notification, event, now) else {
+			continue;
+		};
+		let Some(user) = User::query_by_id(conn, subscription.user_id)? else {
+			continue;
+		};
+		let critical = event.kind.is_critical();
+		let digest = !critical && user.delivery_mode != DeliveryMode::Immediate;
+		let due = deliver_at(conn, &user, due, critical)?;
+
+		let steps = match critical {
+			true => escalations::query_steps(conn, user.id, &subscription.notification)?,
+			false => vec![],
+		};
+		let key = subscription_key(event, &subscription.notification, user.id);
+		let alert = match steps.is_empty() {
+			true => None,
+			false => Some(Alert::raise(conn, &key, user.id, now)?),
+		};
+		// The cancelled deliveries of an acknowledged alert aren't in the outbox anymore.
+		if alert.as_ref().map_or(false, |alert| alert.acknowledged_at.is_some()) {
+			continue;
+		}
+
+		for channel in subscription.channels.iter() {
+			// The user removed the channel, or didn't verify it yet.
+			if !can_deliver(conn, &user, channel, None)? {
+				continue;
+			}
+
+			let key = dedup_key(event, &subscription.notification, user.id, channel);
+			let job = match &alert {
+				Some(alert) =>
+					OutboxJob::enqueue_alert(conn, &key, alert, channel, None, &payload, due)?,
+				None => OutboxJob::enqueue(conn, &key, user.id, channel, &payload, due, digest)?,
+			};
+			if job.is_some() {
+				OutboxJob::set_subscription(conn, &key, Some(&subscription.notification))?;
+				enqueued += 1;
+			}
+		}
+
+		// Every step of the escalation is cancelled if the alert is acknowledged before it is due.
+		let Some(alert) = alert else { continue };
+		for (position, step) in steps.iter().enumerate() {
+			if !can_deliver(conn, &user, &step.channel, step.contact.as_deref())? {
+				continue;
+			}
+			let key = format!("{}:escalation:{}", key, position);
+			let due = due + step.after_minutes as u64 * 60;
+			let contact = step.contact.as_deref();
+			if OutboxJob::enqueue_alert(conn, &key, &alert, &step.channel, contact, &payload, due)?
+				.is_some()
+			{
+				OutboxJob::set_subscription(conn, &key, Some(&subscription.notification))?;
+				enqueued += 1;
+			}
+		}
+	}
+
+	for subscription in OrganizationSubscription::query_all(conn)? {
+		let Some(due) = scheduler::due_at(&subscription.notification, event, now) else {
+			continue;
+		};
+
+		for member in Member::query_by_organization(conn, subscription.organization_id)? {
+			let Some(user) = User::query_by_id(conn, member.user_id)? else {
+				continue;
+			};
+			let critical = event.kind.is_critical();
+			let digest = !critical && user.delivery_mode != DeliveryMode::Immediate;
+			let due = deliver_at(conn, &user, due, critical)?;
+			let channels = match member.channels.is_empty() {
+				true => user.channels(),
+				false => member.channels,
+			};
+
+			for channel in channels.iter() {
+				if !can_deliver(conn, &user, channel, None)? {
+					continue;
+				}
+
+				// The same key as for a subscription of the member, so that members who also
+				// subscribed themselves aren't notified twice.
+				let key = dedup_key(event, &subscription.notification, user.id, channel);
+				match OutboxJob::enqueue(conn, &key, user.id, channel, &payload, due, digest)? {
+					Some(_) => enqueued += 1,
+					// Unsubscribing doesn't drop a delivery the organization makes as well.
+					None => OutboxJob::set_subscription(conn, &key, None)?,
+				}
+			}
+		}
+	}
+
+	log::info!(target: LOG_TARGET, "Enqueued {} deliveries for {:?}", enqueued, event.kind);
+	Ok(enqueued)
+}
+
+/// Returns when a notification which is due at `due` is delivered to the user, taking into
+/// account the digests, quiet hours and muting of the user.
+///
+/// Critical events are neither collected into digests nor held back by quiet hours.
+fn deliver_at(conn: &Connection, user: &User, due: Timestamp, critical: bool) -> Result<Timestamp> {
+	if critical {
+		return Ok(due);
+	}
+
+	let due = scheduler::digest_at(due, user.delivery_mode, timezone(user));
+	let due = match user.quiet_hours {
+		Some(quiet_hours) => scheduler::defer_for_quiet_hours(due, &quiet_hours, timezone(user)),
+		None => due,
+	};
+	// Muted users get the notification once they are no longer muted.
+	Ok(match User::query_muted_until(conn, user.id)? {
+		Some(muted_until) => due.max(muted_until),
+		None => due,
+	})
+}
+
+/// Returns whether notifications can be delivered through the channel to the user's own address,
+/// or to the contact the user escalates alerts to.
+///
+/// Email addresses have to be verified first, see [`storage::channels`], Telegram users have to
+/// start a chat with the bot, see [`bot`], and contacts have to confirm receiving the alerts, see
+/// [`storage::escalations::Contact`].
+pub fn can_deliver(
+	conn: &Connection,
+	user: &User,
+	channel: &Notifier,
+	contact: Option<&str>,
+) -> Result<bool> {
+	if let Some(contact) = contact {
+		return Contact::is_confirmed(conn, user.id, channel, contact);
+	}
+	match channel {
+		_ if user.address(channel).is_none() => Ok(false),
+		Notifier::Email => storage::channels::is_verified(conn, user.id, channel),
+		Notifier::Telegram => Ok(TelegramChat::query_by_user(conn, user.id)?.is_some()),
+		_ => Ok(true),
+	}
+}
+
+/// Returns the key which identifies the delivery of `event` to a user through a channel.
+///
+/// Only values which stay the same when an event is seen again are part of the key. E.g. the
+/// timestamp of a phase is an estimate which differs every time it is computed, whereas the block
+/// in which the phase starts doesn't.
+pub fn dedup_key(
+	event: &Event,
+	subscription: &Notifications,
+	user_id: u32,
+	channel: &Notifier,
+) -> String {
+	format!("{}:{:?}", subscription_key(event, subscription, user_id), channel)
+}
+
+/// Returns the key which identifies the notification of a user about `event`, regardless of the
+/// channels it is delivered through. Also identifies the alert raised for the notification.
+fn subscription_key(event: &Event, subscription: &Notifications, user_id: u32) -> String {
+	let optional = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or("-".into());
+
+	format!(
+		"{}:{:?}:{}:{}:{}:{}:{:?}:{}",
+		event.network,
+		event.kind,
+		event.block,
+		optional(event.timeslice),
+		optional(event.core.map(Into::into)),
+		optional(event.para_id),
+		subscription,
+		user_id,
+	)
+}
+
+/// Returns the current unix timestamp in seconds.
+pub fn timestamp() -> Timestamp {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or_default()
+}
 
-// TODO
\ No newline at end of file
+/// Returns the time zone of the user, falling back to UTC if it isn't known.
+pub fn timezone(user: &User) -> Tz {
+	user.timezone.parse().unwrap_or(Tz::UTC)
+}
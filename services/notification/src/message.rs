@@ -0,0 +1,108 @@
+use crate::templates::Format;
+use types::event::Event;
+
+/// The content of a notification as it is sent out through a channel.
+///
+/// Every channel picks the format it supports.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Message {
+	/// A short summary of the notification.
+	pub subject: String,
+	/// The notification as plain text.
+	pub text: String,
+	/// The notification as HTML.
+	pub html: String,
+	/// The notification as Telegram MarkdownV2.
+	pub markdown: String,
+	/// Values of the event which channels can display separately, e.g. as the fields of a
+	/// Discord embed.
+	pub fields: Vec<Field>,
+	/// The events the notification is about, for channels which deliver them as data.
+	pub events: Vec<Event>,
+	/// The secrets with which the delivery is signed, for channels which sign their deliveries.
+	///
+	/// Not part of the rendered content, the worker sets them before handing the message to the
+	/// channel.
+	pub secrets: Vec<String>,
+	/// A link offered alongside the notification, e.g. to acknowledge an alert.
+	///
+	/// Set by the worker through [`Message::with_action`]. Channels which support buttons show it
+	/// as one, the others link it in the text.
+	pub action: Option<Action>,
+	/// The url which unsubscribes the recipient from the notification.
+	///
+	/// Set by the worker through [`Message::with_unsubscribe`]. Emails also offer it through the
+	/// `List-Unsubscribe` header.
+	pub unsubscribe: Option<String>,
+	/// Identifies the delivery, and stays the same when the delivery is retried.
+	///
+	/// Set by the worker from the dedup keys of the delivered jobs. Channels whose api
+	/// deduplicates requests, e.g. Matrix, derive the key of their request from it.
+	pub delivery: Option<String>,
+}
+
+impl Message {
+	/// A message which isn't about any event, e.g. the link to confirm an email address.
+	pub fn plain(subject: &str, text: &str) -> Self {
+		Self {
+			subject: subject.to_string(),
+			text: text.to_string(),
+			html: format!("<p>{}</p>", Format::Html.escape(text)),
+			markdown: Format::Markdown.escape(text),
+			fields: vec![],
+			events: vec![],
+			secrets: vec![],
+			action: None,
+			unsubscribe: None,
+			delivery: None,
+		}
+	}
+
+	/// Offers the action alongside the message, linking it at the end of the text and HTML.
+	pub fn with_action(self, action: Action) -> Self {
+		let text = format!("{}\n\n{}: {}", self.text, action.label, action.url);
+		let html = format!(
+			"{}\n<p><a href=\"{}\">{}</a></p>",
+			self.html,
+			Format::Html.escape(&action.url),
+			Format::Html.escape(&action.label)
+		);
+		Self { text, html, action: Some(action), ..self }
+	}
+
+	/// Links the url which unsubscribes from the message at the end of every format.
+	pub fn with_unsubscribe(self, label: &str, url: String) -> Self {
+		let text = format!("{}\n\n{}: {}", self.text, label, url);
+		let html = format!(
+			"{}\n<p><small><a href=\"{}\">{}</a></small></p>",
+			self.html,
+			Format::Html.escape(&url),
+			Format::Html.escape(label)
+		);
+		// Within the url of a MarkdownV2 link, only `)` and `\` have to be escaped.
+		let markdown = format!(
+			"{}\n\n[{}]({})",
+			self.markdown,
+			Format::Markdown.escape(label),
+			url.replace('\\', "\\\\").replace(')', "\\)")
+		);
+		Self { text, html, markdown, unsubscribe: Some(url), ..self }
+	}
+}
+
+/// A labeled value of an event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+	/// The localized label of the value.
+	pub name: String,
+	/// The formatted value.
+	pub value: String,
+}
+
+/// A link the recipient can follow to act on a notification.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Action {
+	/// The localized label of the link.
+	pub label: String,
+	pub url: String,
+}
@@ -0,0 +1,43 @@
+//! Policy for retrying failed deliveries.
+
+use rand::Rng;
+use types::Timestamp;
+
+/// Retries transient failures with jittered exponential backoff.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+	/// The delay before the first retry in seconds.
+	pub base_delay: u64,
+	/// The upper bound of the delay between two attempts in seconds.
+	pub max_delay: u64,
+	/// The number of attempts after which a job is moved to the dead-letter state.
+	pub max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		// With these values a delivery is attempted for roughly a day.
+		Self { base_delay: 30, max_delay: 3 * 60 * 60, max_attempts: 12 }
+	}
+}
+
+impl RetryPolicy {
+	/// Returns when to retry a job which failed for the `attempts`th time.
+	///
+	/// Returns `None` if the job shouldn't be retried anymore.
+	pub fn next_attempt_at(&self, attempts: u32, now: Timestamp) -> Option<Timestamp> {
+		if attempts >= self.max_attempts {
+			return None;
+		}
+
+		let backoff = self
+			.base_delay
+			.saturating_mul(2u64.saturating_pow(attempts.saturating_sub(1)))
+			.min(self.max_delay);
+		// Spread out the retries so that jobs which failed together don't hit the channel again
+		// all at once.
+		let delay = rand::thread_rng().gen_range(backoff / 2..=backoff);
+
+		Some(now + delay)
+	}
+}
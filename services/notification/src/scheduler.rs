@@ -0,0 +1,109 @@
+//! Decides when a subscriber should be notified about an event.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use types::{
+	event::{Event, EventKind},
+	DeliveryMode, Notifications, PhaseNotification, QuietHours, Timestamp,
+};
+
+/// The hour of the day, in the user's time zone, at which daily and weekly digests are sent.
+pub const DIGEST_HOUR: u32 = 9;
+
+/// Returns the time at which the subscriber of `subscription` should be notified about `event`.
+///
+/// Returns `None` if the subscription doesn't cover the event, or if the moment the subscriber
+/// wants to be notified about has already passed.
+pub fn due_at(subscription: &Notifications, event: &Event, now: Timestamp) -> Option<Timestamp> {
+	let phase_notification = match (subscription, event.kind) {
+		(Notifications::InterludePhase(n), EventKind::InterludePhase) |
+		(Notifications::LeadinPhaseStart(n), EventKind::LeadinPhase) |
+		(Notifications::FixedPhaseStart(n), EventKind::FixedPhase) => n,
+		(Notifications::CoretimeSale, EventKind::CoretimeSale) => return Some(event.timestamp),
+		(
+			Notifications::ParachainState(para_id),
+			EventKind::CoreAssigned | EventKind::CoreRenewed | EventKind::CoreExpiring,
+		) if event.para_id == Some(*para_id) => return Some(event.timestamp),
+		_ => return None,
+	};
+
+	let (moment, offset) = match phase_notification {
+		PhaseNotification::PriorStart(offset) => (event.timestamp, offset),
+		PhaseNotification::PriorEnd(offset) => (event.end_timestamp?, offset),
+	};
+
+	if moment < now {
+		return None;
+	}
+
+	Some(moment.saturating_sub(*offset))
+}
+
+/// Moves `due` to the end of the quiet hours if it falls into them.
+///
+/// The quiet hours are interpreted in `timezone`.
+pub fn defer_for_quiet_hours(due: Timestamp, quiet_hours: &QuietHours, timezone: Tz) -> Timestamp {
+	let Some(local) = DateTime::from_timestamp(due as i64, 0).map(|t| t.with_timezone(&timezone))
+	else {
+		return due;
+	};
+	if !quiet_hours.contains(local.hour() as u8) {
+		return due;
+	}
+
+	// A window spanning midnight which started on the previous day ends today, otherwise the
+	// window ends on the next day.
+	let mut end_day = local.date_naive();
+	if local.hour() >= quiet_hours.end as u32 {
+		end_day = end_day.succ_opt().unwrap_or(end_day);
+	}
+	let Some(end) = end_day.and_hms_opt(quiet_hours.end as u32, 0, 0) else {
+		return due;
+	};
+
+	to_timestamp(end, timezone).unwrap_or(due)
+}
+
+/// Returns the time of the first digest which is sent at or after `due`.
+///
+/// Hourly digests are sent at the start of every hour, daily digests at [`DIGEST_HOUR`] and
+/// weekly digests at [`DIGEST_HOUR`] on Mondays. Returns `due` for immediate deliveries.
+pub fn digest_at(due: Timestamp, delivery_mode: DeliveryMode, timezone: Tz) -> Timestamp {
+	let Some(local) = DateTime::from_timestamp(due as i64, 0).map(|t| t.with_timezone(&timezone))
+	else {
+		return due;
+	};
+	let local = local.naive_local();
+	let hour = local.date().and_hms_opt(local.hour(), 0, 0).unwrap_or(local);
+	let digest_hour = local.date().and_hms_opt(DIGEST_HOUR, 0, 0).unwrap_or(local);
+
+	let slot = match delivery_mode {
+		DeliveryMode::Immediate => return due,
+		DeliveryMode::HourlyDigest if hour < local => hour + Duration::hours(1),
+		DeliveryMode::HourlyDigest => hour,
+		DeliveryMode::DailyDigest if digest_hour < local => digest_hour + Duration::days(1),
+		DeliveryMode::DailyDigest => digest_hour,
+		DeliveryMode::WeeklyDigest => {
+			let monday =
+				digest_hour - Duration::days(local.weekday().num_days_from_monday() as i64);
+			if monday < local {
+				monday + Duration::weeks(1)
+			} else {
+				monday
+			}
+		},
+	};
+
+	to_timestamp(slot, timezone).unwrap_or(due)
+}
+
+/// Converts a local time to a timestamp.
+///
+/// Local times which don't exist because the clocks are moved forward are moved forward as well.
+fn to_timestamp(local: NaiveDateTime, timezone: Tz) -> Option<Timestamp> {
+	timezone
+		.from_local_datetime(&local)
+		.earliest()
+		.or_else(|| timezone.from_local_datetime(&(local + Duration::hours(1))).earliest())
+		.map(|time| time.timestamp() as Timestamp)
+}
@@ -0,0 +1,91 @@
+use super::Locale;
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use types::event::Event;
+
+/// The values available to templates.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Context {
+	values: HashMap<&'static str, String>,
+}
+
+impl Context {
+	/// The names of all variables which can be used in a template.
+	pub const VARIABLES: [&'static str; 9] = [
+		"network",
+		"block",
+		"timeslice",
+		"price",
+		"cores_left",
+		"para_id",
+		"start",
+		"end",
+		"count",
+	];
+
+	pub fn get(&self, name: &str) -> Option<&String> {
+		self.values.get(name)
+	}
+
+	pub fn insert(&mut self, name: &'static str, value: String) {
+		self.values.insert(name, value);
+	}
+
+	/// Returns the values of the event, formatted according to the locale. Times are shown in
+	/// the given time zone.
+	pub fn new(event: &Event, locale: &Locale, timezone: Tz) -> Self {
+		let mut context = Context::default();
+		let (symbol, decimals) = token(&event.network);
+
+		context.insert("network", capitalize(&event.network));
+		context.insert("block", event.block.to_string());
+		context.insert("start", locale.format_timestamp(event.timestamp, timezone));
+		if let Some(end) = event.end_timestamp {
+			context.insert("end", locale.format_timestamp(end, timezone));
+		}
+		if let Some(timeslice) = event.timeslice {
+			context.insert("timeslice", timeslice.to_string());
+		}
+		if let Some(price) = event.price {
+			context
+				.insert("price", format!("{} {}", locale.format_amount(price, decimals), symbol));
+		}
+		if let Some(cores_left) = event.cores_left {
+			context.insert("cores_left", locale.format_integer(cores_left.into()));
+		}
+		if let Some(para_id) = event.para_id {
+			context.insert("para_id", para_id.to_string());
+		}
+
+		context
+	}
+
+	/// Returns the values of a digest of the events.
+	pub fn digest(events: &[Event], locale: &Locale) -> Self {
+		let mut context = Context::default();
+
+		if let Some(event) = events.first() {
+			context.insert("network", capitalize(&event.network));
+		}
+		context.insert("count", locale.format_integer(events.len() as u128));
+
+		context
+	}
+}
+
+/// Returns the symbol and the number of decimals of the native token of the network.
+fn token(network: &str) -> (&'static str, u32) {
+	match network {
+		"polkadot" => ("DOT", 10),
+		"kusama" => ("KSM", 12),
+		_ => ("ROC", 12),
+	}
+}
+
+fn capitalize(value: &str) -> String {
+	let mut chars = value.chars();
+	match chars.next() {
+		Some(first) => first.to_uppercase().chain(chars).collect(),
+		None => String::new(),
+	}
+}
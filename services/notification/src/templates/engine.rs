@@ -0,0 +1,91 @@
+use super::{Context, Format, TemplateError};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Node {
+	Text(String),
+	Variable(String),
+	/// Only rendered if the variable has a value.
+	Section(String, Vec<Node>),
+}
+
+/// A parsed template.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Template {
+	nodes: Vec<Node>,
+}
+
+impl Template {
+	pub fn parse(source: &str) -> Result<Self, TemplateError> {
+		// The sections which are currently open, together with the nodes preceding them.
+		let mut open: Vec<(String, Vec<Node>)> = vec![];
+		let mut nodes = vec![];
+		let mut rest = source;
+
+		while let Some(start) = rest.find("{{") {
+			if start > 0 {
+				nodes.push(Node::Text(rest[..start].to_string()));
+			}
+			let end =
+				rest[start..].find("}}").ok_or(TemplateError("Unclosed placeholder".into()))? +
+					start;
+			let tag = rest[start + 2..end].trim();
+			rest = &rest[end + 2..];
+
+			if let Some(name) = tag.strip_prefix('#') {
+				let name = Self::variable(name)?;
+				open.push((name, std::mem::take(&mut nodes)));
+			} else if let Some(name) = tag.strip_prefix('/') {
+				let name = Self::variable(name)?;
+				let (opened, mut outer) =
+					open.pop().ok_or(TemplateError(format!("Unexpected end of {}", name)))?;
+				if opened != name {
+					return Err(TemplateError(format!("Expected end of {}", opened)));
+				}
+				outer.push(Node::Section(name, std::mem::take(&mut nodes)));
+				nodes = outer;
+			} else {
+				nodes.push(Node::Variable(Self::variable(tag)?));
+			}
+		}
+
+		if let Some((name, _)) = open.pop() {
+			return Err(TemplateError(format!("Unclosed section {}", name)));
+		}
+		if !rest.is_empty() {
+			nodes.push(Node::Text(rest.to_string()));
+		}
+
+		Ok(Self { nodes })
+	}
+
+	pub fn render(&self, context: &Context, format: Format) -> String {
+		let mut output = String::new();
+		Self::render_nodes(&self.nodes, context, format, &mut output);
+		output
+	}
+
+	fn render_nodes(nodes: &[Node], context: &Context, format: Format, output: &mut String) {
+		for node in nodes {
+			match node {
+				Node::Text(text) => output.push_str(text),
+				Node::Variable(name) =>
+					if let Some(value) = context.get(name) {
+						output.push_str(&format.escape(value));
+					},
+				Node::Section(name, nodes) =>
+					if context.get(name).is_some() {
+						Self::render_nodes(nodes, context, format, output);
+					},
+			}
+		}
+	}
+
+	fn variable(name: &str) -> Result<String, TemplateError> {
+		let name = name.trim();
+		if !Context::VARIABLES.contains(&name) {
+			return Err(TemplateError(format!("Unknown variable {}", name)));
+		}
+
+		Ok(name.to_string())
+	}
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Datelike};
+use chrono_tz::Tz;
+use types::{Balance, Timestamp};
+
+/// Language specific rules for formatting values.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Locale {
+	/// The primary language subtag, e.g. `de`.
+	pub language: &'static str,
+	pub thousands_separator: char,
+	pub decimal_separator: char,
+	months: [&'static str; 12],
+	/// Date format where `{day}`, `{month}`, `{year}`, `{time}` and `{zone}` are replaced.
+	date_format: &'static str,
+	/// Labels of the values which are shown as separate fields.
+	pub labels: Labels,
+}
+
+/// Labels of the values which are shown as separate fields, see [`crate::message::Field`], and
+/// of the actions offered alongside a message, see [`crate::message::Action`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Labels {
+	pub price: &'static str,
+	pub cores_left: &'static str,
+	pub timeslice: &'static str,
+	pub acknowledge: &'static str,
+	pub unsubscribe: &'static str,
+}
+
+const LOCALES: [Locale; 3] = [
+	Locale {
+		language: "en",
+		thousands_separator: ',',
+		decimal_separator: '.',
+		months: [
+			"January",
+			"February",
+			"March",
+			"April",
+			"May",
+			"June",
+			"July",
+			"August",
+			"September",
+			"October",
+			"November",
+			"December",
+		],
+		date_format: "{month} {day}, {year}, {time} {zone}",
+		labels: Labels {
+			price: "Price",
+			cores_left: "Cores left",
+			timeslice: "Timeslice",
+			acknowledge: "Acknowledge",
+			unsubscribe: "Unsubscribe",
+		},
+	},
+	Locale {
+		language: "de",
+		thousands_separator: '.',
+		decimal_separator: ',',
+		months: [
+			"Januar",
+			"Februar",
+			"März",
+			"April",
+			"Mai",
+			"Juni",
+			"Juli",
+			"August",
+			"September",
+			"Oktober",
+			"November",
+			"Dezember",
+		],
+		date_format: "{day}. {month} {year}, {time} {zone}",
+		labels: Labels {
+			price: "Preis",
+			cores_left: "Verfügbare Cores",
+			timeslice: "Timeslice",
+			acknowledge: "Bestätigen",
+			unsubscribe: "Abbestellen",
+		},
+	},
+	Locale {
+		language: "es",
+		thousands_separator: '.',
+		decimal_separator: ',',
+		months: [
+			"enero",
+			"febrero",
+			"marzo",
+			"abril",
+			"mayo",
+			"junio",
+			"julio",
+			"agosto",
+			"septiembre",
+			"octubre",
+			"noviembre",
+			"diciembre",
+		],
+		date_format: "{day} de {month} de {year}, {time} {zone}",
+		labels: Labels {
+			price: "Precio",
+			cores_left: "Cores disponibles",
+			timeslice: "Timeslice",
+			acknowledge: "Confirmar recepción",
+			unsubscribe: "Cancelar suscripción",
+		},
+	},
+];
+
+impl Locale {
+	/// Returns the formatting rules for a language tag such as `de` or `pt-BR`.
+	///
+	/// Regional variants use the rules of their primary language, languages without rules fall
+	/// back to English.
+	pub fn for_language(language: &str) -> &'static Locale {
+		let primary = primary_language(language);
+		LOCALES.iter().find(|locale| locale.language == primary).unwrap_or(&LOCALES[0])
+	}
+
+	/// Formats an integer with thousands separators.
+	pub fn format_integer(&self, value: u128) -> String {
+		let digits = value.to_string();
+		let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+		for (i, digit) in digits.chars().enumerate() {
+			if i > 0 && (digits.len() - i) % 3 == 0 {
+				formatted.push(self.thousands_separator);
+			}
+			formatted.push(digit);
+		}
+		formatted
+	}
+
+	/// Formats an amount of the smallest unit as a decimal number of tokens.
+	pub fn format_amount(&self, amount: Balance, decimals: u32) -> String {
+		let unit = 10u128.pow(decimals);
+		let fraction = format!("{:0width$}", amount % unit, width = decimals as usize);
+		let fraction = fraction.trim_end_matches('0');
+		let integer = self.format_integer(amount / unit);
+
+		if fraction.is_empty() {
+			integer
+		} else {
+			format!("{}{}{}", integer, self.decimal_separator, fraction)
+		}
+	}
+
+	/// Formats a timestamp as a date and time in the given time zone.
+	pub fn format_timestamp(&self, timestamp: Timestamp, timezone: Tz) -> String {
+		DateTime::from_timestamp(timestamp as i64, 0)
+			.map(|time| time.with_timezone(&timezone))
+			.map(|time| {
+				self.date_format
+					.replace("{day}", &time.day().to_string())
+					.replace("{month}", self.months[time.month0() as usize])
+					.replace("{year}", &time.year().to_string())
+					.replace("{time}", &time.format("%H:%M").to_string())
+					.replace("{zone}", &time.format("%Z").to_string())
+			})
+			.unwrap_or_default()
+	}
+}
+
+/// Returns the primary subtag of a language tag, e.g. `pt` for `pt-BR`.
+pub fn primary_language(language: &str) -> &str {
+	language.split('-').next().unwrap_or(language)
+}
@@ -0,0 +1,338 @@
+//! Renders the messages sent out through the channels.
+//!
+//! There is a template for every kind of event in each message [`Format`]. Templates contain
+//! `{{variable}}` placeholders which are replaced with values from the event. A section
+//! `{{#variable}}...{{/variable}}` is only rendered if the event has a value for the variable.
+//!
+//! Values are escaped according to the format they are rendered into. The literal text of a
+//! template is never escaped, so it has to be valid in the target format already.
+//!
+//! Users who receive digests get a single message for multiple events, which starts with the
+//! [`DIGEST`] template and contains the messages of all events.
+//!
+//! Messages are rendered in the language of the user. Every language has its own bundle of
+//! templates in `templates/<language>.toml`. A template missing from a bundle falls back to the
+//! bundle of the primary language (`pt` for `pt-BR`) and then to English, which has to be
+//! complete. Numbers and dates are formatted according to the [`Locale`] of the language, and
+//! times are shown in the time zone of the user.
+//!
+//! Operators can override any of the built-in templates or add new languages by placing
+//! `<language>.toml` bundles with the same layout into the directory configured through
+//! `TEMPLATES_DIR`.
+
+use crate::message::{Field, Message};
+use chrono_tz::Tz;
+use std::{
+	collections::HashMap,
+	env, fmt, fs,
+	path::{Path, PathBuf},
+};
+use types::event::{Event, EventKind};
+
+mod context;
+mod engine;
+mod locale;
+
+pub use context::Context;
+pub use engine::Template;
+pub use locale::{primary_language, Labels, Locale};
+
+/// The language every other language falls back to.
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+const BUILTIN: [(&str, &str); 3] = [
+	("en", include_str!("../../templates/en.toml")),
+	("de", include_str!("../../templates/de.toml")),
+	("es", include_str!("../../templates/es.toml")),
+];
+
+/// Error returned when templates can't be loaded or rendered.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TemplateError(pub String);
+
+impl fmt::Display for TemplateError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+/// The formats in which a message is rendered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Format {
+	/// Email subject, also used as a title by channels which support one.
+	Subject,
+	/// Plain-text email.
+	Text,
+	/// HTML email.
+	Html,
+	/// Telegram MarkdownV2.
+	Markdown,
+}
+
+impl Format {
+	pub const ALL: [Format; 4] = [Format::Subject, Format::Text, Format::Html, Format::Markdown];
+
+	/// The key of the format within a template bundle.
+	pub fn key(&self) -> &'static str {
+		match self {
+			Format::Subject => "subject",
+			Format::Text => "text",
+			Format::Html => "html",
+			Format::Markdown => "markdown",
+		}
+	}
+
+	/// Escapes a value so that it is displayed as is in this format.
+	pub fn escape(&self, value: &str) -> String {
+		match self {
+			Format::Subject | Format::Text => value.to_string(),
+			Format::Html => value
+				.replace('&', "&amp;")
+				.replace('<', "&lt;")
+				.replace('>', "&gt;")
+				.replace('"', "&quot;")
+				.replace('\'', "&#39;"),
+			Format::Markdown => {
+				let mut escaped = String::with_capacity(value.len());
+				for c in value.chars() {
+					if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+						escaped.push('\\');
+					}
+					escaped.push(c);
+				}
+				escaped
+			},
+		}
+	}
+}
+
+/// The key of the event kind within a template bundle.
+pub fn kind_key(kind: EventKind) -> &'static str {
+	match kind {
+		EventKind::InterludePhase => "interlude_phase",
+		EventKind::LeadinPhase => "leadin_phase",
+		EventKind::FixedPhase => "fixed_phase",
+		EventKind::CoretimeSale => "coretime_sale",
+		EventKind::CoreAssigned => "core_assigned",
+		EventKind::CoreRenewed => "core_renewed",
+		EventKind::CoreExpiring => "core_expiring",
+	}
+}
+
+pub const KINDS: [EventKind; 7] = [
+	EventKind::InterludePhase,
+	EventKind::LeadinPhase,
+	EventKind::FixedPhase,
+	EventKind::CoretimeSale,
+	EventKind::CoreAssigned,
+	EventKind::CoreRenewed,
+	EventKind::CoreExpiring,
+];
+
+/// The section of a template bundle which holds the templates of digests.
+///
+/// A digest starts with the rendered digest template, followed by the messages of the events it
+/// contains.
+pub const DIGEST: &str = "digest";
+
+/// The sections of a template bundle: one for each event kind and one for digests.
+fn sections() -> impl Iterator<Item = &'static str> {
+	KINDS.into_iter().map(kind_key).chain([DIGEST])
+}
+
+type Bundle = HashMap<(&'static str, Format), Template>;
+
+/// The template bundles of all languages.
+#[derive(Debug)]
+pub struct Templates {
+	bundles: HashMap<String, Bundle>,
+}
+
+impl Templates {
+	/// Returns the built-in templates.
+	pub fn builtin() -> Self {
+		Self::load(None).expect("Built-in templates are valid; qed")
+	}
+
+	/// Loads the built-in templates and applies the bundles found in `dir`.
+	pub fn load(dir: Option<&Path>) -> Result<Self, TemplateError> {
+		let mut bundles: HashMap<String, Bundle> = HashMap::new();
+		for (language, source) in BUILTIN {
+			Self::apply_bundle(bundles.entry(language.to_string()).or_default(), source)?;
+		}
+
+		if let Some(dir) = dir {
+			for (language, path) in Self::bundle_files(dir)? {
+				let source = fs::read_to_string(&path).map_err(|err| {
+					TemplateError(format!("Failed to read {}: {}", path.display(), err))
+				})?;
+				Self::apply_bundle(bundles.entry(language).or_default(), &source)
+					.map_err(|err| TemplateError(format!("{}: {}", path.display(), err)))?;
+			}
+		}
+
+		let fallback = &bundles[FALLBACK_LANGUAGE];
+		for section in sections() {
+			for format in Format::ALL {
+				if !fallback.contains_key(&(section, format)) {
+					return Err(TemplateError(format!(
+						"Missing {} template for {}",
+						format.key(),
+						section
+					)));
+				}
+			}
+		}
+
+		Ok(Self { bundles })
+	}
+
+	/// Loads the templates, applying the overrides from `TEMPLATES_DIR` if it is set.
+	pub fn from_env() -> Result<Self, TemplateError> {
+		let dir = env::var("TEMPLATES_DIR").ok();
+		Self::load(dir.as_deref().map(Path::new))
+	}
+
+	/// Renders the event in every format, in the given language and time zone.
+	pub fn render(
+		&self,
+		event: &Event,
+		language: &str,
+		timezone: Tz,
+	) -> Result<Message, TemplateError> {
+		let locale = Locale::for_language(language);
+		let context = Context::new(event, locale, timezone);
+		let section = kind_key(event.kind);
+
+		let labels = &locale.labels;
+		let fields = [
+			("price", labels.price),
+			("cores_left", labels.cores_left),
+			("timeslice", labels.timeslice),
+		]
+		.into_iter()
+		.filter_map(|(variable, name)| {
+			let value = context.get(variable)?.clone();
+			Some(Field { name: name.to_string(), value })
+		})
+		.collect();
+
+		Ok(Message {
+			subject: self.render_format(language, &context, section, Format::Subject)?,
+			text: self.render_format(language, &context, section, Format::Text)?,
+			html: self.render_format(language, &context, section, Format::Html)?,
+			markdown: self.render_format(language, &context, section, Format::Markdown)?,
+			fields,
+			events: vec![event.clone()],
+			secrets: vec![],
+			action: None,
+			unsubscribe: None,
+			delivery: None,
+		})
+	}
+
+	/// Renders a single message which contains all of the events.
+	pub fn render_digest(
+		&self,
+		events: &[Event],
+		language: &str,
+		timezone: Tz,
+	) -> Result<Message, TemplateError> {
+		let context = Context::digest(events, Locale::for_language(language));
+		let messages = events
+			.iter()
+			.map(|event| self.render(event, language, timezone))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let text = messages
+			.iter()
+			.map(|message| format!("{}\n\n{}", message.subject, message.text));
+		let html = messages.iter().map(|message| {
+			format!("<h3>{}</h3>\n{}", Format::Html.escape(&message.subject), message.html)
+		});
+		let markdown = messages.iter().map(|message| message.markdown.clone());
+
+		let header = |format| self.render_format(language, &context, DIGEST, format);
+		Ok(Message {
+			subject: header(Format::Subject)?,
+			text: [header(Format::Text)?].into_iter().chain(text).collect::<Vec<_>>().join("\n\n"),
+			html: [header(Format::Html)?]
+				.into_iter()
+				.chain(html)
+				.collect::<Vec<_>>()
+				.join("\n<hr>\n"),
+			markdown: [header(Format::Markdown)?]
+				.into_iter()
+				.chain(markdown)
+				.collect::<Vec<_>>()
+				.join("\n\n"),
+			fields: vec![],
+			events: events.to_vec(),
+			secrets: vec![],
+			action: None,
+			unsubscribe: None,
+			delivery: None,
+		})
+	}
+
+	pub fn render_format(
+		&self,
+		language: &str,
+		context: &Context,
+		section: &str,
+		format: Format,
+	) -> Result<String, TemplateError> {
+		let template = [language, primary_language(language), FALLBACK_LANGUAGE]
+			.into_iter()
+			.filter_map(|language| self.bundles.get(language))
+			.find_map(|bundle| bundle.get(&(section, format)))
+			.ok_or(TemplateError(format!("Missing {} template for {}", format.key(), section)))?;
+
+		Ok(template.render(context, format).trim().to_string())
+	}
+
+	/// Returns the language and path of every `<language>.toml` bundle in `dir`.
+	fn bundle_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, TemplateError> {
+		let entries = fs::read_dir(dir)
+			.map_err(|err| TemplateError(format!("Failed to read {}: {}", dir.display(), err)))?;
+
+		let mut files = vec![];
+		for entry in entries {
+			let path = entry
+				.map_err(|err| TemplateError(format!("Failed to read {}: {}", dir.display(), err)))?
+				.path();
+			if path.extension().map_or(false, |extension| extension == "toml") {
+				if let Some(language) = path.file_stem().and_then(|stem| stem.to_str()) {
+					files.push((language.to_string(), path.clone()));
+				}
+			}
+		}
+
+		Ok(files)
+	}
+
+	fn apply_bundle(templates: &mut Bundle, bundle: &str) -> Result<(), TemplateError> {
+		let bundle: HashMap<String, HashMap<String, String>> = toml::from_str(bundle)
+			.map_err(|err| TemplateError(format!("Invalid template bundle: {}", err)))?;
+
+		for (section_name, sources) in bundle {
+			let section = sections()
+				.find(|section| *section == section_name)
+				.ok_or(TemplateError(format!("Unknown section: {}", section_name)))?;
+
+			for (format_name, source) in sources {
+				let format = Format::ALL
+					.into_iter()
+					.find(|format| format.key() == format_name)
+					.ok_or(TemplateError(format!("Unknown format: {}", format_name)))?;
+
+				let template = Template::parse(&source).map_err(|err| {
+					TemplateError(format!("{}.{}: {}", section_name, format_name, err))
+				})?;
+				templates.insert((section, format), template);
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,259 @@
+use crate::{
+	bot::{commands::Command, handle_update, TelegramBot, Update},
+	channels::Channels,
+	notify,
+	tests::mock::{create_user, event, execute_with, http_response, user, HttpStub, MockChannel},
+	timestamp,
+	worker::Worker,
+};
+use serde_json::{json, Value};
+use storage::{
+	init_db, sales::SaleStatus, subscriptions::Subscription, telegram::TelegramChat, users::User,
+};
+use types::{event::EventKind, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "bot-tests.db";
+pub const DELIVERY_DB_PATH: &'static str = "bot-delivery-tests.db";
+pub const POLLING_DB_PATH: &'static str = "bot-polling-tests.db";
+pub const USERNAME_DB_PATH: &'static str = "bot-username-tests.db";
+
+fn update(chat_id: i64, username: &str, text: &str) -> Update {
+	serde_json::from_value(json!({
+		"update_id": 1,
+		"message": {
+			"chat": { "id": chat_id, "type": "private" },
+			"from": { "username": username },
+			"text": text,
+		},
+	}))
+	.unwrap()
+}
+
+fn telegram_user(id: u32, handle: &str) -> User {
+	User { tg_handle: Some(handle.to_string()), ..user(id) }
+}
+
+#[test]
+fn commands_are_parsed() {
+	assert_eq!(Command::parse("hello"), None);
+	assert_eq!(Command::parse("/start"), Some(Ok(Command::Start)));
+	assert_eq!(Command::parse("/list@CoretimeBot"), Some(Ok(Command::List)));
+	assert_eq!(Command::parse("/subscribe 2000"), Some(Ok(Command::Subscribe(2000))));
+	assert_eq!(Command::parse("/unsubscribe"), Some(Ok(Command::Unsubscribe(None))));
+	assert_eq!(Command::parse("/unsubscribe 2000"), Some(Ok(Command::Unsubscribe(Some(2000)))));
+	assert_eq!(Command::parse("/mute"), Some(Ok(Command::Mute(8))));
+	assert_eq!(Command::parse("/mute 24"), Some(Ok(Command::Mute(24))));
+	assert_eq!(Command::parse("/unknown"), Some(Ok(Command::Help)));
+
+	assert!(matches!(Command::parse("/subscribe"), Some(Err(_))));
+	assert!(matches!(Command::parse("/subscribe abc"), Some(Err(_))));
+	assert!(matches!(Command::parse("/subscribe 2000 2001"), Some(Err(_))));
+	assert!(matches!(Command::parse("/mute 0"), Some(Err(_))));
+	assert!(matches!(Command::parse("/mute 1000"), Some(Err(_))));
+}
+
+#[test]
+fn chats_manage_the_subscriptions_of_their_user() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+		create_user(&conn, &telegram_user(0, "@alice"));
+		let now = 1_714_564_800;
+		let reply = |text: &str| {
+			handle_update(&conn, &update(42, "alice", text), now).unwrap().unwrap().text
+		};
+
+		// CASE 1: messages which aren't commands are ignored.
+		assert_eq!(handle_update(&conn, &update(42, "alice", "hi"), now).unwrap(), None);
+
+		// CASE 2: chats have to be linked first.
+		assert!(reply("/list").contains("/start"));
+
+		// CASE 3: senders without an account can't link their chat.
+		let reply_bob = handle_update(&conn, &update(43, "bob", "/start"), now).unwrap().unwrap();
+		assert!(reply_bob.text.contains("no account"));
+		assert_eq!(TelegramChat::query_by_chat(&conn, 43).unwrap(), None);
+
+		// CASE 4: commands aren't executed in groups, any member could send them.
+		let group: Update = serde_json::from_value(json!({
+			"update_id": 1,
+			"message": {
+				"chat": { "id": -100, "type": "group" },
+				"from": { "username": "alice" },
+				"text": "/start",
+			},
+		}))
+		.unwrap();
+		let reply_group = handle_update(&conn, &group, now).unwrap().unwrap();
+		assert!(reply_group.text.contains("private chat"));
+		assert_eq!(TelegramChat::query_by_chat(&conn, -100).unwrap(), None);
+
+		// CASE 5: the chat is linked to the user with the handle of the sender.
+		assert!(reply("/start").contains("linked"));
+		let chat = TelegramChat::query_by_chat(&conn, 42).unwrap().unwrap();
+		assert_eq!((chat.user_id, chat.handle.as_str()), (0, "@alice"));
+
+		// CASE 6: subscribing delivers the state of the parachain to the chat.
+		assert!(reply("/subscribe 999").contains("isn't a parachain"));
+		reply("/subscribe 2000");
+		assert_eq!(
+			Subscription::query_by_user(&conn, 0).unwrap(),
+			vec![Subscription {
+				user_id: 0,
+				notification: Notifications::ParachainState(2000),
+				channels: vec![Notifier::Telegram],
+			}]
+		);
+		assert_eq!(reply("/list"), "Your subscriptions:\n- State of parachain 2000 (Telegram)");
+
+		// CASE 7: unsubscribing only removes the chat from the subscription.
+		Subscription::create(
+			&conn,
+			0,
+			&Notifications::CoretimeSale,
+			&[Notifier::Email, Notifier::Telegram],
+		)
+		.unwrap();
+		assert!(reply("/unsubscribe 2001").contains("aren't subscribed"));
+		reply("/unsubscribe");
+		assert_eq!(
+			Subscription::query_by_user(&conn, 0).unwrap(),
+			vec![Subscription {
+				user_id: 0,
+				notification: Notifications::CoretimeSale,
+				channels: vec![Notifier::Email],
+			}]
+		);
+
+		// CASE 8: muting holds back notifications until the given time.
+		assert_eq!(
+			reply("/mute 2"),
+			"Notifications are muted until May 1, 2024, 14:00 UTC. Critical alerts are still \
+				delivered."
+		);
+		assert_eq!(User::query_muted_until(&conn, 0).unwrap(), Some(now + 2 * 3600));
+		reply("/unmute");
+		assert_eq!(User::query_muted_until(&conn, 0).unwrap(), None);
+
+		// CASE 9: the status shows the ongoing phase of every sale.
+		assert!(reply("/status").contains("no information"));
+		SaleStatus::record_phases(&conn, "kusama", now - 7200, now - 3600, now + 3600, 5, now)
+			.unwrap();
+		SaleStatus::record_sale(&conn, "kusama", 1_000_000_000_000, 4, now).unwrap();
+		assert_eq!(
+			reply("/status"),
+			"Kusama: leadin phase from May 1, 2024, 11:00 UTC until May 1, 2024, 13:00 UTC\n\
+				Price of the last sale: 1 KSM\nCores left: 4"
+		);
+
+		// CASE 10: removing the handle unlinks the chat.
+		storage::channels::set(&conn, 0, &Notifier::Telegram, None).unwrap();
+		assert_eq!(TelegramChat::query_by_chat(&conn, 42).unwrap(), None);
+	})
+}
+
+#[test]
+fn senders_without_a_username_are_linked_by_signing_in() {
+	execute_with(USERNAME_DB_PATH, || {
+		let conn = init_db(USERNAME_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+		let start: Update = serde_json::from_value(json!({
+			"update_id": 1,
+			"message": { "chat": { "id": 42, "type": "private" }, "from": {}, "text": "/start" },
+		}))
+		.unwrap();
+		let reply = || handle_update(&conn, &start, 0).unwrap().unwrap().text;
+
+		// CASE 1: the chat can't be linked through a username.
+		assert!(reply().contains("sign in with Telegram"));
+
+		// CASE 2: signing in with Telegram linked the chat, as the api does.
+		create_user(&conn, &telegram_user(0, "42"));
+		TelegramChat::link(&conn, 0, 42, "42").unwrap();
+		assert!(reply().contains("linked"));
+		assert_eq!(TelegramChat::query_by_chat(&conn, 42).unwrap().unwrap().user_id, 0);
+	})
+}
+
+#[tokio::test]
+async fn notifications_are_delivered_to_the_linked_chat() {
+	execute_with(DELIVERY_DB_PATH, || async {
+		let conn = init_db(DELIVERY_DB_PATH).unwrap();
+		let channel = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Telegram, Box::new(channel.clone()));
+
+		{
+			let conn = conn.lock().unwrap();
+			create_user(&conn, &telegram_user(0, "@alice"));
+			create_user(&conn, &telegram_user(1, "@bob"));
+			for id in [0, 1] {
+				Subscription::create(
+					&conn,
+					id,
+					&Notifications::CoretimeSale,
+					&[Notifier::Telegram],
+				)
+				.unwrap();
+			}
+			TelegramChat::link(&conn, 0, 42, "@alice").unwrap();
+			notify(&conn, &event(EventKind::CoretimeSale, timestamp())).unwrap();
+		}
+
+		// The bot can't message handles without a linked chat.
+		let worker = Worker::new(conn, channels);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert_eq!(channel.sent.lock().unwrap()[0].0, "42");
+	})
+	.await
+}
+
+#[tokio::test]
+async fn updates_are_polled_and_answered() {
+	execute_with(POLLING_DB_PATH, || async {
+		let conn = init_db(POLLING_DB_PATH).unwrap();
+		create_user(&conn.lock().unwrap(), &telegram_user(0, "@alice"));
+
+		let updates = json!({
+			"ok": true,
+			"result": [
+				{ "update_id": 7, "message": { "chat": { "id": 42, "type": "private" }, "text": "hello" } },
+				{
+					"update_id": 8,
+					"message": {
+						"chat": { "id": 42, "type": "private" },
+						"from": { "username": "alice" },
+						"text": "/start",
+					},
+				},
+			],
+		});
+		let stub = HttpStub::start(vec![
+			http_response(200, &[], &updates.to_string()),
+			http_response(200, &[], r#"{"ok": true, "result": {}}"#),
+			http_response(200, &[], r#"{"ok": true, "result": []}"#),
+		])
+		.await;
+		let mut bot = TelegramBot::new(conn, "token".to_string()).with_api_url(stub.url.clone());
+
+		assert_eq!(bot.process_updates().await.unwrap(), 2);
+		assert_eq!(bot.process_updates().await.unwrap(), 0);
+
+		let requests = stub.requests.lock().unwrap().clone();
+		let paths = requests.iter().map(|request| request.path.as_str()).collect::<Vec<_>>();
+		assert_eq!(
+			paths,
+			vec!["/bottoken/getUpdates", "/bottoken/sendMessage", "/bottoken/getUpdates"]
+		);
+
+		// Only the command is answered.
+		let reply: Value = serde_json::from_str(&requests[1].body).unwrap();
+		assert_eq!(reply["chat_id"], 42);
+		assert!(reply["text"].as_str().unwrap().contains("linked"));
+
+		// Updates which were handled aren't fetched again.
+		let poll: Value = serde_json::from_str(&requests[2].body).unwrap();
+		assert_eq!(poll["offset"], 9);
+	})
+	.await
+}
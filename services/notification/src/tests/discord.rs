@@ -0,0 +1,87 @@
+use crate::{
+	channels::{discord::DiscordChannel, Channel, ChannelError},
+	templates::Templates,
+	tests::mock::{event, http_response, HttpStub},
+};
+use chrono_tz::Tz;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use types::event::EventKind;
+
+fn message() -> crate::message::Message {
+	Templates::builtin()
+		.render(&event(EventKind::CoretimeSale, 1_714_564_800), "en", Tz::UTC)
+		.unwrap()
+}
+
+#[tokio::test]
+async fn messages_are_posted_as_embeds() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = DiscordChannel::new();
+
+	let webhook = format!("{}/api/webhooks/1/token", stub.url);
+	channel.send(&webhook, &message()).await.unwrap();
+
+	let requests = stub.requests.lock().unwrap().clone();
+	assert_eq!(requests.len(), 1);
+	assert_eq!(requests[0].path, "/api/webhooks/1/token?wait=true");
+
+	let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+	let embed = &body["embeds"][0];
+	assert_eq!(embed["title"], "Kusama: coretime sold for 1 KSM");
+	assert_eq!(embed["description"], message().text);
+	let fields = embed["fields"].as_array().unwrap();
+	let fields: Vec<_> = fields
+		.iter()
+		.map(|field| (field["name"].as_str().unwrap(), field["value"].as_str().unwrap()))
+		.collect();
+	assert_eq!(fields, vec![("Price", "1 KSM"), ("Cores left", "5"), ("Timeslice", "42")]);
+}
+
+#[tokio::test]
+async fn rate_limits_are_respected() {
+	let stub = HttpStub::start(vec![
+		// The request is rate limited and can be retried right away.
+		http_response(429, &[("Retry-After", "0.2")], r#"{"retry_after": 0.2}"#),
+		// The request succeeds, but the webhook is exhausted for the next second.
+		http_response(
+			200,
+			&[("X-RateLimit-Remaining", "0"), ("X-RateLimit-Reset-After", "1")],
+			"{}",
+		),
+		// The limit is too long to wait for.
+		http_response(429, &[("Retry-After", "60")], "{}"),
+	])
+	.await;
+	let channel = DiscordChannel::new();
+	let webhook = format!("{}/api/webhooks/1/token", stub.url);
+
+	// CASE 1: the delivery waits for the rate limit to reset and is attempted again.
+	let start = Instant::now();
+	channel.send(&webhook, &message()).await.unwrap();
+	assert!(start.elapsed() >= Duration::from_millis(200));
+	assert_eq!(stub.requests.lock().unwrap().len(), 2);
+
+	// CASE 2: the next delivery waits until the webhook can be used again. It then hits a rate
+	// limit which is too long to wait for, so it is left to the retries of the outbox.
+	let start = Instant::now();
+	let result = channel.send(&webhook, &message()).await;
+	assert!(start.elapsed() >= Duration::from_millis(900));
+	assert!(matches!(result, Err(ChannelError::Transient(_))));
+	assert_eq!(stub.requests.lock().unwrap().len(), 3);
+
+	// CASE 3: deliveries fail right away while the webhook is rate limited.
+	let result = channel.send(&webhook, &message()).await;
+	assert!(matches!(result, Err(ChannelError::Transient(_))));
+	assert_eq!(stub.requests.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn deleted_webhooks_fail_permanently() {
+	let stub =
+		HttpStub::start(vec![http_response(404, &[], r#"{"message": "Unknown Webhook"}"#)]).await;
+	let channel = DiscordChannel::new();
+
+	let result = channel.send(&format!("{}/api/webhooks/1/token", stub.url), &message()).await;
+	assert!(matches!(result, Err(ChannelError::Permanent(_))));
+}
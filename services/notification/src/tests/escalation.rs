@@ -0,0 +1,158 @@
+use crate::{
+	channels::Channels,
+	message::Action,
+	notify,
+	tests::{
+		mock::{create_user, event, execute_with, user, MockChannel},
+		worker::{make_due, query_job},
+	},
+	timestamp,
+	worker::Worker,
+};
+use storage::{
+	escalations::{self, Alert, Contact},
+	init_db,
+	outbox::{JobStatus, OutboxJob},
+	subscriptions::Subscription,
+	telegram::TelegramChat,
+	tokens::hash_token,
+	users::User,
+};
+use types::{event::EventKind, EscalationStep, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "escalation-tests.db";
+pub const CONTACT_DB_PATH: &'static str = "contact-tests.db";
+
+#[tokio::test]
+async fn alerts_are_escalated_until_acknowledged() {
+	execute_with(DB_PATH, || async {
+		let conn = init_db(DB_PATH).unwrap();
+		let email = MockChannel::default();
+		let telegram = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Email, Box::new(email.clone()));
+		channels.insert(Notifier::Telegram, Box::new(telegram.clone()));
+
+		let mut expiring = event(EventKind::CoreExpiring, timestamp());
+		expiring.para_id = Some(2000);
+		{
+			let conn = conn.lock().unwrap();
+			let user = User { tg_handle: Some("@user0".to_string()), ..user(0) };
+			create_user(&conn, &user);
+			TelegramChat::link(&conn, 0, 42, "@user0").unwrap();
+			let notification = Notifications::ParachainState(2000);
+			Subscription::create(&conn, 0, &notification, &[Notifier::Email]).unwrap();
+			let steps = [
+				EscalationStep { after_minutes: 10, channel: Notifier::Telegram, contact: None },
+				EscalationStep {
+					after_minutes: 30,
+					channel: Notifier::Email,
+					contact: Some("team@mail.com".to_string()),
+				},
+			];
+			escalations::set_steps(&conn, 0, &notification, &steps).unwrap();
+			let contact = (0, &Notifier::Email, "team@mail.com");
+			Contact::request(&conn, contact.0, contact.1, contact.2, "hash", 0).unwrap();
+			Contact::confirm(&conn, "hash", 0).unwrap();
+
+			// The alert is delivered right away, the steps are due later on.
+			assert_eq!(notify(&conn, &expiring).unwrap(), 3);
+			let due = |id| OutboxJob::query_by_id(&conn, id).unwrap().unwrap().next_attempt_at;
+			assert_eq!(due(2), due(1) + 10 * 60);
+			assert_eq!(due(3), due(1) + 30 * 60);
+		}
+
+		let worker = Worker::new(conn, channels).with_api_url("https://api.example.com/".into());
+		let token = |action: &Option<Action>| {
+			let action = action.clone().unwrap();
+			assert_eq!(action.label, "Acknowledge");
+			action
+				.url
+				.strip_prefix("https://api.example.com/alert/")
+				.and_then(|url| url.strip_suffix("/acknowledge"))
+				.unwrap()
+				.to_string()
+		};
+
+		// CASE 1: the alert links to its acknowledgement, whose token is stored hashed.
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		let sent = email.sent.lock().unwrap().clone();
+		assert_eq!(sent[0].0, "user0@mail.com");
+		assert!(sent[0].1.text.ends_with(&sent[0].1.action.clone().unwrap().url));
+		let email_token = token(&sent[0].1.action);
+		assert_eq!(Alert::query_by_token(&worker.conn(), &email_token).unwrap(), None);
+		let alert = Alert::query_by_token(&worker.conn(), &hash_token(&email_token)).unwrap();
+		assert_eq!(alert.unwrap().id, 1);
+
+		// CASE 2: the steps are taken once they are due, each with its own token.
+		make_due(&worker, 2);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		let sent = telegram.sent.lock().unwrap().clone();
+		assert_eq!(sent[0].0, "42");
+		assert_ne!(token(&sent[0].1.action), email_token);
+
+		make_due(&worker, 3);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert_eq!(email.sent.lock().unwrap()[1].0, "team@mail.com");
+
+		// CASE 3: acknowledging an alert cancels the steps which aren't due yet.
+		expiring.block += 1;
+		assert_eq!(notify(&worker.conn(), &expiring).unwrap(), 3);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert_eq!(query_job(&worker, 4).status, JobStatus::Sent);
+
+		let token = token(&email.sent.lock().unwrap()[2].1.action);
+		let acknowledged =
+			Alert::acknowledge(&worker.conn(), &hash_token(&token), timestamp()).unwrap();
+		assert!(acknowledged.unwrap().acknowledged_at.is_some());
+		assert!(OutboxJob::query_by_id(&worker.conn(), 5).unwrap().is_none());
+		assert!(OutboxJob::query_by_id(&worker.conn(), 6).unwrap().is_none());
+
+		// Seeing the event again doesn't raise another alert.
+		assert_eq!(notify(&worker.conn(), &expiring).unwrap(), 0);
+	})
+	.await
+}
+
+#[test]
+fn alerts_are_only_escalated_to_confirmed_contacts() {
+	execute_with(CONTACT_DB_PATH, || {
+		let conn = init_db(CONTACT_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+		create_user(&conn, &user(0));
+		let notification = Notifications::ParachainState(2000);
+		Subscription::create(&conn, 0, &notification, &[Notifier::Email]).unwrap();
+		let step = EscalationStep {
+			after_minutes: 10,
+			channel: Notifier::Email,
+			contact: Some("team@mail.com".to_string()),
+		};
+		escalations::set_steps(&conn, 0, &notification, &[step]).unwrap();
+
+		let mut expiring = event(EventKind::CoreExpiring, timestamp());
+		expiring.para_id = Some(2000);
+		let confirmed = || Contact::is_confirmed(&conn, 0, &Notifier::Email, "team@mail.com");
+
+		// CASE 1: the contact didn't confirm yet.
+		assert!(Contact::request(&conn, 0, &Notifier::Email, "team@mail.com", "hash", 0).unwrap());
+		assert!(!confirmed().unwrap());
+		assert_eq!(notify(&conn, &expiring).unwrap(), 1);
+
+		// CASE 2: the confirmation isn't requested again right away.
+		assert!(!Contact::request(&conn, 0, &Notifier::Email, "team@mail.com", "other", 1).unwrap());
+		assert_eq!(Contact::confirm(&conn, "other", 1).unwrap(), None);
+
+		// CASE 3: once confirmed, the alerts are escalated to the contact.
+		let contact = Contact::confirm(&conn, "hash", 2).unwrap().unwrap();
+		assert_eq!(contact.confirmed_at, Some(2));
+		assert!(confirmed().unwrap());
+		expiring.block += 1;
+		assert_eq!(notify(&conn, &expiring).unwrap(), 2);
+
+		// Confirmed contacts aren't asked again.
+		let later = escalations::CONFIRMATION_INTERVAL + 2;
+		assert!(
+			!Contact::request(&conn, 0, &Notifier::Email, "team@mail.com", "new", later).unwrap()
+		);
+	})
+}
@@ -0,0 +1,79 @@
+use crate::{
+	channels::{matrix::MatrixChannel, Channel, ChannelError},
+	message::Message,
+	templates::Templates,
+	tests::mock::{event, http_response, HttpStub},
+};
+use chrono_tz::Tz;
+use serde_json::Value;
+use types::event::EventKind;
+
+fn message() -> Message {
+	Templates::builtin()
+		.render(&event(EventKind::CoretimeSale, 1_714_564_800), "en", Tz::UTC)
+		.unwrap()
+}
+
+#[tokio::test]
+async fn messages_are_sent_to_the_room() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = MatrixChannel::new(stub.url.clone(), "token".to_string());
+
+	channel.send("!room:localhost", &message()).await.unwrap();
+	channel.send("!room:localhost", &message()).await.unwrap();
+
+	let requests = stub.requests.lock().unwrap().clone();
+	assert_eq!(requests.len(), 2);
+
+	let prefix = "/_matrix/client/v3/rooms/!room:localhost/send/m.room.message/";
+	let transactions: Vec<_> = requests
+		.iter()
+		.map(|request| request.path.strip_prefix(prefix).unwrap())
+		.collect();
+	// Every message is a separate transaction.
+	assert_ne!(transactions[0], transactions[1]);
+
+	let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+	assert_eq!(body["msgtype"], "m.text");
+	assert_eq!(body["body"], message().text);
+	assert_eq!(body["format"], "org.matrix.custom.html");
+	assert_eq!(body["formatted_body"], message().html);
+}
+
+#[tokio::test]
+async fn retried_deliveries_keep_their_transaction() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = MatrixChannel::new(stub.url.clone(), "token".to_string());
+	let delivery = |key: &str| Message { delivery: Some(key.to_string()), ..message() };
+
+	channel.send("!room:localhost", &delivery("job1")).await.unwrap();
+	channel.send("!room:localhost", &delivery("job1")).await.unwrap();
+	channel.send("!room:localhost", &delivery("job2")).await.unwrap();
+	channel.send("!other:localhost", &delivery("job1")).await.unwrap();
+
+	let requests = stub.requests.lock().unwrap().clone();
+	let transactions: Vec<_> = requests
+		.iter()
+		.map(|request| request.path.rsplit('/').next().unwrap())
+		.collect();
+	assert_eq!(transactions[0], transactions[1]);
+	assert_ne!(transactions[0], transactions[2]);
+	assert_ne!(transactions[0], transactions[3]);
+}
+
+#[tokio::test]
+async fn errors_are_classified() {
+	let stub = HttpStub::start(vec![
+		http_response(429, &[], r#"{"errcode": "M_LIMIT_EXCEEDED", "retry_after_ms": 2000}"#),
+		http_response(403, &[], r#"{"errcode": "M_FORBIDDEN"}"#),
+	])
+	.await;
+	let channel = MatrixChannel::new(stub.url.clone(), "token".to_string());
+
+	let result = channel.send("!room:localhost", &message()).await;
+	assert!(matches!(result, Err(ChannelError::Transient(_))));
+
+	// The bot isn't part of the room.
+	let result = channel.send("!room:localhost", &message()).await;
+	assert!(matches!(result, Err(ChannelError::Permanent(_))));
+}
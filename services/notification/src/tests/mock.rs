@@ -0,0 +1,183 @@
+use crate::{
+	channels::{Channel, ChannelError},
+	message::Message,
+};
+use async_trait::async_trait;
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+use storage::{channels, users::User};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+};
+use types::{
+	event::{Event, EventKind},
+	DeliveryMode, Notifier,
+};
+
+pub fn execute_with<R>(db_path: &str, f: impl Fn() -> R) -> R {
+	// Don't check the result since it will error if the db already doesn't exist which isn't an
+	// issue.
+	let _ = std::fs::remove_file(db_path);
+
+	f()
+}
+
+pub fn user(id: u32) -> User {
+	User {
+		id,
+		email: Some(format!("user{}@mail.com", id)),
+		tg_handle: None,
+		discord_webhook: None,
+		slack_webhook: None,
+		matrix_room: None,
+		webhook_url: None,
+		language: "en".to_string(),
+		timezone: "UTC".to_string(),
+		quiet_hours: None,
+		delivery_mode: DeliveryMode::Immediate,
+	}
+}
+
+/// Creates the user, whose email address is verified.
+pub fn create_user(conn: &Connection, user: &User) {
+	User::create_user(conn, user).unwrap();
+	if let Some(email) = &user.email {
+		channels::verify(conn, user.id, &Notifier::Email, email, 0).unwrap();
+	}
+}
+
+pub fn event(kind: EventKind, timestamp: u64) -> Event {
+	Event {
+		network: "kusama".to_string(),
+		kind,
+		block: 100,
+		timestamp,
+		end_timestamp: Some(timestamp + 3600),
+		timeslice: Some(42),
+		core: None,
+		price: Some(1_000_000_000_000),
+		cores_left: Some(5),
+		para_id: None,
+	}
+}
+
+/// Records every sent message. Fails with `failure` while it is set.
+#[derive(Clone, Default)]
+pub struct MockChannel {
+	pub sent: Arc<Mutex<Vec<(String, Message)>>>,
+	pub failure: Arc<Mutex<Option<ChannelError>>>,
+}
+
+impl MockChannel {
+	pub fn fail_with(&self, failure: Option<ChannelError>) {
+		*self.failure.lock().unwrap() = failure;
+	}
+}
+
+#[async_trait]
+impl Channel for MockChannel {
+	async fn send(&self, address: &str, message: &Message) -> Result<(), ChannelError> {
+		if let Some(failure) = self.failure.lock().unwrap().clone() {
+			return Err(failure);
+		}
+		self.sent.lock().unwrap().push((address.to_string(), message.clone()));
+		Ok(())
+	}
+}
+
+/// A local HTTP server which answers requests with scripted responses, in order.
+pub struct HttpStub {
+	pub url: String,
+	/// Every received request.
+	pub requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StubRequest {
+	pub path: String,
+	pub headers: Vec<(String, String)>,
+	pub body: String,
+}
+
+impl StubRequest {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(header, _)| header.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+impl HttpStub {
+	/// Starts the server. Once all responses are used up, it answers with `200 OK`.
+	pub async fn start(responses: Vec<String>) -> Self {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let requests = Arc::new(Mutex::new(vec![]));
+
+		let received = requests.clone();
+		tokio::spawn(async move {
+			let mut responses = responses.into_iter();
+			while let Ok((mut stream, _)) = listener.accept().await {
+				let request = read_request(&mut stream).await;
+				received.lock().unwrap().push(request);
+
+				let response = responses.next().unwrap_or(http_response(200, &[], ""));
+				let _ = stream.write_all(response.as_bytes()).await;
+			}
+		});
+
+		Self { url, requests }
+	}
+}
+
+/// Builds a raw HTTP response.
+pub fn http_response(status: u16, headers: &[(&str, &str)], body: &str) -> String {
+	let headers: String =
+		headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+	format!(
+		"HTTP/1.1 {} Stub\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+		status,
+		headers,
+		body.len(),
+		body
+	)
+}
+
+async fn read_request(stream: &mut TcpStream) -> StubRequest {
+	let mut data = vec![];
+	let mut buf = [0u8; 4096];
+	loop {
+		let read = stream.read(&mut buf).await.unwrap_or(0);
+		if read == 0 {
+			break;
+		}
+		data.extend_from_slice(&buf[..read]);
+
+		let request = String::from_utf8_lossy(&data).to_string();
+		let Some((head, body)) = request.split_once("\r\n\r\n") else {
+			continue;
+		};
+		let headers = head
+			.lines()
+			.skip(1)
+			.filter_map(|line| line.split_once(':'))
+			.map(|(name, value)| (name.to_string(), value.trim().to_string()))
+			.collect::<Vec<_>>();
+		let request = StubRequest {
+			path: head.split(' ').nth(1).unwrap_or_default().to_string(),
+			headers,
+			body: body.to_string(),
+		};
+		let content_length = request
+			.header("content-length")
+			.and_then(|value| value.parse().ok())
+			.unwrap_or(0);
+		if request.body.len() >= content_length {
+			return request;
+		}
+	}
+
+	StubRequest::default()
+}
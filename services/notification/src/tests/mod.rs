@@ -0,0 +1,13 @@
+mod bot;
+mod discord;
+mod escalation;
+mod matrix;
+mod mock;
+mod notify;
+mod retry;
+mod scheduler;
+mod slack;
+mod templates;
+mod unsubscribe;
+mod webhook;
+mod worker;
@@ -0,0 +1,260 @@
+use crate::{
+	notify,
+	tests::mock::{create_user, event, execute_with, user},
+	timestamp,
+};
+use chrono::{Timelike, Utc};
+use chrono_tz::Tz;
+use storage::{
+	init_db,
+	organizations::{Member, Organization, OrganizationSubscription},
+	outbox::OutboxJob,
+	subscriptions::Subscription,
+	telegram::TelegramChat,
+	users::User,
+};
+use types::{
+	api::OrganizationRole, event::EventKind, Notifications, Notifier, PhaseNotification, QuietHours,
+};
+
+pub const DB_PATH: &'static str = "notify-tests.db";
+pub const MUTE_DB_PATH: &'static str = "mute-tests.db";
+pub const VERIFICATION_DB_PATH: &'static str = "verification-tests.db";
+pub const ORGANIZATION_DB_PATH: &'static str = "organization-notify-tests.db";
+
+#[test]
+fn duplicate_triggers_are_ignored() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		let prior_start = Notifications::InterludePhase(PhaseNotification::PriorStart(60));
+		let prior_end = Notifications::InterludePhase(PhaseNotification::PriorEnd(60));
+		for id in 0..2 {
+			create_user(&conn, &user(id));
+			Subscription::create(&conn, id, &prior_start, &[Notifier::Email]).unwrap();
+			Subscription::create(&conn, id, &prior_end, &[Notifier::Email]).unwrap();
+		}
+
+		// Two users with two subscriptions each.
+		let interlude = event(EventKind::InterludePhase, timestamp() + 86_400);
+		assert_eq!(notify(&conn, &interlude).unwrap(), 4);
+
+		// CASE 1: the same event is seen again, e.g. after a restart.
+		assert_eq!(notify(&conn, &interlude).unwrap(), 0);
+
+		// CASE 2: the estimated time of the phase changed, it is still the same phase.
+		let mut restarted = interlude.clone();
+		restarted.timestamp += 12;
+		assert_eq!(notify(&conn, &restarted).unwrap(), 0);
+
+		// CASE 3: a phase of the next sale.
+		let mut next_sale = interlude.clone();
+		next_sale.block += 100_800;
+		next_sale.timeslice = Some(next_sale.timeslice.unwrap() + 5_040);
+		assert_eq!(notify(&conn, &next_sale).unwrap(), 4);
+
+		// CASE 4: the same phase on another network.
+		let mut other_network = interlude.clone();
+		other_network.network = "polkadot".to_string();
+		assert_eq!(notify(&conn, &other_network).unwrap(), 4);
+	})
+}
+
+#[test]
+fn purchases_within_a_block_are_notified_separately() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		create_user(&conn, &user(0));
+		Subscription::create(&conn, 0, &Notifications::CoretimeSale, &[Notifier::Email]).unwrap();
+
+		let purchase = |core| {
+			let mut purchase = event(EventKind::CoretimeSale, timestamp());
+			purchase.core = Some(core);
+			purchase
+		};
+		assert_eq!(notify(&conn, &purchase(3)).unwrap(), 1);
+		assert_eq!(notify(&conn, &purchase(4)).unwrap(), 1);
+		// The same purchase is only notified once.
+		assert_eq!(notify(&conn, &purchase(4)).unwrap(), 0);
+	})
+}
+
+#[test]
+fn quiet_hours_only_defer_non_critical_deliveries() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		// The current hour in Berlin is quiet.
+		let hour = Utc::now().with_timezone(&Tz::Europe__Berlin).hour() as u8;
+		let user = User {
+			timezone: "Europe/Berlin".to_string(),
+			quiet_hours: Some(QuietHours { start: hour, end: (hour + 1) % 24 }),
+			..user(0)
+		};
+		create_user(&conn, &user);
+		Subscription::create(&conn, 0, &Notifications::ParachainState(2000), &[Notifier::Email])
+			.unwrap();
+
+		let now = timestamp();
+		let mut assigned = event(EventKind::CoreAssigned, now);
+		assigned.para_id = Some(2000);
+		let mut expiring = event(EventKind::CoreExpiring, now);
+		expiring.para_id = Some(2000);
+		assert_eq!(notify(&conn, &assigned).unwrap(), 1);
+		assert_eq!(notify(&conn, &expiring).unwrap(), 1);
+
+		let due = |id| OutboxJob::query_by_id(&conn, id).unwrap().unwrap().next_attempt_at;
+		// The assignment waits for the window to end with the next full hour.
+		let assigned_due = due(1);
+		assert!(assigned_due > now && assigned_due <= now + 3600);
+		assert_eq!(assigned_due % 3600, 0);
+		// The expiry warning is critical and bypasses the quiet hours.
+		assert_eq!(due(2), now);
+	})
+}
+
+#[test]
+fn subscriptions_are_routed_to_their_channels() {
+	execute_with(DB_PATH, || {
+		let conn = init_db(DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		let user = User { tg_handle: Some("@user0".to_string()), ..user(0) };
+		create_user(&conn, &user);
+		TelegramChat::link(&conn, 0, 42, "@user0").unwrap();
+		let channels = [Notifier::Email, Notifier::Telegram];
+		Subscription::create(&conn, 0, &Notifications::ParachainState(2000), &channels).unwrap();
+		Subscription::create(&conn, 0, &Notifications::CoretimeSale, &[Notifier::Email]).unwrap();
+
+		let now = timestamp();
+		let mut expiring = event(EventKind::CoreExpiring, now);
+		expiring.para_id = Some(2000);
+
+		// CASE 1: expiry warnings go to both channels, sales only to email.
+		assert_eq!(notify(&conn, &expiring).unwrap(), 2);
+		assert_eq!(notify(&conn, &event(EventKind::CoretimeSale, now)).unwrap(), 1);
+
+		let channel = |id| OutboxJob::query_by_id(&conn, id).unwrap().unwrap().channel;
+		assert_eq!(channel(1), Notifier::Email);
+		assert_eq!(channel(2), Notifier::Telegram);
+		assert_eq!(channel(3), Notifier::Email);
+
+		// CASE 2: removing a channel removes it from the routing.
+		User::update(&conn, &User { email: None, ..user }).unwrap();
+		let subscriptions = Subscription::query_all(&conn).unwrap();
+		assert!(subscriptions.iter().all(|s| !s.channels.contains(&Notifier::Email)));
+
+		expiring.block += 1;
+		assert_eq!(notify(&conn, &expiring).unwrap(), 1);
+		assert_eq!(channel(4), Notifier::Telegram);
+	})
+}
+
+#[test]
+fn muting_only_defers_non_critical_deliveries() {
+	execute_with(MUTE_DB_PATH, || {
+		let conn = init_db(MUTE_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		create_user(&conn, &user(0));
+		Subscription::create(&conn, 0, &Notifications::ParachainState(2000), &[Notifier::Email])
+			.unwrap();
+		let now = timestamp();
+		User::mute(&conn, 0, Some(now + 3600)).unwrap();
+
+		let mut assigned = event(EventKind::CoreAssigned, now);
+		assigned.para_id = Some(2000);
+		let mut expiring = event(EventKind::CoreExpiring, now);
+		expiring.para_id = Some(2000);
+		assert_eq!(notify(&conn, &assigned).unwrap(), 1);
+		assert_eq!(notify(&conn, &expiring).unwrap(), 1);
+
+		let due = |id| OutboxJob::query_by_id(&conn, id).unwrap().unwrap().next_attempt_at;
+		// The assignment is delivered once the user is no longer muted.
+		assert_eq!(due(1), now + 3600);
+		assert_eq!(due(2), now);
+
+		// Notifications after unmuting are delivered right away.
+		User::mute(&conn, 0, None).unwrap();
+		assigned.block += 1;
+		assert_eq!(notify(&conn, &assigned).unwrap(), 1);
+		assert_eq!(due(3), now);
+	})
+}
+
+#[test]
+fn unverified_addresses_are_skipped() {
+	execute_with(VERIFICATION_DB_PATH, || {
+		let conn = init_db(VERIFICATION_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		let user = User { tg_handle: Some("@user0".to_string()), ..user(0) };
+		User::create_user(&conn, &user).unwrap();
+		let channels = [Notifier::Email, Notifier::Telegram];
+		Subscription::create(&conn, 0, &Notifications::CoretimeSale, &channels).unwrap();
+
+		let mut sale = event(EventKind::CoretimeSale, timestamp());
+		let channel = |id| OutboxJob::query_by_id(&conn, id).unwrap().unwrap().channel;
+
+		// CASE 1: neither the unverified email address nor Telegram without a chat are notified.
+		assert_eq!(notify(&conn, &sale).unwrap(), 0);
+
+		// CASE 2: only the verified channels are notified.
+		TelegramChat::link(&conn, 0, 42, "@user0").unwrap();
+		sale.block += 1;
+		assert_eq!(notify(&conn, &sale).unwrap(), 1);
+		assert_eq!(channel(1), Notifier::Telegram);
+
+		// CASE 3: once verified, the email address is notified as well.
+		let email = user.email.clone().unwrap();
+		assert!(storage::channels::verify(&conn, 0, &Notifier::Email, &email, 0).unwrap());
+		sale.block += 1;
+		assert_eq!(notify(&conn, &sale).unwrap(), 2);
+
+		// CASE 4: changing the address requires verifying it again.
+		let user = User { email: Some("other@mail.com".to_string()), ..user };
+		User::update(&conn, &user).unwrap();
+		assert!(!storage::channels::verify(&conn, 0, &Notifier::Email, &email, 0).unwrap());
+		sale.block += 1;
+		assert_eq!(notify(&conn, &sale).unwrap(), 1);
+	})
+}
+
+#[test]
+fn organization_subscriptions_fan_out_to_members() {
+	execute_with(ORGANIZATION_DB_PATH, || {
+		let conn = init_db(ORGANIZATION_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+
+		create_user(&conn, &User { tg_handle: Some("@user0".to_string()), ..user(0) });
+		TelegramChat::link(&conn, 0, 42, "@user0").unwrap();
+		create_user(&conn, &user(1));
+		create_user(&conn, &user(2));
+		let organization = Organization::create(&conn, "team", "Team", 0, 0).unwrap();
+		Member::add(&conn, organization.id, 1, OrganizationRole::Member, 0).unwrap();
+		Member::set_channels(&conn, organization.id, 0, &[Notifier::Telegram]).unwrap();
+		let notification = Notifications::ParachainState(2000);
+		OrganizationSubscription::set(&conn, organization.id, &[notification.clone()]).unwrap();
+		// The second member also subscribed on their own.
+		Subscription::create(&conn, 1, &notification, &[Notifier::Email]).unwrap();
+
+		let mut assigned = event(EventKind::CoreAssigned, timestamp());
+		assigned.para_id = Some(2000);
+		let job = |id| OutboxJob::query_by_id(&conn, id).unwrap().unwrap();
+
+		// CASE 1: every member is notified once, through their preferred channels.
+		assert_eq!(notify(&conn, &assigned).unwrap(), 2);
+		assert_eq!((job(1).user_id, job(1).channel), (1, Notifier::Email));
+		assert_eq!((job(2).user_id, job(2).channel), (0, Notifier::Telegram));
+
+		// CASE 2: removed members aren't notified anymore.
+		assert!(Member::remove(&conn, organization.id, 0).unwrap());
+		assigned.block += 1;
+		assert_eq!(notify(&conn, &assigned).unwrap(), 1);
+		assert_eq!(job(conn.last_insert_rowid()).user_id, 1);
+	})
+}
@@ -0,0 +1,29 @@
+use crate::retry::RetryPolicy;
+
+#[test]
+fn backoff_grows_exponentially() {
+	let policy = RetryPolicy { base_delay: 10, max_delay: 100, max_attempts: 6 };
+
+	for _ in 0..100 {
+		let delays: Vec<_> = (1..6)
+			.map(|attempt| policy.next_attempt_at(attempt, 1_000).unwrap() - 1_000)
+			.collect();
+
+		// The delay is jittered between half and the full backoff.
+		assert!((5..=10).contains(&delays[0]));
+		assert!((10..=20).contains(&delays[1]));
+		assert!((20..=40).contains(&delays[2]));
+		assert!((40..=80).contains(&delays[3]));
+		// Capped by the max delay.
+		assert!((50..=100).contains(&delays[4]));
+	}
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+	let policy = RetryPolicy { base_delay: 10, max_delay: 100, max_attempts: 3 };
+
+	assert!(policy.next_attempt_at(2, 0).is_some());
+	assert_eq!(policy.next_attempt_at(3, 0), None);
+	assert_eq!(policy.next_attempt_at(4, 0), None);
+}
@@ -0,0 +1,107 @@
+use crate::{
+	scheduler::{defer_for_quiet_hours, digest_at, due_at},
+	tests::mock::event,
+};
+use chrono_tz::Tz;
+use types::{event::EventKind, DeliveryMode, Notifications, PhaseNotification, QuietHours};
+
+#[test]
+fn phase_notifications_are_scheduled_ahead() {
+	let now = 1_000;
+	let interlude = event(EventKind::InterludePhase, 10_000);
+
+	let subscription = Notifications::InterludePhase(PhaseNotification::PriorStart(3_600));
+	assert_eq!(due_at(&subscription, &interlude, now), Some(6_400));
+
+	let subscription = Notifications::InterludePhase(PhaseNotification::PriorEnd(600));
+	assert_eq!(due_at(&subscription, &interlude, now), Some(13_000));
+
+	// The offset is further in the past than now, so it is due immediately.
+	let subscription = Notifications::InterludePhase(PhaseNotification::PriorStart(86_400));
+	assert_eq!(due_at(&subscription, &interlude, now), Some(0));
+
+	// Not subscribed to this phase.
+	let subscription = Notifications::FixedPhaseStart(PhaseNotification::PriorStart(0));
+	assert_eq!(due_at(&subscription, &interlude, now), None);
+}
+
+#[test]
+fn passed_phases_are_skipped() {
+	let interlude = event(EventKind::InterludePhase, 10_000);
+
+	let subscription = Notifications::InterludePhase(PhaseNotification::PriorStart(0));
+	assert_eq!(due_at(&subscription, &interlude, 10_001), None);
+
+	// The end of the phase is still ahead.
+	let subscription = Notifications::InterludePhase(PhaseNotification::PriorEnd(0));
+	assert_eq!(due_at(&subscription, &interlude, 10_001), Some(13_600));
+}
+
+#[test]
+fn parachain_notifications_match_para_id() {
+	let mut assigned = event(EventKind::CoreAssigned, 10_000);
+	assigned.para_id = Some(2000);
+
+	assert_eq!(due_at(&Notifications::ParachainState(2000), &assigned, 0), Some(10_000));
+	assert_eq!(due_at(&Notifications::ParachainState(2001), &assigned, 0), None);
+	assert_eq!(due_at(&Notifications::CoretimeSale, &assigned, 0), None);
+}
+
+#[test]
+fn quiet_hours_defer_to_the_end_of_the_window() {
+	let night = QuietHours { start: 22, end: 7 };
+	// 2024-05-01 01:00 UTC
+	let due = 1_714_525_200;
+
+	// 01:00 UTC is within the window, which ends at 07:00 on the same day.
+	assert_eq!(defer_for_quiet_hours(due, &night, Tz::UTC), due + 6 * 3600);
+	// 23:00 UTC on the previous day is deferred to 07:00 on the next day.
+	assert_eq!(defer_for_quiet_hours(due - 2 * 3600, &night, Tz::UTC), due + 6 * 3600);
+	// 07:00 UTC is outside of the window.
+	assert_eq!(defer_for_quiet_hours(due + 6 * 3600, &night, Tz::UTC), due + 6 * 3600);
+
+	// The window is interpreted in the user's time zone. 01:00 UTC is 03:00 in Berlin, so the
+	// window ends at 05:00 UTC.
+	assert_eq!(defer_for_quiet_hours(due, &night, Tz::Europe__Berlin), due + 4 * 3600);
+	// It is 21:00 in New York, right before the window starts.
+	assert_eq!(defer_for_quiet_hours(due, &night, Tz::America__New_York), due);
+
+	// A window within the same day.
+	let afternoon = QuietHours { start: 12, end: 14 };
+	assert_eq!(defer_for_quiet_hours(due + 11 * 3600, &afternoon, Tz::UTC), due + 13 * 3600);
+	assert_eq!(defer_for_quiet_hours(due, &afternoon, Tz::UTC), due);
+}
+
+#[test]
+fn quiet_hours_handle_daylight_saving_time() {
+	// 2024-03-31 01:30 CET, the clocks are moved from 02:00 to 03:00 on that day. The window
+	// would end at 02:00 which doesn't exist, so it ends at 03:00 CEST instead.
+	let due = 1_711_845_000;
+	let quiet_hours = QuietHours { start: 0, end: 2 };
+	assert_eq!(defer_for_quiet_hours(due, &quiet_hours, Tz::Europe__Berlin), 1_711_846_800);
+}
+
+#[test]
+fn digests_are_sent_at_fixed_times() {
+	// Wednesday 2024-05-01 01:30 UTC
+	let due = 1_714_527_000;
+
+	assert_eq!(digest_at(due, DeliveryMode::Immediate, Tz::UTC), due);
+
+	// At the start of the next hour.
+	assert_eq!(digest_at(due, DeliveryMode::HourlyDigest, Tz::UTC), due + 1800);
+	assert_eq!(digest_at(due + 1800, DeliveryMode::HourlyDigest, Tz::UTC), due + 1800);
+
+	// At 09:00 in the user's time zone.
+	let nine = due + 7 * 3600 + 1800;
+	assert_eq!(digest_at(due, DeliveryMode::DailyDigest, Tz::UTC), nine);
+	assert_eq!(digest_at(nine + 1, DeliveryMode::DailyDigest, Tz::UTC), nine + 86_400);
+	// 09:00 in Berlin is 07:00 UTC.
+	assert_eq!(digest_at(due, DeliveryMode::DailyDigest, Tz::Europe__Berlin), nine - 2 * 3600);
+
+	// At 09:00 on the next Monday.
+	assert_eq!(digest_at(due, DeliveryMode::WeeklyDigest, Tz::UTC), nine + 5 * 86_400);
+	let monday = nine - 2 * 86_400;
+	assert_eq!(digest_at(monday - 1, DeliveryMode::WeeklyDigest, Tz::UTC), monday);
+	assert_eq!(digest_at(monday, DeliveryMode::WeeklyDigest, Tz::UTC), monday);
+}
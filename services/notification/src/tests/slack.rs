@@ -0,0 +1,78 @@
+use crate::{
+	channels::{slack::SlackChannel, Channel, ChannelError},
+	templates::Templates,
+	tests::mock::{event, http_response, HttpStub},
+};
+use chrono_tz::Tz;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use types::event::EventKind;
+
+fn message() -> crate::message::Message {
+	Templates::builtin()
+		.render(&event(EventKind::CoretimeSale, 1_714_564_800), "en", Tz::UTC)
+		.unwrap()
+}
+
+#[tokio::test]
+async fn messages_are_posted_as_blocks() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = SlackChannel::new();
+
+	let webhook = format!("{}/services/T000/B000/token", stub.url);
+	channel.send(&webhook, &message()).await.unwrap();
+
+	let requests = stub.requests.lock().unwrap().clone();
+	assert_eq!(requests.len(), 1);
+	assert_eq!(requests[0].path, "/services/T000/B000/token");
+
+	let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+	assert_eq!(body["text"], "Kusama: coretime sold for 1 KSM");
+
+	let blocks = body["blocks"].as_array().unwrap();
+	assert_eq!(blocks[0]["type"], "header");
+	assert_eq!(blocks[0]["text"]["text"], "Kusama: coretime sold for 1 KSM");
+	assert_eq!(blocks[1]["text"]["type"], "mrkdwn");
+	assert_eq!(blocks[1]["text"]["text"], message().text);
+	let fields: Vec<_> = blocks[2]["fields"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.map(|field| &field["text"])
+		.collect();
+	assert_eq!(fields, vec!["*Price*\n1 KSM", "*Cores left*\n5", "*Timeslice*\n42"]);
+}
+
+#[tokio::test]
+async fn rate_limited_deliveries_are_retried() {
+	let stub = HttpStub::start(vec![
+		http_response(429, &[("Retry-After", "0.2")], "rate_limited"),
+		http_response(200, &[], "ok"),
+		http_response(429, &[("Retry-After", "60")], "rate_limited"),
+	])
+	.await;
+	let channel = SlackChannel::new();
+	let webhook = format!("{}/services/T000/B000/token", stub.url);
+
+	// CASE 1: the delivery is attempted again once the `Retry-After` elapsed.
+	let start = Instant::now();
+	channel.send(&webhook, &message()).await.unwrap();
+	assert!(start.elapsed() >= Duration::from_millis(200));
+	assert_eq!(stub.requests.lock().unwrap().len(), 2);
+
+	// CASE 2: limits which are too long to wait for are left to the retries of the outbox.
+	let result = channel.send(&webhook, &message()).await;
+	assert!(matches!(result, Err(ChannelError::Transient(_))));
+	assert_eq!(stub.requests.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn removed_webhooks_fail_permanently() {
+	let stub = HttpStub::start(vec![http_response(404, &[], "no_service")]).await;
+	let channel = SlackChannel::new();
+
+	let result = channel
+		.send(&format!("{}/services/T000/B000/token", stub.url), &message())
+		.await;
+	assert!(matches!(result, Err(ChannelError::Permanent(_))));
+}
@@ -0,0 +1,194 @@
+use crate::templates::{kind_key, Context, Format, Locale, Template, Templates, KINDS};
+use chrono_tz::Tz;
+use std::{env, fs, path::Path};
+use types::event::{Event, EventKind};
+
+/// Compares `actual` with the snapshot stored under `name`.
+///
+/// Run the tests with `UPDATE_SNAPSHOTS=1` to update the stored snapshots.
+fn assert_snapshot(name: &str, actual: &str) {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/snapshots").join(name);
+	if env::var("UPDATE_SNAPSHOTS").is_ok() {
+		fs::write(&path, actual).unwrap();
+		return;
+	}
+
+	let expected = fs::read_to_string(&path)
+		.unwrap_or_else(|_| panic!("Missing snapshot {}, run with UPDATE_SNAPSHOTS=1", name));
+	assert_eq!(actual, expected, "Snapshot {} doesn't match", name);
+}
+
+fn sample_event(kind: EventKind) -> Event {
+	let is_phase =
+		matches!(kind, EventKind::InterludePhase | EventKind::LeadinPhase | EventKind::FixedPhase);
+	let is_parachain =
+		matches!(kind, EventKind::CoreAssigned | EventKind::CoreRenewed | EventKind::CoreExpiring);
+
+	Event {
+		network: "kusama".to_string(),
+		kind,
+		block: 1_234_567,
+		// 2024-05-01 12:00 UTC
+		timestamp: 1_714_564_800,
+		end_timestamp: matches!(
+			kind,
+			EventKind::InterludePhase | EventKind::LeadinPhase | EventKind::CoreExpiring
+		)
+		.then_some(1_714_737_600),
+		timeslice: Some(300_000),
+		core: None,
+		price: (!is_phase && kind != EventKind::CoreAssigned).then_some(12_345_000_000_000),
+		cores_left: (!is_parachain).then_some(7),
+		para_id: is_parachain.then_some(2000),
+	}
+}
+
+#[test]
+fn builtin_templates_match_snapshots() {
+	let templates = Templates::builtin();
+
+	for language in ["en", "de", "es"] {
+		for kind in KINDS {
+			let event = sample_event(kind);
+			let context = Context::new(&event, Locale::for_language(language), Tz::UTC);
+
+			for format in Format::ALL {
+				let rendered =
+					templates.render_format(language, &context, kind_key(kind), format).unwrap();
+				assert_snapshot(
+					&format!("{}/{}.{}.snap", language, kind_key(kind), format.key()),
+					&rendered,
+				);
+			}
+		}
+	}
+}
+
+#[test]
+fn values_are_formatted_per_locale() {
+	let en = Locale::for_language("en");
+	let de = Locale::for_language("de");
+	assert_eq!(en.format_amount(1_234_567_500_000_000_000, 12), "1,234,567.5");
+	assert_eq!(de.format_amount(1_234_567_500_000_000_000, 12), "1.234.567,5");
+	assert_eq!(en.format_amount(12_000_000_000_000, 12), "12");
+	assert_eq!(en.format_amount(1, 12), "0.000000000001");
+	assert_eq!(de.format_integer(999), "999");
+	assert_eq!(de.format_integer(1000), "1.000");
+
+	assert_eq!(en.format_timestamp(1_714_564_800, Tz::UTC), "May 1, 2024, 12:00 UTC");
+	assert_eq!(de.format_timestamp(1_714_564_800, Tz::UTC), "1. Mai 2024, 12:00 UTC");
+	assert_eq!(
+		Locale::for_language("es").format_timestamp(1_714_564_800, Tz::UTC),
+		"1 de mayo de 2024, 12:00 UTC"
+	);
+
+	// Times are shown in the time zone of the user.
+	assert_eq!(de.format_timestamp(1_714_564_800, Tz::Europe__Berlin), "1. Mai 2024, 14:00 CEST");
+	assert_eq!(en.format_timestamp(1_714_564_800, Tz::America__New_York), "May 1, 2024, 08:00 EDT");
+
+	// Regional variants use the rules of their language, unknown languages the English ones.
+	assert_eq!(Locale::for_language("de-AT"), de);
+	assert_eq!(Locale::for_language("fr"), en);
+}
+
+#[test]
+fn missing_translations_fall_back() {
+	let templates = Templates::builtin();
+	let event = sample_event(EventKind::CoretimeSale);
+
+	let german = templates.render(&event, "de", Tz::UTC).unwrap();
+	assert_eq!(german.subject, "Kusama: Coretime verkauft für 12,345 KSM");
+	// Regional variants use the templates of their language.
+	assert_eq!(templates.render(&event, "de-CH", Tz::UTC).unwrap(), german);
+
+	// Languages without translations are rendered in English, with English formatting.
+	assert_eq!(
+		templates.render(&event, "fr", Tz::UTC).unwrap(),
+		templates.render(&event, "en", Tz::UTC).unwrap()
+	);
+}
+
+#[test]
+fn digests_contain_every_event() {
+	let templates = Templates::builtin();
+	let events = [sample_event(EventKind::CoretimeSale), sample_event(EventKind::CoreRenewed)];
+	let digest = templates.render_digest(&events, "en", Tz::UTC).unwrap();
+	let messages = events.map(|event| templates.render(&event, "en", Tz::UTC).unwrap());
+
+	assert_eq!(digest.subject, "Kusama: 2 coretime notifications");
+	for message in messages {
+		assert!(digest.text.contains(&format!("{}\n\n{}", message.subject, message.text)));
+		assert!(digest.html.contains(&message.html));
+		assert!(digest.markdown.contains(&message.markdown));
+	}
+	assert_snapshot("en/digest.markdown.snap", &digest.markdown);
+}
+
+#[test]
+fn values_are_escaped_per_format() {
+	assert_eq!(Format::Text.escape("<b>1.5</b>"), "<b>1.5</b>");
+	assert_eq!(Format::Html.escape("<b>\"1&5\"</b>"), "&lt;b&gt;&quot;1&amp;5&quot;&lt;/b&gt;");
+	assert_eq!(
+		Format::Markdown.escape("1.5 KSM (2024-05-01)!"),
+		"1\\.5 KSM \\(2024\\-05\\-01\\)\\!"
+	);
+
+	let template = Template::parse("<i>{{network}}</i>").unwrap();
+	let mut context = Context::default();
+	context.insert("network", "<script>".to_string());
+	assert_eq!(template.render(&context, Format::Html), "<i>&lt;script&gt;</i>");
+}
+
+#[test]
+fn sections_depend_on_values() {
+	let template =
+		Template::parse("Block {{ block }}{{#price}}, price {{price}}{{/price}}.").unwrap();
+
+	let mut context = Context::default();
+	context.insert("block", "1".to_string());
+	assert_eq!(template.render(&context, Format::Text), "Block 1.");
+
+	context.insert("price", "2 KSM".to_string());
+	assert_eq!(template.render(&context, Format::Text), "Block 1, price 2 KSM.");
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+	assert!(Template::parse("{{unknown}}").is_err());
+	assert!(Template::parse("{{block").is_err());
+	assert!(Template::parse("{{#price}}unclosed").is_err());
+	assert!(Template::parse("{{#price}}{{/block}}").is_err());
+	assert!(Template::parse("{{/price}}").is_err());
+}
+
+#[test]
+fn templates_can_be_overridden_from_disk() {
+	let dir = env::temp_dir().join("coretime-notifier-templates");
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(
+		dir.join("en.toml"),
+		"[coretime_sale]\nsubject = 'Sold! {{cores_left}} cores left'\n",
+	)
+	.unwrap();
+	fs::write(dir.join("fr.toml"), "[coretime_sale]\nsubject = 'Coretime vendu'\n").unwrap();
+
+	let event = sample_event(EventKind::CoretimeSale);
+	let templates = Templates::load(Some(&dir)).unwrap();
+	let message = templates.render(&event, "en", Tz::UTC).unwrap();
+	assert_eq!(message.subject, "Sold! 7 cores left");
+	// Templates which weren't overridden are still available.
+	assert_eq!(message.text, Templates::builtin().render(&event, "en", Tz::UTC).unwrap().text);
+
+	// New languages can be added, missing templates fall back to English.
+	let message = templates.render(&event, "fr", Tz::UTC).unwrap();
+	assert_eq!(message.subject, "Coretime vendu");
+	assert_eq!(message.text, Templates::builtin().render(&event, "en", Tz::UTC).unwrap().text);
+
+	// Invalid overrides are reported.
+	fs::write(dir.join("en.toml"), "[coretime_sale]\nsubject = '{{cores}}'\n").unwrap();
+	assert!(Templates::load(Some(&dir)).is_err());
+	fs::write(dir.join("en.toml"), "[coretime]\nsubject = 'Sold'\n").unwrap();
+	assert!(Templates::load(Some(&dir)).is_err());
+
+	fs::remove_dir_all(&dir).unwrap();
+}
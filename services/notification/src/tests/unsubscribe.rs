@@ -0,0 +1,154 @@
+use crate::{
+	channels::{email::EmailChannel, telegram::TelegramChannel, Channel, Channels},
+	message::Message,
+	notify,
+	templates::Templates,
+	tests::mock::{create_user, event, execute_with, user, HttpStub, MockChannel},
+	timestamp,
+	unsubscribe::{Scope, UnsubscribeTokens},
+	worker::Worker,
+};
+use chrono_tz::Tz;
+use lettre::transport::smtp::authentication::Credentials;
+use serde_json::Value;
+use std::slice;
+use storage::{
+	init_db,
+	organizations::{Organization, OrganizationSubscription},
+	outbox::OutboxJob,
+	subscriptions::Subscription,
+	users::User,
+};
+use types::{event::EventKind, DeliveryMode, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "unsubscribe-tests.db";
+pub const PENDING_DB_PATH: &'static str = "unsubscribe-pending-tests.db";
+
+fn tokens() -> UnsubscribeTokens {
+	UnsubscribeTokens::new("secret".to_string())
+}
+
+#[test]
+fn tokens_are_bound_to_their_user_and_scope() {
+	let scope = Scope::Notification(Notifications::ParachainState(2000));
+	let token = tokens().token(1, &scope);
+	assert_eq!(tokens().verify(&token), Some((1, scope)));
+	assert_eq!(tokens().verify(&tokens().token(1, &Scope::All)), Some((1, Scope::All)));
+
+	// Tokens can't be altered, or verified with another secret.
+	let (_, signature) = token.split_once('.').unwrap();
+	assert_eq!(tokens().verify(&format!("2.{}", signature)), None);
+	assert_eq!(UnsubscribeTokens::new("other".to_string()).verify(&token), None);
+	assert_eq!(tokens().verify("1.all"), None);
+}
+
+#[tokio::test]
+async fn deliveries_link_to_unsubscribe() {
+	execute_with(DB_PATH, || async {
+		let conn = init_db(DB_PATH).unwrap();
+		let channel = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Email, Box::new(channel.clone()));
+
+		{
+			let conn = conn.lock().unwrap();
+			create_user(&conn, &user(0));
+			Subscription::create(&conn, 0, &Notifications::CoretimeSale, &[Notifier::Email])
+				.unwrap();
+
+			// The second user is only notified as a member of an organization.
+			create_user(&conn, &user(1));
+			let organization = Organization::create(&conn, "team", "Team", 1, 0).unwrap();
+			let parachain = Notifications::ParachainState(2000);
+			OrganizationSubscription::set(&conn, organization.id, &[parachain]).unwrap();
+
+			notify(&conn, &event(EventKind::CoretimeSale, timestamp())).unwrap();
+			let mut expiring = event(EventKind::CoreExpiring, timestamp());
+			expiring.para_id = Some(2000);
+			notify(&conn, &expiring).unwrap();
+		}
+
+		let worker = Worker::new(conn, channels)
+			.with_api_url("https://api.example.com/".into())
+			.with_unsubscribe_tokens(tokens());
+		assert_eq!(worker.process_batch().await.unwrap(), 2);
+
+		let sent = channel.sent.lock().unwrap().clone();
+		let (_, message) = sent.iter().find(|(address, _)| address == "user0@mail.com").unwrap();
+		let url = message.unsubscribe.clone().unwrap();
+		assert!(message.text.ends_with(&format!("Unsubscribe: {}", url)));
+
+		let token = url.strip_prefix("https://api.example.com/unsubscribe/").unwrap();
+		let scope = Scope::Notification(Notifications::CoretimeSale);
+		assert_eq!(tokens().verify(token), Some((0, scope)));
+
+		let (_, message) = sent.iter().find(|(address, _)| address == "user1@mail.com").unwrap();
+		assert_eq!(message.unsubscribe, None);
+	})
+	.await
+}
+
+#[test]
+fn emails_offer_one_click_unsubscription() {
+	let from = "Coretime <noreply@example.com>".parse().unwrap();
+	let channel =
+		EmailChannel::new("localhost", Credentials::new("user".into(), "password".into()), from)
+			.unwrap();
+	let message = Message::plain("Subject", "Text");
+
+	let email = String::from_utf8(channel.email("user@mail.com", &message).unwrap().formatted());
+	assert!(!email.unwrap().contains("List-Unsubscribe"));
+
+	let message = message.with_unsubscribe("Unsubscribe", "https://api.example.com/u/1".into());
+	let email = String::from_utf8(channel.email("user@mail.com", &message).unwrap().formatted());
+	let email = email.unwrap();
+	assert!(email.contains("List-Unsubscribe: <https://api.example.com/u/1>\r\n"));
+	assert!(email.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"));
+}
+
+#[tokio::test]
+async fn telegram_messages_link_to_unsubscribe() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = TelegramChannel::with_api_url(stub.url.clone(), "token".into());
+	let message = Templates::builtin()
+		.render(&event(EventKind::CoretimeSale, 1_714_564_800), "en", Tz::UTC)
+		.unwrap()
+		.with_unsubscribe("Unsubscribe", "https://api.example.com/unsubscribe/1.all.ab".into());
+
+	channel.send("42", &message).await.unwrap();
+
+	let requests = stub.requests.lock().unwrap().clone();
+	let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+	let text = body["text"].as_str().unwrap();
+	assert!(text.ends_with("\n\n[Unsubscribe](https://api.example.com/unsubscribe/1.all.ab)"));
+	assert_eq!(body["parse_mode"], "MarkdownV2");
+}
+
+#[test]
+fn unsubscribing_drops_the_deliveries_which_werent_made_yet() {
+	execute_with(PENDING_DB_PATH, || {
+		let conn = init_db(PENDING_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+		let parachain = Notifications::ParachainState(2000);
+		for id in [0, 1] {
+			let user = User { delivery_mode: DeliveryMode::DailyDigest, ..user(id) };
+			create_user(&conn, &user);
+			Subscription::create(&conn, id, &parachain, &[Notifier::Email]).unwrap();
+		}
+		// The second user's organization subscribed to the notification as well.
+		let organization = Organization::create(&conn, "team", "Team", 1, 0).unwrap();
+		OrganizationSubscription::set(&conn, organization.id, slice::from_ref(&parachain)).unwrap();
+
+		let mut expiring = event(EventKind::CoreExpiring, timestamp());
+		expiring.para_id = Some(2000);
+		assert_eq!(notify(&conn, &expiring).unwrap(), 2);
+
+		// The digest of the first user is dropped.
+		assert!(Subscription::remove(&conn, 0, &parachain).unwrap());
+		assert_eq!(OutboxJob::query_by_user(&conn, 0).unwrap(), vec![]);
+
+		// The second user still receives the notification through the organization.
+		assert!(Subscription::remove(&conn, 1, &parachain).unwrap());
+		assert_eq!(OutboxJob::query_by_user(&conn, 1).unwrap().len(), 1);
+	})
+}
@@ -0,0 +1,172 @@
+use crate::{
+	channels::{
+		webhook::{is_public, verify, WebhookChannel, SIGNATURE_HEADER, TOLERANCE},
+		Channel, ChannelError, Channels,
+	},
+	message::Message,
+	notify,
+	templates::Templates,
+	tests::mock::{create_user, event, execute_with, http_response, user, HttpStub, MockChannel},
+	timestamp,
+	worker::Worker,
+};
+use chrono_tz::Tz;
+use serde_json::{json, Value};
+use storage::{init_db, subscriptions::Subscription, users::User, webhooks::WebhookSecret};
+use types::{
+	event::{Event, EventKind},
+	Notifications, Notifier,
+};
+
+pub const DB_PATH: &'static str = "webhook-tests.db";
+
+fn message(secrets: &[&str]) -> Message {
+	let message = Templates::builtin()
+		.render(&event(EventKind::CoretimeSale, 1_714_564_800), "en", Tz::UTC)
+		.unwrap();
+	Message { secrets: secrets.iter().map(|secret| secret.to_string()).collect(), ..message }
+}
+
+#[tokio::test]
+async fn deliveries_are_signed() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = WebhookChannel::new().with_private_addresses();
+	let url = format!("{}/hooks/coretime", stub.url);
+
+	channel.send(&url, &message(&["secret"])).await.unwrap();
+	// The same delivery once more, e.g. when it is retried.
+	channel.send(&url, &message(&["secret"])).await.unwrap();
+
+	let requests = stub.requests.lock().unwrap().clone();
+	assert_eq!(requests.len(), 2);
+	assert_eq!(requests[0].path, "/hooks/coretime");
+
+	let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+	assert_eq!(body["version"], 1);
+	assert_eq!(
+		body["events"],
+		json!([{
+			"network": "kusama",
+			"kind": "coretime_sale",
+			"block": 100,
+			"timestamp": 1_714_564_800,
+			"endTimestamp": 1_714_568_400,
+			"timeslice": 42,
+			"core": null,
+			"price": "1000000000000",
+			"coresLeft": 5,
+			"paraId": null,
+		}])
+	);
+	// Receivers can recognize retried deliveries.
+	let retried: Value = serde_json::from_str(&requests[1].body).unwrap();
+	assert_eq!(body["id"], retried["id"]);
+
+	let signature = requests[0].header(SIGNATURE_HEADER).unwrap();
+	let now = timestamp();
+	assert!(verify("secret", signature, &requests[0].body, now));
+	// Signatures of other secrets, other bodies, or which are too old are rejected.
+	assert!(!verify("other", signature, &requests[0].body, now));
+	assert!(!verify("secret", signature, &requests[1].body.replace("42", "43"), now));
+	assert!(!verify("secret", signature, &requests[0].body, now + TOLERANCE + 1));
+}
+
+#[tokio::test]
+async fn rotated_secrets_are_accepted() {
+	let stub = HttpStub::start(vec![]).await;
+	let channel = WebhookChannel::new().with_private_addresses();
+
+	channel.send(&stub.url, &message(&["new", "old"])).await.unwrap();
+
+	let request = stub.requests.lock().unwrap()[0].clone();
+	let signature = request.header(SIGNATURE_HEADER).unwrap();
+	assert!(verify("new", signature, &request.body, timestamp()));
+	assert!(verify("old", signature, &request.body, timestamp()));
+}
+
+#[tokio::test]
+async fn rejected_deliveries_are_classified() {
+	let stub = HttpStub::start(vec![
+		http_response(503, &[], "Service Unavailable"),
+		http_response(410, &[], "Gone"),
+	])
+	.await;
+	let channel = WebhookChannel::new().with_private_addresses();
+
+	let result = channel.send(&stub.url, &message(&["secret"])).await;
+	assert!(matches!(result, Err(ChannelError::Transient(_))));
+
+	let result = channel.send(&stub.url, &message(&["secret"])).await;
+	assert!(matches!(result, Err(ChannelError::Permanent(_))));
+
+	// Deliveries are never sent unsigned.
+	let result = channel.send(&stub.url, &message(&[])).await;
+	assert!(matches!(result, Err(ChannelError::Permanent(_))));
+	assert_eq!(stub.requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn deliveries_are_only_made_to_public_addresses() {
+	for ip in ["127.0.0.1", "10.0.0.1", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0"]
+		.into_iter()
+		.chain(["100.64.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1"])
+	{
+		assert!(!is_public(ip.parse().unwrap()), "{} is public", ip);
+	}
+	for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+		assert!(is_public(ip.parse().unwrap()), "{} isn't public", ip);
+	}
+
+	// The host of the url is checked, whether it's an address or a name.
+	let stub = HttpStub::start(vec![]).await;
+	let channel = WebhookChannel::new();
+	let port = stub.url.rsplit(':').next().unwrap();
+	for url in [
+		stub.url.clone(),
+		format!("http://localhost:{}", port),
+		format!("http://2130706433:{}", port),
+		format!("http://[::1]:{}", port),
+	] {
+		let result = channel.send(&url, &message(&["secret"])).await;
+		assert!(matches!(result, Err(ChannelError::Permanent(_))), "{} was posted to", url);
+	}
+	assert!(stub.requests.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn worker_signs_with_the_secrets_of_the_user() {
+	execute_with(DB_PATH, || async {
+		let conn = init_db(DB_PATH).unwrap();
+		let channel = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Webhook, Box::new(channel.clone()));
+
+		{
+			let conn = conn.lock().unwrap();
+			let user =
+				User { webhook_url: Some("https://example.com/hooks".to_string()), ..user(0) };
+			create_user(&conn, &user);
+			Subscription::create(&conn, 0, &Notifications::CoretimeSale, &[Notifier::Webhook])
+				.unwrap();
+
+			// The previous secret is only used for a while after the rotation.
+			WebhookSecret::rotate(&conn, 0, "first", 0).unwrap();
+			WebhookSecret::rotate(&conn, 0, "second", 0).unwrap();
+			notify(&conn, &event(EventKind::CoretimeSale, timestamp())).unwrap();
+		}
+
+		let worker = Worker::new(conn, channels);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+
+		WebhookSecret::rotate(&worker.conn(), 0, "third", timestamp()).unwrap();
+		let event = Event { block: 101, ..event(EventKind::CoretimeSale, timestamp()) };
+		notify(&worker.conn(), &event).unwrap();
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+
+		let sent = channel.sent.lock().unwrap().clone();
+		assert_eq!(sent[0].0, "https://example.com/hooks");
+		assert_eq!(sent[0].1.secrets, vec!["second".to_string()]);
+		assert_eq!(sent[1].1.secrets, vec!["third".to_string(), "second".to_string()]);
+	})
+	.await
+}
@@ -0,0 +1,235 @@
+use crate::{
+	channels::{ChannelError, Channels},
+	notify,
+	retry::RetryPolicy,
+	tests::mock::{create_user, event, execute_with, user, MockChannel},
+	timestamp,
+	worker::Worker,
+};
+use storage::{
+	init_db,
+	outbox::{JobStatus, OutboxJob},
+	subscriptions::Subscription,
+	users::User,
+};
+use types::{event::EventKind, DeliveryMode, Notifications, Notifier};
+
+pub const DB_PATH: &'static str = "worker-tests.db";
+pub const RECLAIM_DB_PATH: &'static str = "reclaim-tests.db";
+pub const DEAD_LETTER_DB_PATH: &'static str = "dead-letter-tests.db";
+pub const DIGEST_DB_PATH: &'static str = "digest-tests.db";
+
+#[tokio::test]
+async fn deliveries_are_retried_until_acknowledged() {
+	execute_with(DB_PATH, || async {
+		let conn = init_db(DB_PATH).unwrap();
+		let channel = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Email, Box::new(channel.clone()));
+
+		{
+			let conn = conn.lock().unwrap();
+			create_user(&conn, &user(0));
+			Subscription::create(&conn, 0, &Notifications::CoretimeSale, &[Notifier::Email])
+				.unwrap();
+
+			// Users not subscribed to the event don't get notified.
+			create_user(&conn, &user(1));
+			Subscription::create(
+				&conn,
+				1,
+				&Notifications::ParachainState(2000),
+				&[Notifier::Email],
+			)
+			.unwrap();
+
+			let enqueued = notify(&conn, &event(EventKind::CoretimeSale, timestamp())).unwrap();
+			assert_eq!(enqueued, 1);
+		}
+
+		let worker = Worker::new(conn, channels);
+
+		// CASE 1: the channel is down, the job stays in the outbox.
+		channel.fail_with(Some(ChannelError::Transient("Channel is down".into())));
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert!(channel.sent.lock().unwrap().is_empty());
+
+		let job = query_job(&worker, 1);
+		assert_eq!(job.status, JobStatus::Pending);
+		assert_eq!(job.attempts, 1);
+		assert_eq!(job.last_error, Some("Channel is down".to_string()));
+
+		// The retry isn't due yet.
+		assert_eq!(worker.process_batch().await.unwrap(), 0);
+
+		// CASE 2: once the retry is due and the channel is back, the job gets delivered.
+		make_due(&worker, 1);
+		channel.fail_with(None);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+
+		let sent = channel.sent.lock().unwrap().clone();
+		assert_eq!(sent.len(), 1);
+		assert_eq!(sent[0].0, "user0@mail.com");
+		assert_eq!(sent[0].1.subject, "Kusama: coretime sold for 1 KSM");
+		// Retries of the delivery are identified by the job, whichever attempt succeeds.
+		assert_eq!(sent[0].1.delivery, Some(query_job(&worker, 1).dedup_key));
+
+		let job = query_job(&worker, 1);
+		assert_eq!(job.status, JobStatus::Sent);
+		assert_eq!(job.attempts, 2);
+
+		// Acknowledged jobs are never claimed again.
+		make_due(&worker, 1);
+		assert_eq!(worker.process_batch().await.unwrap(), 0);
+	})
+	.await
+}
+
+#[tokio::test]
+async fn unacknowledged_jobs_can_be_reclaimed() {
+	execute_with(RECLAIM_DB_PATH, || async {
+		let conn = init_db(RECLAIM_DB_PATH).unwrap();
+		let conn = conn.lock().unwrap();
+		create_user(&conn, &user(0));
+		let id = OutboxJob::enqueue(&conn, "key", 0, &Notifier::Email, "{}", 100, false)
+			.unwrap()
+			.unwrap();
+
+		// A worker claims the job but crashes before acknowledging it.
+		let claimed = OutboxJob::claim_due(&conn, 100, 60, 10).unwrap();
+		assert_eq!(claimed.len(), 1);
+		assert_eq!(claimed[0].status, JobStatus::InFlight);
+
+		// While leased the job can't be claimed by others.
+		assert!(OutboxJob::claim_due(&conn, 159, 60, 10).unwrap().is_empty());
+
+		// Once the lease expires, the job can be claimed again.
+		let claimed = OutboxJob::claim_due(&conn, 160, 60, 10).unwrap();
+		assert_eq!(claimed.len(), 1);
+		assert_eq!(claimed[0].id, id);
+		assert_eq!(claimed[0].attempts, 2);
+	})
+	.await
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_dead_lettered() {
+	execute_with(DEAD_LETTER_DB_PATH, || async {
+		let conn = init_db(DEAD_LETTER_DB_PATH).unwrap();
+		let channel = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Email, Box::new(channel.clone()));
+
+		{
+			let conn = conn.lock().unwrap();
+			create_user(&conn, &user(0));
+			OutboxJob::enqueue(&conn, "key-1", 0, &Notifier::Email, &payload(), 0, false).unwrap();
+			OutboxJob::enqueue(&conn, "key-2", 0, &Notifier::Email, &payload(), 0, false).unwrap();
+		}
+
+		let policy = RetryPolicy { base_delay: 10, max_delay: 100, max_attempts: 2 };
+		let worker = Worker::new(conn, channels).with_retry_policy(policy);
+
+		// CASE 1: permanent errors are never retried.
+		channel.fail_with(Some(ChannelError::Permanent("Invalid address".into())));
+		assert_eq!(worker.process_batch().await.unwrap(), 2);
+		assert_eq!(query_job(&worker, 1).status, JobStatus::Dead);
+		assert_eq!(query_job(&worker, 1).last_error, Some("Invalid address".to_string()));
+
+		// CASE 2: transient errors are retried until the attempts are exhausted.
+		OutboxJob::requeue(&worker.conn(), 2, 0).unwrap();
+		channel.fail_with(Some(ChannelError::Transient("Timeout".into())));
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert_eq!(query_job(&worker, 2).status, JobStatus::Pending);
+
+		make_due(&worker, 2);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		let job = query_job(&worker, 2);
+		assert_eq!(job.status, JobStatus::Dead);
+		assert_eq!(job.attempts, 2);
+
+		let dead_letters = OutboxJob::query_dead_letters(&worker.conn()).unwrap();
+		assert_eq!(dead_letters.iter().map(|job| job.id).collect::<Vec<_>>(), vec![1, 2]);
+
+		// CASE 3: requeued jobs get delivered.
+		OutboxJob::requeue(&worker.conn(), 1, 0).unwrap();
+		channel.fail_with(None);
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert_eq!(query_job(&worker, 1).status, JobStatus::Sent);
+		assert_eq!(channel.sent.lock().unwrap().len(), 1);
+	})
+	.await
+}
+
+#[tokio::test]
+async fn digests_combine_the_due_jobs_of_a_user() {
+	execute_with(DIGEST_DB_PATH, || async {
+		let conn = init_db(DIGEST_DB_PATH).unwrap();
+		let channel = MockChannel::default();
+		let mut channels: Channels = Channels::new();
+		channels.insert(Notifier::Email, Box::new(channel.clone()));
+
+		{
+			let conn = conn.lock().unwrap();
+			let user = User { delivery_mode: DeliveryMode::HourlyDigest, ..user(0) };
+			create_user(&conn, &user);
+			Subscription::create(&conn, 0, &Notifications::CoretimeSale, &[Notifier::Email])
+				.unwrap();
+			Subscription::create(
+				&conn,
+				0,
+				&Notifications::ParachainState(2000),
+				&[Notifier::Email],
+			)
+			.unwrap();
+
+			let now = timestamp();
+			let mut sale = event(EventKind::CoretimeSale, now + 1);
+			notify(&conn, &sale).unwrap();
+			sale.block += 1;
+			notify(&conn, &sale).unwrap();
+
+			let mut expiring = event(EventKind::CoreExpiring, now);
+			expiring.para_id = Some(2000);
+			notify(&conn, &expiring).unwrap();
+		}
+
+		let worker = Worker::new(conn, channels);
+
+		// CASE 1: critical events are sent right away, the rest waits for the next digest.
+		assert_eq!(worker.process_batch().await.unwrap(), 1);
+		assert_eq!(query_job(&worker, 3).status, JobStatus::Sent);
+		assert!(query_job(&worker, 1).digest);
+		assert!(query_job(&worker, 1).next_attempt_at > timestamp());
+		assert_eq!(query_job(&worker, 1).next_attempt_at % 3600, 0);
+
+		// CASE 2: the digest is due, all of its jobs are sent as a single message.
+		make_due(&worker, 1);
+		make_due(&worker, 2);
+		assert_eq!(worker.process_batch().await.unwrap(), 2);
+		assert_eq!(query_job(&worker, 1).status, JobStatus::Sent);
+		assert_eq!(query_job(&worker, 2).status, JobStatus::Sent);
+
+		let sent = channel.sent.lock().unwrap().clone();
+		assert_eq!(sent.len(), 2);
+		assert_eq!(sent[0].1.subject, "Kusama: coretime of parachain 2000 is about to expire");
+		assert_eq!(sent[1].1.subject, "Kusama: 2 coretime notifications");
+		assert_eq!(sent[1].1.text.matches("Kusama: coretime sold for 1 KSM").count(), 2);
+	})
+	.await
+}
+
+fn payload() -> String {
+	serde_json::to_string(&event(EventKind::CoretimeSale, 0)).unwrap()
+}
+
+pub fn query_job(worker: &Worker, id: i64) -> OutboxJob {
+	OutboxJob::query_by_id(&worker.conn(), id).unwrap().unwrap()
+}
+
+pub fn make_due(worker: &Worker, id: i64) {
+	worker
+		.conn()
+		.execute("UPDATE outbox SET next_attempt_at = 0 WHERE id = ?1", [id])
+		.unwrap();
+}
@@ -0,0 +1,75 @@
+//! Links which unsubscribe from notifications without signing in.
+//!
+//! Deliveries link to `/unsubscribe/<token>` of the api, see [`crate::worker::Worker`]. The token
+//! names the user and what to unsubscribe from, and is signed with the unsubscribe secret so
+//! that it can't be altered. Tokens don't expire, so that the links in old emails keep working.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use types::Notifications;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a link unsubscribes from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Scope {
+	/// Every subscription of the user.
+	All,
+	/// The subscription of the user to the notification.
+	Notification(Notifications),
+}
+
+/// Signs and verifies the tokens of unsubscribe links.
+pub struct UnsubscribeTokens {
+	secret: String,
+}
+
+impl UnsubscribeTokens {
+	pub fn new(secret: String) -> Self {
+		Self { secret }
+	}
+
+	/// Reads the secret from `UNSUBSCRIBE_SECRET`.
+	///
+	/// Fails if the secret isn't set, since links signed with a random secret would stop working
+	/// after a restart, and links signed with an empty one could be forged.
+	pub fn from_env() -> Result<Self, env::VarError> {
+		match env::var("UNSUBSCRIBE_SECRET")? {
+			secret if secret.is_empty() => Err(env::VarError::NotPresent),
+			secret => Ok(Self::new(secret)),
+		}
+	}
+
+	/// Returns the token which unsubscribes the user from the scope.
+	pub fn token(&self, user_id: u32, scope: &Scope) -> String {
+		let scope = match scope {
+			Scope::All => "all".to_string(),
+			Scope::Notification(notification) =>
+				hex::encode(serde_json::to_string(notification).expect("notifications serialize")),
+		};
+		let signature = self.mac(user_id, &scope).finalize().into_bytes();
+		format!("{}.{}.{}", user_id, scope, hex::encode(signature))
+	}
+
+	/// Returns the user and scope of the token, or `None` if it wasn't signed with the secret.
+	pub fn verify(&self, token: &str) -> Option<(u32, Scope)> {
+		let mut parts = token.splitn(3, '.');
+		let (user_id, scope, signature) = (parts.next()?, parts.next()?, parts.next()?);
+		let user_id: u32 = user_id.parse().ok()?;
+		self.mac(user_id, scope).verify_slice(&hex::decode(signature).ok()?).ok()?;
+
+		let scope = match scope {
+			"all" => Scope::All,
+			scope => Scope::Notification(serde_json::from_slice(&hex::decode(scope).ok()?).ok()?),
+		};
+		Some((user_id, scope))
+	}
+
+	fn mac(&self, user_id: u32, scope: &str) -> HmacSha256 {
+		let mut mac =
+			HmacSha256::new_from_slice(self.secret.as_bytes()).expect("any key length is valid");
+		mac.update(format!("unsubscribe.{}.{}", user_id, scope).as_bytes());
+		mac
+	}
+}
@@ -0,0 +1,343 @@
+//! Workers deliver the notifications waiting in the outbox.
+
+use crate::{
+	can_deliver,
+	channels::{ChannelError, Channels},
+	dedup_key,
+	message::{Action, Message},
+	retry::RetryPolicy,
+	templates::{Locale, Templates},
+	timestamp, timezone,
+	unsubscribe::{Scope, UnsubscribeTokens},
+	LOG_TARGET,
+};
+use rusqlite::{Connection, Result};
+use std::{
+	sync::{MutexGuard, PoisonError},
+	time::Duration,
+};
+use storage::{
+	escalations::Alert,
+	outbox::OutboxJob,
+	subscriptions::Subscription,
+	telegram::TelegramChat,
+	tokens::{hash_token, random_token, TOKEN_BYTES},
+	users::User,
+	webhooks::WebhookSecret,
+	DbConn,
+};
+use types::{event::Event, Notifier};
+
+/// How long a claimed job is reserved for the worker that claimed it.
+const LEASE: u64 = 60;
+/// The maximum number of jobs claimed at once.
+const BATCH_SIZE: u32 = 32;
+/// How long to wait before checking the outbox again once there is nothing left to deliver.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long deliveries to webhooks are also signed with the previous secret after a rotation.
+const ROTATION_GRACE_PERIOD: u64 = 24 * 60 * 60;
+
+pub struct Worker {
+	conn: DbConn,
+	channels: Channels,
+	templates: Templates,
+	retry_policy: RetryPolicy,
+	/// The public url of the api, under which alerts are acknowledged.
+	api_url: Option<String>,
+	/// Signs the links through which users unsubscribe, which are offered if the api url is set.
+	unsubscribe: Option<UnsubscribeTokens>,
+}
+
+impl Worker {
+	pub fn new(conn: DbConn, channels: Channels) -> Self {
+		Self {
+			conn,
+			channels,
+			templates: Templates::builtin(),
+			retry_policy: RetryPolicy::default(),
+			api_url: None,
+			unsubscribe: None,
+		}
+	}
+
+	pub fn with_templates(mut self, templates: Templates) -> Self {
+		self.templates = templates;
+		self
+	}
+
+	pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+		self.retry_policy = retry_policy;
+		self
+	}
+
+	/// Links the deliveries of alerts to the api, so that they can be acknowledged.
+	pub fn with_api_url(mut self, api_url: String) -> Self {
+		self.api_url = Some(api_url);
+		self
+	}
+
+	/// Links the deliveries of subscriptions to the api, so that users can unsubscribe from them.
+	pub fn with_unsubscribe_tokens(mut self, tokens: UnsubscribeTokens) -> Self {
+		self.unsubscribe = Some(tokens);
+		self
+	}
+
+	/// Keeps delivering notifications as they become due.
+	pub async fn run(self) {
+		loop {
+			match self.process_batch().await {
+				Ok(0) => tokio::time::sleep(POLL_INTERVAL).await,
+				Ok(_) => {},
+				Err(err) => {
+					log::error!(target: LOG_TARGET, "Failed to process outbox: {:?}", err);
+					tokio::time::sleep(POLL_INTERVAL).await;
+				},
+			}
+		}
+	}
+
+	/// Claims the jobs which are currently due and attempts to deliver them.
+	///
+	/// The due digest jobs of a user are delivered together as a single message.
+	///
+	/// Returns the number of claimed jobs.
+	pub async fn process_batch(&self) -> Result<usize> {
+		let now = timestamp();
+		let jobs = OutboxJob::claim_due(&self.conn(), now, LEASE, BATCH_SIZE)?;
+		let mut claimed = jobs.len();
+
+		let mut deliveries: Vec<Vec<OutboxJob>> = vec![];
+		for job in jobs {
+			let digest = deliveries.iter_mut().find(|delivery| {
+				job.digest &&
+					delivery[0].digest &&
+					delivery[0].user_id == job.user_id &&
+					delivery[0].channel == job.channel
+			});
+			match digest {
+				Some(digest) => digest.push(job),
+				None => deliveries.push(vec![job]),
+			}
+		}
+
+		// Not all jobs of a digest might fit into a single batch.
+		for digest in deliveries.iter_mut().filter(|delivery| delivery[0].digest) {
+			let (user_id, channel) = (digest[0].user_id, digest[0].channel.clone());
+			let rest = OutboxJob::claim_digest(&self.conn(), user_id, &channel, now, LEASE)?;
+			claimed += rest.len();
+			digest.extend(rest);
+		}
+
+		for jobs in deliveries.iter() {
+			let result = self.deliver(jobs).await;
+			self.settle(jobs, result)?;
+		}
+
+		Ok(claimed)
+	}
+
+	/// Acknowledges the delivered jobs, or schedules their next attempt if the delivery failed.
+	fn settle(&self, jobs: &[OutboxJob], result: Result<(), ChannelError>) -> Result<()> {
+		let conn = self.conn();
+		let ids = jobs.iter().map(|job| job.id).collect::<Vec<_>>();
+
+		let err = match result {
+			Ok(()) => {
+				for job in jobs {
+					OutboxJob::ack(&conn, job.id)?;
+				}
+				return Ok(());
+			},
+			Err(err) => err,
+		};
+
+		log::warn!(target: LOG_TARGET, "Failed to deliver jobs {:?}: {}", ids, err);
+		// Jobs of a digest are retried together, so they stay part of the same digest.
+		let attempts = jobs.iter().map(|job| job.attempts).max().unwrap_or_default();
+		let next_attempt_at = err
+			.is_transient()
+			.then(|| self.retry_policy.next_attempt_at(attempts, timestamp()))
+			.flatten();
+
+		for job in jobs {
+			match next_attempt_at {
+				Some(next_attempt_at) => {
+					OutboxJob::retry(&conn, job.id, &err.to_string(), next_attempt_at)?;
+				},
+				None => {
+					log::error!(target: LOG_TARGET, "Giving up on job {}: {}", job.id, err);
+					OutboxJob::dead_letter(&conn, job.id, &err.to_string())?;
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Delivers the jobs, which all belong to the same user and channel, as a single message.
+	async fn deliver(&self, jobs: &[OutboxJob]) -> Result<(), ChannelError> {
+		let job = &jobs[0];
+		let mut events = jobs
+			.iter()
+			.map(|job| serde_json::from_str(&job.payload))
+			.collect::<serde_json::Result<Vec<Event>>>()
+			.map_err(|err| ChannelError::Permanent(format!("Invalid payload: {}", err)))?;
+		events.sort_by_key(|event| event.timestamp);
+
+		let user = User::query_by_id(&self.conn(), job.user_id)
+			.map_err(|err| ChannelError::Transient(err.to_string()))?
+			.ok_or(ChannelError::Permanent("User not found".into()))?;
+
+		let alert = match job.alert_id {
+			Some(alert_id) => Alert::query_by_id(&self.conn(), alert_id)
+				.map_err(|err| ChannelError::Transient(err.to_string()))?,
+			None => None,
+		};
+		// The alert was acknowledged after the job was claimed.
+		if alert.as_ref().map_or(false, |alert| alert.acknowledged_at.is_some()) {
+			log::info!(target: LOG_TARGET, "Skipping job {} of an acknowledged alert", job.id);
+			return Ok(());
+		}
+
+		// The user might have changed the address since the job was enqueued.
+		if !can_deliver(&self.conn(), &user, &job.channel, job.address.as_deref())
+			.map_err(|err| ChannelError::Transient(err.to_string()))?
+		{
+			return Err(ChannelError::Permanent("Address isn't verified".into()));
+		}
+		let address = match job.channel {
+			Notifier::Telegram => self.telegram_chat(&user, job.address.as_deref())?.to_string(),
+			_ => job
+				.address
+				.as_ref()
+				.or(user.address(&job.channel))
+				.cloned()
+				.ok_or(ChannelError::Permanent("User has no address for the channel".into()))?,
+		};
+
+		// The channel might get configured later on.
+		let channel = self.channels.get(&job.channel).ok_or(ChannelError::Transient(format!(
+			"{:?} channel is not configured",
+			job.channel
+		)))?;
+
+		// Broken templates have to be fixed by the operator before the job can be requeued.
+		let message = match events.as_slice() {
+			[event] => self.templates.render(event, &user.language, timezone(&user)),
+			events => self.templates.render_digest(events, &user.language, timezone(&user)),
+		}
+		.map_err(|err| ChannelError::Permanent(format!("Failed to render: {}", err)))?;
+
+		let message = match job.channel {
+			Notifier::Webhook => Message { secrets: self.webhook_secrets(user.id)?, ..message },
+			_ => message,
+		};
+		let message = match (&alert, &self.api_url) {
+			(Some(alert), Some(api_url)) => message.with_action(Action {
+				label: Locale::for_language(&user.language).labels.acknowledge.to_string(),
+				url: format!(
+					"{}/alert/{}/acknowledge",
+					api_url.trim_end_matches('/'),
+					self.acknowledgement_token(alert)?
+				),
+			}),
+			_ => message,
+		};
+		let message = match (&self.unsubscribe, &self.api_url) {
+			(Some(tokens), Some(api_url)) => match self.unsubscribe_scope(jobs, &events, &user)? {
+				Some(scope) => message.with_unsubscribe(
+					Locale::for_language(&user.language).labels.unsubscribe,
+					format!(
+						"{}/unsubscribe/{}",
+						api_url.trim_end_matches('/'),
+						tokens.token(user.id, &scope)
+					),
+				),
+				None => message,
+			},
+			_ => message,
+		};
+
+		// A retried delivery is sent as the same request, so that it isn't delivered twice.
+		let delivery = jobs.iter().map(|job| job.dedup_key.as_str()).collect::<Vec<_>>().join("\n");
+		let message = Message { delivery: Some(delivery), ..message };
+
+		channel.send(&address, &message).await
+	}
+
+	/// Returns what the recipient of the jobs can unsubscribe from: the subscription a single job
+	/// was enqueued for, or all subscriptions of the user for a digest.
+	///
+	/// Contacts an alert is escalated to aren't subscribed, and deliveries of organizations are
+	/// stopped by leaving the organization, so neither can be unsubscribed from.
+	fn unsubscribe_scope(
+		&self,
+		jobs: &[OutboxJob],
+		events: &[Event],
+		user: &User,
+	) -> Result<Option<Scope>, ChannelError> {
+		let job = &jobs[0];
+		if job.address.is_some() {
+			return Ok(None);
+		}
+		let subscriptions = Subscription::query_by_user(&self.conn(), user.id)
+			.map_err(|err| ChannelError::Transient(err.to_string()))?;
+
+		if let [event] = events {
+			return Ok(subscriptions
+				.into_iter()
+				.map(|subscription| subscription.notification)
+				.find(|notification| {
+					dedup_key(event, notification, user.id, &job.channel) == job.dedup_key
+				})
+				.map(Scope::Notification));
+		}
+		Ok((!subscriptions.is_empty()).then_some(Scope::All))
+	}
+
+	/// Returns the chat with the bot through which the user, or the contact with the username, is
+	/// messaged on Telegram.
+	///
+	/// The bot can only message chats which were started with it, see [`crate::bot`].
+	fn telegram_chat(&self, user: &User, contact: Option<&str>) -> Result<i64, ChannelError> {
+		let chat = match contact {
+			Some(username) => TelegramChat::query_by_username(&self.conn(), username),
+			None => TelegramChat::query_by_user(&self.conn(), user.id),
+		}
+		.map_err(|err| ChannelError::Transient(err.to_string()))?;
+
+		chat.map(|chat| chat.chat_id)
+			.ok_or(ChannelError::Permanent("No chat was started with the bot".into()))
+	}
+
+	/// Issues the token with which the recipient of a delivery acknowledges the alert.
+	///
+	/// Every delivery gets its own token, only the hash of which is stored.
+	fn acknowledgement_token(&self, alert: &Alert) -> Result<String, ChannelError> {
+		let token = random_token(TOKEN_BYTES);
+		Alert::add_token(&self.conn(), alert.id, &hash_token(&token))
+			.map_err(|err| ChannelError::Transient(err.to_string()))?;
+		Ok(token)
+	}
+
+	/// Returns the secrets with which deliveries to the webhook of the user are signed.
+	///
+	/// The previous secret stays in use for a while after a rotation, so that integrations can
+	/// switch over to the new one without rejecting deliveries.
+	fn webhook_secrets(&self, user_id: u32) -> Result<Vec<String>, ChannelError> {
+		let secret = WebhookSecret::query_by_user(&self.conn(), user_id)
+			.map_err(|err| ChannelError::Transient(err.to_string()))?
+			.ok_or(ChannelError::Permanent("User has no webhook secret".into()))?;
+
+		let mut secrets = vec![secret.secret];
+		if secret.rotated_at + ROTATION_GRACE_PERIOD > timestamp() {
+			secrets.extend(secret.previous_secret);
+		}
+		Ok(secrets)
+	}
+
+	pub(crate) fn conn(&self) -> MutexGuard<'_, Connection> {
+		// A panic while holding the lock doesn't leave the connection in an unusable state.
+		self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
@@ -0,0 +1,54 @@
+//! A log of the changes made on behalf of users without a session, e.g. through the unsubscribe
+//! links of notifications.
+//!
+//! Entries are never changed, they are only removed along with their user.
+
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use types::Timestamp;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+	pub id: i64,
+	/// The user the change was made for.
+	pub user_id: u32,
+	/// What was changed, e.g. `unsubscribe`.
+	pub action: String,
+	/// The details of the change as JSON.
+	pub details: String,
+	pub created_at: Timestamp,
+}
+
+impl AuditEntry {
+	/// Records the change, returning the id of the entry.
+	pub fn record(
+		conn: &Connection,
+		user_id: u32,
+		action: &str,
+		details: &str,
+		now: Timestamp,
+	) -> Result<i64> {
+		conn.execute(
+			"INSERT INTO audit_log (user_id, action, details, created_at) VALUES (?1, ?2, ?3, ?4)",
+			params![user_id, action, details, now],
+		)?;
+		Ok(conn.last_insert_rowid())
+	}
+
+	pub fn query_by_user(conn: &Connection, user_id: u32) -> Result<Vec<AuditEntry>> {
+		let mut stmt = conn.prepare("SELECT * FROM audit_log WHERE user_id = ?1 ORDER BY id")?;
+		let entries_iter = stmt.query_map(params![user_id], Self::from_row)?;
+
+		entries_iter.collect()
+	}
+
+	fn from_row(row: &Row) -> Result<AuditEntry> {
+		Ok(AuditEntry {
+			id: row.get("id")?,
+			user_id: row.get("user_id")?,
+			action: row.get("action")?,
+			details: row.get("details")?,
+			created_at: row.get("created_at")?,
+		})
+	}
+}
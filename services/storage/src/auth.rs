@@ -0,0 +1,279 @@
+//! Sign-in with Substrate accounts and email addresses.
+//!
+//! To sign in, a client requests a [`Challenge`] for an account and signs its message with the
+//! account. The signed challenge is exchanged for a [`Session`], which authenticates further
+//! requests. Each challenge can only be used once.
+//!
+//! Without an account, users sign in through a [`LoginLink`] sent to their email address. Like
+//! challenges, links can only be used once.
+//!
+//! Scripts act on behalf of a user through an [`ApiKey`] instead, which the user creates while
+//! signed in.
+//!
+//! Sessions, links and keys are looked up by the hash of their token, so that a leaked db doesn't
+//! leak any usable tokens.
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use types::{api::ApiKeyScope, Timestamp};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Challenge {
+	/// The random nonce which identifies the challenge.
+	pub nonce: String,
+	/// The SS58 address of the account which has to sign the message.
+	pub account: String,
+	/// The message to sign, which contains the nonce.
+	pub message: String,
+	/// Unix timestamp after which the challenge can't be used anymore.
+	pub expires_at: Timestamp,
+}
+
+impl Challenge {
+	pub fn create(conn: &Connection, challenge: &Challenge) -> Result<()> {
+		conn.execute(
+			"INSERT INTO auth_challenges (nonce, account, message, expires_at)
+				VALUES (?1, ?2, ?3, ?4)",
+			params![challenge.nonce, challenge.account, challenge.message, challenge.expires_at],
+		)?;
+		Ok(())
+	}
+
+	/// Removes the challenge with the nonce and returns it, unless it expired.
+	///
+	/// Also removes all expired challenges.
+	pub fn take(conn: &Connection, nonce: &str, now: Timestamp) -> Result<Option<Challenge>> {
+		conn.execute("DELETE FROM auth_challenges WHERE expires_at < ?1", params![now])?;
+		let challenge = conn
+			.query_row(
+				"SELECT * FROM auth_challenges WHERE nonce = ?1",
+				params![nonce],
+				Self::from_row,
+			)
+			.optional()?;
+		conn.execute("DELETE FROM auth_challenges WHERE nonce = ?1", params![nonce])?;
+
+		Ok(challenge)
+	}
+
+	fn from_row(row: &Row) -> Result<Challenge> {
+		Ok(Challenge {
+			nonce: row.get("nonce")?,
+			account: row.get("account")?,
+			message: row.get("message")?,
+			expires_at: row.get("expires_at")?,
+		})
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Session {
+	/// The hash of the token which authenticates the session.
+	pub token_hash: String,
+	/// The account which signed in, e.g. an SS58 address.
+	pub account: String,
+	/// Unix timestamp after which the session has to be renewed by signing in again.
+	pub expires_at: Timestamp,
+}
+
+impl Session {
+	pub fn create(conn: &Connection, session: &Session) -> Result<()> {
+		conn.execute(
+			"INSERT INTO sessions (token_hash, account, expires_at) VALUES (?1, ?2, ?3)",
+			params![session.token_hash, session.account, session.expires_at],
+		)?;
+		Ok(())
+	}
+
+	/// Returns the session with the token hash, unless it expired.
+	pub fn query(conn: &Connection, token_hash: &str, now: Timestamp) -> Result<Option<Session>> {
+		conn.query_row(
+			"SELECT * FROM sessions WHERE token_hash = ?1 AND expires_at >= ?2",
+			params![token_hash, now],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Ends the session, along with any expired sessions.
+	pub fn delete(conn: &Connection, token_hash: &str, now: Timestamp) -> Result<()> {
+		conn.execute(
+			"DELETE FROM sessions WHERE token_hash = ?1 OR expires_at < ?2",
+			params![token_hash, now],
+		)?;
+		Ok(())
+	}
+
+	fn from_row(row: &Row) -> Result<Session> {
+		Ok(Session {
+			token_hash: row.get("token_hash")?,
+			account: row.get("account")?,
+			expires_at: row.get("expires_at")?,
+		})
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoginLink {
+	/// The hash of the token in the link.
+	pub token_hash: String,
+	/// The email address the link was sent to.
+	pub email: String,
+	pub created_at: Timestamp,
+	/// Unix timestamp after which the link can't be used anymore.
+	pub expires_at: Timestamp,
+}
+
+impl LoginLink {
+	pub fn create(conn: &Connection, link: &LoginLink) -> Result<()> {
+		conn.execute(
+			"INSERT INTO login_links (token_hash, email, created_at, expires_at)
+				VALUES (?1, ?2, ?3, ?4)",
+			params![link.token_hash, link.email, link.created_at, link.expires_at],
+		)?;
+		Ok(())
+	}
+
+	/// Marks the link with the token hash as used and returns it, unless it was used before or
+	/// expired.
+	pub fn take(conn: &Connection, token_hash: &str, now: Timestamp) -> Result<Option<LoginLink>> {
+		let taken = conn.execute(
+			"UPDATE login_links SET used_at = ?2
+				WHERE token_hash = ?1 AND used_at IS NULL AND expires_at >= ?2",
+			params![token_hash, now],
+		)?;
+		if taken == 0 {
+			return Ok(None);
+		}
+
+		conn.query_row(
+			"SELECT * FROM login_links WHERE token_hash = ?1",
+			params![token_hash],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns how many links were sent to the address since the given time.
+	///
+	/// Used links still count, links created before are removed.
+	pub fn count_since(conn: &Connection, email: &str, since: Timestamp) -> Result<u32> {
+		conn.execute("DELETE FROM login_links WHERE created_at < ?1", params![since])?;
+		conn.query_row("SELECT COUNT(*) FROM login_links WHERE email = ?1", params![email], |row| {
+			row.get(0)
+		})
+	}
+
+	fn from_row(row: &Row) -> Result<LoginLink> {
+		Ok(LoginLink {
+			token_hash: row.get("token_hash")?,
+			email: row.get("email")?,
+			created_at: row.get("created_at")?,
+			expires_at: row.get("expires_at")?,
+		})
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ApiKey {
+	/// The public id of the key, through which it is listed and revoked.
+	pub id: String,
+	/// The user on whose behalf the key acts.
+	pub user_id: u32,
+	/// What the key is used for.
+	pub name: String,
+	/// The hash of the key.
+	pub key_hash: String,
+	pub scope: ApiKeyScope,
+	pub created_at: Timestamp,
+	/// Unix timestamp after which the key can't be used anymore, if any.
+	pub expires_at: Option<Timestamp>,
+	pub last_used_at: Option<Timestamp>,
+}
+
+impl ApiKey {
+	pub fn create(conn: &Connection, key: &ApiKey) -> Result<()> {
+		conn.execute(
+			"INSERT INTO api_keys (id, user_id, name, key_hash, scope, created_at, expires_at)
+				VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+			params![
+				key.id,
+				key.user_id,
+				key.name,
+				key.key_hash,
+				Self::scope_to_text(&key.scope),
+				key.created_at,
+				key.expires_at
+			],
+		)?;
+		Ok(())
+	}
+
+	/// Returns the key with the hash and records that it was used, unless it expired.
+	pub fn authenticate(
+		conn: &Connection,
+		key_hash: &str,
+		now: Timestamp,
+	) -> Result<Option<ApiKey>> {
+		let used = conn.execute(
+			"UPDATE api_keys SET last_used_at = ?2
+				WHERE key_hash = ?1 AND (expires_at IS NULL OR expires_at >= ?2)",
+			params![key_hash, now],
+		)?;
+		if used == 0 {
+			return Ok(None);
+		}
+
+		conn.query_row(
+			"SELECT * FROM api_keys WHERE key_hash = ?1",
+			params![key_hash],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns the keys of the user, including expired ones, oldest first.
+	pub fn query_by_user(conn: &Connection, user_id: u32) -> Result<Vec<ApiKey>> {
+		let mut stmt =
+			conn.prepare("SELECT * FROM api_keys WHERE user_id = ?1 ORDER BY created_at, id")?;
+		let keys = stmt.query_map(params![user_id], Self::from_row)?;
+
+		keys.collect()
+	}
+
+	/// Removes the key of the user. Returns whether the user had the key.
+	pub fn revoke(conn: &Connection, user_id: u32, id: &str) -> Result<bool> {
+		let removed = conn
+			.execute("DELETE FROM api_keys WHERE user_id = ?1 AND id = ?2", params![user_id, id])?;
+		Ok(removed > 0)
+	}
+
+	fn scope_to_text(scope: &ApiKeyScope) -> &'static str {
+		match scope {
+			ApiKeyScope::Full => "full",
+			ApiKeyScope::ReadOnly => "read_only",
+			ApiKeyScope::Subscriptions => "subscriptions",
+		}
+	}
+
+	fn text_to_scope(text: String) -> ApiKeyScope {
+		match text.as_str() {
+			"full" => ApiKeyScope::Full,
+			"subscriptions" => ApiKeyScope::Subscriptions,
+			// Unknown scopes grant the least access.
+			_ => ApiKeyScope::ReadOnly,
+		}
+	}
+
+	fn from_row(row: &Row) -> Result<ApiKey> {
+		Ok(ApiKey {
+			id: row.get("id")?,
+			user_id: row.get("user_id")?,
+			name: row.get("name")?,
+			key_hash: row.get("key_hash")?,
+			scope: Self::text_to_scope(row.get("scope")?),
+			created_at: row.get("created_at")?,
+			expires_at: row.get("expires_at")?,
+			last_used_at: row.get("last_used_at")?,
+		})
+	}
+}
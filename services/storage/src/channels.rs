@@ -0,0 +1,132 @@
+//! The channels through which users receive their notifications.
+//!
+//! A user has at most one channel of each kind, e.g. an email address and a Telegram handle.
+//! Which of them a notification is delivered through is up to the routing of the subscription,
+//! see [`crate::subscriptions::Subscription`].
+//!
+//! Email addresses have to be verified before notifications are delivered to them, so that nobody
+//! can subscribe the inbox of someone else. Changing an address resets its verification.
+//! Addresses which were registered before addresses were verified are kept in a backlog, until
+//! they were sent a confirmation link.
+
+use crate::users::User;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use types::{Notifier, Timestamp};
+
+/// The channels for which an address is stored.
+pub const KINDS: [Notifier; 6] = [
+	Notifier::Email,
+	Notifier::Telegram,
+	Notifier::Discord,
+	Notifier::Slack,
+	Notifier::Matrix,
+	Notifier::Webhook,
+];
+
+/// Sets the address of the user's channel of the given kind.
+///
+/// Passing no address removes the channel, along with the routing of subscriptions, the
+/// preferences for organizations and the escalation steps to it.
+pub fn set(conn: &Connection, user_id: u32, kind: &Notifier, address: Option<&str>) -> Result<()> {
+	// A linked Telegram chat belongs to the handle it was started from.
+	if *kind == Notifier::Telegram {
+		conn.execute(
+			"DELETE FROM telegram_chats WHERE user_id = ?1 AND handle IS NOT ?2",
+			params![user_id, address],
+		)?;
+	}
+
+	let kind = User::notifier_to_text(kind);
+
+	match address {
+		Some(address) => {
+			conn.execute(
+				"INSERT INTO channels (user_id, kind, address) VALUES (?1, ?2, ?3)
+					ON CONFLICT (user_id, kind) DO UPDATE SET
+						verified_at = CASE WHEN address = excluded.address
+							THEN verified_at ELSE NULL END,
+						address = excluded.address",
+				params![user_id, kind, address],
+			)?;
+		},
+		None => {
+			conn.execute(
+				"DELETE FROM channels WHERE user_id = ?1 AND kind = ?2",
+				params![user_id, kind],
+			)?;
+			conn.execute(
+				"DELETE FROM subscription_channels WHERE user_id = ?1 AND channel = ?2",
+				params![user_id, kind],
+			)?;
+			conn.execute(
+				"DELETE FROM organization_member_channels WHERE user_id = ?1 AND channel = ?2",
+				params![user_id, kind],
+			)?;
+			// Escalations to contacts don't depend on the user's own address.
+			conn.execute(
+				"DELETE FROM escalation_steps
+					WHERE user_id = ?1 AND channel = ?2 AND contact IS NULL",
+				params![user_id, kind],
+			)?;
+		},
+	}
+
+	Ok(())
+}
+
+/// Marks the address of the user's channel as verified.
+///
+/// Returns `false` if the channel has a different address by now.
+pub fn verify(
+	conn: &Connection,
+	user_id: u32,
+	kind: &Notifier,
+	address: &str,
+	now: Timestamp,
+) -> Result<bool> {
+	let verified = conn.execute(
+		"UPDATE channels SET verified_at = COALESCE(verified_at, ?4)
+			WHERE user_id = ?1 AND kind = ?2 AND address = ?3",
+		params![user_id, User::notifier_to_text(kind), address, now],
+	)?;
+	Ok(verified > 0)
+}
+
+/// Returns whether the address of the user's channel is verified.
+pub fn is_verified(conn: &Connection, user_id: u32, kind: &Notifier) -> Result<bool> {
+	Ok(verified_at(conn, user_id, kind)?.is_some())
+}
+
+/// Returns when the address of the user's channel was verified, if it was.
+pub fn verified_at(conn: &Connection, user_id: u32, kind: &Notifier) -> Result<Option<Timestamp>> {
+	let verified_at: Option<Option<Timestamp>> = conn
+		.query_row(
+			"SELECT verified_at FROM channels WHERE user_id = ?1 AND kind = ?2",
+			params![user_id, User::notifier_to_text(kind)],
+			|row| row.get(0),
+		)
+		.optional()?;
+	Ok(verified_at.flatten())
+}
+
+/// Returns the users whose email addresses were registered before addresses were verified, along
+/// with the addresses, and empties the backlog.
+///
+/// Addresses which were verified or removed in the meantime aren't returned.
+pub fn take_verification_backlog(conn: &Connection) -> Result<Vec<(u32, String)>> {
+	let tx = conn.unchecked_transaction()?;
+	let backlog = {
+		let mut stmt = tx.prepare(
+			"SELECT channels.user_id, channels.address FROM verification_backlog
+				JOIN channels ON channels.user_id = verification_backlog.user_id
+				WHERE channels.kind = 'email' AND channels.verified_at IS NULL
+				ORDER BY channels.user_id",
+		)?;
+		let backlog_iter = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+		backlog_iter.collect::<Result<Vec<_>>>()?
+	};
+	tx.execute("DELETE FROM verification_backlog", ())?;
+	tx.commit()?;
+
+	Ok(backlog)
+}
@@ -0,0 +1,276 @@
+//! Escalation of critical alerts which aren't acknowledged.
+//!
+//! A subscription can have escalation steps. When a critical event is delivered for such a
+//! subscription, an [`Alert`] is raised and every step is enqueued as a delivery of the alert,
+//! which is due `after_minutes` after the event. Acknowledging the alert cancels its deliveries
+//! which weren't made yet, which stops the escalation.
+//!
+//! Steps can escalate the alert to a [`Contact`] instead of the user's own address. Contacts
+//! have to confirm that they want to receive the alerts of the user before anything is delivered
+//! to them, so that nobody can be flooded with the alerts of someone else.
+//!
+//! Every delivery of an alert links to its own acknowledgement token. Only the hashes of the
+//! tokens are stored, see [`crate::tokens::hash_token`].
+
+use crate::users::User;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use types::{EscalationStep, Notifications, Notifier, Timestamp};
+
+/// Returns the escalation steps of the user's subscription to the notification, in order.
+pub fn query_steps(
+	conn: &Connection,
+	user_id: u32,
+	notification: &Notifications,
+) -> Result<Vec<EscalationStep>> {
+	let mut stmt = conn.prepare(
+		"SELECT * FROM escalation_steps WHERE user_id = ?1 AND notification = ?2 ORDER BY position",
+	)?;
+	let steps_iter =
+		stmt.query_map(params![user_id, notification_to_text(notification)?], step_from_row)?;
+
+	steps_iter.collect()
+}
+
+/// Replaces the escalation steps of the user's subscription to the notification.
+pub fn set_steps(
+	conn: &Connection,
+	user_id: u32,
+	notification: &Notifications,
+	steps: &[EscalationStep],
+) -> Result<()> {
+	let notification = notification_to_text(notification)?;
+
+	conn.execute(
+		"DELETE FROM escalation_steps WHERE user_id = ?1 AND notification = ?2",
+		params![user_id, notification],
+	)?;
+	for (position, step) in steps.iter().enumerate() {
+		conn.execute(
+			"INSERT INTO escalation_steps
+				(user_id, notification, position, after_minutes, channel, contact)
+				VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+			params![
+				user_id,
+				notification,
+				position,
+				step.after_minutes,
+				User::notifier_to_text(&step.channel),
+				step.contact
+			],
+		)?;
+	}
+	Ok(())
+}
+
+/// How long to wait before another confirmation is requested from an unconfirmed contact.
+pub const CONFIRMATION_INTERVAL: u64 = 60 * 60;
+
+/// An address to which a user escalates alerts, which doesn't belong to the user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Contact {
+	/// The user whose alerts are escalated to the contact.
+	pub user_id: u32,
+	pub channel: Notifier,
+	pub address: String,
+	/// Unix timestamp at which the contact confirmed receiving the alerts of the user.
+	pub confirmed_at: Option<Timestamp>,
+}
+
+impl Contact {
+	/// Requests a confirmation from the contact, which is given with the token of the hash.
+	///
+	/// Returns whether the confirmation has to be sent. It doesn't if the contact already
+	/// confirmed, or was asked to within the last [`CONFIRMATION_INTERVAL`], in which case the
+	/// token of the previous request stays valid.
+	pub fn request(
+		conn: &Connection,
+		user_id: u32,
+		channel: &Notifier,
+		address: &str,
+		token_hash: &str,
+		now: Timestamp,
+	) -> Result<bool> {
+		let requested = conn.execute(
+			"INSERT INTO escalation_contacts (user_id, channel, address, token_hash, requested_at)
+				VALUES (?1, ?2, ?3, ?4, ?5)
+				ON CONFLICT (user_id, channel, address) DO UPDATE SET
+					token_hash = excluded.token_hash,
+					requested_at = excluded.requested_at
+				WHERE confirmed_at IS NULL AND requested_at + ?6 <= excluded.requested_at",
+			params![
+				user_id,
+				User::notifier_to_text(channel),
+				address,
+				token_hash,
+				now,
+				CONFIRMATION_INTERVAL
+			],
+		)?;
+		Ok(requested > 0)
+	}
+
+	/// Confirms the contact which was sent the token of the hash.
+	///
+	/// Confirming again keeps the time of the first confirmation. Returns `None` if no contact was
+	/// sent the token.
+	pub fn confirm(conn: &Connection, token_hash: &str, now: Timestamp) -> Result<Option<Contact>> {
+		conn.execute(
+			"UPDATE escalation_contacts SET confirmed_at = COALESCE(confirmed_at, ?2)
+				WHERE token_hash = ?1",
+			params![token_hash, now],
+		)?;
+		Self::query_by_token(conn, token_hash)
+	}
+
+	/// Returns the contact which was sent the token of the hash.
+	pub fn query_by_token(conn: &Connection, token_hash: &str) -> Result<Option<Contact>> {
+		conn.query_row(
+			"SELECT * FROM escalation_contacts WHERE token_hash = ?1",
+			params![token_hash],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns whether the contact confirmed receiving the alerts of the user.
+	pub fn is_confirmed(
+		conn: &Connection,
+		user_id: u32,
+		channel: &Notifier,
+		address: &str,
+	) -> Result<bool> {
+		let confirmed_at: Option<Option<Timestamp>> = conn
+			.query_row(
+				"SELECT confirmed_at FROM escalation_contacts
+					WHERE user_id = ?1 AND channel = ?2 AND address = ?3",
+				params![user_id, User::notifier_to_text(channel), address],
+				|row| row.get(0),
+			)
+			.optional()?;
+		Ok(confirmed_at.flatten().is_some())
+	}
+
+	fn from_row(row: &Row) -> Result<Contact> {
+		Ok(Contact {
+			user_id: row.get("user_id")?,
+			channel: User::text_to_notifier(row.get("channel")?),
+			address: row.get("address")?,
+			confirmed_at: row.get("confirmed_at")?,
+		})
+	}
+}
+
+/// A critical notification which is escalated until it is acknowledged.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Alert {
+	pub id: i64,
+	/// Identifies the alert, see [`Alert::raise`].
+	pub key: String,
+	/// The user whose subscription raised the alert.
+	pub user_id: u32,
+	/// Unix timestamp at which the alert was raised.
+	pub raised_at: Timestamp,
+	/// Unix timestamp at which the alert was acknowledged.
+	pub acknowledged_at: Option<Timestamp>,
+}
+
+impl Alert {
+	/// Raises an alert, unless an alert with the same `key` was raised before.
+	///
+	/// Returns the alert with the key in either case.
+	pub fn raise(conn: &Connection, key: &str, user_id: u32, now: Timestamp) -> Result<Alert> {
+		conn.execute(
+			"INSERT INTO alerts (key, user_id, raised_at) VALUES (?1, ?2, ?3)
+				ON CONFLICT (key) DO NOTHING",
+			params![key, user_id, now],
+		)?;
+		conn.query_row("SELECT * FROM alerts WHERE key = ?1", params![key], Self::from_row)
+	}
+
+	pub fn query_by_id(conn: &Connection, id: i64) -> Result<Option<Alert>> {
+		Self::query_one(conn, "SELECT * FROM alerts WHERE id = ?1", id)
+	}
+
+	/// Returns the alert which can be acknowledged with the token of the hash.
+	pub fn query_by_token(conn: &Connection, token_hash: &str) -> Result<Option<Alert>> {
+		Self::query_one(
+			conn,
+			"SELECT alerts.* FROM alerts
+				JOIN alert_tokens ON alert_tokens.alert_id = alerts.id
+				WHERE alert_tokens.token_hash = ?1",
+			token_hash,
+		)
+	}
+
+	/// Stores the hash of a token with which the alert can be acknowledged.
+	pub fn add_token(conn: &Connection, id: i64, token_hash: &str) -> Result<()> {
+		conn.execute(
+			"INSERT INTO alert_tokens (token_hash, alert_id) VALUES (?1, ?2)",
+			params![token_hash, id],
+		)?;
+		Ok(())
+	}
+
+	/// Acknowledges the alert and cancels its pending deliveries.
+	///
+	/// Acknowledging an alert again keeps the time of the first acknowledgement. Returns `None`
+	/// if there is no alert with the token of the hash.
+	pub fn acknowledge(
+		conn: &Connection,
+		token_hash: &str,
+		now: Timestamp,
+	) -> Result<Option<Alert>> {
+		let Some(alert) = Self::query_by_token(conn, token_hash)? else {
+			return Ok(None);
+		};
+
+		conn.execute(
+			"UPDATE alerts SET acknowledged_at = COALESCE(acknowledged_at, ?2) WHERE id = ?1",
+			params![alert.id, now],
+		)?;
+		conn.execute(
+			"DELETE FROM outbox WHERE alert_id = ?1 AND status = 'pending'",
+			params![alert.id],
+		)?;
+
+		Self::query_by_id(conn, alert.id)
+	}
+
+	fn query_one(
+		conn: &Connection,
+		sql: &str,
+		param: impl rusqlite::ToSql,
+	) -> Result<Option<Alert>> {
+		let mut stmt = conn.prepare(sql)?;
+		let mut alerts_iter = stmt.query_map(params![param], Self::from_row)?;
+
+		match alerts_iter.next() {
+			Some(Ok(alert)) => Ok(Some(alert)),
+			Some(Err(err)) => Err(err),
+			None => Ok(None),
+		}
+	}
+
+	fn from_row(row: &Row) -> Result<Alert> {
+		Ok(Alert {
+			id: row.get("id")?,
+			key: row.get("key")?,
+			user_id: row.get("user_id")?,
+			raised_at: row.get("raised_at")?,
+			acknowledged_at: row.get("acknowledged_at")?,
+		})
+	}
+}
+
+fn step_from_row(row: &Row) -> Result<EscalationStep> {
+	Ok(EscalationStep {
+		after_minutes: row.get("after_minutes")?,
+		channel: User::text_to_notifier(row.get("channel")?),
+		contact: row.get("contact")?,
+	})
+}
+
+fn notification_to_text(notification: &Notifications) -> Result<String> {
+	serde_json::to_string(notification)
+		.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))
+}
@@ -9,15 +9,56 @@ from the `Notifications` enum. (There cannot be duplicates)
 
 */
 use rusqlite::{Connection, Result};
-use std::sync::Mutex;
+use std::{sync::Mutex, time::Duration};
 
+pub mod audit;
+pub mod auth;
+pub mod channels;
+pub mod escalations;
+pub mod migrations;
+pub mod organizations;
+pub mod outbox;
+pub mod sales;
+pub mod subscriptions;
+pub mod telegram;
+pub mod tokens;
 pub mod users;
+pub mod webhooks;
+
+#[cfg(test)]
+mod tests;
 
 pub type DbConn = Mutex<Connection>;
 
 pub fn init_db(db_path: &'static str) -> Result<DbConn> {
 	// Create the db if it does not exist.
-	let conn = Connection::open(db_path)?;
+	let mut conn = Connection::open(db_path)?;
+	// The db is shared between the api, the tracker and the notification workers.
+	conn.busy_timeout(Duration::from_secs(5))?;
+	create_tables(&conn)?;
+	migrations::migrate(&mut conn)?;
+
+	Ok(Mutex::new(conn))
+}
+
+/// Runs the writes within a transaction, or within the transaction of the caller if it opened
+/// one, since SQLite doesn't nest transactions.
+pub(crate) fn in_transaction<T>(
+	conn: &Connection,
+	writes: impl FnOnce(&Connection) -> Result<T>,
+) -> Result<T> {
+	if !conn.is_autocommit() {
+		return writes(conn);
+	}
+
+	let tx = conn.unchecked_transaction()?;
+	let result = writes(&tx)?;
+	tx.commit()?;
+	Ok(result)
+}
+
+/// Creates the tables as they were before the first migration, see [`migrations`].
+pub(crate) fn create_tables(conn: &Connection) -> Result<()> {
 	conn.execute(
 		"CREATE TABLE IF NOT EXISTS users (
                id INTEGER PRIMARY KEY NOT NULL,
@@ -30,6 +71,30 @@ pub fn init_db(db_path: &'static str) -> Result<DbConn> {
            )",
 		(),
 	)?;
+	conn.execute(
+		"CREATE TABLE IF NOT EXISTS subscriptions (
+               user_id INTEGER NOT NULL REFERENCES users(id),
+               notification TEXT NOT NULL,
+               UNIQUE (user_id, notification)
+           )",
+		(),
+	)?;
+	conn.execute(
+		"CREATE TABLE IF NOT EXISTS outbox (
+               id INTEGER PRIMARY KEY AUTOINCREMENT,
+               user_id INTEGER NOT NULL REFERENCES users(id),
+               channel TEXT NOT NULL,
+               payload TEXT NOT NULL,
+               status TEXT NOT NULL CHECK (
+                   status IN ('pending', 'in_flight', 'sent')
+               ),
+               attempts INTEGER NOT NULL DEFAULT 0,
+               next_attempt_at INTEGER NOT NULL,
+               last_error TEXT
+           )",
+		(),
+	)?;
+	conn.execute("CREATE INDEX IF NOT EXISTS outbox_due ON outbox (status, next_attempt_at)", ())?;
 
-	Ok(Mutex::new(conn))
+	Ok(())
 }
@@ -0,0 +1,300 @@
+//! Schema changes which are applied on top of the tables created by `init_db`.
+//!
+//! The number of applied migrations is tracked through the `user_version` pragma, so every
+//! migration runs exactly once per db. Migrations must never be modified or reordered once they
+//! are released; new ones are appended to the end of the list.
+//!
+//! Columns are added with `ALTER TABLE`. Tables are only rebuilt for changes which can't be made
+//! otherwise, e.g. altering a check. Foreign keys aren't enforced while a table is rebuilt, instead
+//! they are checked once the migration is done, as described in
+//! <https://www.sqlite.org/lang_altertable.html#otheralter>.
+
+use rusqlite::{Connection, Error, Result};
+
+const MIGRATIONS: &[&str] = &[
+	// 1: Jobs which ran out of retries are dead-lettered. The table is rebuilt, since the check of
+	// the status can't be altered otherwise.
+	"CREATE TABLE outbox_new (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		channel TEXT NOT NULL,
+		payload TEXT NOT NULL,
+		status TEXT NOT NULL CHECK (status IN ('pending', 'in_flight', 'sent', 'dead')),
+		attempts INTEGER NOT NULL DEFAULT 0,
+		next_attempt_at INTEGER NOT NULL,
+		last_error TEXT
+	);
+	INSERT INTO outbox_new
+		(id, user_id, channel, payload, status, attempts, next_attempt_at, last_error)
+		SELECT id, user_id, channel, payload, status, attempts, next_attempt_at, last_error
+		FROM outbox;
+	DROP TABLE outbox;
+	ALTER TABLE outbox_new RENAME TO outbox;
+	CREATE INDEX outbox_due ON outbox (status, next_attempt_at);",
+	// 2: Deliveries are identified by a key, so that an event triggered multiple times is only
+	// delivered once. Jobs enqueued before get a key of their own.
+	"ALTER TABLE outbox ADD COLUMN dedup_key TEXT;
+	UPDATE outbox SET dedup_key = 'legacy:' || id;
+	CREATE UNIQUE INDEX outbox_dedup_key ON outbox (dedup_key);",
+	// 3: Language in which the user receives notifications.
+	"ALTER TABLE users ADD COLUMN language TEXT NOT NULL DEFAULT 'en';",
+	// 4: Time zone and quiet hours of the user.
+	"ALTER TABLE users ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
+	ALTER TABLE users ADD COLUMN quiet_hours_start INTEGER;
+	ALTER TABLE users ADD COLUMN quiet_hours_end INTEGER;",
+	// 5: Digest delivery.
+	"ALTER TABLE users ADD COLUMN delivery_mode TEXT NOT NULL DEFAULT 'immediate';
+	ALTER TABLE outbox ADD COLUMN digest INTEGER NOT NULL DEFAULT 0;",
+	// 6: Discord webhook of the user.
+	"ALTER TABLE users ADD COLUMN discord_webhook TEXT;",
+	// 7: Slack webhook of the user.
+	"ALTER TABLE users ADD COLUMN slack_webhook TEXT;",
+	// 8: Matrix room of the user.
+	"ALTER TABLE users ADD COLUMN matrix_room TEXT;",
+	// 9: Webhook of the user and the secrets its deliveries are signed with.
+	"ALTER TABLE users ADD COLUMN webhook_url TEXT;
+	CREATE TABLE webhook_secrets (
+		user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id),
+		secret TEXT NOT NULL,
+		previous_secret TEXT,
+		rotated_at INTEGER NOT NULL
+	);",
+	// 10: A user can have multiple channels, to which each subscription is routed. Existing
+	// subscriptions keep being delivered through the notifier of the user. The addresses are moved
+	// out of the users table, which is rebuilt since its unique columns can't be dropped
+	// otherwise.
+	"CREATE TABLE channels (
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		kind TEXT NOT NULL,
+		address TEXT NOT NULL,
+		UNIQUE (user_id, kind)
+	);
+	CREATE UNIQUE INDEX channels_address ON channels (kind, address)
+		WHERE kind IN ('email', 'telegram');
+	INSERT INTO channels (user_id, kind, address)
+		SELECT id, 'email', email FROM users WHERE email IS NOT NULL;
+	INSERT INTO channels (user_id, kind, address)
+		SELECT id, 'telegram', tg_handle FROM users WHERE tg_handle IS NOT NULL;
+	INSERT INTO channels (user_id, kind, address)
+		SELECT id, 'discord', discord_webhook FROM users WHERE discord_webhook IS NOT NULL;
+	INSERT INTO channels (user_id, kind, address)
+		SELECT id, 'slack', slack_webhook FROM users WHERE slack_webhook IS NOT NULL;
+	INSERT INTO channels (user_id, kind, address)
+		SELECT id, 'matrix', matrix_room FROM users WHERE matrix_room IS NOT NULL;
+	INSERT INTO channels (user_id, kind, address)
+		SELECT id, 'webhook', webhook_url FROM users WHERE webhook_url IS NOT NULL;
+	CREATE TABLE subscription_channels (
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		notification TEXT NOT NULL,
+		channel TEXT NOT NULL,
+		UNIQUE (user_id, notification, channel)
+	);
+	INSERT INTO subscription_channels (user_id, notification, channel)
+		SELECT s.user_id, s.notification, u.notifier
+		FROM subscriptions s JOIN channels c ON c.user_id = s.user_id
+		JOIN users u ON u.id = s.user_id AND u.notifier = c.kind;
+	CREATE TABLE users_new (
+		id INTEGER PRIMARY KEY NOT NULL,
+		language TEXT NOT NULL DEFAULT 'en',
+		timezone TEXT NOT NULL DEFAULT 'UTC',
+		quiet_hours_start INTEGER,
+		quiet_hours_end INTEGER,
+		delivery_mode TEXT NOT NULL DEFAULT 'immediate'
+	);
+	INSERT INTO users_new
+		(id, language, timezone, quiet_hours_start, quiet_hours_end, delivery_mode)
+		SELECT id, language, timezone, quiet_hours_start, quiet_hours_end, delivery_mode
+		FROM users;
+	DROP TABLE users;
+	ALTER TABLE users_new RENAME TO users;",
+	// 11: Escalation of unacknowledged alerts. Deliveries of an alert can go to the address of a
+	// contact instead of the user's own address, once the contact confirmed that it wants to
+	// receive them. Each delivery links to its own acknowledgement token.
+	"CREATE TABLE escalation_steps (
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		notification TEXT NOT NULL,
+		position INTEGER NOT NULL,
+		after_minutes INTEGER NOT NULL,
+		channel TEXT NOT NULL,
+		contact TEXT,
+		UNIQUE (user_id, notification, position)
+	);
+	CREATE TABLE escalation_contacts (
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		channel TEXT NOT NULL,
+		address TEXT NOT NULL,
+		token_hash TEXT NOT NULL UNIQUE,
+		requested_at INTEGER NOT NULL,
+		confirmed_at INTEGER,
+		PRIMARY KEY (user_id, channel, address)
+	);
+	CREATE TABLE alerts (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		key TEXT NOT NULL UNIQUE,
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		raised_at INTEGER NOT NULL,
+		acknowledged_at INTEGER
+	);
+	CREATE TABLE alert_tokens (
+		token_hash TEXT PRIMARY KEY NOT NULL,
+		alert_id INTEGER NOT NULL REFERENCES alerts(id)
+	);
+	ALTER TABLE outbox ADD COLUMN alert_id INTEGER REFERENCES alerts(id);
+	ALTER TABLE outbox ADD COLUMN address TEXT;",
+	// 12: Telegram bot: linked chats, muting and the status of the sales.
+	"CREATE TABLE telegram_chats (
+		user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id),
+		chat_id INTEGER NOT NULL UNIQUE,
+		handle TEXT NOT NULL
+	);
+	ALTER TABLE users ADD COLUMN muted_until INTEGER;
+	CREATE TABLE sale_status (
+		network TEXT PRIMARY KEY NOT NULL,
+		interlude_start INTEGER NOT NULL,
+		leadin_start INTEGER NOT NULL,
+		fixed_start INTEGER NOT NULL,
+		last_price TEXT,
+		cores_left INTEGER NOT NULL,
+		updated_at INTEGER NOT NULL
+	);",
+	// 13: Verification of email addresses. Addresses registered before are sent a confirmation
+	// link once, see `channels::take_verification_backlog`.
+	"ALTER TABLE channels ADD COLUMN verified_at INTEGER;
+	CREATE TABLE verification_backlog (
+		user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id)
+	);
+	INSERT INTO verification_backlog (user_id) SELECT user_id FROM channels WHERE kind = 'email';",
+	// 14: Sign-in with Substrate accounts, to which users are bound.
+	"ALTER TABLE users ADD COLUMN account TEXT;
+	CREATE UNIQUE INDEX users_account ON users (account);
+	CREATE TABLE auth_challenges (
+		nonce TEXT PRIMARY KEY NOT NULL,
+		account TEXT NOT NULL,
+		message TEXT NOT NULL,
+		expires_at INTEGER NOT NULL
+	);
+	CREATE TABLE sessions (
+		token_hash TEXT PRIMARY KEY NOT NULL,
+		account TEXT NOT NULL,
+		expires_at INTEGER NOT NULL
+	);",
+	// 15: Sign-in through links sent to email addresses.
+	"CREATE TABLE login_links (
+		token_hash TEXT PRIMARY KEY NOT NULL,
+		email TEXT NOT NULL,
+		created_at INTEGER NOT NULL,
+		expires_at INTEGER NOT NULL,
+		used_at INTEGER
+	);
+	CREATE INDEX login_links_email ON login_links (email);",
+	// 16: Opaque ids through which clients identify users.
+	"ALTER TABLE users ADD COLUMN public_id TEXT;
+	UPDATE users SET public_id = lower(hex(randomblob(16)));
+	CREATE UNIQUE INDEX users_public_id ON users (public_id);",
+	// 17: API keys through which scripts act on behalf of users.
+	"CREATE TABLE api_keys (
+		id TEXT PRIMARY KEY NOT NULL,
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		name TEXT NOT NULL,
+		key_hash TEXT NOT NULL UNIQUE,
+		scope TEXT NOT NULL,
+		created_at INTEGER NOT NULL,
+		expires_at INTEGER,
+		last_used_at INTEGER
+	);
+	CREATE INDEX api_keys_user ON api_keys (user_id);",
+	// 18: Organizations whose members share subscriptions.
+	"CREATE TABLE organizations (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		public_id TEXT NOT NULL UNIQUE,
+		name TEXT NOT NULL,
+		created_at INTEGER NOT NULL
+	);
+	CREATE TABLE organization_members (
+		organization_id INTEGER NOT NULL REFERENCES organizations(id),
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		role TEXT NOT NULL,
+		joined_at INTEGER NOT NULL,
+		PRIMARY KEY (organization_id, user_id)
+	);
+	CREATE INDEX organization_members_user ON organization_members (user_id);
+	CREATE TABLE organization_member_channels (
+		organization_id INTEGER NOT NULL REFERENCES organizations(id),
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		channel TEXT NOT NULL,
+		PRIMARY KEY (organization_id, user_id, channel)
+	);
+	CREATE TABLE organization_invites (
+		token_hash TEXT PRIMARY KEY NOT NULL,
+		organization_id INTEGER NOT NULL REFERENCES organizations(id),
+		role TEXT NOT NULL,
+		expires_at INTEGER NOT NULL
+	);
+	CREATE TABLE organization_subscriptions (
+		organization_id INTEGER NOT NULL REFERENCES organizations(id),
+		notification TEXT NOT NULL,
+		PRIMARY KEY (organization_id, notification)
+	);",
+	// 19: Changes made on behalf of users without a session.
+	"CREATE TABLE audit_log (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		user_id INTEGER NOT NULL REFERENCES users(id),
+		action TEXT NOT NULL,
+		details TEXT NOT NULL,
+		created_at INTEGER NOT NULL
+	);
+	CREATE INDEX audit_log_user ON audit_log (user_id);",
+	// 20: The subscription of the user a delivery was enqueued for, so that unsubscribing drops
+	// the deliveries which weren't made yet.
+	"ALTER TABLE outbox ADD COLUMN subscription TEXT;",
+	// 21: Email addresses are stored in lower case, so that an address can't be registered twice
+	// by typing it differently. Addresses which would then collide with another one are kept.
+	"UPDATE OR IGNORE channels SET address = lower(trim(address)) WHERE kind = 'email';
+	UPDATE OR IGNORE users SET account = 'email:' || lower(trim(substr(account, 7)))
+		WHERE account LIKE 'email:%';
+	UPDATE login_links SET email = lower(trim(email));",
+	// 22: Telegram handles are stored with the leading `@`, so that a handle can't be registered
+	// twice. Accounts without a username are identified by their numeric id, which stays as is.
+	// Handles which would then collide with another one are kept.
+	"UPDATE OR IGNORE channels SET address = '@' || address
+		WHERE kind = 'telegram' AND address NOT LIKE '@%' AND address GLOB '*[^0-9]*';
+	UPDATE telegram_chats SET handle = '@' || handle
+		WHERE handle NOT LIKE '@%' AND handle GLOB '*[^0-9]*';",
+];
+
+/// Applies all migrations which weren't applied to the db yet.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+	let version: usize = conn.query_row("PRAGMA user_version", (), |row| row.get(0))?;
+	if version >= MIGRATIONS.len() {
+		return Ok(());
+	}
+
+	// Dropping a table deletes its rows, which would violate the references to a rebuilt table.
+	let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", (), |row| row.get(0))?;
+	conn.pragma_update(None, "foreign_keys", false)?;
+	let result = apply(conn, version);
+	conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+
+	result
+}
+
+fn apply(conn: &mut Connection, version: usize) -> Result<()> {
+	for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+		let tx = conn.transaction()?;
+		tx.execute_batch(migration)?;
+
+		let violations: usize =
+			tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", (), |row| row.get(0))?;
+		if violations > 0 {
+			return Err(Error::SqliteFailure(
+				rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+				Some(format!("Migration {} violates {} foreign keys", index + 1, violations)),
+			));
+		}
+
+		tx.pragma_update(None, "user_version", index + 1)?;
+		tx.commit()?;
+	}
+
+	Ok(())
+}
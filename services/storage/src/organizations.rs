@@ -0,0 +1,339 @@
+//! Organizations whose members share subscriptions, e.g. the team operating a parachain.
+//!
+//! Every [`Member`] of an organization has a role. The notifications the organization subscribed
+//! to, see [`OrganizationSubscription`], are delivered to every member through the channels the
+//! member prefers for the organization, or through all channels of the member if the member
+//! doesn't prefer any.
+//!
+//! Members join through an [`Invite`], which can only be used once. Invites are looked up by the
+//! hash of their token, like sessions.
+
+use crate::users::User;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use types::{api::OrganizationRole, Notifications, Notifier, Timestamp};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Organization {
+	/// The internal id of the organization.
+	pub id: u32,
+	/// The opaque id through which clients identify the organization.
+	pub public_id: String,
+	pub name: String,
+	pub created_at: Timestamp,
+}
+
+impl Organization {
+	/// Creates an organization, of which the user becomes the owner.
+	pub fn create(
+		conn: &Connection,
+		public_id: &str,
+		name: &str,
+		owner_id: u32,
+		now: Timestamp,
+	) -> Result<Organization> {
+		let tx = conn.unchecked_transaction()?;
+		tx.execute(
+			"INSERT INTO organizations (public_id, name, created_at) VALUES (?1, ?2, ?3)",
+			params![public_id, name, now],
+		)?;
+		let organization = Organization {
+			id: tx.last_insert_rowid() as u32,
+			public_id: public_id.to_string(),
+			name: name.to_string(),
+			created_at: now,
+		};
+		Member::add(&tx, organization.id, owner_id, OrganizationRole::Owner, now)?;
+		tx.commit()?;
+
+		Ok(organization)
+	}
+
+	pub fn query_by_public_id(conn: &Connection, public_id: &str) -> Result<Option<Organization>> {
+		conn.query_row(
+			"SELECT * FROM organizations WHERE public_id = ?1",
+			params![public_id],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns the organizations of which the user is a member, along with the role of the user.
+	pub fn query_by_member(
+		conn: &Connection,
+		user_id: u32,
+	) -> Result<Vec<(Organization, OrganizationRole)>> {
+		let mut stmt = conn.prepare(
+			"SELECT o.*, m.role FROM organizations o
+				JOIN organization_members m ON m.organization_id = o.id
+				WHERE m.user_id = ?1 ORDER BY o.id",
+		)?;
+		let organizations = stmt.query_map(params![user_id], |row| {
+			Ok((Self::from_row(row)?, text_to_role(row.get("role")?)))
+		})?;
+
+		organizations.collect()
+	}
+
+	fn from_row(row: &Row) -> Result<Organization> {
+		Ok(Organization {
+			id: row.get("id")?,
+			public_id: row.get("public_id")?,
+			name: row.get("name")?,
+			created_at: row.get("created_at")?,
+		})
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Member {
+	pub organization_id: u32,
+	pub user_id: u32,
+	pub role: OrganizationRole,
+	/// The channels through which the member receives the notifications of the organization. All
+	/// channels of the member if empty.
+	pub channels: Vec<Notifier>,
+}
+
+/// Selects the members along with their preferred channels, to be grouped by member.
+const SELECT_MEMBERS: &str = "SELECT m.organization_id, m.user_id, m.role,
+		GROUP_CONCAT(c.channel) AS channels
+	FROM organization_members m
+	LEFT JOIN organization_member_channels c
+		ON c.organization_id = m.organization_id AND c.user_id = m.user_id";
+
+impl Member {
+	/// Adds the user to the organization, or changes the role of a member.
+	pub fn add(
+		conn: &Connection,
+		organization_id: u32,
+		user_id: u32,
+		role: OrganizationRole,
+		now: Timestamp,
+	) -> Result<()> {
+		conn.execute(
+			"INSERT INTO organization_members (organization_id, user_id, role, joined_at)
+				VALUES (?1, ?2, ?3, ?4)
+				ON CONFLICT (organization_id, user_id) DO UPDATE SET role = excluded.role",
+			params![organization_id, user_id, role_to_text(&role), now],
+		)?;
+		Ok(())
+	}
+
+	pub fn query(conn: &Connection, organization_id: u32, user_id: u32) -> Result<Option<Member>> {
+		conn.query_row(
+			&format!(
+				"{} WHERE m.organization_id = ?1 AND m.user_id = ?2 GROUP BY m.user_id",
+				SELECT_MEMBERS
+			),
+			params![organization_id, user_id],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns the members of the organization, in the order they joined.
+	pub fn query_by_organization(conn: &Connection, organization_id: u32) -> Result<Vec<Member>> {
+		let mut stmt = conn.prepare(&format!(
+			"{} WHERE m.organization_id = ?1 GROUP BY m.user_id ORDER BY m.joined_at, m.user_id",
+			SELECT_MEMBERS
+		))?;
+		let members = stmt.query_map(params![organization_id], Self::from_row)?;
+
+		members.collect()
+	}
+
+	/// Returns how many owners the organization has.
+	pub fn count_owners(conn: &Connection, organization_id: u32) -> Result<u32> {
+		conn.query_row(
+			"SELECT COUNT(*) FROM organization_members WHERE organization_id = ?1 AND role = ?2",
+			params![organization_id, role_to_text(&OrganizationRole::Owner)],
+			|row| row.get(0),
+		)
+	}
+
+	/// Removes the user from the organization. Returns whether the user was a member.
+	pub fn remove(conn: &Connection, organization_id: u32, user_id: u32) -> Result<bool> {
+		conn.execute(
+			"DELETE FROM organization_member_channels WHERE organization_id = ?1 AND user_id = ?2",
+			params![organization_id, user_id],
+		)?;
+		let removed = conn.execute(
+			"DELETE FROM organization_members WHERE organization_id = ?1 AND user_id = ?2",
+			params![organization_id, user_id],
+		)?;
+		Ok(removed > 0)
+	}
+
+	/// Replaces the channels through which the member receives the notifications of the
+	/// organization.
+	pub fn set_channels(
+		conn: &Connection,
+		organization_id: u32,
+		user_id: u32,
+		channels: &[Notifier],
+	) -> Result<()> {
+		conn.execute(
+			"DELETE FROM organization_member_channels WHERE organization_id = ?1 AND user_id = ?2",
+			params![organization_id, user_id],
+		)?;
+		for channel in channels {
+			conn.execute(
+				"INSERT OR IGNORE INTO organization_member_channels
+					(organization_id, user_id, channel) VALUES (?1, ?2, ?3)",
+				params![organization_id, user_id, User::notifier_to_text(channel)],
+			)?;
+		}
+		Ok(())
+	}
+
+	fn from_row(row: &Row) -> Result<Member> {
+		let channels = row
+			.get::<_, Option<String>>("channels")?
+			.map(|channels| {
+				channels
+					.split(',')
+					.map(|channel| User::text_to_notifier(channel.into()))
+					.collect()
+			})
+			.unwrap_or_default();
+
+		Ok(Member {
+			organization_id: row.get("organization_id")?,
+			user_id: row.get("user_id")?,
+			role: text_to_role(row.get("role")?),
+			channels,
+		})
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Invite {
+	/// The hash of the token with which the invite is accepted.
+	pub token_hash: String,
+	pub organization_id: u32,
+	/// The role with which the invited user joins.
+	pub role: OrganizationRole,
+	/// Unix timestamp after which the invite can't be accepted anymore.
+	pub expires_at: Timestamp,
+}
+
+impl Invite {
+	pub fn create(conn: &Connection, invite: &Invite) -> Result<()> {
+		conn.execute(
+			"INSERT INTO organization_invites (token_hash, organization_id, role, expires_at)
+				VALUES (?1, ?2, ?3, ?4)",
+			params![
+				invite.token_hash,
+				invite.organization_id,
+				role_to_text(&invite.role),
+				invite.expires_at
+			],
+		)?;
+		Ok(())
+	}
+
+	/// Removes the invite with the token hash and returns it, unless it expired.
+	///
+	/// Also removes all expired invites.
+	pub fn take(conn: &Connection, token_hash: &str, now: Timestamp) -> Result<Option<Invite>> {
+		conn.execute("DELETE FROM organization_invites WHERE expires_at < ?1", params![now])?;
+		let invite = conn
+			.query_row(
+				"SELECT * FROM organization_invites WHERE token_hash = ?1",
+				params![token_hash],
+				Self::from_row,
+			)
+			.optional()?;
+		conn.execute(
+			"DELETE FROM organization_invites WHERE token_hash = ?1",
+			params![token_hash],
+		)?;
+
+		Ok(invite)
+	}
+
+	fn from_row(row: &Row) -> Result<Invite> {
+		Ok(Invite {
+			token_hash: row.get("token_hash")?,
+			organization_id: row.get("organization_id")?,
+			role: text_to_role(row.get("role")?),
+			expires_at: row.get("expires_at")?,
+		})
+	}
+}
+
+/// A notification an organization subscribed to on behalf of its members.
+#[derive(Debug, Eq, PartialEq)]
+pub struct OrganizationSubscription {
+	pub organization_id: u32,
+	pub notification: Notifications,
+}
+
+impl OrganizationSubscription {
+	pub fn query_all(conn: &Connection) -> Result<Vec<OrganizationSubscription>> {
+		let mut stmt = conn.prepare("SELECT * FROM organization_subscriptions")?;
+		let subscriptions = stmt.query_map((), Self::from_row)?;
+
+		subscriptions.collect()
+	}
+
+	pub fn query_by_organization(
+		conn: &Connection,
+		organization_id: u32,
+	) -> Result<Vec<Notifications>> {
+		let mut stmt =
+			conn.prepare("SELECT * FROM organization_subscriptions WHERE organization_id = ?1")?;
+		let subscriptions = stmt.query_map(params![organization_id], Self::from_row)?;
+
+		subscriptions.map(|subscription| subscription.map(|s| s.notification)).collect()
+	}
+
+	/// Replaces the notifications the organization subscribed to.
+	pub fn set(
+		conn: &Connection,
+		organization_id: u32,
+		notifications: &[Notifications],
+	) -> Result<()> {
+		let tx = conn.unchecked_transaction()?;
+		tx.execute(
+			"DELETE FROM organization_subscriptions WHERE organization_id = ?1",
+			params![organization_id],
+		)?;
+		for notification in notifications {
+			let notification = serde_json::to_string(notification)
+				.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+			tx.execute(
+				"INSERT OR IGNORE INTO organization_subscriptions (organization_id, notification)
+					VALUES (?1, ?2)",
+				params![organization_id, notification],
+			)?;
+		}
+		tx.commit()
+	}
+
+	fn from_row(row: &Row) -> Result<OrganizationSubscription> {
+		let notification: String = row.get("notification")?;
+		let notification = serde_json::from_str(&notification).map_err(|err| {
+			rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(err))
+		})?;
+
+		Ok(OrganizationSubscription { organization_id: row.get("organization_id")?, notification })
+	}
+}
+
+fn role_to_text(role: &OrganizationRole) -> &'static str {
+	match role {
+		OrganizationRole::Owner => "owner",
+		OrganizationRole::Admin => "admin",
+		OrganizationRole::Member => "member",
+	}
+}
+
+fn text_to_role(text: String) -> OrganizationRole {
+	match text.as_str() {
+		"owner" => OrganizationRole::Owner,
+		"admin" => OrganizationRole::Admin,
+		// Unknown roles grant the least access.
+		_ => OrganizationRole::Member,
+	}
+}
@@ -0,0 +1,296 @@
+//! The outbox holds every pending notification delivery.
+//!
+//! Each row represents a single (event, user, channel) delivery. Rows are only removed from the
+//! queue of pending work once the delivery is acknowledged, so that an event doesn't get lost if
+//! a channel is down or the process crashes while delivering.
+//!
+//! Every delivery carries a deterministic deduplication key which is unique across the outbox.
+//! This way an event which is triggered multiple times, e.g. after a restart of the tracker,
+//! doesn't result in duplicate notifications.
+//!
+//! Jobs of users who receive digests are flagged, so that all of a user's digest jobs which are
+//! due at the same time can be delivered as a single message.
+//!
+//! Deliveries of an [`Alert`] are linked to it, so that they can be cancelled once the alert is
+//! acknowledged. Likewise, deliveries are linked to the subscription they were enqueued for, so
+//! that they are dropped once the user unsubscribes, see
+//! [`crate::subscriptions::Subscription::remove`].
+
+use crate::{escalations::Alert, users::User};
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use types::{Notifications, Notifier, Timestamp};
+
+/// The delivery status of an outbox job.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum JobStatus {
+	/// Waiting to be delivered once `next_attempt_at` is reached.
+	Pending,
+	/// Claimed by a worker. If the worker doesn't acknowledge the job before `next_attempt_at`,
+	/// the job can be claimed again.
+	InFlight,
+	/// Successfully delivered.
+	Sent,
+	/// Delivery failed permanently or ran out of retries. Only an admin can requeue the job.
+	Dead,
+}
+
+/// A single notification delivery.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OutboxJob {
+	/// Unique identifier of the job.
+	pub id: i64,
+	/// Identifies the delivery. There can't be two jobs with the same key.
+	pub dedup_key: String,
+	/// The user to notify.
+	pub user_id: u32,
+	/// The channel through which the user should be notified.
+	pub channel: Notifier,
+	/// The serialized event the user is notified about.
+	pub payload: String,
+	/// The delivery status of the job.
+	pub status: JobStatus,
+	/// The number of delivery attempts made so far.
+	pub attempts: u32,
+	/// Unix timestamp at which the job should be (re)attempted.
+	pub next_attempt_at: Timestamp,
+	/// The error of the last failed delivery attempt.
+	pub last_error: Option<String>,
+	/// Whether the job is delivered as part of a digest.
+	pub digest: bool,
+	/// The alert the job is a delivery of.
+	pub alert_id: Option<i64>,
+	/// The address to deliver to instead of the user's own address of the channel, e.g. the
+	/// address of a contact an alert is escalated to.
+	pub address: Option<String>,
+}
+
+impl OutboxJob {
+	pub fn query_by_id(conn: &Connection, id: i64) -> Result<Option<OutboxJob>> {
+		let mut stmt = conn.prepare("SELECT * FROM outbox WHERE id = ?1")?;
+		let mut jobs_iter = stmt.query_map(params![id], Self::from_row)?;
+
+		match jobs_iter.next() {
+			Some(Ok(job)) => Ok(Some(job)),
+			Some(Err(err)) => Err(err),
+			None => Ok(None),
+		}
+	}
+
+	/// Adds a new delivery to the outbox which will be due at `next_attempt_at`.
+	///
+	/// Returns `None` if a delivery with the same `dedup_key` was already enqueued before.
+	pub fn enqueue(
+		conn: &Connection,
+		dedup_key: &str,
+		user_id: u32,
+		channel: &Notifier,
+		payload: &str,
+		next_attempt_at: Timestamp,
+		digest: bool,
+	) -> Result<Option<i64>> {
+		let inserted = conn.execute(
+			"INSERT INTO outbox
+				(dedup_key, user_id, channel, payload, status, attempts, next_attempt_at, digest)
+				VALUES (?1, ?2, ?3, ?4, 'pending', 0, ?5, ?6)
+				ON CONFLICT (dedup_key) DO NOTHING",
+			params![
+				dedup_key,
+				user_id,
+				User::notifier_to_text(channel),
+				payload,
+				next_attempt_at,
+				digest
+			],
+		)?;
+
+		Ok((inserted > 0).then(|| conn.last_insert_rowid()))
+	}
+
+	/// Adds a delivery of the alert to the outbox which will be due at `next_attempt_at`.
+	///
+	/// The delivery goes to `address` if given, and to the user's own address of the channel
+	/// otherwise. Returns `None` if a delivery with the same `dedup_key` was already enqueued
+	/// before.
+	pub fn enqueue_alert(
+		conn: &Connection,
+		dedup_key: &str,
+		alert: &Alert,
+		channel: &Notifier,
+		address: Option<&str>,
+		payload: &str,
+		next_attempt_at: Timestamp,
+	) -> Result<Option<i64>> {
+		let inserted = conn.execute(
+			"INSERT INTO outbox
+				(dedup_key, user_id, channel, payload, status, attempts, next_attempt_at, digest,
+				alert_id, address)
+				VALUES (?1, ?2, ?3, ?4, 'pending', 0, ?5, 0, ?6, ?7)
+				ON CONFLICT (dedup_key) DO NOTHING",
+			params![
+				dedup_key,
+				alert.user_id,
+				User::notifier_to_text(channel),
+				payload,
+				next_attempt_at,
+				alert.id,
+				address
+			],
+		)?;
+
+		Ok((inserted > 0).then(|| conn.last_insert_rowid()))
+	}
+
+	/// Atomically claims up to `limit` jobs which are due at `now`.
+	///
+	/// Claimed jobs are leased for `lease` seconds. Jobs which are not acknowledged within their
+	/// lease, e.g. because the worker crashed, become claimable again.
+	pub fn claim_due(
+		conn: &Connection,
+		now: Timestamp,
+		lease: u64,
+		limit: u32,
+	) -> Result<Vec<OutboxJob>> {
+		let mut stmt = conn.prepare(
+			"UPDATE outbox
+				SET status = 'in_flight', attempts = attempts + 1, next_attempt_at = ?2
+				WHERE id IN (
+					SELECT id FROM outbox
+						WHERE status IN ('pending', 'in_flight') AND next_attempt_at <= ?1
+						ORDER BY next_attempt_at
+						LIMIT ?3
+				)
+				RETURNING *",
+		)?;
+		let jobs_iter = stmt.query_map(params![now, now + lease, limit], Self::from_row)?;
+
+		jobs_iter.collect()
+	}
+
+	/// Atomically claims the digest jobs of the user for the channel which are due at `now`.
+	///
+	/// Used to complete a digest of which only some jobs were claimed through [`Self::claim_due`].
+	pub fn claim_digest(
+		conn: &Connection,
+		user_id: u32,
+		channel: &Notifier,
+		now: Timestamp,
+		lease: u64,
+	) -> Result<Vec<OutboxJob>> {
+		let mut stmt = conn.prepare(
+			"UPDATE outbox
+				SET status = 'in_flight', attempts = attempts + 1, next_attempt_at = ?4
+				WHERE status IN ('pending', 'in_flight') AND next_attempt_at <= ?3 AND digest = 1
+					AND user_id = ?1 AND channel IS ?2
+				RETURNING *",
+		)?;
+		let jobs_iter = stmt.query_map(
+			params![user_id, User::notifier_to_text(channel), now, now + lease],
+			Self::from_row,
+		)?;
+
+		jobs_iter.collect()
+	}
+
+	/// Links the job with the key to the subscription of its user it was enqueued for.
+	///
+	/// `None` unlinks the job, so that it is delivered even if the user unsubscribes, e.g. because
+	/// an organization of the user subscribed to the notification as well.
+	pub fn set_subscription(
+		conn: &Connection,
+		dedup_key: &str,
+		subscription: Option<&Notifications>,
+	) -> Result<()> {
+		let subscription = subscription
+			.map(serde_json::to_string)
+			.transpose()
+			.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+		conn.execute(
+			"UPDATE outbox SET subscription = ?2 WHERE dedup_key = ?1",
+			params![dedup_key, subscription],
+		)?;
+		Ok(())
+	}
+
+	/// Marks the job as delivered.
+	pub fn ack(conn: &Connection, id: i64) -> Result<usize> {
+		conn.execute(
+			"UPDATE outbox SET status = 'sent', last_error = NULL WHERE id = ?1 AND status = 'in_flight'",
+			params![id],
+		)
+	}
+
+	/// Releases the job after a failed delivery so that it is attempted again at
+	/// `next_attempt_at`.
+	pub fn retry(
+		conn: &Connection,
+		id: i64,
+		error: &str,
+		next_attempt_at: Timestamp,
+	) -> Result<usize> {
+		conn.execute(
+			"UPDATE outbox SET status = 'pending', last_error = ?2, next_attempt_at = ?3
+				WHERE id = ?1 AND status = 'in_flight'",
+			params![id, error, next_attempt_at],
+		)
+	}
+
+	/// Moves the job to the dead-letter state so it is no longer attempted.
+	pub fn dead_letter(conn: &Connection, id: i64, error: &str) -> Result<usize> {
+		conn.execute(
+			"UPDATE outbox SET status = 'dead', last_error = ?2 WHERE id = ?1 AND status = 'in_flight'",
+			params![id, error],
+		)
+	}
+
+	/// Returns every delivery to the user, including the ones which were already made.
+	pub fn query_by_user(conn: &Connection, user_id: u32) -> Result<Vec<OutboxJob>> {
+		let mut stmt = conn.prepare("SELECT * FROM outbox WHERE user_id = ?1 ORDER BY id")?;
+		let jobs_iter = stmt.query_map(params![user_id], Self::from_row)?;
+
+		jobs_iter.collect()
+	}
+
+	pub fn query_dead_letters(conn: &Connection) -> Result<Vec<OutboxJob>> {
+		let mut stmt = conn.prepare("SELECT * FROM outbox WHERE status = 'dead' ORDER BY id")?;
+		let jobs_iter = stmt.query_map((), Self::from_row)?;
+
+		jobs_iter.collect()
+	}
+
+	/// Moves a dead job back into the queue with a fresh set of attempts.
+	///
+	/// Returns the number of requeued jobs, which is zero if there is no dead job with the id.
+	pub fn requeue(conn: &Connection, id: i64, now: Timestamp) -> Result<usize> {
+		conn.execute(
+			"UPDATE outbox SET status = 'pending', attempts = 0, next_attempt_at = ?2
+				WHERE id = ?1 AND status = 'dead'",
+			params![id, now],
+		)
+	}
+
+	fn from_row(row: &Row) -> Result<OutboxJob> {
+		let status = match row.get::<_, String>("status")?.as_str() {
+			"pending" => JobStatus::Pending,
+			"in_flight" => JobStatus::InFlight,
+			"dead" => JobStatus::Dead,
+			_ => JobStatus::Sent,
+		};
+
+		Ok(OutboxJob {
+			id: row.get("id")?,
+			dedup_key: row.get("dedup_key")?,
+			user_id: row.get("user_id")?,
+			channel: User::text_to_notifier(row.get("channel")?),
+			payload: row.get("payload")?,
+			status,
+			attempts: row.get("attempts")?,
+			next_attempt_at: row.get("next_attempt_at")?,
+			last_error: row.get("last_error")?,
+			digest: row.get("digest")?,
+			alert_id: row.get("alert_id")?,
+			address: row.get("address")?,
+		})
+	}
+}
@@ -0,0 +1,109 @@
+//! The state of the ongoing coretime sale of every network, as last seen by the tracker.
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use types::{Balance, Timestamp};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SaleStatus {
+	/// The network of the sale, e.g. `kusama`.
+	pub network: String,
+	/// Unix timestamp at which the interlude phase starts.
+	pub interlude_start: Timestamp,
+	/// Unix timestamp at which the leadin phase starts.
+	pub leadin_start: Timestamp,
+	/// Unix timestamp at which the fixed price phase starts.
+	pub fixed_start: Timestamp,
+	/// The price of the last purchase of the sale.
+	pub last_price: Option<Balance>,
+	/// The number of cores which are still for sale.
+	pub cores_left: u16,
+	/// Unix timestamp of the last update.
+	pub updated_at: Timestamp,
+}
+
+impl SaleStatus {
+	pub fn query_all(conn: &Connection) -> Result<Vec<SaleStatus>> {
+		let mut stmt = conn.prepare("SELECT * FROM sale_status ORDER BY network")?;
+		let status_iter = stmt.query_map((), Self::from_row)?;
+
+		status_iter.collect()
+	}
+
+	pub fn query_by_network(conn: &Connection, network: &str) -> Result<Option<SaleStatus>> {
+		conn.query_row(
+			"SELECT * FROM sale_status WHERE network = ?1",
+			params![network],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Records the schedule of a sale. The price of the last purchase is reset once a new sale
+	/// starts.
+	pub fn record_phases(
+		conn: &Connection,
+		network: &str,
+		interlude_start: Timestamp,
+		leadin_start: Timestamp,
+		fixed_start: Timestamp,
+		cores_left: u16,
+		now: Timestamp,
+	) -> Result<()> {
+		conn.execute(
+			"INSERT INTO sale_status
+				(network, interlude_start, leadin_start, fixed_start, cores_left, updated_at)
+				VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+				ON CONFLICT (network) DO UPDATE SET
+					last_price = CASE WHEN leadin_start = excluded.leadin_start
+						THEN last_price ELSE NULL END,
+					interlude_start = excluded.interlude_start,
+					leadin_start = excluded.leadin_start,
+					fixed_start = excluded.fixed_start,
+					cores_left = excluded.cores_left,
+					updated_at = excluded.updated_at",
+			params![network, interlude_start, leadin_start, fixed_start, cores_left, now],
+		)?;
+		Ok(())
+	}
+
+	/// Records a purchase in the ongoing sale of the network.
+	pub fn record_sale(
+		conn: &Connection,
+		network: &str,
+		price: Balance,
+		cores_left: u16,
+		now: Timestamp,
+	) -> Result<usize> {
+		// Balances don't fit into an integer column.
+		conn.execute(
+			"UPDATE sale_status SET last_price = ?2, cores_left = ?3, updated_at = ?4
+				WHERE network = ?1",
+			params![network, price.to_string(), cores_left, now],
+		)
+	}
+
+	fn from_row(row: &Row) -> Result<SaleStatus> {
+		let last_price = row
+			.get::<_, Option<String>>("last_price")?
+			.map(|price| {
+				price.parse().map_err(|err| {
+					rusqlite::Error::FromSqlConversionFailure(
+						0,
+						rusqlite::types::Type::Text,
+						Box::new(err),
+					)
+				})
+			})
+			.transpose()?;
+
+		Ok(SaleStatus {
+			network: row.get("network")?,
+			interlude_start: row.get("interlude_start")?,
+			leadin_start: row.get("leadin_start")?,
+			fixed_start: row.get("fixed_start")?,
+			last_price,
+			cores_left: row.get("cores_left")?,
+			updated_at: row.get("updated_at")?,
+		})
+	}
+}
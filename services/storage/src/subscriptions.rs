@@ -0,0 +1,159 @@
+use crate::users::User;
+use rusqlite::{params, Connection, Result, Row};
+use types::{Notifications, Notifier};
+
+/// A notification a user subscribed to.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Subscription {
+	/// The user who subscribed.
+	pub user_id: u32,
+	/// The notification the user subscribed to.
+	pub notification: Notifications,
+	/// The channels of the user through which the notification is delivered.
+	pub channels: Vec<Notifier>,
+}
+
+/// Selects the subscriptions along with their channels, to be grouped by subscription.
+const SELECT_SUBSCRIPTIONS: &str =
+	"SELECT s.user_id, s.notification, GROUP_CONCAT(c.channel) AS channels
+	FROM subscriptions s
+	LEFT JOIN subscription_channels c
+		ON c.user_id = s.user_id AND c.notification = s.notification";
+
+impl Subscription {
+	pub fn query_all(conn: &Connection) -> Result<Vec<Subscription>> {
+		let mut stmt =
+			conn.prepare(&format!("{} GROUP BY s.user_id, s.notification", SELECT_SUBSCRIPTIONS))?;
+		let subscriptions_iter = stmt.query_map((), Self::from_row)?;
+
+		subscriptions_iter.collect()
+	}
+
+	pub fn query_by_user(conn: &Connection, user_id: u32) -> Result<Vec<Subscription>> {
+		let mut stmt = conn.prepare(&format!(
+			"{} WHERE s.user_id = ?1 GROUP BY s.user_id, s.notification",
+			SELECT_SUBSCRIPTIONS
+		))?;
+		let subscriptions_iter = stmt.query_map(params![user_id], Self::from_row)?;
+
+		subscriptions_iter.collect()
+	}
+
+	/// Subscribes the user to the notification, delivered through the given channels.
+	///
+	/// Subscribing twice adds the channels to the existing subscription.
+	pub fn create(
+		conn: &Connection,
+		user_id: u32,
+		notification: &Notifications,
+		channels: &[Notifier],
+	) -> Result<()> {
+		let notification = serde_json::to_string(notification)
+			.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+		conn.execute(
+			"INSERT OR IGNORE INTO subscriptions (user_id, notification) VALUES (?1, ?2)",
+			params![user_id, notification],
+		)?;
+		for channel in channels {
+			conn.execute(
+				"INSERT OR IGNORE INTO subscription_channels (user_id, notification, channel)
+					VALUES (?1, ?2, ?3)",
+				params![user_id, notification, User::notifier_to_text(channel)],
+			)?;
+		}
+		Ok(())
+	}
+
+	/// Stops delivering the notification through the channel.
+	///
+	/// A subscription without any channels left is removed along with its escalation steps.
+	/// Returns whether the notification was delivered through the channel.
+	pub fn remove_channel(
+		conn: &Connection,
+		user_id: u32,
+		notification: &Notifications,
+		channel: &Notifier,
+	) -> Result<bool> {
+		let notification = serde_json::to_string(notification)
+			.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+		let removed = conn.execute(
+			"DELETE FROM subscription_channels
+				WHERE user_id = ?1 AND notification = ?2 AND channel = ?3",
+			params![user_id, notification, User::notifier_to_text(channel)],
+		)?;
+		let channels_left: u32 = conn.query_row(
+			"SELECT COUNT(*) FROM subscription_channels WHERE user_id = ?1 AND notification = ?2",
+			params![user_id, notification],
+			|row| row.get(0),
+		)?;
+		if channels_left == 0 {
+			conn.execute(
+				"DELETE FROM subscriptions WHERE user_id = ?1 AND notification = ?2",
+				params![user_id, notification],
+			)?;
+			conn.execute(
+				"DELETE FROM escalation_steps WHERE user_id = ?1 AND notification = ?2",
+				params![user_id, notification],
+			)?;
+		}
+
+		Ok(removed > 0)
+	}
+
+	/// Unsubscribes the user from the notification, along with its channels and escalation steps.
+	///
+	/// The deliveries of the notification which weren't made yet are dropped, e.g. the ones held
+	/// back for a digest. Returns whether the user was subscribed to the notification.
+	pub fn remove(conn: &Connection, user_id: u32, notification: &Notifications) -> Result<bool> {
+		let notification = serde_json::to_string(notification)
+			.map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+		let tx = conn.unchecked_transaction()?;
+		let removed = tx.execute(
+			"DELETE FROM subscriptions WHERE user_id = ?1 AND notification = ?2",
+			params![user_id, notification],
+		)?;
+		tx.execute(
+			"DELETE FROM subscription_channels WHERE user_id = ?1 AND notification = ?2",
+			params![user_id, notification],
+		)?;
+		tx.execute(
+			"DELETE FROM escalation_steps WHERE user_id = ?1 AND notification = ?2",
+			params![user_id, notification],
+		)?;
+		tx.execute(
+			"DELETE FROM outbox
+				WHERE user_id = ?1 AND subscription = ?2 AND status = 'pending'",
+			params![user_id, notification],
+		)?;
+		tx.commit()?;
+
+		Ok(removed > 0)
+	}
+
+	fn from_row(row: &Row) -> Result<Subscription> {
+		let channels = row
+			.get::<_, Option<String>>("channels")?
+			.map(|channels| {
+				channels
+					.split(',')
+					.map(|channel| User::text_to_notifier(channel.into()))
+					.collect()
+			})
+			.unwrap_or_default();
+
+		Ok(Subscription {
+			user_id: row.get("user_id")?,
+			notification: Self::text_to_notification(row.get("notification")?)?,
+			channels,
+		})
+	}
+
+	fn text_to_notification(text: String) -> Result<Notifications> {
+		serde_json::from_str(&text).map_err(|err| {
+			rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(err))
+		})
+	}
+}
@@ -0,0 +1,74 @@
+//! Telegram chats linked to users.
+//!
+//! The bot can only message users who started a chat with it, and has to address them by the id
+//! of that chat rather than their handle. A chat is linked to the user with the handle of the
+//! Telegram account the chat was started from, and stays linked as long as the user keeps that
+//! handle.
+
+use rusqlite::{params, Connection, Result, Row};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TelegramChat {
+	pub user_id: u32,
+	/// The id of the chat with the bot.
+	pub chat_id: i64,
+	/// The handle of the user the chat was started from, e.g. `@alice`.
+	pub handle: String,
+}
+
+impl TelegramChat {
+	/// Links the chat to the user, replacing any other chat of the user and any other user of
+	/// the chat.
+	pub fn link(conn: &Connection, user_id: u32, chat_id: i64, handle: &str) -> Result<()> {
+		conn.execute(
+			"DELETE FROM telegram_chats WHERE user_id = ?1 OR chat_id = ?2",
+			params![user_id, chat_id],
+		)?;
+		conn.execute(
+			"INSERT INTO telegram_chats (user_id, chat_id, handle) VALUES (?1, ?2, ?3)",
+			params![user_id, chat_id, handle],
+		)?;
+		Ok(())
+	}
+
+	pub fn query_by_chat(conn: &Connection, chat_id: i64) -> Result<Option<TelegramChat>> {
+		Self::query_one(conn, "SELECT * FROM telegram_chats WHERE chat_id = ?1", chat_id)
+	}
+
+	pub fn query_by_user(conn: &Connection, user_id: u32) -> Result<Option<TelegramChat>> {
+		Self::query_one(conn, "SELECT * FROM telegram_chats WHERE user_id = ?1", user_id)
+	}
+
+	/// Returns the chat started from the Telegram account with the username, which is matched
+	/// with or without the leading `@`.
+	pub fn query_by_username(conn: &Connection, username: &str) -> Result<Option<TelegramChat>> {
+		Self::query_one(
+			conn,
+			"SELECT * FROM telegram_chats WHERE handle IN (?1, '@' || ?1)",
+			username.trim_start_matches('@'),
+		)
+	}
+
+	fn query_one(
+		conn: &Connection,
+		sql: &str,
+		param: impl rusqlite::ToSql,
+	) -> Result<Option<TelegramChat>> {
+		let mut stmt = conn.prepare(sql)?;
+		let mut chats_iter = stmt.query_map(params![param], Self::from_row)?;
+
+		match chats_iter.next() {
+			Some(Ok(chat)) => Ok(Some(chat)),
+			Some(Err(err)) => Err(err),
+			None => Ok(None),
+		}
+	}
+
+	fn from_row(row: &Row) -> Result<TelegramChat> {
+		Ok(TelegramChat {
+			user_id: row.get("user_id")?,
+			chat_id: row.get("chat_id")?,
+			handle: row.get("handle")?,
+		})
+	}
+}
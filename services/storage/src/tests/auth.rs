@@ -0,0 +1,71 @@
+use super::db;
+use crate::{
+	auth::{ApiKey, Challenge, LoginLink, Session},
+	users::User,
+};
+use types::api::ApiKeyScope;
+
+#[test]
+fn challenges_can_only_be_taken_once() {
+	let conn = db();
+	let challenge = Challenge {
+		nonce: "nonce".into(),
+		account: "account".into(),
+		message: "message".into(),
+		expires_at: 10,
+	};
+	Challenge::create(&conn, &challenge).unwrap();
+	Challenge::create(&conn, &Challenge { nonce: "expired".into(), ..challenge.clone() }).unwrap();
+
+	assert_eq!(Challenge::take(&conn, "nonce", 10).unwrap(), Some(challenge));
+	assert_eq!(Challenge::take(&conn, "nonce", 10).unwrap(), None);
+	assert_eq!(Challenge::take(&conn, "expired", 11).unwrap(), None);
+}
+
+#[test]
+fn login_links_can_only_be_taken_once() {
+	let conn = db();
+	let link = LoginLink {
+		token_hash: "hash".into(),
+		email: "alice@mail.com".into(),
+		created_at: 0,
+		expires_at: 10,
+	};
+	LoginLink::create(&conn, &link).unwrap();
+	LoginLink::create(&conn, &LoginLink { token_hash: "expired".into(), ..link.clone() }).unwrap();
+
+	assert_eq!(LoginLink::take(&conn, "hash", 10).unwrap(), Some(link));
+	assert_eq!(LoginLink::take(&conn, "hash", 10).unwrap(), None);
+	assert_eq!(LoginLink::take(&conn, "expired", 11).unwrap(), None);
+
+	// Used links still count towards the rate limit.
+	assert_eq!(LoginLink::count_since(&conn, "alice@mail.com", 0).unwrap(), 2);
+	assert_eq!(LoginLink::count_since(&conn, "alice@mail.com", 1).unwrap(), 0);
+}
+
+#[test]
+fn expired_sessions_and_keys_are_rejected() {
+	let conn = db();
+	User::create_user(&conn, &User::new(0)).unwrap();
+	let session = Session { token_hash: "hash".into(), account: "account".into(), expires_at: 10 };
+	Session::create(&conn, &session).unwrap();
+	let key = ApiKey {
+		id: "key".into(),
+		user_id: 0,
+		name: "script".into(),
+		key_hash: "key hash".into(),
+		scope: ApiKeyScope::ReadOnly,
+		created_at: 0,
+		expires_at: Some(10),
+		last_used_at: None,
+	};
+	ApiKey::create(&conn, &key).unwrap();
+
+	assert_eq!(Session::query(&conn, "hash", 10).unwrap(), Some(session));
+	assert_eq!(Session::query(&conn, "hash", 11).unwrap(), None);
+
+	let authenticated = ApiKey::authenticate(&conn, "key hash", 10).unwrap();
+	assert_eq!(authenticated, Some(ApiKey { last_used_at: Some(10), ..key }));
+	assert_eq!(ApiKey::authenticate(&conn, "key hash", 11).unwrap(), None);
+	assert_eq!(ApiKey::authenticate(&conn, "unknown", 10).unwrap(), None);
+}
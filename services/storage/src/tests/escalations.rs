@@ -0,0 +1,48 @@
+use super::db;
+use crate::{
+	escalations::Alert,
+	outbox::{JobStatus, OutboxJob},
+	users::User,
+};
+use types::Notifier;
+
+#[test]
+fn alerts_are_raised_once_per_key() {
+	let conn = db();
+	User::create_user(&conn, &User::new(0)).unwrap();
+
+	let alert = Alert::raise(&conn, "alert", 0, 1).unwrap();
+	assert_eq!((alert.user_id, alert.raised_at, alert.acknowledged_at), (0, 1, None));
+
+	// Raising the alert again returns the alert raised first.
+	assert_eq!(Alert::raise(&conn, "alert", 0, 2).unwrap(), alert);
+	assert_ne!(Alert::raise(&conn, "other", 0, 2).unwrap().id, alert.id);
+}
+
+#[test]
+fn acknowledging_cancels_the_pending_deliveries() {
+	let conn = db();
+	User::create_user(&conn, &User::new(0)).unwrap();
+	let alert = Alert::raise(&conn, "alert", 0, 0).unwrap();
+	for (key, due) in [("first", 0), ("second", 600), ("third", 1200)] {
+		OutboxJob::enqueue_alert(&conn, key, &alert, &Notifier::Email, None, "{}", due).unwrap();
+	}
+	OutboxJob::claim_due(&conn, 0, 60, 1).unwrap();
+	Alert::add_token(&conn, alert.id, "hash").unwrap();
+	Alert::add_token(&conn, alert.id, "other hash").unwrap();
+
+	// CASE 1: unknown tokens don't acknowledge anything.
+	assert_eq!(Alert::acknowledge(&conn, "unknown", 10).unwrap(), None);
+	assert_eq!(Alert::query_by_id(&conn, alert.id).unwrap().unwrap().acknowledged_at, None);
+
+	// CASE 2: any token of the alert acknowledges it, the claimed delivery is kept.
+	let acknowledged = Alert::acknowledge(&conn, "hash", 10).unwrap().unwrap();
+	assert_eq!(acknowledged.acknowledged_at, Some(10));
+	assert_eq!(OutboxJob::query_by_id(&conn, 1).unwrap().unwrap().status, JobStatus::InFlight);
+	assert_eq!(OutboxJob::query_by_id(&conn, 2).unwrap(), None);
+	assert_eq!(OutboxJob::query_by_id(&conn, 3).unwrap(), None);
+
+	// CASE 3: acknowledging again keeps the first acknowledgement.
+	let acknowledged = Alert::acknowledge(&conn, "other hash", 20).unwrap().unwrap();
+	assert_eq!(acknowledged.acknowledged_at, Some(10));
+}
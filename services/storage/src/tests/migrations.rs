@@ -0,0 +1,120 @@
+use crate::{
+	channels, create_tables, migrations,
+	outbox::{JobStatus, OutboxJob},
+	subscriptions::Subscription,
+	users::User,
+};
+use rusqlite::Connection;
+use types::{Notifications, Notifier};
+
+/// Returns a db with the tables of the first release, before any migration.
+fn unmigrated_db() -> Connection {
+	let conn = Connection::open_in_memory().unwrap();
+	create_tables(&conn).unwrap();
+	conn
+}
+
+#[test]
+fn users_keep_their_addresses_and_subscriptions() {
+	let mut conn = unmigrated_db();
+	conn.execute_batch(
+		"INSERT INTO users (id, tg_handle, email, notifier)
+			VALUES (0, '@alice', 'alice@mail.com', 'telegram');
+		INSERT INTO users (id, email, notifier) VALUES (1, 'bob@mail.com', 'email');
+		INSERT INTO subscriptions (user_id, notification) VALUES (0, '\"CoretimeSale\"');
+		INSERT INTO subscriptions (user_id, notification) VALUES (1, '\"CoretimeSale\"');",
+	)
+	.unwrap();
+
+	// The users table is rebuilt while other tables reference it.
+	migrations::migrate(&mut conn).unwrap();
+
+	let alice = User::query_by_id(&conn, 0).unwrap().unwrap();
+	assert_eq!(alice.email.as_deref(), Some("alice@mail.com"));
+	assert_eq!(alice.tg_handle.as_deref(), Some("@alice"));
+	let bob = User::query_by_id(&conn, 1).unwrap().unwrap();
+	assert_eq!(bob.email.as_deref(), Some("bob@mail.com"));
+
+	// Subscriptions are routed to the notifier of the user.
+	let channels =
+		|user_id| Subscription::query_by_user(&conn, user_id).unwrap().remove(0).channels;
+	assert_eq!(channels(0), vec![Notifier::Telegram]);
+	assert_eq!(channels(1), vec![Notifier::Email]);
+	assert_eq!(
+		Subscription::query_by_user(&conn, 0).unwrap()[0].notification,
+		Notifications::CoretimeSale
+	);
+
+	// The addresses aren't verified, but are sent a confirmation link once.
+	assert!(!channels::is_verified(&conn, 1, &Notifier::Email).unwrap());
+	let backlog = vec![(0, "alice@mail.com".to_string()), (1, "bob@mail.com".to_string())];
+	assert_eq!(channels::take_verification_backlog(&conn).unwrap(), backlog);
+	assert_eq!(channels::take_verification_backlog(&conn).unwrap(), vec![]);
+
+	// Foreign keys are enforced again once the migrations are applied.
+	let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", (), |row| row.get(0)).unwrap();
+	assert!(foreign_keys);
+}
+
+#[test]
+fn migrations_are_only_applied_once() {
+	let mut conn = unmigrated_db();
+	migrations::migrate(&mut conn).unwrap();
+	let version: usize = conn.query_row("PRAGMA user_version", (), |row| row.get(0)).unwrap();
+
+	migrations::migrate(&mut conn).unwrap();
+	let again: usize = conn.query_row("PRAGMA user_version", (), |row| row.get(0)).unwrap();
+	assert_eq!(version, again);
+	assert!(version > 0);
+}
+
+#[test]
+fn jobs_enqueued_before_dead_lettering_can_be_dead_lettered() {
+	let mut conn = unmigrated_db();
+	conn.execute_batch(
+		"INSERT INTO users (id, email, notifier) VALUES (0, 'alice@mail.com', 'email');
+		INSERT INTO outbox (user_id, channel, payload, status, next_attempt_at)
+			VALUES (0, 'email', '{}', 'in_flight', 0);",
+	)
+	.unwrap();
+
+	migrations::migrate(&mut conn).unwrap();
+
+	assert_eq!(OutboxJob::dead_letter(&conn, 1, "Invalid address").unwrap(), 1);
+	let job = OutboxJob::query_by_id(&conn, 1).unwrap().unwrap();
+	assert_eq!(job.status, JobStatus::Dead);
+	assert_eq!(job.last_error.as_deref(), Some("Invalid address"));
+
+	// The rebuilt table keeps counting ids from where the old one stopped.
+	let id = OutboxJob::enqueue(&conn, "other", 0, &Notifier::Email, "{}", 0, false).unwrap();
+	assert_eq!(id, Some(2));
+}
+
+#[test]
+fn jobs_enqueued_before_deduplication_get_a_key() {
+	let mut conn = unmigrated_db();
+	conn.execute_batch(
+		"INSERT INTO users (id, email, notifier) VALUES (0, 'alice@mail.com', 'email');
+		INSERT INTO outbox (user_id, channel, payload, status, next_attempt_at)
+			VALUES (0, 'email', '{}', 'pending', 0);
+		INSERT INTO outbox (user_id, channel, payload, status, next_attempt_at)
+			VALUES (0, 'email', '{}', 'pending', 0);",
+	)
+	.unwrap();
+
+	migrations::migrate(&mut conn).unwrap();
+
+	let jobs = OutboxJob::query_by_user(&conn, 0).unwrap();
+	let keys = jobs.iter().map(|job| job.dedup_key.as_str()).collect::<Vec<_>>();
+	assert_eq!(keys, vec!["legacy:1", "legacy:2"]);
+
+	// The key is unique.
+	assert_eq!(
+		OutboxJob::enqueue(&conn, "key", 0, &Notifier::Email, "{}", 0, false).unwrap(),
+		Some(3)
+	);
+	assert_eq!(
+		OutboxJob::enqueue(&conn, "key", 0, &Notifier::Email, "{}", 0, false).unwrap(),
+		None
+	);
+}
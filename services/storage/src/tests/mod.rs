@@ -0,0 +1,18 @@
+use crate::create_tables;
+use rusqlite::Connection;
+
+mod auth;
+mod escalations;
+mod migrations;
+mod organizations;
+mod subscriptions;
+mod tokens;
+mod users;
+
+/// Returns an in-memory db with all migrations applied.
+fn db() -> Connection {
+	let mut conn = Connection::open_in_memory().unwrap();
+	create_tables(&conn).unwrap();
+	crate::migrations::migrate(&mut conn).unwrap();
+	conn
+}
@@ -0,0 +1,25 @@
+use super::db;
+use crate::{
+	organizations::{Invite, Organization},
+	users::User,
+};
+use types::api::OrganizationRole;
+
+#[test]
+fn invites_can_only_be_taken_once() {
+	let conn = db();
+	User::create_user(&conn, &User::new(0)).unwrap();
+	let organization = Organization::create(&conn, "public", "Team", 0, 0).unwrap();
+	let invite = Invite {
+		token_hash: "hash".into(),
+		organization_id: organization.id,
+		role: OrganizationRole::Member,
+		expires_at: 10,
+	};
+	Invite::create(&conn, &invite).unwrap();
+	Invite::create(&conn, &Invite { token_hash: "expired".into(), ..invite.clone() }).unwrap();
+
+	assert_eq!(Invite::take(&conn, "hash", 10).unwrap(), Some(invite));
+	assert_eq!(Invite::take(&conn, "hash", 10).unwrap(), None);
+	assert_eq!(Invite::take(&conn, "expired", 11).unwrap(), None);
+}
@@ -0,0 +1,33 @@
+use super::db;
+use crate::{
+	outbox::{JobStatus, OutboxJob},
+	subscriptions::Subscription,
+	users::User,
+};
+use types::{Notifications, Notifier};
+
+#[test]
+fn unsubscribing_drops_the_pending_deliveries() {
+	let conn = db();
+	User::create_user(&conn, &User::new(0)).unwrap();
+	let (sale, parachain) = (Notifications::CoretimeSale, Notifications::ParachainState(2000));
+	for notification in [&sale, &parachain] {
+		Subscription::create(&conn, 0, notification, &[Notifier::Email]).unwrap();
+	}
+	for (key, due) in [("claimed", 0), ("pending", 600), ("unlinked", 600), ("other", 600)] {
+		OutboxJob::enqueue(&conn, key, 0, &Notifier::Email, "{}", due, true).unwrap();
+	}
+	OutboxJob::claim_due(&conn, 0, 60, 1).unwrap();
+	for key in ["claimed", "pending"] {
+		OutboxJob::set_subscription(&conn, key, Some(&parachain)).unwrap();
+	}
+	OutboxJob::set_subscription(&conn, "other", Some(&sale)).unwrap();
+
+	assert!(Subscription::remove(&conn, 0, &parachain).unwrap());
+
+	// The claimed delivery, and the ones of other notifications are kept.
+	let jobs = OutboxJob::query_by_user(&conn, 0).unwrap();
+	let keys = jobs.iter().map(|job| job.dedup_key.as_str()).collect::<Vec<_>>();
+	assert_eq!(keys, vec!["claimed", "unlinked", "other"]);
+	assert_eq!(jobs[0].status, JobStatus::InFlight);
+}
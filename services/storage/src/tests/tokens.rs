@@ -0,0 +1,12 @@
+use crate::tokens::{hash_token, random_token, TOKEN_BYTES};
+
+#[test]
+fn tokens_are_random_and_hashed_deterministically() {
+	let token = random_token(TOKEN_BYTES);
+	assert_eq!(token.len(), 2 * TOKEN_BYTES);
+	assert_eq!(random_token(8).len(), 16);
+	assert_ne!(token, random_token(TOKEN_BYTES));
+
+	assert_eq!(hash_token(&token), hash_token(&token));
+	assert_ne!(hash_token(&token), token);
+}
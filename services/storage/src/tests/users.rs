@@ -0,0 +1,132 @@
+use super::db;
+use crate::{
+	audit::AuditEntry,
+	auth::{ApiKey, Session},
+	escalations::{Alert, Contact},
+	organizations::{Member, Organization},
+	outbox::OutboxJob,
+	subscriptions::Subscription,
+	users::User,
+};
+use rusqlite::{params, Connection};
+use types::{
+	api::{ApiKeyScope, OrganizationRole},
+	Notifications, Notifier,
+};
+
+/// Returns how many rows of the table belong to the user.
+fn count(conn: &Connection, table: &str, user_id: u32) -> u32 {
+	conn.query_row(
+		&format!("SELECT COUNT(*) FROM {} WHERE user_id = ?1", table),
+		params![user_id],
+		|row| row.get(0),
+	)
+	.unwrap()
+}
+
+#[test]
+fn deleting_a_user_removes_everything_stored_about_it() {
+	let conn = db();
+	for id in [0, 1] {
+		let user = User { email: Some(format!("user{}@mail.com", id)), ..User::new(id) };
+		User::create_user(&conn, &user).unwrap();
+		User::bind_account(&conn, id, &format!("account{}", id)).unwrap();
+		Session::create(
+			&conn,
+			&Session {
+				token_hash: format!("session{}", id),
+				account: format!("account{}", id),
+				expires_at: 10,
+			},
+		)
+		.unwrap();
+		ApiKey::create(
+			&conn,
+			&ApiKey {
+				id: format!("key{}", id),
+				user_id: id,
+				name: "script".into(),
+				key_hash: format!("key hash{}", id),
+				scope: ApiKeyScope::Full,
+				created_at: 0,
+				expires_at: None,
+				last_used_at: None,
+			},
+		)
+		.unwrap();
+		Subscription::create(&conn, id, &Notifications::CoretimeSale, &[Notifier::Email]).unwrap();
+		let alert = Alert::raise(&conn, &format!("alert{}", id), id, 0).unwrap();
+		Alert::add_token(&conn, alert.id, &format!("alert{}", id)).unwrap();
+		OutboxJob::enqueue_alert(
+			&conn,
+			&format!("job{}", id),
+			&alert,
+			&Notifier::Email,
+			None,
+			"{}",
+			0,
+		)
+		.unwrap();
+		let contact = format!("contact{}@mail.com", id);
+		Contact::request(&conn, id, &Notifier::Email, &contact, &format!("contact{}", id), 0)
+			.unwrap();
+		AuditEntry::record(&conn, id, "register", "{}", 0).unwrap();
+	}
+	let own = Organization::create(&conn, "own", "Own", 0, 0).unwrap();
+	let shared = Organization::create(&conn, "shared", "Shared", 1, 0).unwrap();
+	Member::add(&conn, shared.id, 0, OrganizationRole::Member, 0).unwrap();
+
+	assert!(User::delete(&conn, 0).unwrap());
+
+	assert_eq!(User::query_by_id(&conn, 0).unwrap(), None);
+	assert_eq!(Session::query(&conn, "session0", 0).unwrap(), None);
+	assert_eq!(ApiKey::authenticate(&conn, "key hash0", 0).unwrap(), None);
+	assert_eq!(Alert::acknowledge(&conn, "alert0", 0).unwrap(), None);
+	assert_eq!(Contact::confirm(&conn, "contact0", 0).unwrap(), None);
+	for table in [
+		"channels",
+		"subscriptions",
+		"subscription_channels",
+		"alerts",
+		"outbox",
+		"escalation_contacts",
+		"api_keys",
+		"organization_members",
+		"audit_log",
+	] {
+		assert_eq!(count(&conn, table, 0), 0, "{} of the user weren't removed", table);
+		assert_eq!(count(&conn, table, 1), 1, "{} of another user were removed", table);
+	}
+	let tokens: u32 = conn
+		.query_row("SELECT COUNT(*) FROM alert_tokens", [], |row| row.get(0))
+		.unwrap();
+	assert_eq!(tokens, 1);
+
+	// The organization without any members left is removed, the shared one is kept.
+	assert_eq!(Organization::query_by_public_id(&conn, &own.public_id).unwrap(), None);
+	assert_eq!(Organization::query_by_public_id(&conn, &shared.public_id).unwrap(), Some(shared));
+
+	// The other user is kept.
+	assert!(Session::query(&conn, "session1", 0).unwrap().is_some());
+	assert!(User::query_by_id(&conn, 1).unwrap().is_some());
+
+	assert!(!User::delete(&conn, 0).unwrap());
+}
+
+#[test]
+fn users_are_created_within_the_transaction_of_the_caller() {
+	let conn = db();
+	let user = User { email: Some("user@mail.com".to_string()), ..User::new(0) };
+
+	// CASE 1: the user is rolled back along with the other writes of the caller.
+	let tx = conn.unchecked_transaction().unwrap();
+	User::create_user(&tx, &user).unwrap();
+	assert!(User::query_by_id(&tx, 0).unwrap().is_some());
+	drop(tx);
+	assert_eq!(User::query_by_id(&conn, 0).unwrap(), None);
+	assert_eq!(count(&conn, "channels", 0), 0);
+
+	// CASE 2: without a transaction of the caller, the user is created right away.
+	User::create_user(&conn, &user).unwrap();
+	assert_eq!(User::query_by_id(&conn, 0).unwrap(), Some(user));
+}
@@ -0,0 +1,22 @@
+//! Random tokens, e.g. for sessions, API keys and the links sent to users.
+//!
+//! Tokens which grant access are only stored as their hash, so that they can't be used by anyone
+//! who gets hold of the db.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// The number of random bytes of a token which grants access.
+pub const TOKEN_BYTES: usize = 32;
+
+/// Generates a hex encoded token of `bytes` random bytes.
+pub fn random_token(bytes: usize) -> String {
+	let mut token = vec![0u8; bytes];
+	rand::thread_rng().fill_bytes(&mut token);
+	hex::encode(token)
+}
+
+/// Returns the hash under which the token is stored.
+pub fn hash_token(token: &str) -> String {
+	hex::encode(Sha256::digest(token.as_bytes()))
+}
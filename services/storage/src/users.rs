@@ -1,57 +1,67 @@
-use rusqlite::{params, Connection, Error, Result};
+use crate::{channels, in_transaction};
+use rusqlite::{params, Connection, Error, OptionalExtension, Result, Row};
 use serde::{Deserialize, Serialize};
-use types::Notifier;
+use types::{DeliveryMode, Notifier, QuietHours, Timestamp};
+
+/// The language used when the user didn't pick one.
+pub const DEFAULT_LANGUAGE: &str = "en";
+/// The time zone used when the user didn't pick one.
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// Selects the users along with the addresses of their channels.
+const SELECT_USERS: &str = "SELECT users.*,
+	(SELECT address FROM channels WHERE user_id = users.id AND kind = 'email') AS email,
+	(SELECT address FROM channels WHERE user_id = users.id AND kind = 'telegram') AS tg_handle,
+	(SELECT address FROM channels WHERE user_id = users.id AND kind = 'discord') AS discord_webhook,
+	(SELECT address FROM channels WHERE user_id = users.id AND kind = 'slack') AS slack_webhook,
+	(SELECT address FROM channels WHERE user_id = users.id AND kind = 'matrix') AS matrix_room,
+	(SELECT address FROM channels WHERE user_id = users.id AND kind = 'webhook') AS webhook_url
+	FROM users";
 
 /// The data stored for each user in the database.
+///
+/// The addresses are stored as the channels of the user, a user can have any number of them.
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct User {
-	/// A unique identifier for a user.
+	/// A unique identifier for a user, assigned by the service. Only used internally, clients
+	/// identify the user through its public id.
+	#[serde(skip)]
 	pub id: u32,
 	/// Email of the user.
 	pub email: Option<String>,
 	/// Telegram handle of the user.
 	pub tg_handle: Option<String>,
-	/// Defines the channel through which the user would like to be notified.
-	pub notifier: Notifier,
+	/// Url of the Discord webhook through which the user is notified.
+	pub discord_webhook: Option<String>,
+	/// Url of the Slack incoming webhook through which the user is notified.
+	pub slack_webhook: Option<String>,
+	/// Id of the Matrix room in which the user is notified, e.g. `!abc:matrix.org`.
+	pub matrix_room: Option<String>,
+	/// Url of the endpoint to which the events are posted, see the `webhook` channel of the
+	/// notification service.
+	pub webhook_url: Option<String>,
+	/// The language in which the user would like to be notified, e.g. `en` or `de`.
+	pub language: String,
+	/// The IANA time zone of the user, e.g. `Europe/Berlin`.
+	pub timezone: String,
+	/// The hours during which the user doesn't want to receive non-critical notifications.
+	pub quiet_hours: Option<QuietHours>,
+	/// Whether notifications are sent right away or collected into digests.
+	pub delivery_mode: DeliveryMode,
 }
 
 impl User {
 	pub fn query_all(conn: &Connection) -> Result<Vec<User>> {
-		let mut stmt = conn.prepare("SELECT * FROM users")?;
-		let users_iter = stmt.query_map((), |row| {
-			let notifier = match row.get::<_, String>("notifier")?.as_str() {
-				"email" => Notifier::Email,
-				"telegram" => Notifier::Telegram,
-				_ => Notifier::Null,
-			};
-
-			Ok(User {
-				id: row.get("id")?,
-				tg_handle: row.get("tg_handle")?,
-				email: row.get("email")?,
-				notifier,
-			})
-		})?;
+		let mut stmt = conn.prepare(SELECT_USERS)?;
+		let users_iter = stmt.query_map((), Self::from_row)?;
 
 		let users = users_iter.filter_map(Result::ok).collect();
 		Ok(users)
 	}
 
 	pub fn query_by_id(conn: &Connection, id: u32) -> Result<Option<User>> {
-		let mut smth = conn.prepare("SELECT * FROM users WHERE id=?1")?;
-		let mut users_iter = smth.query_map(&[&id], |row| {
-			let notifier = match row.get::<_, String>("notifier")?.as_str() {
-				"email" => Notifier::Email,
-				"telegram" => Notifier::Telegram,
-				_ => Notifier::Null,
-			};
-			Ok(User {
-				id: row.get("id")?,
-				tg_handle: row.get("tg_handle")?,
-				email: row.get("email")?,
-				notifier,
-			})
-		})?;
+		let mut smth = conn.prepare(&format!("{} WHERE id=?1", SELECT_USERS))?;
+		let mut users_iter = smth.query_map(&[&id], Self::from_row)?;
 
 		match users_iter.next() {
 			Some(Ok(data)) => Ok(Some(data)),
@@ -61,43 +71,25 @@ impl User {
 	}
 
 	pub fn query_by_email(conn: &Connection, email: String) -> Result<Option<User>> {
-		let mut smth = conn.prepare("SELECT * FROM users WHERE email=?1")?;
-		let mut users_iter = smth.query_map(&[&email], |row| {
-			let notifier = match row.get::<_, String>("notifier")?.as_str() {
-				"email" => Notifier::Email,
-				"telegram" => Notifier::Telegram,
-				_ => Notifier::Null,
-			};
-			Ok(User {
-				id: row.get("id")?,
-				tg_handle: row.get("tg_handle")?,
-				email: row.get("email")?,
-				notifier,
-			})
-		})?;
-
-		match users_iter.next() {
-			Some(Ok(data)) => Ok(Some(data)),
-			Some(Err(err)) => Err(err),
-			None => Ok(None),
-		}
+		Self::query_by_address(conn, &Notifier::Email, &email)
 	}
 
 	pub fn query_by_tg_handle(conn: &Connection, handle: String) -> Result<Option<User>> {
-		let mut smth = conn.prepare("SELECT * FROM users WHERE tg_handle=?1")?;
-		let mut users_iter = smth.query_map(&[&handle], |row| {
-			let notifier = match row.get::<_, String>("notifier")?.as_str() {
-				"email" => Notifier::Email,
-				"telegram" => Notifier::Telegram,
-				_ => Notifier::Null,
-			};
-			Ok(User {
-				id: row.get("id")?,
-				tg_handle: row.get("tg_handle")?,
-				email: row.get("email")?,
-				notifier,
-			})
-		})?;
+		Self::query_by_address(conn, &Notifier::Telegram, &handle)
+	}
+
+	/// Returns the user whose channel of the given kind has the address.
+	pub fn query_by_address(
+		conn: &Connection,
+		kind: &Notifier,
+		address: &str,
+	) -> Result<Option<User>> {
+		let mut smth = conn.prepare(&format!(
+			"{} WHERE id IN (SELECT user_id FROM channels WHERE kind = ?1 AND address = ?2)",
+			SELECT_USERS
+		))?;
+		let mut users_iter =
+			smth.query_map(params![Self::notifier_to_text(kind), address], Self::from_row)?;
 
 		match users_iter.next() {
 			Some(Ok(data)) => Ok(Some(data)),
@@ -106,57 +98,284 @@ impl User {
 		}
 	}
 
+	/// Returns a user without any channels, with the default settings.
+	pub fn new(id: u32) -> User {
+		User {
+			id,
+			email: None,
+			tg_handle: None,
+			discord_webhook: None,
+			slack_webhook: None,
+			matrix_room: None,
+			webhook_url: None,
+			language: DEFAULT_LANGUAGE.to_string(),
+			timezone: DEFAULT_TIMEZONE.to_string(),
+			quiet_hours: None,
+			delivery_mode: Default::default(),
+		}
+	}
+
+	/// Returns an id which isn't used by any user yet.
+	pub fn next_id(conn: &Connection) -> Result<u32> {
+		conn.query_row("SELECT COALESCE(MAX(id) + 1, 0) FROM users", [], |row| row.get(0))
+	}
+
+	/// Creates the user, along with a random public id.
 	pub fn create_user(conn: &Connection, user: &User) -> Result<()> {
-		let User { id, email, tg_handle, .. } = user;
-		let notifier = Self::notifier_to_text(&user.notifier);
+		let User { id, language, timezone, quiet_hours, .. } = user;
+		let delivery_mode = Self::delivery_mode_to_text(&user.delivery_mode);
 
-		match notifier {
-			Some(notifier) => {
-				conn.execute(
-					"INSERT INTO users
-                        (email, tg_handle, notifier)
-                        VALUES (?1, ?2, ?3, ?4)
-                    ",
-					params![id, email, tg_handle, notifier],
-				)?;
-			},
-			None => {
-				conn.execute(
-					"INSERT INTO users
-                        (email, tg_handle, notifier)
-                        VALUES (?1, ?2, ?3, NULL)
-                    ",
-					params![id, email, tg_handle],
-				)?;
-			},
-		};
-		Ok(())
+		in_transaction(conn, |tx| {
+			tx.execute(
+				"INSERT INTO users
+                    (id, language, timezone, quiet_hours_start, quiet_hours_end, delivery_mode,
+                    public_id)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, lower(hex(randomblob(16))))
+                ",
+				params![
+					id,
+					language,
+					timezone,
+					quiet_hours.map(|q| q.start),
+					quiet_hours.map(|q| q.end),
+					delivery_mode
+				],
+			)?;
+			user.set_channels(tx)
+		})
 	}
 
+	/// Updates the user. Channels without an address are removed.
 	pub fn update(conn: &Connection, user: &User) -> Result<usize, Error> {
-		let User { id, email, tg_handle, .. } = user;
-		let notifier = Self::notifier_to_text(&user.notifier);
-
-		let result = if notifier.is_some() {
-			conn.execute(
-				"UPDATE users SET email = ?1, tg_handle = ?2, notifier = ?3 WHERE id = ?4",
-				params![email, tg_handle, notifier, id],
-			)
-		} else {
-			conn.execute(
-				"UPDATE users SET email = ?1, tg_handle = ?2, notifier = NULL WHERE id = ?3",
-				params![email, tg_handle, id],
-			)
-		};
+		let User { id, language, timezone, quiet_hours, .. } = user;
+		let delivery_mode = Self::delivery_mode_to_text(&user.delivery_mode);
+
+		let tx = conn.unchecked_transaction()?;
+		let updated = tx.execute(
+			"UPDATE users SET language = ?1, timezone = ?2, quiet_hours_start = ?3,
+                quiet_hours_end = ?4, delivery_mode = ?5
+                WHERE id = ?6",
+			params![
+				language,
+				timezone,
+				quiet_hours.map(|q| q.start),
+				quiet_hours.map(|q| q.end),
+				delivery_mode,
+				id
+			],
+		)?;
+		if updated > 0 {
+			user.set_channels(&tx)?;
+		}
+		tx.commit()?;
+
+		Ok(updated)
+	}
+
+	/// Removes the user along with everything stored about it: its channels, subscriptions,
+	/// sessions, API keys, memberships, audit log and deliveries, including the ones which weren't
+	/// made yet.
+	///
+	/// Organizations of which the user was the only member are removed as well. Returns whether
+	/// the user existed.
+	pub fn delete(conn: &Connection, id: u32) -> Result<bool> {
+		let account = Self::query_account(conn, id)?;
+		let email = Self::query_by_id(conn, id)?.and_then(|user| user.email);
+
+		let tx = conn.unchecked_transaction()?;
+		tx.execute("DELETE FROM sessions WHERE account = ?1", params![account])?;
+		tx.execute("DELETE FROM auth_challenges WHERE account = ?1", params![account])?;
+		tx.execute("DELETE FROM login_links WHERE email = ?1", params![email])?;
+		tx.execute(
+			"DELETE FROM outbox WHERE user_id = ?1
+				OR alert_id IN (SELECT id FROM alerts WHERE user_id = ?1)",
+			params![id],
+		)?;
+		tx.execute(
+			"DELETE FROM alert_tokens WHERE alert_id IN (SELECT id FROM alerts WHERE user_id = ?1)",
+			params![id],
+		)?;
+		for table in [
+			"alerts",
+			"escalation_steps",
+			"escalation_contacts",
+			"subscription_channels",
+			"subscriptions",
+			"channels",
+			"verification_backlog",
+			"telegram_chats",
+			"webhook_secrets",
+			"api_keys",
+			"organization_member_channels",
+			"organization_members",
+			"audit_log",
+		] {
+			tx.execute(&format!("DELETE FROM {} WHERE user_id = ?1", table), params![id])?;
+		}
+		tx.execute_batch(
+			"DELETE FROM organization_subscriptions
+				WHERE organization_id NOT IN (SELECT organization_id FROM organization_members);
+			DELETE FROM organization_invites
+				WHERE organization_id NOT IN (SELECT organization_id FROM organization_members);
+			DELETE FROM organizations
+				WHERE id NOT IN (SELECT organization_id FROM organization_members);",
+		)?;
+		let deleted = tx.execute("DELETE FROM users WHERE id = ?1", params![id])?;
+		tx.commit()?;
+
+		Ok(deleted > 0)
+	}
+
+	/// Holds back the non-critical notifications of the user until `until`, or lifts the mute if
+	/// `None`.
+	pub fn mute(conn: &Connection, id: u32, until: Option<Timestamp>) -> Result<usize> {
+		conn.execute("UPDATE users SET muted_until = ?1 WHERE id = ?2", params![until, id])
+	}
+
+	/// Returns until when the user muted their notifications.
+	pub fn query_muted_until(conn: &Connection, id: u32) -> Result<Option<Timestamp>> {
+		conn.query_row("SELECT muted_until FROM users WHERE id = ?1", params![id], |row| row.get(0))
+			.optional()
+			.map(Option::flatten)
+	}
 
-		result
+	/// Binds the user to the account which manages it, e.g. a Substrate account or a Telegram
+	/// account.
+	pub fn bind_account(conn: &Connection, id: u32, account: &str) -> Result<usize> {
+		conn.execute("UPDATE users SET account = ?1 WHERE id = ?2", params![account, id])
 	}
 
-	fn notifier_to_text(notifier: &Notifier) -> Option<String> {
+	/// Binds the user of an account to another account, unless a user is bound to that account
+	/// already.
+	pub fn rebind_account(conn: &Connection, from: &str, to: &str) -> Result<usize> {
+		conn.execute(
+			"UPDATE OR IGNORE users SET account = ?2 WHERE account = ?1",
+			params![from, to],
+		)
+	}
+
+	/// Returns the opaque id through which clients identify the user.
+	pub fn query_public_id(conn: &Connection, id: u32) -> Result<Option<String>> {
+		conn.query_row("SELECT public_id FROM users WHERE id = ?1", params![id], |row| row.get(0))
+			.optional()
+			.map(Option::flatten)
+	}
+
+	/// Returns the user with the opaque id.
+	pub fn query_by_public_id(conn: &Connection, public_id: &str) -> Result<Option<User>> {
+		conn.query_row(
+			&format!("{} WHERE public_id = ?1", SELECT_USERS),
+			params![public_id],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns the account the user is bound to.
+	pub fn query_account(conn: &Connection, id: u32) -> Result<Option<String>> {
+		conn.query_row("SELECT account FROM users WHERE id = ?1", params![id], |row| row.get(0))
+			.optional()
+			.map(Option::flatten)
+	}
+
+	/// Returns the user bound to the account.
+	pub fn query_by_account(conn: &Connection, account: &str) -> Result<Option<User>> {
+		conn.query_row(
+			&format!("{} WHERE account = ?1", SELECT_USERS),
+			params![account],
+			Self::from_row,
+		)
+		.optional()
+	}
+
+	/// Returns the address of the user's channel of the given kind.
+	pub fn address(&self, kind: &Notifier) -> Option<&String> {
+		match kind {
+			Notifier::Email => self.email.as_ref(),
+			Notifier::Telegram => self.tg_handle.as_ref(),
+			Notifier::Discord => self.discord_webhook.as_ref(),
+			Notifier::Slack => self.slack_webhook.as_ref(),
+			Notifier::Matrix => self.matrix_room.as_ref(),
+			Notifier::Webhook => self.webhook_url.as_ref(),
+			Notifier::Null => None,
+		}
+	}
+
+	/// Returns the kinds of channels the user has an address for.
+	pub fn channels(&self) -> Vec<Notifier> {
+		channels::KINDS
+			.into_iter()
+			.filter(|kind| self.address(kind).is_some())
+			.collect()
+	}
+
+	fn set_channels(&self, conn: &Connection) -> Result<()> {
+		for kind in channels::KINDS.iter() {
+			channels::set(conn, self.id, kind, self.address(kind).map(String::as_str))?;
+		}
+		Ok(())
+	}
+
+	pub(crate) fn notifier_to_text(notifier: &Notifier) -> Option<String> {
 		match notifier {
 			Notifier::Email => Some(String::from("email")),
 			Notifier::Telegram => Some("telegram".to_string()),
+			Notifier::Discord => Some("discord".to_string()),
+			Notifier::Slack => Some("slack".to_string()),
+			Notifier::Matrix => Some("matrix".to_string()),
+			Notifier::Webhook => Some("webhook".to_string()),
 			_ => None,
 		}
 	}
+
+	pub(crate) fn text_to_notifier(text: String) -> Notifier {
+		match text.as_str() {
+			"email" => Notifier::Email,
+			"telegram" => Notifier::Telegram,
+			"discord" => Notifier::Discord,
+			"slack" => Notifier::Slack,
+			"matrix" => Notifier::Matrix,
+			"webhook" => Notifier::Webhook,
+			_ => Notifier::Null,
+		}
+	}
+
+	fn delivery_mode_to_text(delivery_mode: &DeliveryMode) -> &'static str {
+		match delivery_mode {
+			DeliveryMode::Immediate => "immediate",
+			DeliveryMode::HourlyDigest => "hourly",
+			DeliveryMode::DailyDigest => "daily",
+			DeliveryMode::WeeklyDigest => "weekly",
+		}
+	}
+
+	fn text_to_delivery_mode(text: String) -> DeliveryMode {
+		match text.as_str() {
+			"hourly" => DeliveryMode::HourlyDigest,
+			"daily" => DeliveryMode::DailyDigest,
+			"weekly" => DeliveryMode::WeeklyDigest,
+			_ => DeliveryMode::Immediate,
+		}
+	}
+
+	fn from_row(row: &Row) -> Result<User> {
+		let quiet_hours = match (row.get("quiet_hours_start")?, row.get("quiet_hours_end")?) {
+			(Some(start), Some(end)) => Some(QuietHours { start, end }),
+			_ => None,
+		};
+
+		Ok(User {
+			id: row.get("id")?,
+			tg_handle: row.get("tg_handle")?,
+			email: row.get("email")?,
+			discord_webhook: row.get("discord_webhook")?,
+			slack_webhook: row.get("slack_webhook")?,
+			matrix_room: row.get("matrix_room")?,
+			webhook_url: row.get("webhook_url")?,
+			language: row.get("language")?,
+			timezone: row.get("timezone")?,
+			quiet_hours,
+			delivery_mode: Self::text_to_delivery_mode(row.get("delivery_mode")?),
+		})
+	}
 }
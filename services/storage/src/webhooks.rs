@@ -0,0 +1,53 @@
+//! Secrets with which the deliveries to the webhooks of users are signed.
+//!
+//! When a secret is rotated, the previous one is kept so that deliveries can still be signed with
+//! it until the user switched their integration over to the new secret.
+
+use rusqlite::{params, Connection, Result, Row};
+use types::Timestamp;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WebhookSecret {
+	/// The user the secret belongs to.
+	pub user_id: u32,
+	/// The current secret.
+	pub secret: String,
+	/// The secret which was replaced by the last rotation.
+	pub previous_secret: Option<String>,
+	/// Unix timestamp of the last rotation.
+	pub rotated_at: Timestamp,
+}
+
+impl WebhookSecret {
+	pub fn query_by_user(conn: &Connection, user_id: u32) -> Result<Option<WebhookSecret>> {
+		let mut stmt = conn.prepare("SELECT * FROM webhook_secrets WHERE user_id = ?1")?;
+		let mut secrets_iter = stmt.query_map(params![user_id], Self::from_row)?;
+
+		match secrets_iter.next() {
+			Some(Ok(secret)) => Ok(Some(secret)),
+			Some(Err(err)) => Err(err),
+			None => Ok(None),
+		}
+	}
+
+	/// Replaces the secret of the user. The replaced secret becomes the previous secret.
+	pub fn rotate(conn: &Connection, user_id: u32, secret: &str, now: Timestamp) -> Result<()> {
+		conn.execute(
+			"INSERT INTO webhook_secrets (user_id, secret, previous_secret, rotated_at)
+				VALUES (?1, ?2, NULL, ?3)
+				ON CONFLICT (user_id) DO UPDATE SET
+					previous_secret = secret, secret = excluded.secret, rotated_at = excluded.rotated_at",
+			params![user_id, secret, now],
+		)?;
+		Ok(())
+	}
+
+	fn from_row(row: &Row) -> Result<WebhookSecret> {
+		Ok(WebhookSecret {
+			user_id: row.get("user_id")?,
+			secret: row.get("secret")?,
+			previous_secret: row.get("previous_secret")?,
+			rotated_at: row.get("rotated_at")?,
+		})
+	}
+}
@@ -2,22 +2,32 @@
 //!
 //! Responsible for tracking the Coretime chain and triggering the notification service
 //! when needed.
-use crate::coretime_chain::runtime_types::pallet_broker::types::{ConfigRecord, SaleInfoRecord};
+use crate::coretime_chain::runtime_types::pallet_broker::types::{
+	CompletionStatus, ConfigRecord, SaleInfoRecord,
+};
+use storage::{sales::SaleStatus, DbConn};
 use subxt::{blocks::Block, OnlineClient, PolkadotConfig};
-use types::ParaId;
+use types::{
+	event::{Event, EventKind},
+	Balance, BlockNumber, ParaId, Timeslice, Timestamp,
+};
 
 const LOG_TARGET: &str = "tracker";
 const RPC: &str = "wss://sys.ibp.network/coretime-kusama/";
+const NETWORK: &str = "kusama";
+/// Expected block time of the Coretime chain in seconds.
+const BLOCK_TIME: u64 = 12;
 
 #[subxt::subxt(runtime_metadata_path = "../../artifacts/kusama-coretime.scale")]
 mod coretime_chain {}
-use coretime_chain::broker::events as broker_events;
+use coretime_chain::{
+	broker::events as broker_events,
+	runtime_types::pallet_broker::coretime_interface::CoreAssignment,
+};
 
-type Balance = u128;
-type BlockNumber = u32;
 type RelayBlockNumber = u32;
 
-pub async fn track() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn track(conn: DbConn) -> Result<(), Box<dyn std::error::Error>> {
 	let result = OnlineClient::<PolkadotConfig>::from_url(RPC).await;
 	let Ok(client) = result else {
 		log::error!(
@@ -29,16 +39,8 @@ pub async fn track() -> Result<(), Box<dyn std::error::Error>> {
 		return Err("Failed to create an online client".into());
 	};
 
-	let sale_info = sale_info(&client).await?;
-	let config = coretime_config(&client).await?;
-
-	// There shouldn't be a need to convert these to timestamps. We will just follow the latest
-	// block, and once it is one of the key ones, we will trigger a notification.
-	//
-	// NOTE: these values should be updated whenever a rotation to a new sale happens.
-	let leadin_start: u32 = sale_info.sale_start;
-	let interlude_start: u32 = leadin_start.saturating_sub(config.interlude_length);
-	let fixed_phase_start: u32 = leadin_start.saturating_add(config.leadin_length);
+	let latest = client.blocks().at_latest().await?;
+	schedule_phases(&conn, &client, latest.number()).await?;
 
 	let mut blocks_sub = client
 		.blocks()
@@ -49,27 +51,161 @@ pub async fn track() -> Result<(), Box<dyn std::error::Error>> {
 	// Wait for new finalized blocks, then check if an event we are waiting for happened.
 	while let Some(Ok(block)) = blocks_sub.next().await {
 		// Track everything we want to track:
-		track_coretime_sales(&client, &block).await;
-		track_interlude_phase(&block, interlude_start);
-		track_leadin_phase(&block, leadin_start);
-		track_fixed_phase(&block, fixed_phase_start);
-		track_assignments_and_renewals(&block);
+		if let Err(err) = track_sale_rotation(&conn, &client, &block).await {
+			log::error!(target: LOG_TARGET, "Failed to track sale rotation: {:?}", err);
+		}
+		if let Err(err) = track_coretime_sales(&conn, &client, &block).await {
+			log::error!(target: LOG_TARGET, "Failed to track coretime sales: {:?}", err);
+		}
+		if let Err(err) = track_assignments_and_renewals(&conn, &block).await {
+			log::error!(target: LOG_TARGET, "Failed to track assignments: {:?}", err);
+		}
+	}
+
+	Ok(())
+}
+
+async fn track_sale_rotation(
+	conn: &DbConn,
+	client: &OnlineClient<PolkadotConfig>,
+	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let events = block.events().await.map_err(|_| "Failed to get events")?;
+	let has = events
+		.has::<broker_events::SaleInitialized>()
+		.map_err(|_| "Event search failed")?;
+	if has {
+		// A new sale started, so all phases have to be scheduled again.
+		schedule_phases(conn, client, block.number()).await?;
 	}
 
 	Ok(())
 }
 
+/// Triggers the notifications of all phases of the ongoing sale.
+///
+/// The notification service decides when subscribers get notified based on the expected
+/// start and end of each phase.
+async fn schedule_phases(
+	conn: &DbConn,
+	client: &OnlineClient<PolkadotConfig>,
+	current_block: BlockNumber,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let sale_info = sale_info(client).await?;
+	let config = coretime_config(client).await?;
+
+	// There shouldn't be a need to convert these to timestamps ahead of time. However, to notify
+	// users prior to a phase starting we have to estimate when that will happen.
+	//
+	// NOTE: these values should be updated whenever a rotation to a new sale happens.
+	let leadin_start: u32 = sale_info.sale_start;
+	let interlude_start: u32 = leadin_start.saturating_sub(config.interlude_length);
+	let fixed_phase_start: u32 = leadin_start.saturating_add(config.leadin_length);
+
+	let now = notification::timestamp();
+	let phase = |kind, start, end: Option<BlockNumber>| Event {
+		network: NETWORK.to_string(),
+		kind,
+		block: start,
+		timestamp: block_timestamp(start, current_block, now),
+		end_timestamp: end.map(|end| block_timestamp(end, current_block, now)),
+		timeslice: Some(sale_info.region_begin),
+		core: None,
+		price: None,
+		cores_left: Some(sale_info.cores_offered.saturating_sub(sale_info.cores_sold)),
+		para_id: None,
+	};
+
+	// Parachains which can still renew their core have to do so during the interlude phase, after
+	// which their core can be bought by anyone.
+	let expiring = expiring_parachains(client, sale_info.region_begin).await?;
+
+	let conn = conn.lock().map_err(|_| "Failed to get db connection")?;
+	SaleStatus::record_phases(
+		&conn,
+		NETWORK,
+		block_timestamp(interlude_start, current_block, now),
+		block_timestamp(leadin_start, current_block, now),
+		block_timestamp(fixed_phase_start, current_block, now),
+		sale_info.cores_offered.saturating_sub(sale_info.cores_sold),
+		now,
+	)?;
+
+	// The end of the fixed price phase is the start of the next sale, which isn't known yet.
+	for event in [
+		phase(EventKind::InterludePhase, interlude_start, Some(leadin_start)),
+		phase(EventKind::LeadinPhase, leadin_start, Some(fixed_phase_start)),
+		phase(EventKind::FixedPhase, fixed_phase_start, None),
+	] {
+		notification::notify(&conn, &event)?;
+	}
+
+	for (para_id, price) in expiring {
+		let event = Event {
+			price: Some(price),
+			cores_left: None,
+			para_id: Some(para_id),
+			..phase(EventKind::CoreExpiring, interlude_start, Some(leadin_start))
+		};
+		notification::notify(&conn, &event)?;
+	}
+
+	Ok(())
+}
+
+/// Returns the parachains whose core expires at `region_begin` unless it is renewed, along with
+/// the renewal price.
+async fn expiring_parachains(
+	client: &OnlineClient<PolkadotConfig>,
+	region_begin: Timeslice,
+) -> Result<Vec<(ParaId, Balance)>, Box<dyn std::error::Error>> {
+	let renewals_query = coretime_chain::storage().broker().allowed_renewals_iter();
+	let mut renewals = client.storage().at_latest().await?.iter(renewals_query).await?;
+
+	let mut expiring = vec![];
+	while let Some(Ok((key, renewal))) = renewals.next().await {
+		// The key ends with the SCALE encoded `AllowedRenewalId { core: u16, when: u32 }`.
+		let Some(when) = key.len().checked_sub(4).and_then(|at| key[at..].try_into().ok()) else {
+			continue;
+		};
+		if u32::from_le_bytes(when) != region_begin {
+			continue;
+		}
+
+		if let CompletionStatus::Complete(workload) = renewal.completion {
+			for schedule_item in workload.0.iter() {
+				if let CoreAssignment::Task(para_id) = schedule_item.assignment {
+					expiring.push((para_id, renewal.price));
+				}
+			}
+		}
+	}
+
+	Ok(expiring)
+}
+
 async fn track_assignments_and_renewals(
+	conn: &DbConn,
 	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
 	let events = block.events().await.map_err(|_| "Failed to get events")?;
+	let mut triggered = vec![];
 
 	// Iterate over assignments:
 	events.find::<broker_events::Assigned>().for_each(|maybe_assignment| {
 		if let Ok(assignment) = maybe_assignment {
-			let task_id = assignment.task;
-
-			// TODO: notify everyone subscribed to the parachain.
+			triggered.push(Event {
+				network: NETWORK.to_string(),
+				kind: EventKind::CoreAssigned,
+				block: block.number(),
+				timestamp: notification::timestamp(),
+				end_timestamp: None,
+				timeslice: Some(assignment.region_id.begin),
+				core: Some(assignment.region_id.core),
+				price: None,
+				cores_left: None,
+				para_id: Some(assignment.task),
+			});
 		}
 	});
 
@@ -81,58 +217,80 @@ async fn track_assignments_and_renewals(
 			// Given that only non interlaced cores are renewed there should always be a sinle item
 			// in the workload. However, we will still iterate over each.
 			workload.0.iter().for_each(|schedule_item| {
-				// TODO: notify everyone subscribed to the parachain.
+				if let CoreAssignment::Task(para_id) = schedule_item.assignment {
+					triggered.push(Event {
+						network: NETWORK.to_string(),
+						kind: EventKind::CoreRenewed,
+						block: block.number(),
+						timestamp: notification::timestamp(),
+						end_timestamp: None,
+						timeslice: Some(renewal.begin),
+						core: Some(renewal.core),
+						price: Some(renewal.price),
+						cores_left: None,
+						para_id: Some(para_id),
+					});
+				}
 			});
 		}
 	});
 
+	let conn = conn.lock().map_err(|_| "Failed to get db connection")?;
+	for event in triggered {
+		notification::notify(&conn, &event)?;
+	}
+
 	Ok(())
 }
 
 async fn track_coretime_sales(
+	conn: &DbConn,
 	client: &OnlineClient<PolkadotConfig>,
 	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
 	// Check if a sale was made.
 	let events = block.events().await.map_err(|_| "Failed to get events")?;
-	let has = events.has::<broker_events::Purchased>().map_err(|_| "Event search failed")?;
-	if has {
+	let purchases: Vec<_> =
+		events.find::<broker_events::Purchased>().filter_map(Result::ok).collect();
+	if !purchases.is_empty() {
 		let sale_info = sale_info(&client).await?;
 		let available_cores = sale_info.cores_offered - sale_info.cores_sold;
 
-		// TODO: if a specific number of cores are left trigger notification service.
+		let conn = conn.lock().map_err(|_| "Failed to get db connection")?;
+		// Purchases within the same block are told apart by the core of their region.
+		for purchase in purchases {
+			let event = Event {
+				network: NETWORK.to_string(),
+				kind: EventKind::CoretimeSale,
+				block: block.number(),
+				timestamp: notification::timestamp(),
+				end_timestamp: None,
+				timeslice: Some(purchase.region_id.begin),
+				core: Some(purchase.region_id.core),
+				price: Some(purchase.price),
+				cores_left: Some(available_cores),
+				para_id: None,
+			};
+			SaleStatus::record_sale(
+				&conn,
+				NETWORK,
+				purchase.price,
+				available_cores,
+				event.timestamp,
+			)?;
+			notification::notify(&conn, &event)?;
+		}
 	}
 
 	Ok(())
 }
 
-fn track_interlude_phase(
-	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
-	interlude_start: u32,
-) {
-	// Check if interlude started
-	if block.header().number == interlude_start {
-		// TODO: Trigger notifier
-	}
-}
-
-fn track_leadin_phase(
-	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
-	leadin_start: u32,
-) {
-	// Check if leadin started
-	if block.header().number == leadin_start {
-		// TODO: Trigger notifier
-	}
-}
-
-fn track_fixed_phase(
-	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
-	fixed_phase_start: u32,
-) {
-	// Check if fixed phase started
-	if block.header().number == fixed_phase_start {
-		// TODO: Trigger notifier
+/// Estimates the unix timestamp of `block` based on the expected block time.
+fn block_timestamp(block: BlockNumber, current_block: BlockNumber, now: Timestamp) -> Timestamp {
+	if block >= current_block {
+		now + (block - current_block) as u64 * BLOCK_TIME
+	} else {
+		now.saturating_sub((current_block - block) as u64 * BLOCK_TIME)
 	}
 }
 
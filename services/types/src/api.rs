@@ -1,3 +1,4 @@
+use crate::Timestamp;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
@@ -10,3 +11,174 @@ impl From<&str> for ErrorResponse {
 		ErrorResponse { message: s.to_string() }
 	}
 }
+
+/// The secret with which the deliveries to the webhook of a user are signed.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct WebhookSecret {
+	pub secret: String,
+}
+
+/// The opaque id of a user, which is assigned on registration.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct UserId {
+	pub id: String,
+}
+
+/// Requests a challenge to sign in with a Substrate account.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct ChallengeRequest {
+	/// The SS58 address of the account.
+	pub address: String,
+}
+
+/// A message which has to be signed with the account to sign in.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct Challenge {
+	pub nonce: String,
+	pub message: String,
+	#[serde(rename = "expiresAt")]
+	pub expires_at: Timestamp,
+}
+
+/// Exchanges a signed challenge for a session.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct LoginRequest {
+	/// The nonce of the challenge.
+	pub nonce: String,
+	/// The hex encoded signature of the message of the challenge.
+	pub signature: String,
+}
+
+/// The token which authenticates the requests of a session.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct SessionToken {
+	pub token: String,
+	#[serde(rename = "expiresAt")]
+	pub expires_at: Timestamp,
+}
+
+/// The data of a Telegram account, as signed by the Telegram Login Widget.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct TelegramLogin {
+	/// The id of the Telegram account, which is also the id of its chat with the bot.
+	pub id: i64,
+	pub first_name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub last_name: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub username: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub photo_url: Option<String>,
+	pub auth_date: Timestamp,
+	/// The hex encoded HMAC of the other fields.
+	pub hash: String,
+}
+
+/// Requests a link which signs in with the email address.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct EmailLoginRequest {
+	pub email: String,
+}
+
+/// What an API key is allowed to do.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum ApiKeyScope {
+	/// Everything a session can do, except for managing API keys.
+	#[default]
+	Full,
+	/// Only reading the user.
+	ReadOnly,
+	/// Reading the user and managing its subscriptions.
+	Subscriptions,
+}
+
+/// Creates an API key for scripts which act on behalf of the user.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct ApiKeyRequest {
+	/// What the key is used for, e.g. `ci`.
+	pub name: String,
+	#[serde(default)]
+	pub scope: ApiKeyScope,
+	/// Unix timestamp after which the key can't be used anymore. Keys without one don't expire.
+	#[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+	pub expires_at: Option<Timestamp>,
+}
+
+/// An API key of the user, without the key itself.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct ApiKey {
+	/// The id through which the key is revoked.
+	pub id: String,
+	pub name: String,
+	pub scope: ApiKeyScope,
+	#[serde(rename = "createdAt")]
+	pub created_at: Timestamp,
+	#[serde(rename = "expiresAt")]
+	pub expires_at: Option<Timestamp>,
+	#[serde(rename = "lastUsedAt")]
+	pub last_used_at: Option<Timestamp>,
+}
+
+/// A newly created API key. The key is only returned once, it can't be looked up later.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct NewApiKey {
+	pub key: String,
+	#[serde(flatten)]
+	pub api_key: ApiKey,
+}
+
+/// The role of a member of an organization.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum OrganizationRole {
+	/// Manages the organization, including its owners.
+	Owner,
+	/// Manages the subscriptions and members of the organization, except for its owners.
+	Admin,
+	/// Receives the notifications of the organization.
+	Member,
+}
+
+/// Creates an organization, of which the user becomes the owner.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct OrganizationRequest {
+	pub name: String,
+}
+
+/// An organization of which the user is a member.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct Organization {
+	pub id: String,
+	pub name: String,
+	/// The role of the user in the organization.
+	pub role: OrganizationRole,
+}
+
+/// A member of an organization, identified by the opaque id of the user.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct OrganizationMember {
+	pub id: String,
+	pub role: OrganizationRole,
+}
+
+/// An organization along with its members and subscriptions.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct OrganizationDetails {
+	#[serde(flatten)]
+	pub organization: Organization,
+	pub members: Vec<OrganizationMember>,
+	pub subscriptions: Vec<crate::Notifications>,
+}
+
+/// Invites someone to join an organization with the given role.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct InviteRequest {
+	pub role: OrganizationRole,
+}
+
+/// The token with which an invite is accepted. It is only returned once.
+#[derive(Debug, Serialize, Eq, PartialEq, Deserialize)]
+pub struct Invite {
+	pub token: String,
+	#[serde(rename = "expiresAt")]
+	pub expires_at: Timestamp,
+}
@@ -0,0 +1,62 @@
+//! Events observed on the Coretime chain about which users can get notified.
+
+use crate::{Balance, BlockNumber, CoreIndex, ParaId, Timeslice, Timestamp};
+use serde::{Deserialize, Serialize};
+
+/// The kind of an on-chain event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[serde(crate = "rocket::serde")]
+pub enum EventKind {
+	/// The interlude phase of a sale.
+	InterludePhase,
+	/// The leadin phase of a sale.
+	LeadinPhase,
+	/// The fixed price phase of a sale.
+	FixedPhase,
+	/// Coretime was purchased.
+	CoretimeSale,
+	/// Coretime was assigned to a parachain.
+	CoreAssigned,
+	/// The coretime of a parachain got renewed.
+	CoreRenewed,
+	/// The core of a parachain expires at the end of the current region unless it is renewed.
+	CoreExpiring,
+}
+
+impl EventKind {
+	/// Whether subscribers have to be notified right away, even during their quiet hours.
+	pub fn is_critical(&self) -> bool {
+		matches!(self, EventKind::CoreExpiring)
+	}
+}
+
+/// An event which is passed from the tracker to the notification service.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Event {
+	/// The network on which the event occurred, e.g. `kusama`.
+	pub network: String,
+	/// The kind of the event.
+	pub kind: EventKind,
+	/// The block in which the event happened, or will happen.
+	pub block: BlockNumber,
+	/// Unix timestamp (in seconds) at which the event happened, or is expected to happen.
+	pub timestamp: Timestamp,
+	/// For phases this is the expected unix timestamp of the phase end. For expiring cores it is
+	/// the deadline for renewing them.
+	#[serde(rename = "endTimestamp")]
+	pub end_timestamp: Option<Timestamp>,
+	/// The timeslice the event relates to.
+	pub timeslice: Option<Timeslice>,
+	/// The core the event relates to, e.g. the core of a purchased region. Tells apart multiple
+	/// events of the same kind within a block.
+	pub core: Option<CoreIndex>,
+	/// The price of coretime relevant to the event.
+	pub price: Option<Balance>,
+	/// The number of cores which are still available for purchase.
+	#[serde(rename = "coresLeft")]
+	pub cores_left: Option<u16>,
+	/// The parachain the event relates to.
+	#[serde(rename = "paraId")]
+	pub para_id: Option<ParaId>,
+}
@@ -1,8 +1,18 @@
 use serde::{Deserialize, Serialize};
 
 pub mod api;
+pub mod event;
 
 pub type ParaId = u32;
+pub type BlockNumber = u32;
+pub type Timeslice = u32;
+pub type CoreIndex = u16;
+pub type Balance = u128;
+/// Unix timestamp in seconds.
+pub type Timestamp = u64;
+
+/// Parachain ids below this are reserved for the relay chain and never assigned to a parachain.
+pub const MIN_PARA_ID: ParaId = 1000;
 
 /// Different events to which a user can subscribe to.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -53,6 +63,74 @@ pub enum Notifier {
 	Email,
 	// User will receive notifications via their telegram.
 	Telegram,
+	/// User will receive notifications through a Discord webhook.
+	Discord,
+	/// User will receive notifications through a Slack incoming webhook.
+	Slack,
+	/// User will receive notifications in a Matrix room.
+	Matrix,
+	/// User will receive the events as signed JSON payloads posted to their own endpoint.
+	Webhook,
 	/// If `Null` user will not receive notifications.
 	Null,
 }
+
+/// A daily window during which the user doesn't want to receive non-critical notifications.
+///
+/// The hours are in the user's time zone. A window which starts later than it ends spans
+/// midnight, e.g. from 22 to 7.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(crate = "rocket::serde")]
+pub struct QuietHours {
+	/// The hour of the day at which the window starts.
+	pub start: u8,
+	/// The hour of the day at which the window ends, exclusive.
+	pub end: u8,
+}
+
+impl QuietHours {
+	/// Whether the window is well-formed.
+	pub fn is_valid(&self) -> bool {
+		self.start < 24 && self.end < 24 && self.start != self.end
+	}
+
+	/// Whether `hour` falls into the window.
+	pub fn contains(&self, hour: u8) -> bool {
+		if self.start < self.end {
+			self.start <= hour && hour < self.end
+		} else {
+			hour >= self.start || hour < self.end
+		}
+	}
+}
+
+/// How the notifications of a user are delivered.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[serde(crate = "rocket::serde")]
+pub enum DeliveryMode {
+	/// Every notification is sent as soon as it is due.
+	#[default]
+	Immediate,
+	/// Notifications are collected and sent as a single message at the start of every hour.
+	HourlyDigest,
+	/// Notifications are collected and sent as a single message once a day.
+	DailyDigest,
+	/// Notifications are collected and sent as a single message once a week.
+	WeeklyDigest,
+}
+
+/// A step in the escalation of a critical alert which wasn't acknowledged.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+#[serde(crate = "rocket::serde")]
+pub struct EscalationStep {
+	/// Minutes after the alert was due at which the step is taken.
+	#[serde(rename = "afterMinutes")]
+	pub after_minutes: u32,
+	/// The channel through which the alert is sent again.
+	pub channel: Notifier,
+	/// The address of an additional contact, e.g. the email address of a team member.
+	///
+	/// If undefined, the alert is sent to the user's own address of the channel.
+	#[serde(default)]
+	pub contact: Option<String>,
+}
@@ -1,8 +1,60 @@
-/// ## Coretime Notifier
+use notification::{
+	bot::TelegramBot, templates::Templates, unsubscribe::UnsubscribeTokens, worker::Worker,
+};
+use std::env;
+use storage::init_db;
+
+// TODO: don't hardcode here.
+const DB_PATH: &str = "users.db";
+const LOG_TARGET: &str = "coretime-notifier";
 
+/// ## Coretime Notifier
 #[tokio::main]
 async fn main() {
+	// Initialize the notification workers
+	let conn = init_db(DB_PATH).expect("Failed to init db connection");
+	let templates = Templates::from_env().expect("Failed to load templates");
+	let unsubscribe = UnsubscribeTokens::from_env().expect("UNSUBSCRIBE_SECRET must be set");
+	let mut worker = Worker::new(conn, notification::channels::from_env())
+		.with_templates(templates)
+		.with_unsubscribe_tokens(unsubscribe);
+	// Alerts can only be acknowledged if they link to the api.
+	let public_api_url = env::var("PUBLIC_API_URL").ok();
+	match &public_api_url {
+		Some(api_url) => worker = worker.with_api_url(api_url.clone()),
+		None => log::warn!(
+			target: LOG_TARGET,
+			"PUBLIC_API_URL is not set, alerts can't be acknowledged"
+		),
+	}
+	tokio::spawn(worker.run());
+
+	// Initialize the telegram bot
+	if let Ok(token) = env::var("TELEGRAM_BOT_TOKEN") {
+		let conn = init_db(DB_PATH).expect("Failed to init db connection");
+		let bot = TelegramBot::new(conn, token);
+		// Telegram pushes the updates to the api if it is reachable, otherwise they are polled.
+		match (public_api_url, env::var("TELEGRAM_WEBHOOK_SECRET")) {
+			(Some(api_url), Ok(secret)) => {
+				let url = format!("{}/telegram/webhook", api_url.trim_end_matches('/'));
+				if let Err(err) = bot.set_webhook(&url, &secret).await {
+					log::error!(target: LOG_TARGET, "Failed to set the telegram webhook: {}", err);
+				}
+			},
+			_ => {
+				tokio::spawn(bot.run());
+			},
+		}
+	}
+
+	// Initialize the tracker
+	let conn = init_db(DB_PATH).expect("Failed to init db connection");
+	tokio::spawn(async move {
+		if let Err(err) = tracker::track(conn).await {
+			log::error!(target: LOG_TARGET, "Tracker stopped: {:?}", err);
+		}
+	});
+
 	// Initialize the API service
 	api::rocket().await.launch().await.unwrap();
-	tracker::track().await.unwrap();
 }